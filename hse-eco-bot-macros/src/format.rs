@@ -44,7 +44,7 @@ impl<'a> Parser<'a> {
     pub fn parse(&mut self) -> Result<Parsed, ParseError> {
         let items = self.parse_string()?;
 
-        if let Some(_) = self.iter.peek() {
+        if self.iter.peek().is_some() {
             Err(ParseError::ExpectedEnd)
         } else {
             Ok(Parsed { items })
@@ -54,7 +54,7 @@ impl<'a> Parser<'a> {
     fn parse_string(&mut self) -> Result<Vec<Item>, ParseError> {
         let mut items = Vec::new();
         loop {
-            let peek = self.iter.peek().map(|x| *x);
+            let peek = self.iter.peek().copied();
             match peek {
                 Some((_, '{')) => items.push(Item::Placeholder(self.parse_placeholder()?)),
                 Some((_, '@')) => items.push(Item::Entity(self.parse_entity()?)),
@@ -166,7 +166,7 @@ impl Parsed {
                 entities: Some(entities),
             }
         };
-        (code.into(), param_counter)
+        (code, param_counter)
     }
 
     fn process_items(items: &[Item], param_counter: &mut usize) -> TokenStream {
@@ -223,6 +223,5 @@ impl Parsed {
                 }
             }
         }
-        .into()
     }
 }
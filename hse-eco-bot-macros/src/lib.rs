@@ -1,7 +1,7 @@
 mod format;
 mod strings;
 
-const YAML_FILE_PATH: &'static str =
+const YAML_FILE_PATH: &str =
     concat!(env!("CARGO_MANIFEST_DIR"), "/../resources/strings.yml");
 
 #[proc_macro]
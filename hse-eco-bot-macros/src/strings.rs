@@ -83,13 +83,13 @@ fn generate_code_for(node: &Node, prefix: &mut Vec<String>) -> TokenStream {
 
         #substructs
     };
-    code.into()
+    code
 }
 
 fn check_name(name: &str) {
     if !name
         .chars()
-        .all(|c| ('a'..='z').contains(&c) || c.is_ascii_digit() || c == '_')
+        .all(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
     {
         panic!("Invalid char");
     }
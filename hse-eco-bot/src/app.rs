@@ -1,28 +1,95 @@
-use crate::db::AccessTask;
-use crate::dispatch::DialogStorage;
+use crate::audit::AuditLog;
+use crate::config::{Config, ConfigError};
+use crate::db::{self, AccessTask};
+use crate::dispatch::{self, DialogStorage};
+use crate::events::EventStorage;
 use crate::global_state::GlobalState;
-use crate::kb::Tree;
+use crate::kb::command::Context;
+use crate::kb::providers::fs;
+use crate::kb::{ProviderError, Tree};
+use crate::links::LinkStorage;
 use crate::message_queue::MessageQueue;
-use crate::newsletter::{feedback::FeedbackNewsletter};
-use crate::newsletter::{Newsletter, NewsletterWorker};
+use crate::migrations;
+use crate::newsletter::announcements::AnnouncementNewsletter;
+use crate::newsletter::event_reminders::EventReminderNewsletter;
+use crate::newsletter::feedback::{self, FeedbackNewsletter};
+use crate::newsletter::{ConfiguredNewsletter, Newsletter, NewsletterWorker};
+use crate::pins::PinStorage;
+use crate::schedule::ScheduleStorage;
+use crate::trash;
 use crate::types::BotType;
 use crate::ui;
+use crate::user::storage::{self, UserStorage};
+use crate::user::Permission;
 use crate::util::UnsafeRc;
 use std::error::Error;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
-use teloxide::adaptors::throttle::Limits;
+use std::time::Duration;
 use teloxide::prelude::*;
 
 /// The application with its state.
 pub struct App {
     bot: BotType,
+    config: Config,
+}
+
+/// Mount a directory tree on disk on the knowledge base directory named after it, which is
+/// created in the root directory if needed. The mounted tree is read-only.
+pub fn mount_kb_directory(tree: &mut Tree, path: &Path) -> Result<(), Box<dyn Error>> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| format!("cannot mount `{}`: invalid directory name", path.display()))?;
+    fs::mount(tree, name, path.to_owned(), true)
+        .map_err(|e| format!("cannot mount `{}`: {}", path.display(), e))?;
+    Ok(())
+}
+
+/// Report the error of mounting the newsletter archive on the configured directory, which only
+/// happens when the name of the directory is taken, as an invalid configuration.
+pub fn archive_dir_error(e: ProviderError) -> Box<dyn Error> {
+    let reason = match e {
+        ProviderError::TargetNameAlreadyExists(_) => "names an item that is not a directory",
+        ProviderError::MountPointNotEmpty => "names a non-empty directory of the database",
+        e => return e.into(),
+    };
+    ConfigError::InvalidValue {
+        key: String::from("kb.archive_dir"),
+        reason,
+    }
+    .into()
+}
+
+/// Warn about the configured admins who are not admins in the database. The configuration only
+/// grants the admin role when the database is created by `init-db`; the roles are managed in the
+/// bot afterwards.
+fn warn_about_missing_admins(db: &rusqlite::Connection, config: &Config) -> rusqlite::Result<()> {
+    let permissions = storage::load_permissions(db)?;
+    for user_id in config.admin_users() {
+        let is_admin = permissions
+            .get(&user_id)
+            .is_some_and(|permissions| permissions.contains(Permission::Admin));
+        if !is_admin {
+            warn!(
+                "User {} is listed in `admins` but is not an admin: the list only applies to \
+                 `init-db`, grant the role in the bot instead",
+                user_id.0
+            );
+        }
+    }
+    Ok(())
 }
 
 impl App {
-    /// Create an application. The Telegram Bot API token must be provided.
-    pub fn new(api_token: String) -> Self {
+    /// Create an application. The Telegram Bot API token must be provided, as well as the
+    /// configuration.
+    pub fn new(api_token: String, config: Config) -> Self {
         Self {
-            bot: Bot::new(api_token).auto_send(), // .throttle(Limits::default()).auto_send(),
+            bot: Bot::new(api_token)
+                .throttle(config.throttle.limits())
+                .auto_send(),
+            config,
         }
     }
 
@@ -33,33 +100,83 @@ impl App {
         let callback_query_handler =
             Update::filter_callback_query().endpoint(ui::handle_callback_query);
 
+        let inline_query_handler =
+            Update::filter_inline_query().endpoint(ui::inline::handle_inline_query);
+
         let root_handler = teloxide::dptree::entry()
             .branch(message_handler)
-            .branch(callback_query_handler);
+            .branch(callback_query_handler)
+            .branch(inline_query_handler);
 
-        let db = rusqlite::Connection::open("hse-eco-bot.sqlite")?;
+        let db = rusqlite::Connection::open(&self.config.database.path)?;
         db.execute("PRAGMA foreign_keys=ON", rusqlite::params![])?;
-        let dialog_storage = DialogStorage::new(&db);
+        migrations::migrate(&db)?;
+        warn_about_missing_admins(&db, &self.config)?;
+        let dialog_storage = DialogStorage::new(&db)?;
 
+        let (db_cmd_sender, db_cmd_receiver) = db::command_channel();
         let (feedback_newsletter, feedback_tx) = FeedbackNewsletter::new();
+        let feedback_newsletter = ConfiguredNewsletter::new(feedback_newsletter, &self.config);
+        let event_reminder_newsletter = ConfiguredNewsletter::new(
+            EventReminderNewsletter::new(db_cmd_sender.clone()),
+            &self.config,
+        );
+        let (announcement_newsletter, announcement_tx) =
+            AnnouncementNewsletter::new(db_cmd_sender.clone());
+        let announcement_newsletter =
+            ConfiguredNewsletter::new(announcement_newsletter, &self.config);
 
-        let newsletters: &[&dyn Newsletter] = &[&feedback_newsletter];
+        let newsletters: &[&dyn Newsletter] = &[
+            &feedback_newsletter,
+            &event_reminder_newsletter,
+            &announcement_newsletter,
+        ];
 
         // SAFETY: clones of [`db_rc`] are never shared between threads.
         let db_rc = unsafe { UnsafeRc::new(db) };
-        let (kb_tree, _provider_registry, newsletter_sink) =
-            unsafe { Tree::new(db_rc, newsletters) };
-        let (db_access_task, db_cmd_sender) = AccessTask::new(kb_tree, newsletter_sink);
+        let (mut kb_tree, _provider_registry, newsletter_sink) = unsafe {
+            Tree::new(
+                UnsafeRc::clone(&db_rc),
+                newsletters,
+                &self.config.kb.archive_dir,
+            )
+        }
+        .map_err(archive_dir_error)?;
+        for path in &self.config.kb.directories {
+            mount_kb_directory(&mut kb_tree, path)?;
+        }
+        let users = unsafe { UserStorage::new(UnsafeRc::clone(&db_rc)) };
+        let pins = unsafe { PinStorage::new(UnsafeRc::clone(&db_rc)) };
+        let links = unsafe { LinkStorage::new(UnsafeRc::clone(&db_rc)) };
+        let events = unsafe { EventStorage::new(UnsafeRc::clone(&db_rc)) };
+        let schedule = unsafe { ScheduleStorage::new(UnsafeRc::clone(&db_rc)) };
+        let audit = unsafe { AuditLog::new(db_rc) };
+        let db_access_task = AccessTask::new(db_cmd_receiver, Context {
+            tree: kb_tree,
+            newsletter_sink,
+            users,
+            pins,
+            links,
+            events,
+            schedule,
+            audit,
+        });
         let db_access_task_handle = db_access_task.spawn();
         let global_state = Arc::new(GlobalState {
             dialog_storage,
             db: db_cmd_sender,
             feedback_tx: Mutex::new(None),
+            announcement_tx: Mutex::new(None),
+            // Feedback is sent regardless of subscriptions, so it is not offered in the
+            // subscriptions menu.
             newsletters: newsletters
                 .iter()
                 .copied()
+                .filter(|nl| nl.name() != feedback::NAME)
                 .map(|nl| (nl.name(), nl.description(), nl.allowed()))
                 .collect(),
+            announcement_tags: announcement_newsletter.tags(),
+            trash_retention_days: self.config.trash.retention_days,
         });
 
         let (message_queue, message_queue_tx) = MessageQueue::new();
@@ -76,6 +193,53 @@ impl App {
         );
         *global_state.feedback_tx.lock().unwrap() = Some(feedback_tx);
 
+        tokio::spawn(
+            NewsletterWorker::new(
+                self.bot.clone(),
+                event_reminder_newsletter,
+                Arc::clone(&global_state),
+                message_queue_tx.clone(),
+            )
+            .manage(),
+        );
+
+        tokio::spawn(
+            NewsletterWorker::new(
+                self.bot.clone(),
+                announcement_newsletter,
+                Arc::clone(&global_state),
+                message_queue_tx.clone(),
+            )
+            .manage(),
+        );
+        *global_state.announcement_tx.lock().unwrap() = Some(announcement_tx);
+
+        tokio::spawn(dispatch::refresh_permissions(
+            Arc::clone(&global_state),
+            Duration::from_secs(60),
+        ));
+
+        tokio::spawn(trash::purge_expired(
+            global_state.db.clone(),
+            self.config.trash.retention_days,
+            Duration::from_secs(60 * 60),
+        ));
+
+        if !self.config.kb.directories.is_empty() {
+            tokio::spawn(ui::reload_kb_periodically(
+                self.bot.clone(),
+                Arc::clone(&global_state),
+                message_queue_tx.clone(),
+                Duration::from_secs(60),
+            ));
+        }
+
+        tokio::spawn(ui::resume_dialogs(
+            self.bot.clone(),
+            Arc::clone(&global_state),
+            message_queue_tx.clone(),
+        ));
+
         let mut dispatcher = Dispatcher::builder(self.bot, root_handler)
             .dependencies(teloxide::dptree::deps![global_state, message_queue_tx])
             .build();
@@ -109,4 +273,9 @@ fn assert_traits() {
         declval(),
         declval(),
     ));
+    must_be_send(ui::inline::handle_inline_query(
+        declval(),
+        declval(),
+        declval(),
+    ));
 }
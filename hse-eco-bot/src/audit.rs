@@ -0,0 +1,421 @@
+//! The append-only log of the administrative and editorial actions.
+
+use crate::db::{FullDirectoryId, FullItemId, FullNoteId, FullRevisionId};
+use crate::events::{day_start, time_from_sql, time_to_sql};
+use crate::util::{NestedTransaction, UnsafeRc};
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::io::Write;
+use std::str::FromStr;
+use teloxide::types::UserId;
+
+/// An action recorded in the audit log.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum AuditAction {
+    CreateDirectory,
+    RenameDirectory,
+    MoveDirectory,
+    DeleteDirectory,
+    CreateNote,
+    RenameNote,
+    MoveNote,
+    UpdateNote,
+    DeleteNote,
+    RestoreRevision,
+    PinItem,
+    UnpinItem,
+    RenamePin,
+    MovePin,
+    CreateEvent,
+    UpdateEvent,
+    DeleteEvent,
+    SendAnnouncement,
+    ScheduleAnnouncement,
+    UpdateScheduledAnnouncement,
+    CancelScheduledAnnouncement,
+    GrantRole,
+    RevokeRole,
+    ReloadKnowledgeBase,
+}
+
+impl AuditAction {
+    /// All the actions, in the order they are offered as filters.
+    pub const ALL: [Self; 24] = [
+        Self::CreateDirectory,
+        Self::RenameDirectory,
+        Self::MoveDirectory,
+        Self::DeleteDirectory,
+        Self::CreateNote,
+        Self::RenameNote,
+        Self::MoveNote,
+        Self::UpdateNote,
+        Self::DeleteNote,
+        Self::RestoreRevision,
+        Self::PinItem,
+        Self::UnpinItem,
+        Self::RenamePin,
+        Self::MovePin,
+        Self::CreateEvent,
+        Self::UpdateEvent,
+        Self::DeleteEvent,
+        Self::SendAnnouncement,
+        Self::ScheduleAnnouncement,
+        Self::UpdateScheduledAnnouncement,
+        Self::CancelScheduledAnnouncement,
+        Self::GrantRole,
+        Self::RevokeRole,
+        Self::ReloadKnowledgeBase,
+    ];
+
+    /// Get the name of the action stored in the database and accepted by the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::CreateDirectory => "create_directory",
+            Self::RenameDirectory => "rename_directory",
+            Self::MoveDirectory => "move_directory",
+            Self::DeleteDirectory => "delete_directory",
+            Self::CreateNote => "create_note",
+            Self::RenameNote => "rename_note",
+            Self::MoveNote => "move_note",
+            Self::UpdateNote => "update_note",
+            Self::DeleteNote => "delete_note",
+            Self::RestoreRevision => "restore_revision",
+            Self::PinItem => "pin_item",
+            Self::UnpinItem => "unpin_item",
+            Self::RenamePin => "rename_pin",
+            Self::MovePin => "move_pin",
+            Self::CreateEvent => "create_event",
+            Self::UpdateEvent => "update_event",
+            Self::DeleteEvent => "delete_event",
+            Self::SendAnnouncement => "send_announcement",
+            Self::ScheduleAnnouncement => "schedule_announcement",
+            Self::UpdateScheduledAnnouncement => "update_scheduled_announcement",
+            Self::CancelScheduledAnnouncement => "cancel_scheduled_announcement",
+            Self::GrantRole => "grant_role",
+            Self::RevokeRole => "revoke_role",
+            Self::ReloadKnowledgeBase => "reload_kb",
+        }
+    }
+
+    /// Get the description of the action shown to the admins.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::CreateDirectory => "Создание раздела",
+            Self::RenameDirectory => "Переименование раздела",
+            Self::MoveDirectory => "Перемещение раздела",
+            Self::DeleteDirectory => "Удаление раздела",
+            Self::CreateNote => "Создание заметки",
+            Self::RenameNote => "Переименование заметки",
+            Self::MoveNote => "Перемещение заметки",
+            Self::UpdateNote => "Изменение заметки",
+            Self::DeleteNote => "Удаление заметки",
+            Self::RestoreRevision => "Восстановление версии",
+            Self::PinItem => "Закрепление материала",
+            Self::UnpinItem => "Открепление материала",
+            Self::RenamePin => "Переименование закреплённого материала",
+            Self::MovePin => "Перемещение закреплённого материала",
+            Self::CreateEvent => "Создание мероприятия",
+            Self::UpdateEvent => "Изменение мероприятия",
+            Self::DeleteEvent => "Удаление мероприятия",
+            Self::SendAnnouncement => "Отправка объявления",
+            Self::ScheduleAnnouncement => "Планирование объявления",
+            Self::UpdateScheduledAnnouncement => "Изменение запланированного объявления",
+            Self::CancelScheduledAnnouncement => "Отмена запланированного объявления",
+            Self::GrantRole => "Выдача роли",
+            Self::RevokeRole => "Отзыв роли",
+            Self::ReloadKnowledgeBase => "Перечитывание разделов из файлов",
+        }
+    }
+}
+
+impl Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.name() == s)
+            .ok_or(())
+    }
+}
+
+/// An object affected by an action recorded in the audit log.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum AuditTarget {
+    Directory(FullDirectoryId),
+    Note(FullNoteId),
+    Revision(FullRevisionId),
+    Pin(u64),
+    Event(u64),
+    ScheduledAnnouncement(u64),
+    User(UserId),
+    Role(String),
+}
+
+impl Display for AuditTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Directory(id) => write!(f, "directory:{}", id),
+            Self::Note(id) => write!(f, "note:{}", id),
+            Self::Revision(id) => write!(f, "revision:{}", id),
+            Self::Pin(id) => write!(f, "pin:{}", id),
+            Self::Event(id) => write!(f, "event:{}", id),
+            Self::ScheduledAnnouncement(id) => write!(f, "announcement:{}", id),
+            Self::User(user_id) => write!(f, "user:{}", user_id.0),
+            Self::Role(name) => write!(f, "role:{}", name),
+        }
+    }
+}
+
+impl FromStr for AuditTarget {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn parse_ids(ids: &str) -> Result<(u64, u64), ()> {
+            let (provider, item) = ids.split_once(':').ok_or(())?;
+            Ok((
+                provider.parse().map_err(|_| ())?,
+                item.parse().map_err(|_| ())?,
+            ))
+        }
+
+        let (kind, id) = s.split_once(':').ok_or(())?;
+        let parse_id = || id.parse::<u64>().map_err(|_| ());
+        match kind {
+            "directory" => {
+                let (provider, directory) = parse_ids(id)?;
+                Ok(Self::Directory(FullDirectoryId {
+                    provider: provider.into(),
+                    directory: directory.into(),
+                }))
+            }
+            "note" => {
+                let (provider, note) = parse_ids(id)?;
+                Ok(Self::Note(FullNoteId {
+                    provider: provider.into(),
+                    note: note.into(),
+                }))
+            }
+            "revision" => {
+                let (provider, revision) = parse_ids(id)?;
+                Ok(Self::Revision(FullRevisionId {
+                    provider: provider.into(),
+                    revision: revision.into(),
+                }))
+            }
+            "pin" => Ok(Self::Pin(parse_id()?)),
+            "event" => Ok(Self::Event(parse_id()?)),
+            "announcement" => Ok(Self::ScheduledAnnouncement(parse_id()?)),
+            "user" => Ok(Self::User(UserId(parse_id()?))),
+            "role" => Ok(Self::Role(id.to_owned())),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<FullItemId> for AuditTarget {
+    fn from(item: FullItemId) -> Self {
+        match item {
+            FullItemId::Directory(id) => Self::Directory(id),
+            FullItemId::Note(id) => Self::Note(id),
+        }
+    }
+}
+
+/// An entry of the audit log.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AuditEntry {
+    pub id: u64,
+    pub time: NaiveDateTime,
+    /// The user who has performed the action, if it has been performed on behalf of a user.
+    pub user_id: Option<UserId>,
+    pub action: AuditAction,
+    pub targets: Vec<AuditTarget>,
+}
+
+/// The conditions on the listed audit log entries. The conditions which are `None` are ignored.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AuditFilter {
+    pub user_id: Option<UserId>,
+    pub action: Option<AuditAction>,
+    /// The first day of the listed period.
+    pub since: Option<NaiveDate>,
+    /// The last day of the listed period.
+    pub until: Option<NaiveDate>,
+}
+
+impl AuditFilter {
+    /// Get the query parameters of the conditions, in the order they are used in
+    /// [`FILTERED_ENTRIES`].
+    fn sql_params(
+        &self,
+    ) -> (
+        Option<u64>,
+        Option<&'static str>,
+        Option<String>,
+        Option<String>,
+    ) {
+        let start_of = |day| time_to_sql(day_start(day));
+        (
+            self.user_id.map(|user_id| user_id.0),
+            self.action.map(AuditAction::name),
+            self.since.map(start_of),
+            self.until.and_then(|day| day.succ_opt()).map(start_of),
+        )
+    }
+}
+
+const ENTRY_COLUMNS: &str = "id, time, user_id, action, targets";
+
+/// The entries matching the parameters returned by [`AuditFilter::sql_params`].
+const FILTERED_ENTRIES: &str = concat!(
+    "SELECT * FROM audit_log\n",
+    "WHERE (?1 IS NULL OR user_id = ?1) AND (?2 IS NULL OR action = ?2)\n",
+    "    AND (?3 IS NULL OR time >= ?3) AND (?4 IS NULL OR time < ?4)\n",
+);
+
+fn conversion_error(index: usize, value: &str) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(
+        index,
+        Type::Text,
+        format!("Invalid audit log value `{}`", value).into(),
+    )
+}
+
+fn entry_from_row(row: &Row<'_>) -> rusqlite::Result<AuditEntry> {
+    let action: String = row.get(3)?;
+    let targets: String = row.get(4)?;
+    Ok(AuditEntry {
+        id: row.get(0)?,
+        time: time_from_sql(row, 1)?,
+        user_id: row.get::<_, Option<u64>>(2)?.map(UserId),
+        action: action.parse().map_err(|()| conversion_error(3, &action))?,
+        targets: serde_json::from_str::<Vec<String>>(&targets)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(4, Type::Text, Box::new(e)))?
+            .iter()
+            .map(|target| target.parse().map_err(|()| conversion_error(4, target)))
+            .collect::<rusqlite::Result<_>>()?,
+    })
+}
+
+/// Quote a field of a CSV record if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Persistent storage of the audit log.
+///
+/// The entries are never changed or deleted, which is enforced by the triggers on the table.
+pub struct AuditLog {
+    db: UnsafeRc<Connection>,
+}
+
+impl AuditLog {
+    /// SAFETY: the caller must uphold the invariants of [`UnsafeRc`].
+    pub unsafe fn new(db: UnsafeRc<Connection>) -> Self {
+        Self { db }
+    }
+
+    /// Begin the transaction in which an action is performed and recorded, so that the entry is
+    /// only kept if the action is committed, and the other way round. The storages share the
+    /// connection of the log, so their own transactions are nested in this one.
+    pub fn transaction(&self) -> rusqlite::Result<NestedTransaction<'_>> {
+        NestedTransaction::immediate(&self.db)
+    }
+
+    /// Record an action performed now.
+    pub fn record(
+        &self,
+        user_id: Option<UserId>,
+        action: AuditAction,
+        targets: &[AuditTarget],
+    ) -> rusqlite::Result<()> {
+        let targets: Vec<_> = targets.iter().map(ToString::to_string).collect();
+        let targets = serde_json::to_string(&targets)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.db
+            .prepare("INSERT INTO audit_log(time, user_id, action, targets) VALUES (?, ?, ?, ?)")?
+            .execute(params![
+                time_to_sql(chrono::Local::now().naive_local()),
+                user_id.map(|user_id| user_id.0),
+                action.name(),
+                targets,
+            ])?;
+        Ok(())
+    }
+
+    /// Get a page of the entries matching the filter, the latest first.
+    pub fn list(
+        &self,
+        filter: &AuditFilter,
+        limit: usize,
+        offset: usize,
+    ) -> rusqlite::Result<Vec<AuditEntry>> {
+        let (user_id, action, since, until) = filter.sql_params();
+        let mut stmt = self.db.prepare(&format!(
+            "SELECT {} FROM ({}) ORDER BY id DESC LIMIT ? OFFSET ?",
+            ENTRY_COLUMNS, FILTERED_ENTRIES,
+        ))?;
+        let rows = stmt.query_map(
+            params![user_id, action, since, until, limit, offset],
+            entry_from_row,
+        )?;
+        rows.collect()
+    }
+
+    /// Count the entries matching the filter.
+    pub fn count(&self, filter: &AuditFilter) -> rusqlite::Result<usize> {
+        let (user_id, action, since, until) = filter.sql_params();
+        self.db
+            .prepare(&format!("SELECT count(*) FROM ({})", FILTERED_ENTRIES))?
+            .query_row(params![user_id, action, since, until], |row| row.get(0))
+    }
+
+    /// Write the entries matching the filter as CSV, the earliest first.
+    pub fn export_csv(&self, filter: &AuditFilter, out: &mut impl Write) -> std::io::Result<()> {
+        let (user_id, action, since, until) = filter.sql_params();
+        let to_io_error = |e: rusqlite::Error| std::io::Error::other(e);
+        let mut stmt = self
+            .db
+            .prepare(&format!(
+                "SELECT {} FROM ({}) ORDER BY id",
+                ENTRY_COLUMNS, FILTERED_ENTRIES,
+            ))
+            .map_err(to_io_error)?;
+        let rows = stmt
+            .query_map(params![user_id, action, since, until], entry_from_row)
+            .map_err(to_io_error)?;
+
+        writeln!(out, "id,time,user_id,action,targets")?;
+        for entry in rows {
+            let entry = entry.map_err(to_io_error)?;
+            let targets: Vec<_> = entry.targets.iter().map(ToString::to_string).collect();
+            writeln!(
+                out,
+                "{},{},{},{},{}",
+                entry.id,
+                time_to_sql(entry.time),
+                entry
+                    .user_id
+                    .map(|user_id| user_id.0.to_string())
+                    .unwrap_or_default(),
+                entry.action,
+                csv_field(&targets.join(" ")),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,31 @@
+use crate::message::FormattedText;
+use crate::strings::STRINGS;
+use crate::user_facing_error::UserFacingError;
+use std::error::Error;
+use std::fmt::Display;
+
+/// The error when the audit log cannot be filtered as requested.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AuditError {
+    /// The period is not in the expected format or ends before it starts.
+    InvalidPeriod,
+}
+
+impl Display for AuditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPeriod => write!(f, "Invalid period"),
+        }
+    }
+}
+
+impl Error for AuditError {}
+
+impl UserFacingError for AuditError {
+    fn user_message(&self) -> FormattedText {
+        let s = &STRINGS.errors.audit;
+        match self {
+            Self::InvalidPeriod => s.invalid_period(),
+        }
+    }
+}
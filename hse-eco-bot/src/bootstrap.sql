@@ -1,10 +1,10 @@
 PRAGMA foreign_keys = ON;
 
-BEGIN TRANSACTION;
-
 CREATE TABLE kb_notes (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
-    content TEXT NOT NULL
+    content TEXT NOT NULL,
+    entities TEXT,
+    attachments TEXT
 );
 
 CREATE TABLE kb_dirs (
@@ -33,20 +33,231 @@ CREATE TABLE kb_newsletters (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     name TEXT NOT NULL,
     content TEXT NOT NULL,
-    timestamp TEXT NOT NULL
+    entities TEXT,
+    timestamp TEXT NOT NULL,
+    attachments TEXT
+);
+
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY,
+    chat_id INTEGER NOT NULL,
+    username TEXT,
+    dialog_state TEXT
+);
+
+CREATE TABLE user_subscriptions (
+    user_id INTEGER NOT NULL
+        REFERENCES users(id) ON DELETE CASCADE,
+    newsletter TEXT NOT NULL,
+    PRIMARY KEY (user_id, newsletter)
+);
+
+CREATE TABLE roles (
+    name TEXT PRIMARY KEY
+);
+
+CREATE TABLE role_permissions (
+    role TEXT NOT NULL
+        REFERENCES roles(name) ON DELETE CASCADE ON UPDATE CASCADE,
+    permission TEXT NOT NULL,
+    PRIMARY KEY (role, permission)
+);
+
+-- Roles can be granted to users who have not started the bot yet, so `user_id`
+-- does not reference `users`.
+CREATE TABLE user_roles (
+    user_id INTEGER NOT NULL,
+    role TEXT NOT NULL
+        REFERENCES roles(name) ON DELETE CASCADE ON UPDATE CASCADE,
+    PRIMARY KEY (user_id, role)
+);
+
+-- The roles granted to usernames by the versions of the bot which have not known
+-- the user IDs. They are moved to `user_roles` when a user with the username is
+-- stored.
+CREATE TABLE pending_roles (
+    username TEXT NOT NULL COLLATE NOCASE,
+    role TEXT NOT NULL
+        REFERENCES roles(name) ON DELETE CASCADE ON UPDATE CASCADE,
+    PRIMARY KEY (username, role)
+);
+
+-- The IDs of the knowledge base providers, stored by the keys they are mounted
+-- under, so that they do not depend on the order of mounting. The root provider
+-- is 0 and the newsletter archive is 1.
+CREATE TABLE kb_providers (
+    id INTEGER PRIMARY KEY,
+    key TEXT NOT NULL UNIQUE
+);
+
+-- The IDs assigned to the files and directories of the directories on disk, by
+-- the keys the directories are mounted under. `manifest` is a JSON object with
+-- the IDs by the paths relative to the directory.
+CREATE TABLE kb_fs_manifests (
+    provider TEXT PRIMARY KEY,
+    manifest TEXT NOT NULL
+);
+
+-- Knowledge base items pinned to the main menu. `kind` is either 'note' or
+-- 'directory', `provider` and `item` are the IDs of the provider and of the
+-- item in it.
+CREATE TABLE kb_pins (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK (kind IN ('note', 'directory')),
+    provider INTEGER NOT NULL,
+    item INTEGER NOT NULL,
+    label TEXT,
+    position INTEGER NOT NULL,
+    UNIQUE (kind, provider, item)
+);
+
+-- The states of the knowledge base items before their changes. `kind` is either
+-- 'note' or 'directory', `item` is the ID of the note or the directory in
+-- `DbProvider`, `parent_id`, `name` and (for notes) the content columns are the
+-- state before the change. The items deleted along with a directory refer to
+-- the revision of its deletion as their `batch`. `time` is local and formatted
+-- as `YYYY-MM-DD HH:MM:SS`.
+CREATE TABLE kb_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK (kind IN ('note', 'directory')),
+    item INTEGER NOT NULL,
+    action TEXT NOT NULL
+        CHECK (action IN ('update', 'rename', 'move', 'delete', 'restore')),
+    batch INTEGER
+        REFERENCES kb_revisions(id) ON DELETE CASCADE,
+    user_id INTEGER,
+    time TEXT NOT NULL,
+    parent_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    content TEXT,
+    entities TEXT,
+    attachments TEXT
+);
+
+-- Slugs of the links to the knowledge base items shared as `/start` payloads.
+-- The columns `kind`, `provider` and `item` are the same as in `kb_pins`.
+-- `provider` is the ID registered for the provider in `kb_providers`, so it does
+-- not change when the directories on disk are mounted in another order.
+CREATE TABLE kb_links (
+    slug TEXT PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('note', 'directory')),
+    provider INTEGER NOT NULL,
+    item INTEGER NOT NULL,
+    UNIQUE (kind, provider, item)
+);
+
+-- Eco events. The times are local and formatted as `YYYY-MM-DD HH:MM:SS`, so
+-- that they are ordered lexicographically. `location` is JSON.
+CREATE TABLE events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    description_entities TEXT,
+    start_time TEXT NOT NULL,
+    end_time TEXT NOT NULL,
+    location TEXT NOT NULL,
+    reminder_sent BOOL NOT NULL DEFAULT FALSE
+);
+
+-- Users who have asked to be reminded about an event.
+CREATE TABLE event_reminders (
+    event_id INTEGER NOT NULL
+        REFERENCES events(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL
+        REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (event_id, user_id)
 );
 
-CREATE TABLE permissions (
-    user TEXT UNIQUE NOT NULL,
-    edit_kb BOOL NOT NULL,
-    receive_feedback BOOL NOT NULL
+-- Announcements scheduled to be sent later. `send_at` is a local time in the same
+-- format as the times of the events, `attachments` is JSON.
+CREATE TABLE scheduled_announcements (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    send_at TEXT NOT NULL,
+    text TEXT NOT NULL,
+    text_entities TEXT,
+    attachments TEXT NOT NULL,
+    audience TEXT NOT NULL
+);
+
+-- The administrative and editorial actions. `user_id` is the user who has
+-- performed the action, `targets` is a JSON array of the affected objects, e.g.
+-- `["note:0:42"]`. `time` is local and formatted as `YYYY-MM-DD HH:MM:SS`. The
+-- entries are never changed or deleted.
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    time TEXT NOT NULL,
+    user_id INTEGER,
+    action TEXT NOT NULL,
+    targets TEXT NOT NULL
 );
 
 CREATE UNIQUE INDEX kb_notes_by_id ON kb_notes(id);
 CREATE UNIQUE INDEX kb_dirs_by_id ON kb_dirs(id);
 CREATE UNIQUE INDEX kb_note_children_by_child_id ON kb_note_children(child_id);
 CREATE UNIQUE INDEX kb_dir_children_by_child_id ON kb_dir_children(child_id);
+CREATE INDEX users_by_username ON users(username COLLATE NOCASE);
+CREATE INDEX events_by_start_time ON events(start_time);
+CREATE INDEX scheduled_announcements_by_send_at ON scheduled_announcements(send_at);
+CREATE INDEX kb_revisions_by_item ON kb_revisions(kind, item);
+CREATE INDEX kb_revisions_by_parent_id ON kb_revisions(parent_id);
+CREATE INDEX kb_revisions_by_batch ON kb_revisions(batch);
+CREATE INDEX audit_log_by_user_id ON audit_log(user_id);
+CREATE INDEX audit_log_by_action ON audit_log(action);
+CREATE INDEX audit_log_by_time ON audit_log(time);
+
+-- Full-text search indexes of the knowledge base notes and the archived
+-- newsletters. The rowids are the IDs of the notes and the newsletters.
+CREATE VIRTUAL TABLE kb_notes_fts USING fts5(name, content);
+
+CREATE VIRTUAL TABLE kb_newsletters_fts USING fts5(content);
+
+CREATE TRIGGER kb_notes_fts_insert AFTER INSERT ON kb_note_children BEGIN
+    INSERT INTO kb_notes_fts(rowid, name, content)
+        SELECT new.child_id, new.child_name, content FROM kb_notes WHERE id = new.child_id;
+END;
+
+CREATE TRIGGER kb_notes_fts_rename AFTER UPDATE OF child_name ON kb_note_children BEGIN
+    UPDATE kb_notes_fts SET name = new.child_name WHERE rowid = new.child_id;
+END;
+
+CREATE TRIGGER kb_notes_fts_update AFTER UPDATE OF content ON kb_notes BEGIN
+    UPDATE kb_notes_fts SET content = new.content WHERE rowid = new.id;
+END;
+
+CREATE TRIGGER kb_notes_fts_delete AFTER DELETE ON kb_note_children BEGIN
+    DELETE FROM kb_notes_fts WHERE rowid = old.child_id;
+END;
+
+CREATE TRIGGER kb_newsletters_fts_insert AFTER INSERT ON kb_newsletters BEGIN
+    INSERT INTO kb_newsletters_fts(rowid, content) VALUES (new.id, new.content);
+END;
+
+CREATE TRIGGER kb_newsletters_fts_delete AFTER DELETE ON kb_newsletters BEGIN
+    DELETE FROM kb_newsletters_fts WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log BEGIN
+    SELECT RAISE(ABORT, 'the audit log is append-only');
+END;
+
+CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log BEGIN
+    SELECT RAISE(ABORT, 'the audit log is append-only');
+END;
 
 INSERT INTO kb_dirs(id) VALUES (0);
+INSERT INTO kb_providers(id, key) VALUES (0, 'db'), (1, 'newsletter-archive');
 
-COMMIT;
+INSERT INTO roles(name) VALUES
+    ('admin'), ('editor'), ('feedback'), ('service'), ('events'), ('announcer');
+INSERT INTO role_permissions(role, permission) VALUES
+    ('admin', 'edit_kb'),
+    ('admin', 'receive_service_notifications'),
+    ('admin', 'receive_feedback'),
+    ('admin', 'admin'),
+    ('admin', 'manage_events'),
+    ('admin', 'send_global_notifications'),
+    ('editor', 'edit_kb'),
+    ('feedback', 'receive_feedback'),
+    ('service', 'receive_service_notifications'),
+    ('events', 'manage_events'),
+    ('announcer', 'send_global_notifications');
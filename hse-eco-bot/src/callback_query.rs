@@ -1,10 +1,14 @@
-use crate::db::{FullDirectoryId, FullNoteId};
+use crate::audit::AuditAction;
+use crate::db::{FullDirectoryId, FullNoteId, FullRevisionId};
 use crate::feedback::FeedbackTopic;
+use crate::newsletter::announcements::Audience;
+use chrono::NaiveDate;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::convert::TryFrom;
 use std::error::Error;
 use std::fmt::Display;
+use teloxide::types::UserId;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Query {
@@ -61,6 +65,10 @@ pub enum Query {
     KbUnpinNote {
         id: FullNoteId,
     },
+    /// Get a link to a note in the knowledge base.
+    KbShareNote {
+        id: FullNoteId,
+    },
     /// Confirm note deletion.
     KbConfirmNoteDeletion {
         id: FullNoteId,
@@ -69,6 +77,12 @@ pub enum Query {
     KbCancelNoteDeletion {
         id: FullNoteId,
     },
+    /// Save the note being created or edited with the attachments sent so far.
+    KbSaveNote,
+    /// Remove all the attachments of a note.
+    KbClearNoteAttachments {
+        id: FullNoteId,
+    },
     /// Edit a directory.
     KbEditDir {
         id: FullDirectoryId,
@@ -118,9 +132,37 @@ pub enum Query {
     KbCancelDirectoryDeletion {
         id: FullDirectoryId,
     },
+    /// Search for notes starting from a directory.
+    KbSearch {
+        from: FullDirectoryId,
+    },
+    /// Open a page of the search results.
+    KbSearchPage {
+        page: usize,
+    },
+    /// Open the history of changes of a note.
+    KbNoteHistory {
+        id: FullNoteId,
+    },
+    /// Open the history of changes of a directory and the items deleted from it.
+    KbDirectoryHistory {
+        id: FullDirectoryId,
+    },
+    /// Return an item to its state in a revision.
+    KbRestoreRevision {
+        id: FullRevisionId,
+    },
+    /// Exit from anywhere and open the deleted knowledge base items.
+    OpenTrash,
+    /// Open a page of the deleted knowledge base items.
+    TrashPage {
+        page: usize,
+    },
     FormOption {
         index: usize,
     },
+    /// Finish sending the images of a gallery in a form.
+    FormDone,
     Subscribe {
         newsletter: String,
     },
@@ -128,6 +170,122 @@ pub enum Query {
         newsletter: String,
     },
     ManageSubscriptions,
+    /// Exit from anywhere and open the management of the items pinned to the main menu.
+    OpenPinManagement,
+    /// Move a pinned item closer to the top of the main menu.
+    KbMovePinUp {
+        pin: u64,
+    },
+    /// Move a pinned item closer to the bottom of the main menu.
+    KbMovePinDown {
+        pin: u64,
+    },
+    /// Set a custom label of a pinned item.
+    KbRenamePin {
+        pin: u64,
+    },
+    /// Use the name of a pinned item as its label.
+    KbResetPinLabel {
+        pin: u64,
+    },
+    /// Exit from anywhere and open the admin panel.
+    OpenAdminPanel,
+    /// Ask for the user whose roles should be managed.
+    AdminFindUser,
+    /// Open the roles of a specific user.
+    AdminOpenUser {
+        user_id: UserId,
+    },
+    /// Grant a role to a user.
+    AdminGrantRole {
+        user_id: UserId,
+        role: String,
+    },
+    /// Revoke a role from a user.
+    AdminRevokeRole {
+        user_id: UserId,
+        role: String,
+    },
+    /// Read the knowledge base sections served from files again.
+    AdminReloadKb,
+    /// Open the audit log without filters.
+    OpenAuditLog,
+    /// Open the audit log of the actions performed by a specific user.
+    AuditLogUser {
+        user_id: UserId,
+    },
+    /// Open another page of the audit log.
+    AuditLogPage {
+        page: usize,
+    },
+    /// Ask for the action the audit log should be filtered by.
+    AuditLogActions,
+    /// Show only the given action in the audit log, or all the actions if it is `None`.
+    AuditLogFilterAction {
+        action: Option<AuditAction>,
+    },
+    /// Remove all the filters of the audit log.
+    AuditLogResetFilter,
+    /// Open the calendar page of the week starting on the given day.
+    CalendarWeek {
+        first_day: NaiveDate,
+    },
+    /// Open the calendar page of the month starting on the given day.
+    CalendarMonth {
+        first_day: NaiveDate,
+    },
+    /// Open an event from the calendar.
+    OpenEvent {
+        id: u64,
+    },
+    /// Create an event.
+    CreateEvent,
+    /// Edit an event.
+    EditEvent {
+        id: u64,
+    },
+    /// Delete an event.
+    DeleteEvent {
+        id: u64,
+    },
+    /// Confirm deletion of an event.
+    ConfirmEventDeletion {
+        id: u64,
+    },
+    /// Ask to be reminded about an event.
+    RemindAboutEvent {
+        id: u64,
+    },
+    /// Cancel the reminder about an event.
+    CancelEventReminder {
+        id: u64,
+    },
+    /// Exit from anywhere and start composing an announcement.
+    OpenAnnouncement,
+    /// Preview the announcement being composed.
+    PreviewAnnouncement,
+    /// Choose the users the announcement is sent to.
+    AnnouncementAudience {
+        audience: Audience,
+    },
+    /// Send the previewed announcement.
+    SendAnnouncement,
+    /// Choose the time to send the previewed announcement at.
+    ScheduleAnnouncement,
+    /// Exit from anywhere and open the list of the scheduled announcements.
+    OpenScheduledAnnouncements,
+    /// Open a scheduled announcement.
+    OpenScheduledAnnouncement {
+        id: u64,
+    },
+    /// Edit a scheduled announcement.
+    EditScheduledAnnouncement {
+        id: u64,
+    },
+    /// Cancel sending a scheduled announcement.
+    CancelScheduledAnnouncement {
+        id: u64,
+    },
 }
 
 impl Display for Query {
@@ -158,12 +316,17 @@ impl Display for Query {
             Self::KbDeleteNote { id } => write!(f, "{}@{}", cmd::KB_DELETE_NOTE, id),
             Self::KbPinNote { id } => write!(f, "{}@{}", cmd::KB_PIN_NOTE, id),
             Self::KbUnpinNote { id } => write!(f, "{}@{}", cmd::KB_UNPIN_NOTE, id),
+            Self::KbShareNote { id } => write!(f, "{}@{}", cmd::KB_SHARE_NOTE, id),
             Self::KbConfirmNoteDeletion { id } => {
                 write!(f, "{}@{}", cmd::KB_CONFIRM_NOTE_DELETION, id)
             }
             Self::KbCancelNoteDeletion { id } => {
                 write!(f, "{}@{}", cmd::KB_CANCEL_NOTE_DELETION, id)
             }
+            Self::KbSaveNote => write!(f, "{}", cmd::KB_SAVE_NOTE),
+            Self::KbClearNoteAttachments { id } => {
+                write!(f, "{}@{}", cmd::KB_CLEAR_NOTE_ATTACHMENTS, id)
+            }
             Self::KbEditDir { id } => {
                 write!(f, "{}@{}", cmd::KB_EDIT_DIR, id)
             }
@@ -209,10 +372,82 @@ impl Display for Query {
             Self::KbCancelDirectoryDeletion { id } => {
                 write!(f, "{}@{}", cmd::KB_CANCEL_DIR_DELETION, id)
             }
+            Self::KbSearch { from } => write!(f, "{}@{}", cmd::KB_SEARCH, from),
+            Self::KbSearchPage { page } => write!(f, "{}@{}", cmd::KB_SEARCH_PAGE, page),
+            Self::KbNoteHistory { id } => write!(f, "{}@{}", cmd::KB_NOTE_HISTORY, id),
+            Self::KbDirectoryHistory { id } => write!(f, "{}@{}", cmd::KB_DIR_HISTORY, id),
+            Self::KbRestoreRevision { id } => write!(f, "{}@{}", cmd::KB_RESTORE_REVISION, id),
+            Self::OpenTrash => write!(f, "{}", cmd::OPEN_TRASH),
+            Self::TrashPage { page } => write!(f, "{}@{}", cmd::TRASH_PAGE, page),
             Self::FormOption { index } => write!(f, "{}@{}", cmd::FORM_OPTION, index),
+            Self::FormDone => write!(f, "{}", cmd::FORM_DONE),
             Self::Subscribe { newsletter } => write!(f, "{}@{}", cmd::SUBSCRIBE, &newsletter),
             Self::Unsubscribe { newsletter } => write!(f, "{}@{}", cmd::UNSUBSCRIBE, &newsletter),
             Self::ManageSubscriptions => write!(f, "{}", cmd::MANAGE_SUBSCRIPTIONS),
+            Self::OpenPinManagement => write!(f, "{}", cmd::OPEN_PIN_MANAGEMENT),
+            Self::KbMovePinUp { pin } => write!(f, "{}@{}", cmd::KB_MOVE_PIN_UP, pin),
+            Self::KbMovePinDown { pin } => write!(f, "{}@{}", cmd::KB_MOVE_PIN_DOWN, pin),
+            Self::KbRenamePin { pin } => write!(f, "{}@{}", cmd::KB_RENAME_PIN, pin),
+            Self::KbResetPinLabel { pin } => write!(f, "{}@{}", cmd::KB_RESET_PIN_LABEL, pin),
+            Self::OpenAdminPanel => write!(f, "{}", cmd::OPEN_ADMIN_PANEL),
+            Self::AdminFindUser => write!(f, "{}", cmd::ADMIN_FIND_USER),
+            Self::AdminOpenUser { user_id } => {
+                write!(f, "{}@{}", cmd::ADMIN_OPEN_USER, user_id.0)
+            }
+            Self::AdminGrantRole { user_id, role } => {
+                write!(f, "{}@{},{}", cmd::ADMIN_GRANT_ROLE, user_id.0, role)
+            }
+            Self::AdminRevokeRole { user_id, role } => {
+                write!(f, "{}@{},{}", cmd::ADMIN_REVOKE_ROLE, user_id.0, role)
+            }
+            Self::AdminReloadKb => write!(f, "{}", cmd::ADMIN_RELOAD_KB),
+            Self::OpenAuditLog => write!(f, "{}", cmd::OPEN_AUDIT_LOG),
+            Self::AuditLogUser { user_id } => write!(f, "{}@{}", cmd::AUDIT_LOG_USER, user_id.0),
+            Self::AuditLogPage { page } => write!(f, "{}@{}", cmd::AUDIT_LOG_PAGE, page),
+            Self::AuditLogActions => write!(f, "{}", cmd::AUDIT_LOG_ACTIONS),
+            Self::AuditLogFilterAction { action: None } => {
+                write!(f, "{}", cmd::AUDIT_LOG_FILTER_ACTION)
+            }
+            Self::AuditLogFilterAction {
+                action: Some(action),
+            } => write!(f, "{}@{}", cmd::AUDIT_LOG_FILTER_ACTION, action),
+            Self::AuditLogResetFilter => write!(f, "{}", cmd::AUDIT_LOG_RESET_FILTER),
+            Self::CalendarWeek { first_day } => {
+                write!(f, "{}@{}", cmd::CALENDAR_WEEK, first_day)
+            }
+            Self::CalendarMonth { first_day } => {
+                write!(f, "{}@{}", cmd::CALENDAR_MONTH, first_day)
+            }
+            Self::OpenEvent { id } => write!(f, "{}@{}", cmd::OPEN_EVENT, id),
+            Self::CreateEvent => write!(f, "{}", cmd::CREATE_EVENT),
+            Self::EditEvent { id } => write!(f, "{}@{}", cmd::EDIT_EVENT, id),
+            Self::DeleteEvent { id } => write!(f, "{}@{}", cmd::DELETE_EVENT, id),
+            Self::ConfirmEventDeletion { id } => {
+                write!(f, "{}@{}", cmd::CONFIRM_EVENT_DELETION, id)
+            }
+            Self::RemindAboutEvent { id } => write!(f, "{}@{}", cmd::REMIND_ABOUT_EVENT, id),
+            Self::CancelEventReminder { id } => {
+                write!(f, "{}@{}", cmd::CANCEL_EVENT_REMINDER, id)
+            }
+            Self::OpenAnnouncement => write!(f, "{}", cmd::OPEN_ANNOUNCEMENT),
+            Self::PreviewAnnouncement => write!(f, "{}", cmd::PREVIEW_ANNOUNCEMENT),
+            Self::AnnouncementAudience { audience } => {
+                write!(f, "{}@{}", cmd::ANNOUNCEMENT_AUDIENCE, audience)
+            }
+            Self::SendAnnouncement => write!(f, "{}", cmd::SEND_ANNOUNCEMENT),
+            Self::ScheduleAnnouncement => write!(f, "{}", cmd::SCHEDULE_ANNOUNCEMENT),
+            Self::OpenScheduledAnnouncements => {
+                write!(f, "{}", cmd::OPEN_SCHEDULED_ANNOUNCEMENTS)
+            }
+            Self::OpenScheduledAnnouncement { id } => {
+                write!(f, "{}@{}", cmd::OPEN_SCHEDULED_ANNOUNCEMENT, id)
+            }
+            Self::EditScheduledAnnouncement { id } => {
+                write!(f, "{}@{}", cmd::EDIT_SCHEDULED_ANNOUNCEMENT, id)
+            }
+            Self::CancelScheduledAnnouncement { id } => {
+                write!(f, "{}@{}", cmd::CANCEL_SCHEDULED_ANNOUNCEMENT, id)
+            }
         }
     }
 }
@@ -242,6 +477,9 @@ impl TryFrom<RawQuery<'_>> for Query {
                 right.parse::<u64>().ok()?.into(),
             ))
         }
+        fn parse_user_id(s: Option<&str>) -> Option<UserId> {
+            Some(UserId(s?.parse().ok()?))
+        }
         let err_fn = || QueryParseError::InvalidPayload {
             command: value.command.to_owned(),
             payload: value.payload.map(str::to_owned),
@@ -260,6 +498,11 @@ impl TryFrom<RawQuery<'_>> for Query {
             Ok(FullNoteId { provider, note })
         };
 
+        let parse_revision_id = |s| {
+            let (provider, revision) = parse_id_pair(s).ok_or_else(err_fn)?;
+            Ok(FullRevisionId { provider, revision })
+        };
+
         let parse_destination_note_pair = |s| {
             let s: &str = Option::ok_or_else(s, err_fn)?;
             let (left, right) = s.split_once(',').ok_or_else(err_fn)?;
@@ -275,6 +518,19 @@ impl TryFrom<RawQuery<'_>> for Query {
             ))
         };
 
+        // The numeric ID of a pin, an event or a scheduled announcement.
+        let parse_row_id = |s: Option<&str>| s.and_then(|s| s.parse().ok()).ok_or_else(err_fn);
+
+        let parse_date = |s: Option<&str>| {
+            s.and_then(|s| s.parse::<NaiveDate>().ok()).ok_or_else(err_fn)
+        };
+
+        let parse_user_role_pair = |s| {
+            let s: &str = Option::ok_or_else(s, err_fn)?;
+            let (left, right) = s.split_once(',').ok_or_else(err_fn)?;
+            Ok((parse_user_id(Some(left)).ok_or_else(err_fn)?, right.to_owned()))
+        };
+
         let (query, payload_must_be_none) = match value.command {
             cmd::OPEN_MAIN_MENU => (Query::OpenMainMenu, true),
             cmd::OPEN_KB => (Query::OpenKb, true),
@@ -343,6 +599,12 @@ impl TryFrom<RawQuery<'_>> for Query {
                 },
                 false,
             ),
+            cmd::KB_SHARE_NOTE => (
+                Query::KbShareNote {
+                    id: parse_note_id(value.payload)?,
+                },
+                false,
+            ),
             cmd::KB_CONFIRM_NOTE_DELETION => (
                 Query::KbConfirmNoteDeletion {
                     id: parse_note_id(value.payload)?,
@@ -355,6 +617,13 @@ impl TryFrom<RawQuery<'_>> for Query {
                 },
                 false,
             ),
+            cmd::KB_SAVE_NOTE => (Query::KbSaveNote, true),
+            cmd::KB_CLEAR_NOTE_ATTACHMENTS => (
+                Query::KbClearNoteAttachments {
+                    id: parse_note_id(value.payload)?,
+                },
+                false,
+            ),
             cmd::KB_EDIT_DIR => (
                 Query::KbEditDir {
                     id: parse_directory_id(value.payload)?,
@@ -429,6 +698,49 @@ impl TryFrom<RawQuery<'_>> for Query {
                 },
                 false,
             ),
+            cmd::KB_SEARCH => (
+                Query::KbSearch {
+                    from: parse_directory_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::KB_SEARCH_PAGE => (
+                Query::KbSearchPage {
+                    page: value
+                        .payload
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(err_fn)?,
+                },
+                false,
+            ),
+            cmd::KB_NOTE_HISTORY => (
+                Query::KbNoteHistory {
+                    id: parse_note_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::KB_DIR_HISTORY => (
+                Query::KbDirectoryHistory {
+                    id: parse_directory_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::KB_RESTORE_REVISION => (
+                Query::KbRestoreRevision {
+                    id: parse_revision_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::OPEN_TRASH => (Query::OpenTrash, true),
+            cmd::TRASH_PAGE => (
+                Query::TrashPage {
+                    page: value
+                        .payload
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(err_fn)?,
+                },
+                false,
+            ),
             cmd::FORM_OPTION => (
                 Query::FormOption {
                     index: value.payload.and_then(|s| s.parse().ok()).ok_or_else(|| {
@@ -440,6 +752,7 @@ impl TryFrom<RawQuery<'_>> for Query {
                 },
                 false,
             ),
+            cmd::FORM_DONE => (Query::FormDone, true),
             cmd::SUBSCRIBE => (
                 Query::Subscribe {
                     newsletter: value
@@ -465,6 +778,156 @@ impl TryFrom<RawQuery<'_>> for Query {
                 false,
             ),
             cmd::MANAGE_SUBSCRIPTIONS => (Query::ManageSubscriptions, true),
+            cmd::OPEN_PIN_MANAGEMENT => (Query::OpenPinManagement, true),
+            cmd::KB_MOVE_PIN_UP => (
+                Query::KbMovePinUp {
+                    pin: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::KB_MOVE_PIN_DOWN => (
+                Query::KbMovePinDown {
+                    pin: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::KB_RENAME_PIN => (
+                Query::KbRenamePin {
+                    pin: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::KB_RESET_PIN_LABEL => (
+                Query::KbResetPinLabel {
+                    pin: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::OPEN_ADMIN_PANEL => (Query::OpenAdminPanel, true),
+            cmd::ADMIN_FIND_USER => (Query::AdminFindUser, true),
+            cmd::ADMIN_OPEN_USER => (
+                Query::AdminOpenUser {
+                    user_id: parse_user_id(value.payload).ok_or_else(err_fn)?,
+                },
+                false,
+            ),
+            cmd::ADMIN_GRANT_ROLE => {
+                let (user_id, role) = parse_user_role_pair(value.payload)?;
+                (Query::AdminGrantRole { user_id, role }, false)
+            }
+            cmd::ADMIN_REVOKE_ROLE => {
+                let (user_id, role) = parse_user_role_pair(value.payload)?;
+                (Query::AdminRevokeRole { user_id, role }, false)
+            }
+            cmd::ADMIN_RELOAD_KB => (Query::AdminReloadKb, true),
+            cmd::OPEN_AUDIT_LOG => (Query::OpenAuditLog, true),
+            cmd::AUDIT_LOG_USER => (
+                Query::AuditLogUser {
+                    user_id: parse_user_id(value.payload).ok_or_else(err_fn)?,
+                },
+                false,
+            ),
+            cmd::AUDIT_LOG_PAGE => (
+                Query::AuditLogPage {
+                    page: value
+                        .payload
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(err_fn)?,
+                },
+                false,
+            ),
+            cmd::AUDIT_LOG_ACTIONS => (Query::AuditLogActions, true),
+            cmd::AUDIT_LOG_FILTER_ACTION => (
+                Query::AuditLogFilterAction {
+                    action: value
+                        .payload
+                        .map(|s| s.parse().map_err(|()| err_fn()))
+                        .transpose()?,
+                },
+                false,
+            ),
+            cmd::AUDIT_LOG_RESET_FILTER => (Query::AuditLogResetFilter, true),
+            cmd::CALENDAR_WEEK => (
+                Query::CalendarWeek {
+                    first_day: parse_date(value.payload)?,
+                },
+                false,
+            ),
+            cmd::CALENDAR_MONTH => (
+                Query::CalendarMonth {
+                    first_day: parse_date(value.payload)?,
+                },
+                false,
+            ),
+            cmd::OPEN_EVENT => (
+                Query::OpenEvent {
+                    id: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::CREATE_EVENT => (Query::CreateEvent, true),
+            cmd::EDIT_EVENT => (
+                Query::EditEvent {
+                    id: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::DELETE_EVENT => (
+                Query::DeleteEvent {
+                    id: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::CONFIRM_EVENT_DELETION => (
+                Query::ConfirmEventDeletion {
+                    id: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::REMIND_ABOUT_EVENT => (
+                Query::RemindAboutEvent {
+                    id: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::CANCEL_EVENT_REMINDER => (
+                Query::CancelEventReminder {
+                    id: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::OPEN_ANNOUNCEMENT => (Query::OpenAnnouncement, true),
+            cmd::PREVIEW_ANNOUNCEMENT => (Query::PreviewAnnouncement, true),
+            cmd::ANNOUNCEMENT_AUDIENCE => (
+                Query::AnnouncementAudience {
+                    audience: value
+                        .payload
+                        .and_then(|s| s.parse().ok())
+                        .ok_or_else(err_fn)?,
+                },
+                false,
+            ),
+            cmd::SEND_ANNOUNCEMENT => (Query::SendAnnouncement, true),
+            cmd::SCHEDULE_ANNOUNCEMENT => (Query::ScheduleAnnouncement, true),
+            cmd::OPEN_SCHEDULED_ANNOUNCEMENTS => (Query::OpenScheduledAnnouncements, true),
+            cmd::OPEN_SCHEDULED_ANNOUNCEMENT => (
+                Query::OpenScheduledAnnouncement {
+                    id: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::EDIT_SCHEDULED_ANNOUNCEMENT => (
+                Query::EditScheduledAnnouncement {
+                    id: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
+            cmd::CANCEL_SCHEDULED_ANNOUNCEMENT => (
+                Query::CancelScheduledAnnouncement {
+                    id: parse_row_id(value.payload)?,
+                },
+                false,
+            ),
             _ => {
                 return Err(QueryParseError::InvalidCommand {
                     command: value.command.to_owned(),
@@ -472,7 +935,7 @@ impl TryFrom<RawQuery<'_>> for Query {
             }
         };
 
-        if payload_must_be_none && !value.payload.is_none() {
+        if payload_must_be_none && value.payload.is_some() {
             return Err(QueryParseError::InvalidPayload {
                 command: value.command.to_owned(),
                 payload: value.payload.map(str::to_owned),
@@ -485,41 +948,87 @@ impl TryFrom<RawQuery<'_>> for Query {
 
 mod strings {
     pub mod cmd {
-        pub const KB_GO_UP: &'static str = "kb-go-up";
-        pub const KB_NAV_TO_DIR: &'static str = "kb-nav-to-dir";
-        pub const KB_NAV_TO_NOTE: &'static str = "kb-nav-to-note";
-        pub const OPEN_CALENDAR: &'static str = "open-calendar";
-        pub const OPEN_FEEDBACK_TOPIC: &'static str = "open-feedback-topic";
-        pub const OPEN_FEEDBACK: &'static str = "open-feedback";
-        pub const OPEN_KB: &'static str = "open-kb";
-        pub const OPEN_MAIN_MENU: &'static str = "open-main-menu";
-        pub const OPEN_NL_ARCHIVE: &'static str = "open-nl-archive";
-        pub const OPEN_NL_SETTINGS: &'static str = "open-nl-settings";
-        pub const GO_BACK: &'static str = "kb-go-back";
-        pub const KB_EDIT_NOTE: &'static str = "kb-edit-note";
-        pub const KB_RENAME_NOTE: &'static str = "kb-rename-note";
-        pub const KB_MOVE_NOTE: &'static str = "kb-move-note";
-        pub const KB_DELETE_NOTE: &'static str = "kb-delete-note";
-        pub const KB_PIN_NOTE: &'static str = "kb-pin-note";
-        pub const KB_UNPIN_NOTE: &'static str = "kb-unpin-note";
-        pub const KB_CONFIRM_NOTE_DELETION: &'static str = "kb-confirm-note-del";
-        pub const KB_CANCEL_NOTE_DELETION: &'static str = "kb-cancel-note-del";
-        pub const KB_EDIT_DIR: &'static str = "kb-edit-dir";
-        pub const KB_CREATE_NOTE: &'static str = "kb-create-note";
-        pub const KB_MOVE_NOTE_HERE: &'static str = "kb-move-note-here";
-        pub const KB_MOVE_DIRECTORY_HERE: &'static str = "kb-move-dir-here";
-        pub const KB_MOVE_DIRECTORY: &'static str = "kb-move-dir";
-        pub const KB_CREATE_DIR: &'static str = "kb-create-dir";
-        pub const KB_RENAME_DIR: &'static str = "kb-rename-dir";
-        pub const KB_DELETE_DIR: &'static str = "kb-delete-dir";
-        pub const KB_PIN_DIR: &'static str = "kb-pin-dir";
-        pub const KB_UNPIN_DIR: &'static str = "kb-unpin-dir";
-        pub const KB_CONFIRM_DIR_DELETION: &'static str = "kb-confirm-dir-del";
-        pub const KB_CANCEL_DIR_DELETION: &'static str = "kb-cancel-dir-del";
-        pub const FORM_OPTION: &'static str = "form-opt";
-        pub const SUBSCRIBE: &'static str = "subscribe";
-        pub const UNSUBSCRIBE: &'static str = "unsubscribe";
-        pub const MANAGE_SUBSCRIPTIONS: &'static str = "open-sub-settings";
+        pub const KB_GO_UP: &str = "kb-go-up";
+        pub const KB_NAV_TO_DIR: &str = "kb-nav-to-dir";
+        pub const KB_NAV_TO_NOTE: &str = "kb-nav-to-note";
+        pub const OPEN_CALENDAR: &str = "open-calendar";
+        pub const OPEN_FEEDBACK_TOPIC: &str = "open-feedback-topic";
+        pub const OPEN_FEEDBACK: &str = "open-feedback";
+        pub const OPEN_KB: &str = "open-kb";
+        pub const OPEN_MAIN_MENU: &str = "open-main-menu";
+        pub const OPEN_NL_ARCHIVE: &str = "open-nl-archive";
+        pub const OPEN_NL_SETTINGS: &str = "open-nl-settings";
+        pub const GO_BACK: &str = "kb-go-back";
+        pub const KB_EDIT_NOTE: &str = "kb-edit-note";
+        pub const KB_RENAME_NOTE: &str = "kb-rename-note";
+        pub const KB_MOVE_NOTE: &str = "kb-move-note";
+        pub const KB_DELETE_NOTE: &str = "kb-delete-note";
+        pub const KB_PIN_NOTE: &str = "kb-pin-note";
+        pub const KB_UNPIN_NOTE: &str = "kb-unpin-note";
+        pub const KB_SHARE_NOTE: &str = "kb-share-note";
+        pub const KB_CONFIRM_NOTE_DELETION: &str = "kb-confirm-note-del";
+        pub const KB_CANCEL_NOTE_DELETION: &str = "kb-cancel-note-del";
+        pub const KB_SAVE_NOTE: &str = "kb-save-note";
+        pub const KB_CLEAR_NOTE_ATTACHMENTS: &str = "kb-clear-note-media";
+        pub const KB_EDIT_DIR: &str = "kb-edit-dir";
+        pub const KB_CREATE_NOTE: &str = "kb-create-note";
+        pub const KB_MOVE_NOTE_HERE: &str = "kb-move-note-here";
+        pub const KB_MOVE_DIRECTORY_HERE: &str = "kb-move-dir-here";
+        pub const KB_MOVE_DIRECTORY: &str = "kb-move-dir";
+        pub const KB_CREATE_DIR: &str = "kb-create-dir";
+        pub const KB_RENAME_DIR: &str = "kb-rename-dir";
+        pub const KB_DELETE_DIR: &str = "kb-delete-dir";
+        pub const KB_PIN_DIR: &str = "kb-pin-dir";
+        pub const KB_UNPIN_DIR: &str = "kb-unpin-dir";
+        pub const KB_CONFIRM_DIR_DELETION: &str = "kb-confirm-dir-del";
+        pub const KB_CANCEL_DIR_DELETION: &str = "kb-cancel-dir-del";
+        pub const KB_SEARCH: &str = "kb-search";
+        pub const KB_SEARCH_PAGE: &str = "kb-search-page";
+        pub const KB_NOTE_HISTORY: &str = "kb-note-history";
+        pub const KB_DIR_HISTORY: &str = "kb-dir-history";
+        pub const KB_RESTORE_REVISION: &str = "kb-restore";
+        pub const OPEN_TRASH: &str = "open-trash";
+        pub const TRASH_PAGE: &str = "trash-page";
+        pub const FORM_OPTION: &str = "form-opt";
+        pub const FORM_DONE: &str = "form-done";
+        pub const SUBSCRIBE: &str = "subscribe";
+        pub const UNSUBSCRIBE: &str = "unsubscribe";
+        pub const MANAGE_SUBSCRIPTIONS: &str = "open-sub-settings";
+        pub const OPEN_PIN_MANAGEMENT: &str = "open-pins";
+        pub const KB_MOVE_PIN_UP: &str = "kb-pin-up";
+        pub const KB_MOVE_PIN_DOWN: &str = "kb-pin-down";
+        pub const KB_RENAME_PIN: &str = "kb-rename-pin";
+        pub const KB_RESET_PIN_LABEL: &str = "kb-reset-pin-label";
+        pub const OPEN_ADMIN_PANEL: &str = "open-admin";
+        pub const ADMIN_FIND_USER: &str = "admin-find-user";
+        pub const ADMIN_OPEN_USER: &str = "admin-user";
+        pub const ADMIN_GRANT_ROLE: &str = "admin-grant";
+        pub const ADMIN_REVOKE_ROLE: &str = "admin-revoke";
+        pub const ADMIN_RELOAD_KB: &str = "admin-reload";
+        pub const OPEN_AUDIT_LOG: &str = "audit-log";
+        pub const AUDIT_LOG_USER: &str = "audit-user";
+        pub const AUDIT_LOG_PAGE: &str = "audit-page";
+        pub const AUDIT_LOG_ACTIONS: &str = "audit-actions";
+        pub const AUDIT_LOG_FILTER_ACTION: &str = "audit-action";
+        pub const AUDIT_LOG_RESET_FILTER: &str = "audit-reset";
+        pub const CALENDAR_WEEK: &str = "cal-week";
+        pub const CALENDAR_MONTH: &str = "cal-month";
+        pub const OPEN_EVENT: &str = "event";
+        pub const CREATE_EVENT: &str = "event-create";
+        pub const EDIT_EVENT: &str = "event-edit";
+        pub const DELETE_EVENT: &str = "event-del";
+        pub const CONFIRM_EVENT_DELETION: &str = "event-confirm-del";
+        pub const REMIND_ABOUT_EVENT: &str = "event-remind";
+        pub const CANCEL_EVENT_REMINDER: &str = "event-unremind";
+        pub const OPEN_ANNOUNCEMENT: &str = "open-announce";
+        pub const PREVIEW_ANNOUNCEMENT: &str = "announce-preview";
+        pub const ANNOUNCEMENT_AUDIENCE: &str = "announce-to";
+        pub const SEND_ANNOUNCEMENT: &str = "announce-send";
+        pub const SCHEDULE_ANNOUNCEMENT: &str = "announce-schedule";
+        pub const OPEN_SCHEDULED_ANNOUNCEMENTS: &str = "open-scheduled";
+        pub const OPEN_SCHEDULED_ANNOUNCEMENT: &str = "scheduled";
+        pub const EDIT_SCHEDULED_ANNOUNCEMENT: &str = "scheduled-edit";
+        pub const CANCEL_SCHEDULED_ANNOUNCEMENT: &str = "scheduled-cancel";
     }
 }
 
@@ -530,6 +1039,7 @@ struct RawQuery<'a> {
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum QueryParseError {
     InvalidSyntax,
     InvalidCommand {
@@ -559,7 +1069,7 @@ impl Display for QueryParseError {
 
 impl Error for QueryParseError {}
 
-pub fn parse_callback_query<'a>(query: &'a str) -> Result<Query, QueryParseError> {
+pub fn parse_callback_query(query: &str) -> Result<Query, QueryParseError> {
     lazy_static! {
         static ref REGEX: Regex =
             Regex::new(r"^(?P<command>[a-zA-Z0-9_-]+)(?:@(?P<payload>.*))?$").unwrap();
@@ -0,0 +1,338 @@
+//! The command line interface: running the bot and the maintenance commands.
+
+use crate::app::{archive_dir_error, mount_kb_directory};
+use crate::audit::{AuditAction, AuditFilter, AuditLog, AuditTarget};
+use crate::config::Config;
+use crate::kb::transfer::{self, ConflictPolicy, TextFormat};
+use crate::kb::{ItemRef, ProviderUserContext, Tree};
+use crate::migrations;
+use crate::user::storage::UserStorage;
+use crate::user::Permissions;
+use crate::util::{NestedTransaction, UnsafeRc};
+use rusqlite::{Connection, OpenFlags};
+use std::error::Error;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use teloxide::types::UserId;
+
+/// A command given on the command line.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Command {
+    /// Run the bot. This is the default when no command is given.
+    Run,
+    /// Write the audit log entries matching the filter to the standard output as CSV.
+    ExportAuditLog(AuditFilter),
+    /// Create the database and grant the admin role to the users.
+    InitDb { admins: Vec<UserId> },
+    /// Import a directory tree on disk into the knowledge base directory with the path `target`.
+    Import {
+        source: PathBuf,
+        target: Vec<String>,
+        policy: ConflictPolicy,
+    },
+    /// Export the knowledge base stored in the database to a directory on disk.
+    Export {
+        destination: PathBuf,
+        format: TextFormat,
+    },
+}
+
+/// The command line arguments: the global options followed by the command.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Invocation {
+    /// The path to the configuration file. The default configuration is used if it is not given.
+    pub config: Option<PathBuf>,
+    pub command: Command,
+}
+
+/// The error when the command line arguments cannot be parsed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CliError {
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingArgument(String),
+    MissingValue(String),
+    InvalidValue { option: String, value: String },
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownCommand(command) => write!(f, "unknown command `{}`", command),
+            Self::UnknownOption(option) => write!(f, "unknown option `{}`", option),
+            Self::MissingArgument(argument) => write!(f, "missing argument `{}`", argument),
+            Self::MissingValue(option) => write!(f, "option `{}` requires a value", option),
+            Self::InvalidValue { option, value } => {
+                write!(f, "invalid value of option `{}`: `{}`", option, value)
+            }
+        }
+    }
+}
+
+impl Error for CliError {}
+
+/// Take the value of an option which requires one.
+fn option_value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, CliError> {
+    args.next()
+        .ok_or_else(|| CliError::MissingValue(option.to_owned()))
+}
+
+fn invalid_value(option: String, value: String) -> CliError {
+    CliError::InvalidValue { option, value }
+}
+
+/// Parse the command line arguments, excluding the name of the program.
+pub fn parse_invocation(args: impl IntoIterator<Item = String>) -> Result<Invocation, CliError> {
+    let mut args = args.into_iter().peekable();
+    let mut config = None;
+    while let Some(option) = args.next_if(|arg| arg.starts_with('-')) {
+        match option.as_str() {
+            "--config" => config = Some(PathBuf::from(option_value(&mut args, &option)?)),
+            _ => return Err(CliError::UnknownOption(option)),
+        }
+    }
+    Ok(Invocation {
+        config,
+        command: parse_args(args)?,
+    })
+}
+
+/// Parse the command and its arguments.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut args = args.into_iter();
+    let command = match args.next() {
+        Some(command) => command,
+        None => return Ok(Command::Run),
+    };
+    match command.as_str() {
+        "run" => match args.next() {
+            Some(option) => Err(CliError::UnknownOption(option)),
+            None => Ok(Command::Run),
+        },
+        "export-audit-log" => {
+            let mut filter = AuditFilter::default();
+            while let Some(option) = args.next() {
+                let value = args
+                    .next()
+                    .ok_or_else(|| CliError::MissingValue(option.clone()))?;
+                let invalid_value = || CliError::InvalidValue {
+                    option: option.clone(),
+                    value: value.clone(),
+                };
+                match option.as_str() {
+                    "--user" => {
+                        filter.user_id = Some(UserId(value.parse().map_err(|_| invalid_value())?));
+                    }
+                    "--action" => {
+                        filter.action = Some(value.parse().map_err(|()| invalid_value())?);
+                    }
+                    "--since" => filter.since = Some(value.parse().map_err(|_| invalid_value())?),
+                    "--until" => filter.until = Some(value.parse().map_err(|_| invalid_value())?),
+                    _ => return Err(CliError::UnknownOption(option)),
+                }
+            }
+            Ok(Command::ExportAuditLog(filter))
+        }
+        "init-db" => {
+            let mut admins = Vec::new();
+            while let Some(option) = args.next() {
+                match option.as_str() {
+                    "--admin" => {
+                        let value = option_value(&mut args, &option)?;
+                        match value.parse() {
+                            Ok(user_id) => admins.push(UserId(user_id)),
+                            Err(_) => return Err(invalid_value(option, value)),
+                        }
+                    }
+                    _ => return Err(CliError::UnknownOption(option)),
+                }
+            }
+            Ok(Command::InitDb { admins })
+        }
+        "import" => {
+            let mut source = None;
+            let mut target = Vec::new();
+            let mut policy = ConflictPolicy::Fail;
+            while let Some(option) = args.next() {
+                match option.as_str() {
+                    "--target" => {
+                        target = option_value(&mut args, &option)?
+                            .split('/')
+                            .filter(|name| !name.is_empty())
+                            .map(String::from)
+                            .collect();
+                    }
+                    "--merge" => policy = ConflictPolicy::Merge,
+                    _ if source.is_none() && !option.starts_with('-') => {
+                        source = Some(PathBuf::from(option));
+                    }
+                    _ => return Err(CliError::UnknownOption(option)),
+                }
+            }
+            Ok(Command::Import {
+                source: source.ok_or_else(|| CliError::MissingArgument(String::from("<dir>")))?,
+                target,
+                policy,
+            })
+        }
+        "export" => {
+            let mut destination = None;
+            let mut format = TextFormat::Markdown;
+            while let Some(option) = args.next() {
+                match option.as_str() {
+                    "--format" => {
+                        let value = option_value(&mut args, &option)?;
+                        match value.parse() {
+                            Ok(parsed) => format = parsed,
+                            Err(()) => return Err(invalid_value(option, value)),
+                        }
+                    }
+                    _ if destination.is_none() && !option.starts_with('-') => {
+                        destination = Some(PathBuf::from(option));
+                    }
+                    _ => return Err(CliError::UnknownOption(option)),
+                }
+            }
+            Ok(Command::Export {
+                destination: destination
+                    .ok_or_else(|| CliError::MissingArgument(String::from("<dir>")))?,
+                format,
+            })
+        }
+        _ => Err(CliError::UnknownCommand(command)),
+    }
+}
+
+/// Open the existing database of the bot and bring its schema to the latest version.
+fn open_database(config: &Config) -> Result<Connection, Box<dyn Error>> {
+    let db = Connection::open_with_flags(&config.database.path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    db.execute("PRAGMA foreign_keys=ON", rusqlite::params![])?;
+    migrations::migrate(&db)?;
+    Ok(db)
+}
+
+/// Open the knowledge base with the directories on disk mounted like when the bot runs, so that
+/// their mount points are not taken for the directories of the database.
+fn open_tree(config: &Config) -> Result<(Tree, UnsafeRc<Connection>), Box<dyn Error>> {
+    // SAFETY: the connection is only used by this thread.
+    let db = unsafe { UnsafeRc::new(open_database(config)?) };
+    let mut tree = unsafe { Tree::new(UnsafeRc::clone(&db), &[], &config.kb.archive_dir) }
+        .map_err(archive_dir_error)?
+        .0;
+    for path in &config.kb.directories {
+        mount_kb_directory(&mut tree, path)?;
+    }
+    Ok((tree, db))
+}
+
+/// The context of the maintenance commands, which are allowed everything.
+fn maintenance_uctx() -> ProviderUserContext {
+    ProviderUserContext {
+        permissions: Permissions::all(),
+        user_id: None,
+    }
+}
+
+/// Write the audit log entries matching the filter to the standard output as CSV.
+pub fn export_audit_log(config: &Config, filter: &AuditFilter) -> Result<(), Box<dyn Error>> {
+    let db = open_database(config)?;
+    // SAFETY: the connection is only used by this thread.
+    let audit = unsafe { AuditLog::new(UnsafeRc::new(db)) };
+    audit.export_csv(filter, &mut std::io::stdout().lock())?;
+    Ok(())
+}
+
+/// Create the database with the current schema and grant the admin role to the users given on the
+/// command line and in the configuration. The grants are recorded in the audit log without an actor.
+///
+/// An existing database is never overwritten.
+pub fn init_db(config: &Config, admins: &[UserId]) -> Result<(), Box<dyn Error>> {
+    let path = &config.database.path;
+    if path.exists() {
+        return Err(format!(
+            "`{}` already exists, remove it manually for a clean installation",
+            path.display()
+        )
+        .into());
+    }
+    let db = Connection::open(path)?;
+    migrations::migrate(&db)?;
+    // SAFETY: the connection is only used by this thread.
+    let db = unsafe { UnsafeRc::new(db) };
+    let users = unsafe { UserStorage::new(UnsafeRc::clone(&db)) };
+    let audit = unsafe { AuditLog::new(db) };
+    let mut granted = Vec::new();
+    for user_id in admins.iter().copied().chain(config.admin_users()) {
+        if granted.contains(&user_id) {
+            continue;
+        }
+        let txn = audit.transaction()?;
+        users.grant_role(user_id, "admin")?;
+        let targets = [AuditTarget::User(user_id), AuditTarget::Role(String::from("admin"))];
+        audit.record(None, AuditAction::GrantRole, &targets)?;
+        txn.commit()?;
+        println!("Granted the admin role to user {}", user_id.0);
+        granted.push(user_id);
+    }
+    println!("Created `{}`", path.display());
+    Ok(())
+}
+
+/// Import a directory tree on disk into the knowledge base directory with the provided path.
+///
+/// The import is done in one transaction, so nothing is imported if it fails halfway.
+pub fn import(
+    config: &Config,
+    source: &Path,
+    target: &[String],
+    policy: ConflictPolicy,
+) -> Result<(), Box<dyn Error>> {
+    let (tree, db) = open_tree(config)?;
+    let uctx = maintenance_uctx();
+    let mut directory = tree.root_directory_ref()?;
+    for name in target {
+        directory = directory
+            .read(uctx)?
+            .children
+            .into_iter()
+            .find_map(|(child_name, item)| match item {
+                ItemRef::Directory(child) if child_name == *name => Some(child),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                format!(
+                    "there is no directory `{}` in the knowledge base",
+                    target.join("/")
+                )
+            })?;
+    }
+    let txn = NestedTransaction::deferred(&db)?;
+    let stats = transfer::import_directory(&directory, uctx, source, policy)?;
+    txn.commit()?;
+    println!(
+        "Imported {} directories and {} notes",
+        stats.directories, stats.notes
+    );
+    Ok(())
+}
+
+/// Export the knowledge base stored in the database to a directory on disk.
+pub fn export(
+    config: &Config,
+    destination: &Path,
+    format: TextFormat,
+) -> Result<(), Box<dyn Error>> {
+    let (tree, _) = open_tree(config)?;
+    let stats = transfer::export_tree(&tree, maintenance_uctx(), destination, format)?;
+    println!(
+        "Exported {} directories and {} notes",
+        stats.directories, stats.notes
+    );
+    if stats.notes_with_attachments > 0 {
+        println!(
+            "The attachments of {} notes have not been exported",
+            stats.notes_with_attachments
+        );
+    }
+    Ok(())
+}
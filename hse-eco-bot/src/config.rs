@@ -0,0 +1,264 @@
+//! The configuration of the bot, read from a TOML file given on the command line.
+//!
+//! Every section and value is optional: the defaults are used for the missing ones.
+
+use crate::newsletter::{announcements, event_reminders, feedback};
+use crate::trash;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::path::PathBuf;
+use teloxide::adaptors::throttle::Limits;
+use teloxide::types::UserId;
+
+/// The default environment variable containing the bot API token.
+pub const DEFAULT_TOKEN_VARIABLE: &str = "HSE_ECO_BOT_TOKEN";
+
+/// The default path to the database of the bot.
+pub const DEFAULT_DATABASE_PATH: &str = "hse-eco-bot.sqlite";
+
+/// The default name of the root directory the newsletter archive is mounted on.
+pub const DEFAULT_ARCHIVE_DIR: &str = "Архив рассылок";
+
+/// The names of the newsletters which can be defined in the configuration.
+const NEWSLETTER_NAMES: [&str; 3] = [feedback::NAME, event_reminders::NAME, announcements::NAME];
+
+/// Where the bot API token is read from. Exactly one of the keys of the `[token]` section must be
+/// set.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum TokenSource {
+    /// The name of an environment variable.
+    Env(String),
+    /// The path to a file containing the token.
+    File(PathBuf),
+    /// The token itself.
+    Value(String),
+}
+
+impl Default for TokenSource {
+    fn default() -> Self {
+        Self::Env(String::from(DEFAULT_TOKEN_VARIABLE))
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub path: PathBuf,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from(DEFAULT_DATABASE_PATH),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KbConfig {
+    /// The name of the root directory the newsletter archive is mounted on. When it is changed,
+    /// the directory with the previous name is left in the root as an empty regular directory.
+    pub archive_dir: String,
+    /// The directories on disk mounted on the root directories named after them.
+    pub directories: Vec<PathBuf>,
+}
+
+impl Default for KbConfig {
+    fn default() -> Self {
+        Self {
+            archive_dir: String::from(DEFAULT_ARCHIVE_DIR),
+            directories: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrashConfig {
+    /// The number of days the deleted knowledge base items are kept.
+    pub retention_days: u32,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: trash::DEFAULT_RETENTION_DAYS,
+        }
+    }
+}
+
+/// The limits of the messages sent by the bot, see [`Limits`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThrottleConfig {
+    pub messages_per_sec_chat: u32,
+    pub messages_per_min_chat: u32,
+    pub messages_per_sec_overall: u32,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        let limits = Limits::default();
+        Self {
+            messages_per_sec_chat: limits.messages_per_sec_chat,
+            messages_per_min_chat: limits.messages_per_min_chat,
+            messages_per_sec_overall: limits.messages_per_sec_overall,
+        }
+    }
+}
+
+impl ThrottleConfig {
+    pub fn limits(&self) -> Limits {
+        Limits {
+            messages_per_sec_chat: self.messages_per_sec_chat,
+            messages_per_min_chat: self.messages_per_min_chat,
+            messages_per_sec_overall: self.messages_per_sec_overall,
+            ..Limits::default()
+        }
+    }
+}
+
+/// The settings of a newsletter overriding the defaults of the newsletter.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NewsletterDefinition {
+    /// The description shown in the list of subscriptions.
+    pub description: Option<String>,
+    /// The hashtags in the header of the messages.
+    pub tags: Option<String>,
+}
+
+/// The configuration of the bot.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub token: TokenSource,
+    pub database: DatabaseConfig,
+    pub kb: KbConfig,
+    pub trash: TrashConfig,
+    pub throttle: ThrottleConfig,
+    /// The users who are granted the admin role when the database is created. Changing the list
+    /// afterwards has no effect on the database, only a warning at startup.
+    pub admins: Vec<u64>,
+    /// The newsletters by their names.
+    pub newsletters: BTreeMap<String, NewsletterDefinition>,
+}
+
+/// The error when the configuration cannot be parsed or contains an invalid value.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConfigError {
+    Syntax(toml::de::Error),
+    InvalidValue { key: String, reason: &'static str },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Syntax(e) => write!(f, "{}", e),
+            Self::InvalidValue { key, reason } => write!(f, "invalid `{}`: {}", key, reason),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Syntax(e)
+    }
+}
+
+fn invalid(key: impl Into<String>, reason: &'static str) -> ConfigError {
+    ConfigError::InvalidValue {
+        key: key.into(),
+        reason,
+    }
+}
+
+impl Config {
+    /// Parse and validate the configuration.
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        match &self.token {
+            TokenSource::Env(name) if name.is_empty() => {
+                return Err(invalid("token.env", "must not be empty"))
+            }
+            TokenSource::File(path) if path.as_os_str().is_empty() => {
+                return Err(invalid("token.file", "must not be empty"))
+            }
+            TokenSource::Value(token) if token.trim().is_empty() => {
+                return Err(invalid("token.value", "must not be empty"))
+            }
+            _ => (),
+        }
+        if self.database.path.as_os_str().is_empty() {
+            return Err(invalid("database.path", "must not be empty"));
+        }
+        if self.kb.archive_dir.trim().is_empty() {
+            return Err(invalid("kb.archive_dir", "must not be empty"));
+        }
+        if self
+            .kb
+            .directories
+            .iter()
+            .any(|path| path.as_os_str().is_empty())
+        {
+            return Err(invalid("kb.directories", "paths must not be empty"));
+        }
+        if self.trash.retention_days == 0 {
+            return Err(invalid("trash.retention_days", "must be positive"));
+        }
+        let limits = [
+            ("messages_per_sec_chat", self.throttle.messages_per_sec_chat),
+            ("messages_per_min_chat", self.throttle.messages_per_min_chat),
+            (
+                "messages_per_sec_overall",
+                self.throttle.messages_per_sec_overall,
+            ),
+        ];
+        for (key, limit) in limits {
+            if limit == 0 {
+                return Err(invalid(format!("throttle.{}", key), "must be positive"));
+            }
+        }
+        if self.admins.contains(&0) {
+            return Err(invalid("admins", "user IDs must be positive"));
+        }
+        for (name, definition) in &self.newsletters {
+            if !NEWSLETTER_NAMES.contains(&name.as_str()) {
+                return Err(invalid(
+                    format!("newsletters.{}", name),
+                    "unknown newsletter",
+                ));
+            }
+            if let Some(description) = &definition.description {
+                if description.trim().is_empty() {
+                    return Err(invalid(
+                        format!("newsletters.{}.description", name),
+                        "must not be empty",
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the users who are granted the admin role when the database is created.
+    pub fn admin_users(&self) -> impl Iterator<Item = UserId> + '_ {
+        self.admins.iter().copied().map(UserId)
+    }
+
+    /// Get the settings of a newsletter, which are the defaults if it is not defined.
+    pub fn newsletter(&self, name: &str) -> NewsletterDefinition {
+        self.newsletters.get(name).cloned().unwrap_or_default()
+    }
+}
@@ -1,14 +1,25 @@
+use crate::audit::{AuditAction, AuditEntry, AuditFilter, AuditTarget};
+use crate::events::Event;
 use crate::kb::command::{Command, Context, ErasedCommand, ErasedCommandReturnType};
 use crate::kb::{
-    DirectoryId, DirectoryRef, ItemRef, Note, NoteId, NoteRef, ProviderError, ProviderId,
-    ProviderUserContext, Tree,
+    DirectoryId, DirectoryRef, ItemId, ItemRef, Note, NoteId, NoteRef, ProviderError, ProviderId,
+    ProviderUserContext, Revision, RevisionId,
 };
-use crate::newsletter::archive::Sink;
+use crate::pins::Pin;
+use crate::schedule::ScheduledAnnouncement;
+use crate::newsletter::announcements::Announcement;
+use crate::state::DialogState;
+use crate::user::storage::PrivilegedUser;
+use crate::user::{Permissions, Role, User};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use chrono::{NaiveDate, NaiveDateTime};
 use std::fmt::Display;
+use teloxide::types::{ChatId, UserId};
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::{self, JoinHandle};
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct FullDirectoryId {
     pub provider: ProviderId,
     pub directory: DirectoryId,
@@ -29,7 +40,7 @@ impl Display for FullDirectoryId {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct FullNoteId {
     pub provider: ProviderId,
     pub note: NoteId,
@@ -56,12 +67,60 @@ pub enum FullItemId {
     Note(FullNoteId),
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct FullRevisionId {
+    pub provider: ProviderId,
+    pub revision: RevisionId,
+}
+
+impl Display for FullRevisionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.provider, self.revision)
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Directory {
     pub directories: Vec<(String, FullDirectoryId)>,
     pub notes: Vec<(String, FullNoteId)>,
 }
 
+/// An item in the trash, returned by [`CommandSender::deleted_items`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeletedItem {
+    /// The revision restoring the item.
+    pub revision: FullRevisionId,
+    pub is_directory: bool,
+    pub name: String,
+    /// The local time of the deletion.
+    pub time: NaiveDateTime,
+    /// The user who has deleted the item, if known.
+    pub user_id: Option<UserId>,
+    /// The names of the directories containing the item before the deletion, starting from the
+    /// root, or `None` if the parent directory no longer exists.
+    pub location: Option<Vec<String>>,
+}
+
+/// A note found by [`CommandSender::search_notes`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SearchResult {
+    pub id: FullNoteId,
+    pub name: String,
+    /// The names of the directories containing the note, starting from the root.
+    pub path: Vec<String>,
+    /// A fragment of the text of the note around the matched words.
+    pub snippet: String,
+}
+
+/// The result of [`CommandSender::reload_kb`].
+#[derive(Debug)]
+pub struct KbReload {
+    /// The items which have disappeared from their sources.
+    pub vanished: Vec<FullItemId>,
+    /// The names of the providers whose sources could not be read, along with the errors.
+    pub failed: Vec<(String, ProviderError)>,
+}
+
 struct CommandPackage {
     command: ErasedCommand,
     response_sender: oneshot::Sender<ErasedCommandReturnType>,
@@ -104,7 +163,7 @@ impl CommandSender {
 
     pub async fn root_directory(
         &self,
-        uctx: ProviderUserContext,
+        _uctx: ProviderUserContext,
     ) -> Result<FullDirectoryId, ProviderError> {
         let (provider, directory) = self
             .send(Command::new(|ctx| ctx.tree.root_directory()))
@@ -171,6 +230,38 @@ impl CommandSender {
         .await
     }
 
+    /// Get the names of the directories from the global root to this one, excluding the root.
+    ///
+    /// The path continues through the mount points, so the directories of a mounted provider are
+    /// shown inside the directory where it is mounted.
+    pub async fn directory_path(
+        &self,
+        uctx: ProviderUserContext,
+        directory: FullDirectoryId,
+    ) -> Result<Vec<String>, ProviderError> {
+        self.send(Command::new(move |ctx| {
+            let directory = ctx
+                .tree
+                .make_directory_ref(directory.provider, directory.directory)?;
+            directory.path(uctx)
+        }))
+        .await
+    }
+
+    /// Get the names of the directories from the global root to this note, excluding the root, and
+    /// the name of the note.
+    pub async fn note_path(
+        &self,
+        uctx: ProviderUserContext,
+        note: FullNoteId,
+    ) -> Result<Vec<String>, ProviderError> {
+        self.send(Command::new(move |ctx| {
+            let note = ctx.tree.make_note_ref(note.provider, note.note)?;
+            note.path(uctx)
+        }))
+        .await
+    }
+
     pub async fn read_directory(
         &self,
         uctx: ProviderUserContext,
@@ -202,11 +293,16 @@ impl CommandSender {
         name: String,
     ) -> Result<FullDirectoryId, ProviderError> {
         self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
             let destination = ctx
                 .tree
                 .make_directory_ref(destination.provider, destination.directory)?;
-            let created_ref = destination.create_directory(uctx, &name)?;
-            Ok(created_ref.into())
+            let created = destination.create_directory(uctx, &name)?.into();
+            let targets = [AuditTarget::Directory(created)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::CreateDirectory, &targets)?;
+            txn.commit()?;
+            Ok(created)
         }))
         .await
     }
@@ -218,10 +314,15 @@ impl CommandSender {
         new_name: String,
     ) -> Result<(), ProviderError> {
         self.send(Command::new(move |ctx| {
-            let directory = ctx
-                .tree
-                .make_directory_ref(directory.provider, directory.directory)?;
-            directory.rename(uctx, &new_name)
+            let txn = ctx.audit.transaction()?;
+            ctx.tree
+                .make_directory_ref(directory.provider, directory.directory)?
+                .rename(uctx, &new_name)?;
+            let targets = [AuditTarget::Directory(directory)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::RenameDirectory, &targets)?;
+            txn.commit()?;
+            Ok(())
         }))
         .await
     }
@@ -233,14 +334,22 @@ impl CommandSender {
         destination: FullDirectoryId,
     ) -> Result<(), ProviderError> {
         self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
             if directory.provider != destination.provider {
                 return Err(ProviderError::CrossProviderMove);
             }
 
-            let directory = ctx
-                .tree
-                .make_directory_ref(directory.provider, directory.directory)?;
-            directory.move_to(uctx, destination.directory)
+            ctx.tree
+                .make_directory_ref(directory.provider, directory.directory)?
+                .move_to(uctx, destination.directory)?;
+            let targets = [
+                AuditTarget::Directory(directory),
+                AuditTarget::Directory(destination),
+            ];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::MoveDirectory, &targets)?;
+            txn.commit()?;
+            Ok(())
         }))
         .await
     }
@@ -251,10 +360,15 @@ impl CommandSender {
         directory: FullDirectoryId,
     ) -> Result<(), ProviderError> {
         self.send(Command::new(move |ctx| {
-            let directory = ctx
-                .tree
-                .make_directory_ref(directory.provider, directory.directory)?;
-            directory.delete(uctx)
+            let txn = ctx.audit.transaction()?;
+            ctx.tree
+                .make_directory_ref(directory.provider, directory.directory)?
+                .delete(uctx)?;
+            let targets = [AuditTarget::Directory(directory)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::DeleteDirectory, &targets)?;
+            txn.commit()?;
+            Ok(())
         }))
         .await
     }
@@ -279,11 +393,16 @@ impl CommandSender {
         note: Note,
     ) -> Result<FullNoteId, ProviderError> {
         self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
             let destination = ctx
                 .tree
                 .make_directory_ref(destination.provider, destination.directory)?;
-            let created_ref = destination.create_note(uctx, note, &name)?;
-            Ok(created_ref.into())
+            let created = destination.create_note(uctx, note, &name)?.into();
+            let targets = [AuditTarget::Note(created)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::CreateNote, &targets)?;
+            txn.commit()?;
+            Ok(created)
         }))
         .await
     }
@@ -295,8 +414,15 @@ impl CommandSender {
         new_name: String,
     ) -> Result<(), ProviderError> {
         self.send(Command::new(move |ctx| {
-            let note = ctx.tree.make_note_ref(note.provider, note.note)?;
-            note.rename(uctx, &new_name)
+            let txn = ctx.audit.transaction()?;
+            ctx.tree
+                .make_note_ref(note.provider, note.note)?
+                .rename(uctx, &new_name)?;
+            let targets = [AuditTarget::Note(note)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::RenameNote, &targets)?;
+            txn.commit()?;
+            Ok(())
         }))
         .await
     }
@@ -308,12 +434,19 @@ impl CommandSender {
         destination: FullDirectoryId,
     ) -> Result<(), ProviderError> {
         self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
             if note.provider != destination.provider {
                 return Err(ProviderError::CrossProviderMove);
             }
 
-            let note = ctx.tree.make_note_ref(note.provider, note.note)?;
-            note.move_to(uctx, destination.directory)
+            ctx.tree
+                .make_note_ref(note.provider, note.note)?
+                .move_to(uctx, destination.directory)?;
+            let targets = [AuditTarget::Note(note), AuditTarget::Directory(destination)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::MoveNote, &targets)?;
+            txn.commit()?;
+            Ok(())
         }))
         .await
     }
@@ -324,8 +457,15 @@ impl CommandSender {
         note: FullNoteId,
     ) -> Result<(), ProviderError> {
         self.send(Command::new(move |ctx| {
-            let note = ctx.tree.make_note_ref(note.provider, note.note)?;
-            note.delete(uctx, )
+            let txn = ctx.audit.transaction()?;
+            ctx.tree
+                .make_note_ref(note.provider, note.note)?
+                .delete(uctx)?;
+            let targets = [AuditTarget::Note(note)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::DeleteNote, &targets)?;
+            txn.commit()?;
+            Ok(())
         }))
         .await
     }
@@ -337,12 +477,581 @@ impl CommandSender {
         note: Note,
     ) -> Result<(), ProviderError> {
         self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
             let note_ref = ctx.tree.make_note_ref(note_id.provider, note_id.note)?;
             note_ref.write(uctx, note)?;
+            let targets = [AuditTarget::Note(note_id)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::UpdateNote, &targets)?;
+            txn.commit()?;
             Ok(())
         }))
         .await
     }
+
+    pub async fn note_history(
+        &self,
+        uctx: ProviderUserContext,
+        note: FullNoteId,
+        limit: usize,
+    ) -> Result<Vec<Revision>, ProviderError> {
+        self.send(Command::new(move |ctx| {
+            let note = ctx.tree.make_note_ref(note.provider, note.note)?;
+            note.history(uctx, limit)
+        }))
+        .await
+    }
+
+    pub async fn directory_history(
+        &self,
+        uctx: ProviderUserContext,
+        directory: FullDirectoryId,
+        limit: usize,
+    ) -> Result<Vec<Revision>, ProviderError> {
+        self.send(Command::new(move |ctx| {
+            let directory = ctx
+                .tree
+                .make_directory_ref(directory.provider, directory.directory)?;
+            directory.history(uctx, limit)
+        }))
+        .await
+    }
+
+    /// Return an item to its state in a revision and get the ID of the restored item.
+    pub async fn restore_revision(
+        &self,
+        uctx: ProviderUserContext,
+        revision: FullRevisionId,
+    ) -> Result<FullItemId, ProviderError> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let item = ctx
+                .tree
+                .restore_revision(uctx, revision.provider, revision.revision)?;
+            let item = match item {
+                ItemRef::Directory(dir) => FullItemId::Directory(dir.into()),
+                ItemRef::Note(note) => FullItemId::Note(note.into()),
+            };
+            let targets = [AuditTarget::Revision(revision), item.into()];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::RestoreRevision, &targets)?;
+            txn.commit()?;
+            Ok(item)
+        }))
+        .await
+    }
+
+    pub async fn deleted_items(
+        &self,
+        uctx: ProviderUserContext,
+        limit: usize,
+    ) -> Result<Vec<DeletedItem>, ProviderError> {
+        self.send(Command::new(move |ctx| {
+            let deleted = ctx.tree.deleted_items(uctx, limit)?;
+            Ok(deleted
+                .into_iter()
+                .map(|(provider, revision)| {
+                    let location = ctx
+                        .tree
+                        .make_directory_ref(provider, revision.parent)
+                        .and_then(|parent| parent.path(uctx))
+                        .ok();
+                    DeletedItem {
+                        revision: FullRevisionId {
+                            provider,
+                            revision: revision.id,
+                        },
+                        is_directory: matches!(revision.item, ItemId::Directory(_)),
+                        name: revision.name,
+                        time: revision.time,
+                        user_id: revision.user_id,
+                        location,
+                    }
+                })
+                .collect())
+        }))
+        .await
+    }
+
+    pub async fn purge_deleted_items(
+        &self,
+        uctx: ProviderUserContext,
+        deleted_before: NaiveDateTime,
+    ) -> Result<(), ProviderError> {
+        self.send(Command::new(move |ctx| {
+            ctx.tree.purge_deleted_items(uctx, deleted_before)
+        }))
+        .await
+    }
+
+    /// Read the knowledge base items served from external sources again and get the items which
+    /// have disappeared from them.
+    ///
+    /// A reload requested by a user is recorded in the audit log.
+    pub async fn reload_kb(&self, uctx: ProviderUserContext) -> Result<KbReload, ProviderError> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let reload = ctx.tree.reload(uctx);
+            if uctx.user_id.is_some() {
+                ctx.audit
+                    .record(uctx.user_id, AuditAction::ReloadKnowledgeBase, &[])?;
+            }
+            txn.commit()?;
+            let vanished = reload
+                .vanished
+                .into_iter()
+                .map(|(provider, item)| match item {
+                    ItemId::Directory(directory) => {
+                        FullItemId::Directory(FullDirectoryId { provider, directory })
+                    }
+                    ItemId::Note(note) => FullItemId::Note(FullNoteId { provider, note }),
+                })
+                .collect();
+            Ok(KbReload {
+                vanished,
+                failed: reload.failed,
+            })
+        }))
+        .await
+    }
+
+    pub async fn search_notes(
+        &self,
+        uctx: ProviderUserContext,
+        query: String,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>, ProviderError> {
+        self.send(Command::new(move |ctx| {
+            ctx.tree
+                .search_notes(uctx, &query, limit)?
+                .into_iter()
+                .map(|hit| {
+                    Ok(SearchResult {
+                        id: hit.note.into(),
+                        name: hit.note.name(uctx)?,
+                        path: hit.note.parent(uctx)?.path(uctx)?,
+                        snippet: hit.snippet,
+                    })
+                })
+                .collect()
+        }))
+        .await
+    }
+
+    pub async fn pins(&self) -> rusqlite::Result<Vec<Pin>> {
+        self.send(Command::new(|ctx| ctx.pins.list())).await
+    }
+
+    pub async fn pin(&self, pin: u64) -> rusqlite::Result<Option<Pin>> {
+        self.send(Command::new(move |ctx| ctx.pins.get(pin))).await
+    }
+
+    pub async fn find_pin(&self, item: FullItemId) -> rusqlite::Result<Option<u64>> {
+        self.send(Command::new(move |ctx| ctx.pins.find(item))).await
+    }
+
+    pub async fn pin_item(&self, uctx: ProviderUserContext, item: FullItemId) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let pinned = ctx.pins.pin(item)?;
+            if pinned {
+                ctx.audit
+                    .record(uctx.user_id, AuditAction::PinItem, &[item.into()])?;
+            }
+            txn.commit()?;
+            Ok(pinned)
+        }))
+        .await
+    }
+
+    pub async fn unpin_item(&self, uctx: ProviderUserContext, item: FullItemId) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let unpinned = ctx.pins.unpin(item)?;
+            if unpinned {
+                ctx.audit
+                    .record(uctx.user_id, AuditAction::UnpinItem, &[item.into()])?;
+            }
+            txn.commit()?;
+            Ok(unpinned)
+        }))
+        .await
+    }
+
+    pub async fn set_pin_label(
+        &self,
+        uctx: ProviderUserContext,
+        pin: u64,
+        label: Option<String>,
+    ) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let changed = ctx.pins.set_label(pin, label.as_deref())?;
+            if changed {
+                let targets = [AuditTarget::Pin(pin)];
+                ctx.audit
+                    .record(uctx.user_id, AuditAction::RenamePin, &targets)?;
+            }
+            txn.commit()?;
+            Ok(changed)
+        }))
+        .await
+    }
+
+    pub async fn move_pin(&self, uctx: ProviderUserContext, pin: u64, up: bool) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let moved = ctx.pins.move_pin(pin, up)?;
+            if moved {
+                let targets = [AuditTarget::Pin(pin)];
+                ctx.audit
+                    .record(uctx.user_id, AuditAction::MovePin, &targets)?;
+            }
+            txn.commit()?;
+            Ok(moved)
+        }))
+        .await
+    }
+
+    pub async fn link_slug(&self, item: FullItemId) -> rusqlite::Result<String> {
+        self.send(Command::new(move |ctx| ctx.links.get_or_create(item)))
+            .await
+    }
+
+    pub async fn resolve_link(&self, slug: String) -> rusqlite::Result<Option<FullItemId>> {
+        self.send(Command::new(move |ctx| ctx.links.resolve(&slug)))
+            .await
+    }
+
+    pub async fn events_between(
+        &self,
+        first_day: NaiveDate,
+        last_day: NaiveDate,
+    ) -> rusqlite::Result<Vec<(u64, Event)>> {
+        self.send(Command::new(move |ctx| ctx.events.between(first_day, last_day)))
+            .await
+    }
+
+    pub async fn event(&self, id: u64) -> rusqlite::Result<Option<Event>> {
+        self.send(Command::new(move |ctx| ctx.events.get(id))).await
+    }
+
+    pub async fn create_event(&self, uctx: ProviderUserContext, event: Event) -> rusqlite::Result<u64> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let id = ctx.events.create(&event)?;
+            let targets = [AuditTarget::Event(id)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::CreateEvent, &targets)?;
+            txn.commit()?;
+            Ok(id)
+        }))
+        .await
+    }
+
+    pub async fn update_event(
+        &self,
+        uctx: ProviderUserContext,
+        id: u64,
+        event: Event,
+    ) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let updated = ctx.events.update(id, &event)?;
+            if updated {
+                let targets = [AuditTarget::Event(id)];
+                ctx.audit
+                    .record(uctx.user_id, AuditAction::UpdateEvent, &targets)?;
+            }
+            txn.commit()?;
+            Ok(updated)
+        }))
+        .await
+    }
+
+    pub async fn delete_event(&self, uctx: ProviderUserContext, id: u64) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let deleted = ctx.events.delete(id)?;
+            if deleted {
+                let targets = [AuditTarget::Event(id)];
+                ctx.audit
+                    .record(uctx.user_id, AuditAction::DeleteEvent, &targets)?;
+            }
+            txn.commit()?;
+            Ok(deleted)
+        }))
+        .await
+    }
+
+    pub async fn add_event_reminder(&self, id: u64, user_id: UserId) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| ctx.events.add_reminder(id, user_id)))
+            .await
+    }
+
+    pub async fn remove_event_reminder(
+        &self,
+        id: u64,
+        user_id: UserId,
+    ) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| ctx.events.remove_reminder(id, user_id)))
+            .await
+    }
+
+    pub async fn has_event_reminder(&self, id: u64, user_id: UserId) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| ctx.events.has_reminder(id, user_id)))
+            .await
+    }
+
+    pub async fn is_event_reminder_sent(&self, id: u64) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| ctx.events.is_reminder_sent(id)))
+            .await
+    }
+
+    pub async fn take_due_event_reminder(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> rusqlite::Result<Option<(u64, Event, Vec<UserId>)>> {
+        self.send(Command::new(move |ctx| ctx.events.take_due_reminder(from, until)))
+            .await
+    }
+
+    pub async fn store_user(
+        &self,
+        user_id: UserId,
+        chat_id: ChatId,
+        user: User,
+    ) -> rusqlite::Result<Option<Permissions>> {
+        self.send(Command::new(move |ctx| {
+            ctx.users.store(user_id, chat_id, &user)
+        }))
+        .await
+    }
+
+    pub async fn find_user_by_username(
+        &self,
+        username: String,
+    ) -> rusqlite::Result<Option<UserId>> {
+        self.send(Command::new(move |ctx| ctx.users.find_by_username(&username)))
+            .await
+    }
+
+    pub async fn scheduled_announcements(
+        &self,
+    ) -> rusqlite::Result<Vec<(u64, ScheduledAnnouncement)>> {
+        self.send(Command::new(|ctx| ctx.schedule.list())).await
+    }
+
+    pub async fn scheduled_announcement(
+        &self,
+        id: u64,
+    ) -> rusqlite::Result<Option<ScheduledAnnouncement>> {
+        self.send(Command::new(move |ctx| ctx.schedule.get(id))).await
+    }
+
+    pub async fn schedule_announcement(
+        &self,
+        uctx: ProviderUserContext,
+        scheduled: ScheduledAnnouncement,
+    ) -> rusqlite::Result<u64> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let id = ctx.schedule.create(&scheduled)?;
+            let targets = [AuditTarget::ScheduledAnnouncement(id)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::ScheduleAnnouncement, &targets)?;
+            txn.commit()?;
+            Ok(id)
+        }))
+        .await
+    }
+
+    pub async fn update_scheduled_announcement(
+        &self,
+        uctx: ProviderUserContext,
+        id: u64,
+        scheduled: ScheduledAnnouncement,
+    ) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let updated = ctx.schedule.update(id, &scheduled)?;
+            if updated {
+                let targets = [AuditTarget::ScheduledAnnouncement(id)];
+                ctx.audit.record(
+                    uctx.user_id,
+                    AuditAction::UpdateScheduledAnnouncement,
+                    &targets,
+                )?;
+            }
+            txn.commit()?;
+            Ok(updated)
+        }))
+        .await
+    }
+
+    pub async fn cancel_scheduled_announcement(
+        &self,
+        uctx: ProviderUserContext,
+        id: u64,
+    ) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let cancelled = ctx.schedule.delete(id)?;
+            if cancelled {
+                let targets = [AuditTarget::ScheduledAnnouncement(id)];
+                ctx.audit.record(
+                    uctx.user_id,
+                    AuditAction::CancelScheduledAnnouncement,
+                    &targets,
+                )?;
+            }
+            txn.commit()?;
+            Ok(cancelled)
+        }))
+        .await
+    }
+
+    pub async fn next_due_announcement(
+        &self,
+        now: NaiveDateTime,
+    ) -> rusqlite::Result<Option<(u64, Announcement)>> {
+        self.send(Command::new(move |ctx| ctx.schedule.next_due(now)))
+            .await
+    }
+
+    /// Remove a scheduled announcement that has been sent.
+    pub async fn remove_sent_announcement(&self, id: u64) -> rusqlite::Result<bool> {
+        self.send(Command::new(move |ctx| ctx.schedule.delete(id)))
+            .await
+    }
+
+    pub async fn username(&self, user_id: UserId) -> rusqlite::Result<Option<String>> {
+        self.send(Command::new(move |ctx| ctx.users.username(user_id)))
+            .await
+    }
+
+    pub async fn privileged_users(&self) -> rusqlite::Result<Vec<PrivilegedUser>> {
+        self.send(Command::new(|ctx| ctx.users.privileged_users()))
+            .await
+    }
+
+    pub async fn roles(&self) -> rusqlite::Result<Vec<Role>> {
+        self.send(Command::new(|ctx| ctx.users.roles())).await
+    }
+
+    pub async fn user_roles(&self, user_id: UserId) -> rusqlite::Result<Vec<String>> {
+        self.send(Command::new(move |ctx| ctx.users.user_roles(user_id)))
+            .await
+    }
+
+    pub async fn users_with_role(&self, role: String) -> rusqlite::Result<Vec<UserId>> {
+        self.send(Command::new(move |ctx| ctx.users.users_with_role(&role)))
+            .await
+    }
+
+    pub async fn grant_role(
+        &self,
+        uctx: ProviderUserContext,
+        user_id: UserId,
+        role: String,
+    ) -> rusqlite::Result<Permissions> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let permissions = ctx.users.grant_role(user_id, &role)?;
+            let targets = [AuditTarget::User(user_id), AuditTarget::Role(role)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::GrantRole, &targets)?;
+            txn.commit()?;
+            Ok(permissions)
+        }))
+        .await
+    }
+
+    pub async fn revoke_role(
+        &self,
+        uctx: ProviderUserContext,
+        user_id: UserId,
+        role: String,
+    ) -> rusqlite::Result<Permissions> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            let permissions = ctx.users.revoke_role(user_id, &role)?;
+            let targets = [AuditTarget::User(user_id), AuditTarget::Role(role)];
+            ctx.audit
+                .record(uctx.user_id, AuditAction::RevokeRole, &targets)?;
+            txn.commit()?;
+            Ok(permissions)
+        }))
+        .await
+    }
+
+    /// Record an announcement sent by a user. An announcement scheduled earlier is removed in the
+    /// same transaction, so that it is not sent again at its time.
+    pub async fn record_sent_announcement(
+        &self,
+        uctx: ProviderUserContext,
+        scheduled: Option<u64>,
+    ) -> rusqlite::Result<()> {
+        self.send(Command::new(move |ctx| {
+            let txn = ctx.audit.transaction()?;
+            if let Some(id) = scheduled {
+                ctx.schedule.delete(id)?;
+            }
+            let targets: Vec<_> = scheduled
+                .map(AuditTarget::ScheduledAnnouncement)
+                .into_iter()
+                .collect();
+            ctx.audit
+                .record(uctx.user_id, AuditAction::SendAnnouncement, &targets)?;
+            txn.commit()
+        }))
+        .await
+    }
+
+    /// Get a page of the audit log entries matching the filter, the latest first.
+    pub async fn audit_log(
+        &self,
+        filter: AuditFilter,
+        limit: usize,
+        offset: usize,
+    ) -> rusqlite::Result<Vec<AuditEntry>> {
+        self.send(Command::new(move |ctx| ctx.audit.list(&filter, limit, offset)))
+            .await
+    }
+
+    pub async fn count_audit_log(&self, filter: AuditFilter) -> rusqlite::Result<usize> {
+        self.send(Command::new(move |ctx| ctx.audit.count(&filter)))
+            .await
+    }
+
+    pub async fn all_user_permissions(&self) -> rusqlite::Result<HashMap<UserId, Permissions>> {
+        self.send(Command::new(|ctx| ctx.users.all_permissions()))
+            .await
+    }
+
+    pub async fn store_dialog_state(
+        &self,
+        user_id: UserId,
+        state: DialogState,
+    ) -> rusqlite::Result<()> {
+        self.send(Command::new(move |ctx| {
+            ctx.users.store_state(user_id, &state)
+        }))
+        .await
+    }
+}
+
+/// The receiving end of the channel of an [`AccessTask`], created with [`command_channel`].
+pub struct CommandReceiver {
+    receiver: mpsc::Receiver<CommandPackage>,
+}
+
+/// Create the channel of an [`AccessTask`] before the task itself, so that the sender can be
+/// handed out to the parts of the bot created before the context of the task.
+pub fn command_channel() -> (CommandSender, CommandReceiver) {
+    let (sender, receiver) = mpsc::channel(1);
+    (CommandSender { sender }, CommandReceiver { receiver })
 }
 
 pub struct AccessTask {
@@ -351,14 +1060,11 @@ pub struct AccessTask {
 }
 
 impl AccessTask {
-    pub fn new(tree: Tree, newsletter_sink: Sink) -> (Self, CommandSender) {
-        let context = Context {
-            tree,
-            newsletter_sink,
-        };
-        let (sender, receiver) = mpsc::channel(1);
-        let command_sender = CommandSender { sender };
-        (Self { receiver, context }, command_sender)
+    pub fn new(receiver: CommandReceiver, context: Context) -> Self {
+        Self {
+            receiver: receiver.receiver,
+            context,
+        }
     }
 
     fn run_blocking(mut self) {
@@ -1,21 +1,26 @@
+use crate::global_state::GlobalState;
+use crate::media::Attachment;
 use crate::message::{FormattedMessage, FormattedText};
 use crate::state::DialogState;
 use crate::strings::STRINGS;
 use crate::types::{BotType, HandlerResult};
 use crate::user::Permissions;
+use crate::user::storage::{load_permissions, load_users};
 use crate::user::User;
 use crate::user_facing_error::UserFacingError;
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::env;
 use std::sync::{Arc, Mutex, RwLock};
 use teloxide::prelude::*;
 use teloxide::requests::HasPayload;
-use rusqlite::{Connection, params};
+use teloxide::types::InputFile;
+use rusqlite::Connection;
+use std::time::Duration;
 
 /// The dialog with a certain user.
 pub struct UserDialog {
     chat_id: ChatId,
+    user_id: UserId,
     data: RwLock<UserDialogData>,
 }
 
@@ -37,10 +42,11 @@ impl UserDialogData {
 }
 
 impl UserDialog {
-    /// Create from the ID of the chat with the user and the ID of this user.
-    pub fn new(chat_id: ChatId, user: User) -> Self {
+    /// Create from the ID of the chat with the user, the ID of this user and the user data.
+    pub fn new(chat_id: ChatId, user_id: UserId, user: User) -> Self {
         Self {
             chat_id,
+            user_id,
             data: RwLock::new(UserDialogData::new(user)),
         }
     }
@@ -49,11 +55,16 @@ impl UserDialog {
         self.chat_id
     }
 
+    pub fn user_id(&self) -> UserId {
+        self.user_id
+    }
+
     pub async fn send_message_with_id(
         chat_id: ChatId,
         bot: &BotType,
         message: FormattedMessage,
     ) -> HandlerResult<()> {
+        send_attachments(chat_id, bot, message.attachments).await?;
         let mut request = bot.send_message(chat_id, message.text.raw_text);
         let payload = request.payload_mut();
         payload.entities = message.text.entities;
@@ -76,6 +87,39 @@ impl UserDialog {
     }
 }
 
+/// Send the attachments of a message.
+///
+/// Images and videos are sent as media groups, and documents are sent as separate groups, since
+/// Telegram does not allow mixing them.
+async fn send_attachments(
+    chat_id: ChatId,
+    bot: &BotType,
+    attachments: Vec<Attachment>,
+) -> HandlerResult<()> {
+    /// The maximum size of a media group.
+    const MAX_GROUP_SIZE: usize = 10;
+
+    let (documents, media): (Vec<_>, Vec<_>) = attachments
+        .into_iter()
+        .partition(|attachment| matches!(attachment, Attachment::Document(_)));
+    for attachments in [media, documents] {
+        for group in attachments.chunks(MAX_GROUP_SIZE) {
+            if let [attachment] = group {
+                let input_file = InputFile::file_id(attachment.file().id.clone());
+                match attachment {
+                    Attachment::Image(_) => bot.send_photo(chat_id, input_file).await?,
+                    Attachment::Video(_) => bot.send_video(chat_id, input_file).await?,
+                    Attachment::Document(_) => bot.send_document(chat_id, input_file).await?,
+                };
+            } else {
+                bot.send_media_group(chat_id, group.iter().map(Attachment::input_media))
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
 /// The error when the bot has been invoked in a kind of chat it does not support (e.g. in a group chat).
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct InvalidChatError {
@@ -111,40 +155,34 @@ pub struct DialogStorage {
 
 pub struct RawDialogStorage {
     dialogs: HashMap<UserId, Arc<UserDialog>>,
-    dialogs_by_username: HashMap<String, UserDialogData>,
+    /// The effective permissions of all the users that have at least one role, including the ones
+    /// without a dialog yet.
+    permissions: HashMap<UserId, Permissions>,
 }
 
 impl DialogStorage {
-    /// Create an empty [`DialogStorage`].
-    pub fn new(db: &Connection) -> Self {
-        let mut dialogs_by_username = HashMap::new();
-
-        let txn = db.unchecked_transaction().unwrap();
-        let mut stmt = txn.prepare("SELECT user, edit_kb, receive_feedback FROM permissions").unwrap();
-        let permissions_for_users = stmt.query_map(params![], |row| {
-            let user: String = row.get(0)?;
-            let edit_kb = row.get(1)?;
-            let receive_feedback = row.get(2)?;
-            Ok((user, Permissions { edit_kb, receive_feedback, ..Default::default() }))
-        }).unwrap();
-
-        for (username, permissions) in permissions_for_users.map(|x| x.unwrap()) {
-            debug!("Granting @{} with additional permissions", &username);
-            let mut user = User::new();
-            *user.permissions_mut() = permissions;
-            if permissions.receive_feedback {
-                user.subscriptions_mut().insert(String::from("feedback"));
-            }
-            let dialog_data = UserDialogData::new(user);
-            dialogs_by_username.insert(username, dialog_data);
-        }
+    /// Create a [`DialogStorage`] with the users stored in the database.
+    ///
+    /// The dialogs are restored in their stored states. The stored users are considered to have
+    /// already passed the initial state, so the ones without a stored state are returned to the
+    /// main menu.
+    pub fn new(db: &Connection) -> rusqlite::Result<Self> {
+        let dialogs = load_users(db)?
+            .into_iter()
+            .map(|(user_id, stored)| {
+                let mut dialog = UserDialog::new(stored.chat_id, user_id, stored.user);
+                dialog.data_mut().state = stored.state.unwrap_or(DialogState::MainMenu);
+                (user_id, Arc::new(dialog))
+            })
+            .collect();
+        let permissions = load_permissions(db)?;
 
-        Self {
+        Ok(Self {
             raw: Mutex::new(RefCell::new(RawDialogStorage {
-                dialogs: HashMap::new(),
-                dialogs_by_username,
+                dialogs,
+                permissions,
             })),
-        }
+        })
     }
 
     /// Get the dialog with the specified chat and user IDs.
@@ -161,42 +199,81 @@ impl DialogStorage {
         &self,
         chat_id: ChatId,
         user_id: UserId,
-        maybe_username: Option<&str>,
     ) -> Result<Arc<UserDialog>, InvalidChatError> {
         if !chat_id.is_user() {
             return Err(InvalidChatError { chat_id });
         }
 
         let lock = self.raw.lock().unwrap();
-        if let Some(username) = maybe_username {
-            let contains_key = lock.borrow().dialogs_by_username.contains_key(username);
-            if contains_key {
-                debug!("Recognizing @{}", username);
-                let mut borrow_mut = lock.borrow_mut();
-                let dialog_data = borrow_mut.dialogs_by_username.remove(username).unwrap();
-                let dialog = UserDialog {
-                    chat_id,
-                    data: RwLock::new(dialog_data),
-                };
-                borrow_mut.dialogs.insert(user_id, Arc::new(dialog));
-            }
-        }
-
         let mut borrow_mut = lock.borrow_mut();
-        let dialog_ref = borrow_mut.dialogs.entry(user_id).or_insert_with(|| {
-            let user = User::new();
-            let dialog = UserDialog::new(chat_id, user);
+        let RawDialogStorage {
+            dialogs,
+            permissions,
+        } = &mut *borrow_mut;
+        let dialog_ref = dialogs.entry(user_id).or_insert_with(|| {
+            let mut user = User::new();
+            *user.permissions_mut() = permissions.get(&user_id).copied().unwrap_or_default();
+            let dialog = UserDialog::new(chat_id, user_id, user);
             Arc::new(dialog)
         });
         Ok(Arc::clone(dialog_ref))
     }
 
+    /// Set the effective permissions of a user.
+    ///
+    /// The permissions take effect immediately, even in the middle of a dialog.
+    pub fn set_permissions(&self, user_id: UserId, new_permissions: Permissions) {
+        let lock = self.raw.lock().unwrap();
+        let mut borrow_mut = lock.borrow_mut();
+        if new_permissions == Permissions::default() {
+            borrow_mut.permissions.remove(&user_id);
+        } else {
+            borrow_mut.permissions.insert(user_id, new_permissions);
+        }
+        if let Some(dialog) = borrow_mut.dialogs.get(&user_id) {
+            *dialog.data().write().unwrap().user.permissions_mut() = new_permissions;
+        }
+    }
+
+    /// Replace the effective permissions of all users.
+    ///
+    /// The users missing from `all_permissions` are left without any permissions.
+    pub fn set_all_permissions(&self, all_permissions: HashMap<UserId, Permissions>) {
+        let lock = self.raw.lock().unwrap();
+        let mut borrow_mut = lock.borrow_mut();
+        for (user_id, dialog) in borrow_mut.dialogs.iter() {
+            let new_permissions = all_permissions.get(user_id).copied().unwrap_or_default();
+            let mut dialog_data = dialog.data().write().unwrap();
+            if *dialog_data.user.permissions() != new_permissions {
+                debug!("Permissions of user {} have changed", user_id.0);
+                *dialog_data.user.permissions_mut() = new_permissions;
+            }
+        }
+        borrow_mut.permissions = all_permissions;
+    }
+
     pub fn inspect_dialogs<F>(&self, inspector: &mut F)
     where
         F: FnMut(UserId, &Arc<UserDialog>),
     {
-        for (&k, ref v) in self.raw.lock().unwrap().borrow().dialogs.iter() {
+        for (&k, v) in self.raw.lock().unwrap().borrow().dialogs.iter() {
             inspector(k, v);
         }
     }
 }
+
+/// Periodically reload the permissions of all users from the database.
+///
+/// This way, the roles changed directly in the database take effect without a restart.
+pub async fn refresh_permissions(global_state: Arc<GlobalState>, period: Duration) {
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        match global_state.db.all_user_permissions().await {
+            Ok(all_permissions) => global_state
+                .dialog_storage
+                .set_all_permissions(all_permissions),
+            Err(e) => warn!("Cannot reload user permissions: {}", e),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::message::FormattedText;
+use crate::strings::STRINGS;
+use crate::user_facing_error::UserFacingError;
+use std::error::Error;
+use std::fmt::Display;
+
+/// The error when an event cannot be viewed or saved.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EventError {
+    /// The event does not exist (e.g. it has been deleted).
+    NoSuchEvent,
+    /// The end time of the event is earlier than its start time.
+    EndBeforeStart,
+}
+
+impl Display for EventError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSuchEvent => write!(f, "No such event"),
+            Self::EndBeforeStart => write!(f, "The event ends before it starts"),
+        }
+    }
+}
+
+impl Error for EventError {}
+
+impl UserFacingError for EventError {
+    fn user_message(&self) -> FormattedText {
+        let s = &STRINGS.errors.event;
+        match self {
+            Self::NoSuchEvent => s.no_such_event(),
+            Self::EndBeforeStart => s.end_before_start(),
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::media::LocationOrAddress;
+use crate::message::{entities_from_json, entities_to_json, FormattedText};
+use crate::util::{NestedTransaction, UnsafeRc};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use teloxide::types::UserId;
+
+/// An eco event shown in the calendar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub title: String,
+    pub description: FormattedText,
+    /// The local time when the event starts.
+    pub start: NaiveDateTime,
+    /// The local time when the event ends. It is never earlier than `start`.
+    pub end: NaiveDateTime,
+    pub location: LocationOrAddress,
+}
+
+/// The period of time shown on a single page of the calendar.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum CalendarPeriod {
+    Week,
+    Month,
+}
+
+impl CalendarPeriod {
+    /// Get the first day of the period containing the given day.
+    pub fn first_day(self, day: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Week => day - Duration::days(day.weekday().num_days_from_monday().into()),
+            Self::Month => day.with_day(1).unwrap(),
+        }
+    }
+
+    /// Get the first day of the period following the one starting on `first_day`.
+    pub fn next(self, first_day: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Week => first_day + Duration::days(7),
+            Self::Month => {
+                let (year, month) = if first_day.month() == 12 {
+                    (first_day.year() + 1, 1)
+                } else {
+                    (first_day.year(), first_day.month() + 1)
+                };
+                NaiveDate::from_ymd_opt(year, month, 1).unwrap()
+            }
+        }
+    }
+
+    /// Get the first day of the period preceding the one starting on `first_day`.
+    pub fn previous(self, first_day: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Week => first_day - Duration::days(7),
+            Self::Month => self.first_day(first_day - Duration::days(1)),
+        }
+    }
+}
+
+/// The format of timestamps in the database. It is chosen so that the timestamps are ordered
+/// lexicographically.
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Format a local time for storage in the database.
+pub fn time_to_sql(time: NaiveDateTime) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+/// Read a local time stored with [`time_to_sql`] from a column.
+pub fn time_from_sql(row: &Row<'_>, index: usize) -> rusqlite::Result<NaiveDateTime> {
+    let text: String = row.get(index)?;
+    NaiveDateTime::parse_from_str(&text, TIME_FORMAT)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+}
+
+/// Get the time when a day starts.
+pub fn day_start(day: NaiveDate) -> NaiveDateTime {
+    day.and_hms_opt(0, 0, 0).unwrap()
+}
+
+const EVENT_COLUMNS: &str =
+    "id, title, description, description_entities, start_time, end_time, location";
+
+fn event_from_row(row: &Row<'_>) -> rusqlite::Result<(u64, Event)> {
+    let entities_json: Option<String> = row.get(3)?;
+    let location_json: String = row.get(6)?;
+    let event = Event {
+        title: row.get(1)?,
+        description: FormattedText {
+            raw_text: row.get(2)?,
+            entities: entities_from_json(entities_json).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e))
+            })?,
+        },
+        start: time_from_sql(row, 4)?,
+        end: time_from_sql(row, 5)?,
+        location: serde_json::from_str(&location_json)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(6, Type::Text, Box::new(e)))?,
+    };
+    Ok((row.get(0)?, event))
+}
+
+fn to_sql_error(e: serde_json::Error) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(Box::new(e))
+}
+
+/// Persistent storage of the events.
+pub struct EventStorage {
+    db: UnsafeRc<Connection>,
+}
+
+impl EventStorage {
+    /// SAFETY: the caller must uphold the invariants of [`UnsafeRc`].
+    pub unsafe fn new(db: UnsafeRc<Connection>) -> Self {
+        Self { db }
+    }
+
+    /// Get the events that take place (at least partially) on the days from `first_day` to
+    /// `last_day` inclusive, sorted by their start time.
+    pub fn between(
+        &self,
+        first_day: NaiveDate,
+        last_day: NaiveDate,
+    ) -> rusqlite::Result<Vec<(u64, Event)>> {
+        let mut stmt = self.db.prepare(&format!(
+            concat!(
+                "SELECT {} FROM events WHERE start_time < ? AND end_time >= ? ",
+                "ORDER BY start_time, id",
+            ),
+            EVENT_COLUMNS,
+        ))?;
+        let period_end = day_start(last_day + Duration::days(1));
+        let rows = stmt.query_map(
+            params![time_to_sql(period_end), time_to_sql(day_start(first_day))],
+            event_from_row,
+        )?;
+        rows.collect()
+    }
+
+    pub fn get(&self, id: u64) -> rusqlite::Result<Option<Event>> {
+        Ok(self
+            .db
+            .prepare(&format!("SELECT {} FROM events WHERE id = ?", EVENT_COLUMNS))?
+            .query_row(params![id], event_from_row)
+            .optional()?
+            .map(|(_, event)| event))
+    }
+
+    /// Create an event and return its ID.
+    pub fn create(&self, event: &Event) -> rusqlite::Result<u64> {
+        let entities = entities_to_json(&event.description.entities).map_err(to_sql_error)?;
+        let location = serde_json::to_string(&event.location).map_err(to_sql_error)?;
+        self.db
+            .prepare(concat!(
+                "INSERT INTO events(title, description, description_entities, ",
+                "start_time, end_time, location) VALUES (?, ?, ?, ?, ?, ?)",
+            ))?
+            .execute(params![
+                &event.title,
+                &event.description.raw_text,
+                entities,
+                time_to_sql(event.start),
+                time_to_sql(event.end),
+                location,
+            ])?;
+        Ok(self.db.last_insert_rowid() as u64)
+    }
+
+    /// Replace an event.
+    ///
+    /// If the start time changes, the reminder about the event will be sent again.
+    ///
+    /// Returns `false` if there is no such event.
+    pub fn update(&self, id: u64, event: &Event) -> rusqlite::Result<bool> {
+        let entities = entities_to_json(&event.description.entities).map_err(to_sql_error)?;
+        let location = serde_json::to_string(&event.location).map_err(to_sql_error)?;
+        let updated = self
+            .db
+            .prepare(concat!(
+                "UPDATE events SET title = ?1, description = ?2, description_entities = ?3, ",
+                "reminder_sent = reminder_sent AND start_time = ?4, ",
+                "start_time = ?4, end_time = ?5, location = ?6 WHERE id = ?7",
+            ))?
+            .execute(params![
+                &event.title,
+                &event.description.raw_text,
+                entities,
+                time_to_sql(event.start),
+                time_to_sql(event.end),
+                location,
+                id,
+            ])?;
+        Ok(updated != 0)
+    }
+
+    /// Delete an event.
+    ///
+    /// Returns `false` if there is no such event.
+    pub fn delete(&self, id: u64) -> rusqlite::Result<bool> {
+        let deleted = self
+            .db
+            .prepare("DELETE FROM events WHERE id = ?")?
+            .execute(params![id])?;
+        Ok(deleted != 0)
+    }
+
+    /// Ask to remind a user about an event.
+    ///
+    /// Returns `false` if the user has already asked for it.
+    pub fn add_reminder(&self, id: u64, user_id: UserId) -> rusqlite::Result<bool> {
+        let inserted = self
+            .db
+            .prepare("INSERT OR IGNORE INTO event_reminders(event_id, user_id) VALUES (?, ?)")?
+            .execute(params![id, user_id.0])?;
+        Ok(inserted != 0)
+    }
+
+    /// Cancel the reminder about an event for a user.
+    ///
+    /// Returns `false` if the user has not asked for it.
+    pub fn remove_reminder(&self, id: u64, user_id: UserId) -> rusqlite::Result<bool> {
+        let deleted = self
+            .db
+            .prepare("DELETE FROM event_reminders WHERE event_id = ? AND user_id = ?")?
+            .execute(params![id, user_id.0])?;
+        Ok(deleted != 0)
+    }
+
+    /// Check whether a user has asked to be reminded about an event.
+    pub fn has_reminder(&self, id: u64, user_id: UserId) -> rusqlite::Result<bool> {
+        self.db
+            .prepare(concat!(
+                "SELECT EXISTS (SELECT 1 FROM event_reminders ",
+                "WHERE event_id = ? AND user_id = ?)",
+            ))?
+            .query_row(params![id, user_id.0], |row| row.get(0))
+    }
+
+    /// Check whether the reminder about an event has already been sent.
+    pub fn is_reminder_sent(&self, id: u64) -> rusqlite::Result<bool> {
+        Ok(self
+            .db
+            .prepare("SELECT reminder_sent FROM events WHERE id = ?")?
+            .query_row(params![id], |row| row.get(0))
+            .optional()?
+            .unwrap_or(false))
+    }
+
+    /// Find the earliest event starting after `from` and no later than `until` whose reminder has
+    /// been requested, but not sent yet, and mark the reminder as sent.
+    ///
+    /// Returns the event along with the users who have requested the reminder.
+    pub fn take_due_reminder(
+        &self,
+        from: NaiveDateTime,
+        until: NaiveDateTime,
+    ) -> rusqlite::Result<Option<(u64, Event, Vec<UserId>)>> {
+        let txn = NestedTransaction::immediate(&self.db)?;
+        let event = txn
+            .prepare(&format!(
+                concat!(
+                    "SELECT {} FROM events WHERE NOT reminder_sent ",
+                    "AND start_time > ? AND start_time <= ? ",
+                    "AND EXISTS (SELECT 1 FROM event_reminders WHERE event_id = events.id) ",
+                    "ORDER BY start_time, id LIMIT 1",
+                ),
+                EVENT_COLUMNS,
+            ))?
+            .query_row(params![time_to_sql(from), time_to_sql(until)], event_from_row)
+            .optional()?;
+        let (id, event) = match event {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        txn.prepare("UPDATE events SET reminder_sent = TRUE WHERE id = ?")?
+            .execute(params![id])?;
+        let users = {
+            let mut stmt = txn.prepare("SELECT user_id FROM event_reminders WHERE event_id = ?")?;
+            let rows = stmt.query_map(params![id], |row| Ok(UserId(row.get(0)?)))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        txn.commit()?;
+        Ok(Some((id, event, users)))
+    }
+}
@@ -32,7 +32,7 @@ impl Display for InvalidTopicStrError {
 }
 
 impl FromStr for FeedbackTopic {
-    type Err = ();
+    type Err = InvalidTopicStrError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
@@ -41,7 +41,7 @@ impl FromStr for FeedbackTopic {
             strings::SUGGEST_ECO_INITIATIVE => Ok(Self::SuggestEcoInitiative),
             strings::REPORT_GARBAGE_DUMP => Ok(Self::ReportGarbageDump),
             strings::OTHER => Ok(Self::Other),
-            _ => Err(()),
+            _ => Err(InvalidTopicStrError),
         }
     }
 }
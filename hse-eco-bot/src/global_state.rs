@@ -1,13 +1,54 @@
 use crate::db::CommandSender;
 use crate::dispatch::DialogStorage;
-use crate::ui::form::{Form, FormInput};
+use crate::newsletter::announcements::{self, Announcement};
+use crate::newsletter::{feedback, AccessCheck};
+use crate::types::InternalError;
+use crate::ui::form::{CompletedForm, FormCompletionTarget};
 use std::sync::Mutex;
 use tokio::sync::mpsc::Sender;
-use crate::user::Permissions;
 
 pub struct GlobalState {
     pub dialog_storage: DialogStorage,
     pub db: CommandSender,
-    pub feedback_tx: Mutex<Option<Sender<(Form, Vec<FormInput>)>>>,
-    pub newsletters: Vec<(String, String, Box<dyn Fn(&Permissions) -> bool + Send + Sync>)>,
+    pub feedback_tx: Mutex<Option<Sender<CompletedForm>>>,
+    pub announcement_tx: Mutex<Option<Sender<Announcement>>>,
+    pub newsletters: Vec<(String, String, AccessCheck)>,
+    /// The tags of the announcements newsletter, shown in the previews of the announcements.
+    pub announcement_tags: String,
+    /// The number of days the deleted knowledge base items are kept in the trash.
+    pub trash_retention_days: u32,
+}
+
+impl GlobalState {
+    /// Get the channel that receives the forms completed for the given target.
+    ///
+    /// Returns `None` for the targets whose forms are completed by the dialog itself.
+    pub fn form_completion_sender(
+        &self,
+        target: FormCompletionTarget,
+    ) -> Result<Option<Sender<CompletedForm>>, InternalError> {
+        match target {
+            FormCompletionTarget::Feedback => self
+                .feedback_tx
+                .lock()
+                .unwrap()
+                .as_ref()
+                .filter(|tx| !tx.is_closed())
+                .cloned()
+                .map(Some)
+                .ok_or(InternalError::NewsletterStopped(feedback::NAME)),
+            FormCompletionTarget::CreateEvent | FormCompletionTarget::EditEvent { .. } => Ok(None),
+        }
+    }
+
+    /// Get the channel that receives the announcements to be sent.
+    pub fn announcement_sender(&self) -> Result<Sender<Announcement>, InternalError> {
+        self.announcement_tx
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|tx| !tx.is_closed())
+            .cloned()
+            .ok_or(InternalError::NewsletterStopped(announcements::NAME))
+    }
 }
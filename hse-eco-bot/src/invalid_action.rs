@@ -10,6 +10,7 @@ pub enum InvalidAction {
     InvalidState,
     UnexpectedMessage,
     UnexpectedMessageKind,
+    PermissionDenied,
 }
 
 impl Display for InvalidAction {
@@ -19,6 +20,7 @@ impl Display for InvalidAction {
             Self::InvalidState => write!(f, "Invalid state for selected action"),
             Self::UnexpectedMessage => write!(f, "A message was received when it was not expected"),
             Self::UnexpectedMessageKind => write!(f, "An unexpected type of message was received"),
+            Self::PermissionDenied => write!(f, "The user is not allowed to perform this action"),
         }
     }
 }
@@ -32,6 +34,7 @@ impl UserFacingError for InvalidAction {
             Self::InvalidState => STRINGS.errors.action.invalid_state(),
             Self::UnexpectedMessage => STRINGS.errors.action.unexpected_message(),
             Self::UnexpectedMessageKind => STRINGS.errors.action.unexpected_message_kind(),
+            Self::PermissionDenied => STRINGS.errors.action.permission_denied(),
         }
     }
 }
@@ -1,30 +1,44 @@
 pub mod command;
 pub mod providers;
+pub mod transfer;
 
+use crate::media::Attachment;
 use crate::message::FormattedText;
 use crate::newsletter::archive::Sink;
-use crate::newsletter::Newsletter;
+use crate::newsletter::{AccessCheck, Newsletter};
 use crate::strings::STRINGS;
 use crate::user::Permissions;
 use crate::user_facing_error::UserFacingError;
 use crate::util::UnsafeRc;
+use chrono::NaiveDateTime;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
+use teloxide::types::UserId;
+
+/// The key under which the ID of the newsletter archive provider is registered.
+const ARCHIVE_PROVIDER_KEY: &str = "newsletter-archive";
 
 /// The identificator of a directory local to a [`Provider`].
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub struct DirectoryId(u64);
 
 /// The identificator of a note local to a [`Provider`].
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub struct NoteId(u64);
 
 /// The identificator of a provider in a [`Tree`].
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub struct ProviderId(u64);
 
+/// The identificator of a revision local to a [`Provider`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct RevisionId(u64);
+
 impl Display for DirectoryId {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -43,6 +57,12 @@ impl Display for ProviderId {
     }
 }
 
+impl Display for RevisionId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl From<u64> for DirectoryId {
     fn from(raw: u64) -> Self {
         Self(raw)
@@ -79,6 +99,25 @@ impl From<ProviderId> for u64 {
     }
 }
 
+impl From<u64> for RevisionId {
+    fn from(raw: u64) -> Self {
+        Self(raw)
+    }
+}
+
+impl From<RevisionId> for u64 {
+    fn from(id: RevisionId) -> Self {
+        id.0
+    }
+}
+
+/// The identificator of an item local to a [`Provider`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ItemId {
+    Directory(DirectoryId),
+    Note(NoteId),
+}
+
 /// The reference to an item in a specific [`Provider`].
 #[derive(Debug, Copy, Clone)]
 pub enum ItemRef<'c> {
@@ -88,11 +127,7 @@ pub enum ItemRef<'c> {
 
 impl ItemRef<'_> {
     pub fn is_note(&self) -> bool {
-        if let Self::Note(_) = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::Note(_))
     }
 }
 
@@ -232,6 +267,29 @@ impl<'c> DirectoryRef<'c> {
             .borrow()
             .get_directory_name(self.ctx, uctx, self.id)
     }
+
+    /// Get the revisions of this directory and the deletions of the items in it, the latest first.
+    pub fn history(
+        &self,
+        uctx: ProviderUserContext,
+        limit: usize,
+    ) -> Result<Vec<Revision>, ProviderError> {
+        self.provider()
+            .borrow()
+            .directory_history(self.ctx, uctx, self.id, limit)
+    }
+
+    /// Get the names of the directories from the global root to this one, excluding the root.
+    pub fn path(&self, uctx: ProviderUserContext) -> Result<Vec<String>, ProviderError> {
+        let mut path = Vec::new();
+        let mut current = Some(*self);
+        while let Some(directory) = current {
+            path.extend(directory.name(uctx)?);
+            current = directory.parent(uctx)?;
+        }
+        path.reverse();
+        Ok(path)
+    }
 }
 
 /// The reference to a note in a specific [`Provider`].
@@ -282,6 +340,25 @@ impl<'c> NoteRef<'c> {
             .get_note_parent(self.ctx, uctx, self.id)
     }
 
+    /// Get the names of the directories from the global root to this note, excluding the root, and
+    /// the name of this note.
+    pub fn path(&self, uctx: ProviderUserContext) -> Result<Vec<String>, ProviderError> {
+        let mut path = self.parent(uctx)?.path(uctx)?;
+        path.push(self.name(uctx)?);
+        Ok(path)
+    }
+
+    /// Get the revisions of this note, the latest first.
+    pub fn history(
+        &self,
+        uctx: ProviderUserContext,
+        limit: usize,
+    ) -> Result<Vec<Revision>, ProviderError> {
+        self.provider()
+            .borrow()
+            .note_history(self.ctx, uctx, self.id, limit)
+    }
+
     /// Read this note.
     pub fn read(&self, uctx: ProviderUserContext) -> Result<Note, ProviderError> {
         self.provider().borrow().read_note(self.ctx, uctx, self.id)
@@ -332,6 +409,8 @@ impl<'c> NoteRef<'c> {
 pub struct Note {
     /// The text of the note.
     pub text: FormattedText,
+    /// The photos, videos and documents attached to the note.
+    pub attachments: Vec<Attachment>,
 }
 
 /// The data of a directory.
@@ -341,6 +420,56 @@ pub struct Directory<'c> {
     pub children: Vec<(String, ItemRef<'c>)>,
 }
 
+/// The change of an item after which a [`Revision`] has been recorded.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RevisionAction {
+    /// The text or the attachments of a note have been changed.
+    Update,
+    Rename,
+    Move,
+    Delete,
+    /// The item has been restored from another revision.
+    Restore,
+}
+
+/// The state of an item before a change.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Revision {
+    pub id: RevisionId,
+    pub item: ItemId,
+    pub action: RevisionAction,
+    /// The user who has made the change, if known.
+    pub user_id: Option<UserId>,
+    /// The local time of the change.
+    pub time: NaiveDateTime,
+    /// The parent directory of the item before the change.
+    pub parent: DirectoryId,
+    /// The name of the item before the change.
+    pub name: String,
+    /// The note before the change, or `None` for a directory.
+    pub note: Option<Note>,
+}
+
+/// A note found by [`Provider::search_notes`].
+#[derive(Debug, Clone)]
+pub struct SearchHit<'c> {
+    pub note: NoteRef<'c>,
+    /// The relevance of the note to the query: the lower, the better.
+    pub rank: f64,
+    /// A fragment of the text of the note around the matched words.
+    pub snippet: String,
+}
+
+/// The result of [`Tree::reload`].
+#[derive(Debug, Default)]
+pub struct Reload {
+    /// The items which have disappeared from the sources.
+    pub vanished: Vec<(ProviderId, ItemId)>,
+    /// The names of the providers whose sources could not be read, along with the errors. These
+    /// providers keep serving the items read before.
+    pub failed: Vec<(String, ProviderError)>,
+}
+
 /// The error returned by a [`Provider`] if some of its operations fail.
 #[non_exhaustive]
 #[derive(Debug, PartialEq)]
@@ -363,14 +492,24 @@ pub enum ProviderError {
     TargetNameAlreadyExists(String),
     /// The provider with such ID does not exist.
     NoSuchProvider(ProviderId),
+    /// The revision with the provided ID does not exist.
+    NoSuchRevision(RevisionId),
     /// Moving an item between providers is not supported.
     CrossProviderMove,
+    /// The name cannot be used by this provider.
+    InvalidName(String),
     /// SQLite error.
     SqliteError(rusqlite::Error),
+    /// Filesystem error.
+    IoError(String),
     /// Storage is corrupt.
     Corrupt { description: String },
     /// Permission denied.
     PermissionDenied,
+    /// Another provider is already mounted on the directory.
+    MountPointTaken,
+    /// A provider cannot be mounted on a directory that has items of its own.
+    MountPointNotEmpty,
 }
 
 impl Display for ProviderError {
@@ -389,10 +528,17 @@ impl Display for ProviderError {
             Self::NoSuchProvider(id) => {
                 write!(f, "Provider with ID {} does not exist", id)
             }
+            Self::NoSuchRevision(id) => write!(f, "There is no revision with ID {}", id),
             Self::CrossProviderMove => write!(f, "Cannot move an item between providers"),
+            Self::InvalidName(name) => write!(f, "Invalid name: {}", name),
             Self::SqliteError(e) => write!(f, "SQLite error: {}", e),
+            Self::IoError(e) => write!(f, "I/O error: {}", e),
             Self::Corrupt { description } => write!(f, "Database is corrupt: {}", description),
             Self::PermissionDenied => write!(f, "Permission denied"),
+            Self::MountPointTaken => write!(f, "Another provider is mounted on the directory"),
+            Self::MountPointNotEmpty => {
+                write!(f, "Cannot mount a provider on a non-empty directory")
+            }
         }
     }
 }
@@ -412,10 +558,15 @@ impl UserFacingError for ProviderError {
             Self::CannotDeleteRoot => p.cannot_delete_root(),
             Self::TargetNameAlreadyExists(ref name) => p.target_name_already_exists(name),
             Self::NoSuchProvider(_id) => STRINGS.errors.kb.no_such_provider(),
+            Self::NoSuchRevision(_id) => p.no_such_revision(),
             Self::CrossProviderMove => p.cross_provider_move(),
+            Self::InvalidName(ref name) => p.invalid_name(name),
             Self::SqliteError(_) => p.internal_error(),
+            Self::IoError(_) => p.internal_error(),
             Self::Corrupt { .. } => p.internal_error(),
             Self::PermissionDenied => p.permission_denied(),
+            Self::MountPointTaken => p.internal_error(),
+            Self::MountPointNotEmpty => p.internal_error(),
         }
     }
 }
@@ -426,17 +577,48 @@ impl From<rusqlite::Error> for ProviderError {
     }
 }
 
+impl From<std::io::Error> for ProviderError {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for ProviderError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Corrupt {
+            description: format!("cannot (de)serialize stored data: {}", e),
+        }
+    }
+}
+
+/// The mounted providers by their IDs.
+pub type ProviderMap = HashMap<ProviderId, Box<RefCell<dyn Provider + Send>>>;
+
 /// The context each provider is provided with for its operations.
 #[derive(Copy, Clone)]
 pub struct ProviderContext<'c> {
     /// The mapping that allows to get a provider by its ID.
-    pub provider_map: &'c HashMap<ProviderId, Box<RefCell<dyn Provider + Send>>>,
-    pub newsletters: &'c HashMap<String, Box<dyn Fn(&Permissions) -> bool + Send + Sync>>,
+    pub provider_map: &'c ProviderMap,
+    pub newsletters: &'c HashMap<String, AccessCheck>,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub struct ProviderUserContext {
     pub permissions: Permissions,
+    /// The user on whose behalf the operation is performed, if any. It is recorded in the history
+    /// of changes.
+    pub user_id: Option<UserId>,
+}
+
+impl ProviderUserContext {
+    /// The context of a user without any permissions, e.g. of someone who is not talking to the
+    /// bot directly.
+    pub fn anonymous() -> Self {
+        Self {
+            permissions: Permissions::default(),
+            user_id: None,
+        }
+    }
 }
 
 /// A provider and/or a storage of a subtree of directories and notes.
@@ -635,6 +817,98 @@ pub trait Provider {
         provider: ProviderId,
     ) -> Result<(), ProviderError>;
 
+    /// Find the notes whose names or texts match a query typed by the user.
+    ///
+    /// At most `limit` best matches are returned. Notes the user is not allowed to read must not
+    /// be returned. Providers that do not support search return nothing.
+    fn search_notes<'c>(
+        &self,
+        _ctx: ProviderContext<'c>,
+        _uctx: ProviderUserContext,
+        _query: &str,
+        _limit: usize,
+    ) -> Result<Vec<SearchHit<'c>>, ProviderError> {
+        Ok(Vec::new())
+    }
+
+    /// Get the revisions of a note, the latest first.
+    ///
+    /// At most `limit` revisions are returned. Providers that do not keep the history of changes
+    /// return nothing.
+    fn note_history(
+        &self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        _id: NoteId,
+        _limit: usize,
+    ) -> Result<Vec<Revision>, ProviderError> {
+        Ok(Vec::new())
+    }
+
+    /// Get the revisions of a directory and the deletions of the items in it which have not been
+    /// restored since, the latest first.
+    ///
+    /// At most `limit` revisions are returned. Providers that do not keep the history of changes
+    /// return nothing.
+    fn directory_history(
+        &self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        _id: DirectoryId,
+        _limit: usize,
+    ) -> Result<Vec<Revision>, ProviderError> {
+        Ok(Vec::new())
+    }
+
+    /// Return an item to its state in a revision.
+    ///
+    /// A deleted item is created again with the same ID. A deleted directory is restored along
+    /// with everything that has been deleted with it.
+    fn restore_revision<'c>(
+        &mut self,
+        _ctx: ProviderContext<'c>,
+        _uctx: ProviderUserContext,
+        _id: RevisionId,
+    ) -> Result<ItemRef<'c>, ProviderError> {
+        Err(ProviderError::OperationNotSupported)
+    }
+
+    /// Get the deletions of the items which can still be restored, the latest first.
+    ///
+    /// The items deleted along with a directory are not listed separately. At most `limit`
+    /// deletions are returned.
+    fn deleted_items(
+        &self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        _limit: usize,
+    ) -> Result<Vec<Revision>, ProviderError> {
+        Ok(Vec::new())
+    }
+
+    /// Forget the items deleted before the provided time, so that they cannot be restored
+    /// anymore.
+    fn purge_deleted_items(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        _deleted_before: NaiveDateTime,
+    ) -> Result<(), ProviderError> {
+        Ok(())
+    }
+
+    /// Read the items again from their source, if this provider serves a copy of them.
+    ///
+    /// Returns the items which have disappeared from the source. If reloading fails, the
+    /// provider keeps serving the items it has read before.
+    fn reload(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+    ) -> Result<Vec<ItemId>, ProviderError> {
+        Ok(Vec::new())
+    }
+
     /// Get this provider's ID.
     ///
     /// May panic before the ID is first assigned.
@@ -646,21 +920,28 @@ pub trait Provider {
 
 /// The global tree of knowledge base items.
 pub struct Tree {
-    providers: HashMap<ProviderId, Box<RefCell<dyn Provider + Send>>>,
+    db: UnsafeRc<rusqlite::Connection>,
+    providers: ProviderMap,
     root_provider: ProviderId,
-    newsletters: HashMap<String, Box<dyn Fn(&Permissions) -> bool + Send + Sync>>,
+    /// The providers mounted on the directories of the root provider.
+    mount_points: HashMap<DirectoryId, ProviderId>,
+    newsletters: HashMap<String, AccessCheck>,
 }
 
 impl Tree {
-    /// Create an example of a tree.
+    /// Create an example of a tree. The newsletter archive is mounted on the root directory named
+    /// `archive_dir`.
+    ///
+    /// Fails if `archive_dir` names a non-empty directory of the root directory.
     ///
     /// This method is temporary and its signature is subject to change.
     ///
     /// SAFETY: the caller must uphold the invariants of [`UnsafeRc`].
-    pub unsafe fn new<'a>(
+    pub unsafe fn new(
         db: UnsafeRc<rusqlite::Connection>,
-        newsletters: &[&'a dyn Newsletter],
-    ) -> (Self, HashMap<String, ProviderId>, Sink) {
+        newsletters: &[&dyn Newsletter],
+        archive_dir: &str,
+    ) -> Result<(Self, HashMap<String, ProviderId>, Sink), ProviderError> {
         let mut providers = HashMap::new();
 
         let mut root_provider: Box<RefCell<dyn Provider + Send>> = Box::new(RefCell::new(
@@ -676,90 +957,123 @@ impl Tree {
             .map(|nl| (nl.name(), nl.allowed()))
             .collect();
 
-        let uctx = ProviderUserContext {
-            permissions: Permissions::all(),
-        };
-
-        let mount_point_id = {
-            let ctx = ProviderContext {
-                provider_map: &providers,
-                newsletters: &ctx_newsletters,
-            };
-            let root_dir = providers[&root_provider_id]
-                .borrow()
-                .root_directory(
-                    ctx,
-                    ProviderUserContext {
-                        permissions: Permissions::all(),
-                    },
-                )
-                .unwrap();
-            const ARCHIVE_DIR: &str = "Архив рассылок";
-            root_dir
-                .create_directory(
-                    ProviderUserContext {
-                        permissions: Permissions::all(),
-                    },
-                    ARCHIVE_DIR,
-                )
-                .unwrap_or_else(|_| {
-                    let item_ref = root_dir
-                        .read(uctx)
-                        .unwrap()
-                        .children
-                        .iter()
-                        .find(|&(name, _)| name == ARCHIVE_DIR)
-                        .unwrap()
-                        .1;
-                    match item_ref {
-                        ItemRef::Directory(d) => d,
-                        _ => unreachable!(),
-                    }
-                })
-                .id()
+        let mut me = Self {
+            db: UnsafeRc::clone(&db),
+            providers,
+            root_provider: root_provider_id,
+            mount_points: HashMap::new(),
+            newsletters: ctx_newsletters,
         };
 
-        let mut archive_provider: Box<RefCell<dyn Provider + Send>> =
-            Box::new(RefCell::new(providers::archive::ArchiveProvider::new(
-                UnsafeRc::clone(&db),
-                newsletters.iter().copied(),
-                (root_provider_id, mount_point_id),
-            )));
-        let archive_provider_id = ProviderId::from(1);
-        archive_provider.get_mut().assign_id(archive_provider_id);
-        providers.insert(archive_provider_id, archive_provider);
-
-        {
-            let ctx = ProviderContext {
-                provider_map: &providers,
-                newsletters: &ctx_newsletters,
-            };
-            providers[&root_provider_id]
-                .borrow_mut()
-                .add_mount_point(
-                    ctx,
-                    ProviderUserContext {
-                        permissions: Permissions::all(),
-                    },
-                    mount_point_id,
-                    archive_provider_id,
-                )
-                .unwrap();
-        }
+        let mount_point_id = me.mount_point(archive_dir)?;
+        let archive_provider = providers::archive::ArchiveProvider::new(
+            UnsafeRc::clone(&db),
+            newsletters.iter().copied(),
+            (root_provider_id, mount_point_id),
+        );
+        me.mount(mount_point_id, ARCHIVE_PROVIDER_KEY, archive_provider)?;
 
-        let provider_registry = providers
+        let provider_registry = me
+            .providers
             .iter()
             .map(|(&id, provider)| (provider.borrow().name(), id))
             .collect();
 
-        let root_provider = ProviderId::from(0);
-        let me = Self {
-            providers,
-            root_provider,
-            newsletters: ctx_newsletters,
-        };
         let newsletter_sink = Sink::new(db);
-        (me, provider_registry, newsletter_sink)
+        Ok((me, provider_registry, newsletter_sink))
+    }
+
+    /// Get the database the tree is stored in, for the providers which keep their state in it.
+    pub fn db(&self) -> UnsafeRc<rusqlite::Connection> {
+        UnsafeRc::clone(&self.db)
+    }
+
+    /// Find the directory with the provided name in the root directory, creating it if needed, to
+    /// mount another provider on it.
+    pub fn mount_point(&self, name: &str) -> Result<DirectoryId, ProviderError> {
+        let uctx = ProviderUserContext {
+            permissions: Permissions::all(),
+            user_id: None,
+        };
+        let root_dir = self.root_directory_ref()?;
+        match root_dir.create_directory(uctx, name) {
+            Ok(directory) => Ok(directory.id()),
+            Err(ProviderError::TargetNameAlreadyExists(_)) => root_dir
+                .read(uctx)?
+                .children
+                .into_iter()
+                .find_map(|(child_name, item)| match item {
+                    ItemRef::Directory(directory) if child_name == name => Some(directory.id()),
+                    _ => None,
+                })
+                .ok_or_else(|| ProviderError::TargetNameAlreadyExists(name.to_owned())),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the ID of the provider mounted under the given key, registering the key if it is new.
+    ///
+    /// The IDs are kept in the database, so that the stored references to the items of a provider
+    /// (pins, links, revisions, the audit log) remain valid when the other providers are mounted
+    /// in a different order or no longer mounted.
+    fn registered_provider_id(&self, key: &str) -> rusqlite::Result<ProviderId> {
+        self.db
+            .prepare("INSERT OR IGNORE INTO kb_providers(key) VALUES (?)")?
+            .execute(params![key])?;
+        self.db
+            .prepare("SELECT id FROM kb_providers WHERE key = ?")?
+            .query_row(params![key], |row| Ok(ProviderId(row.get(0)?)))
+    }
+
+    /// Add a provider to this tree and mount it on an empty directory of the root provider.
+    ///
+    /// The provider must be created knowing its mount point, see [`Self::mount_point`]. Its ID is
+    /// the one registered for `key`, which must stay the same across restarts.
+    pub fn mount(
+        &mut self,
+        mount_dir: DirectoryId,
+        key: &str,
+        provider: impl Provider + Send + 'static,
+    ) -> Result<ProviderId, ProviderError> {
+        let uctx = ProviderUserContext {
+            permissions: Permissions::all(),
+            user_id: None,
+        };
+        if self.mount_points.contains_key(&mount_dir) {
+            return Err(ProviderError::MountPointTaken);
+        }
+        // The items of the mount point would be hidden by the mounted provider, but still found
+        // by the search.
+        let mount_dir_ref = self.make_directory_ref(self.root_provider, mount_dir)?;
+        if !mount_dir_ref.read(uctx)?.children.is_empty() {
+            return Err(ProviderError::MountPointNotEmpty);
+        }
+        let provider_id = self.registered_provider_id(key)?;
+        if self.providers.contains_key(&provider_id) {
+            return Err(ProviderError::MountPointTaken);
+        }
+        let mut provider: Box<RefCell<dyn Provider + Send>> = Box::new(RefCell::new(provider));
+        provider.get_mut().assign_id(provider_id);
+        self.providers.insert(provider_id, provider);
+
+        let ctx = ProviderContext {
+            provider_map: &self.providers,
+            newsletters: &self.newsletters,
+        };
+        let result = self.providers[&self.root_provider]
+            .borrow_mut()
+            .add_mount_point(ctx, uctx, mount_dir, provider_id);
+        if let Err(e) = result {
+            self.providers.remove(&provider_id);
+            return Err(e);
+        }
+        self.mount_points.insert(mount_dir, provider_id);
+        Ok(provider_id)
+    }
+
+    /// Get the provider mounted on a directory of the root provider, if any.
+    pub fn mounted_provider(&self, mount_dir: DirectoryId) -> Option<ProviderId> {
+        self.mount_points.get(&mount_dir).copied()
     }
 
     /// Get the root provider of this tree.
@@ -785,6 +1099,7 @@ impl Tree {
             ctx,
             ProviderUserContext {
                 permissions: Permissions::all(),
+                user_id: None,
             },
         )?;
         Ok((provider_id, directory_ref.id()))
@@ -817,6 +1132,106 @@ impl Tree {
         })
     }
 
+    /// Find the notes matching a query typed by the user in all providers.
+    ///
+    /// At most `limit` best matches are returned, the best ones first.
+    pub fn search_notes(
+        &self,
+        uctx: ProviderUserContext,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit<'_>>, ProviderError> {
+        let ctx = ProviderContext {
+            provider_map: &self.providers,
+            newsletters: &self.newsletters,
+        };
+        let mut hits = Vec::new();
+        for provider in self.providers.values() {
+            hits.extend(provider.borrow().search_notes(ctx, uctx, query, limit)?);
+        }
+        hits.sort_by(|a, b| a.rank.total_cmp(&b.rank));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    /// Return an item to its state in a revision stored by a provider.
+    pub fn restore_revision(
+        &self,
+        uctx: ProviderUserContext,
+        provider_id: ProviderId,
+        revision: RevisionId,
+    ) -> Result<ItemRef<'_>, ProviderError> {
+        let provider = self
+            .providers
+            .get(&provider_id)
+            .ok_or(ProviderError::NoSuchProvider(provider_id))?;
+        let ctx = ProviderContext {
+            provider_map: &self.providers,
+            newsletters: &self.newsletters,
+        };
+        provider.borrow_mut().restore_revision(ctx, uctx, revision)
+    }
+
+    /// Get the deletions of the items which can still be restored in all providers, the latest
+    /// first.
+    pub fn deleted_items(
+        &self,
+        uctx: ProviderUserContext,
+        limit: usize,
+    ) -> Result<Vec<(ProviderId, Revision)>, ProviderError> {
+        let ctx = ProviderContext {
+            provider_map: &self.providers,
+            newsletters: &self.newsletters,
+        };
+        let mut deleted = Vec::new();
+        for (&provider_id, provider) in &self.providers {
+            let revisions = provider.borrow().deleted_items(ctx, uctx, limit)?;
+            deleted.extend(revisions.into_iter().map(|revision| (provider_id, revision)));
+        }
+        deleted.sort_by_key(|(_, revision)| Reverse(revision.time));
+        deleted.truncate(limit);
+        Ok(deleted)
+    }
+
+    /// Forget the items deleted before the provided time in all providers.
+    pub fn purge_deleted_items(
+        &self,
+        uctx: ProviderUserContext,
+        deleted_before: NaiveDateTime,
+    ) -> Result<(), ProviderError> {
+        let ctx = ProviderContext {
+            provider_map: &self.providers,
+            newsletters: &self.newsletters,
+        };
+        for provider in self.providers.values() {
+            provider
+                .borrow_mut()
+                .purge_deleted_items(ctx, uctx, deleted_before)?;
+        }
+        Ok(())
+    }
+
+    /// Read the items of all providers again from their sources.
+    ///
+    /// A provider whose source cannot be read does not stop the others from being read.
+    pub fn reload(&self, uctx: ProviderUserContext) -> Reload {
+        let ctx = ProviderContext {
+            provider_map: &self.providers,
+            newsletters: &self.newsletters,
+        };
+        let mut result = Reload::default();
+        for (&provider_id, provider) in &self.providers {
+            let mut provider = provider.borrow_mut();
+            match provider.reload(ctx, uctx) {
+                Ok(items) => result
+                    .vanished
+                    .extend(items.into_iter().map(|item| (provider_id, item))),
+                Err(e) => result.failed.push((provider.name(), e)),
+            }
+        }
+        result
+    }
+
     /// Given provider and note IDs, make a corresponding [`NoteRef`].
     pub fn make_note_ref(
         &self,
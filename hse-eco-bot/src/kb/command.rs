@@ -1,10 +1,22 @@
 use super::Tree;
 use std::any::Any;
+use crate::audit::AuditLog;
+use crate::events::EventStorage;
+use crate::links::LinkStorage;
 use crate::newsletter::archive::Sink;
+use crate::pins::PinStorage;
+use crate::schedule::ScheduleStorage;
+use crate::user::storage::UserStorage;
 
 pub struct Context {
     pub tree: Tree,
     pub newsletter_sink: Sink,
+    pub users: UserStorage,
+    pub pins: PinStorage,
+    pub links: LinkStorage,
+    pub events: EventStorage,
+    pub schedule: ScheduleStorage,
+    pub audit: AuditLog,
 }
 
 // TODO: use enum dispatch instead of dynamic dispatch if the performance impact of the latter
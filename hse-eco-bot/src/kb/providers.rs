@@ -1,2 +1,20 @@
 pub mod archive;
 pub mod db;
+pub mod fs;
+
+/// Make an SQLite FTS5 query out of a query typed by the user.
+///
+/// Every word of the query must be present in a match, possibly as a prefix of a longer word.
+/// Returns `None` if there are no words in the query.
+pub fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<_> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
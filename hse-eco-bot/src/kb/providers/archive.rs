@@ -1,10 +1,12 @@
+use super::fts_query;
 use crate::kb::{
     Directory, DirectoryId, DirectoryRef, ItemRef, Note, NoteId, NoteRef, Provider,
-    ProviderContext, ProviderError, ProviderId, ProviderUserContext,
+    ProviderContext, ProviderError, ProviderId, ProviderUserContext, SearchHit,
 };
-use crate::message::FormattedText;
+use crate::media::attachments_from_json;
+use crate::message::{entities_from_json, FormattedText};
 use crate::newsletter::Newsletter;
-use crate::util::UnsafeRc;
+use crate::util::{NestedTransaction, UnsafeRc};
 use chrono::prelude::*;
 use rusqlite::{params, Connection};
 use std::collections::HashMap;
@@ -94,7 +96,7 @@ impl Provider for ArchiveProvider {
         uctx: ProviderUserContext,
         id: DirectoryId,
     ) -> Result<Directory<'c>, ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
         let children = if id == ROOT_DIR_ID {
             self.ids_map
                 .iter()
@@ -154,7 +156,7 @@ impl Provider for ArchiveProvider {
         _uctx: ProviderUserContext,
         id: NoteId,
     ) -> Result<DirectoryRef<'c>, ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
         let name: String = txn
             .prepare("SELECT name FROM kb_newsletters WHERE id = ?")?
             .query_row(params![id.0], |row| row.get(0))?;
@@ -189,7 +191,7 @@ impl Provider for ArchiveProvider {
         _uctx: ProviderUserContext,
         id: NoteId,
     ) -> Result<String, ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
         let timestamp_str: String = txn
             .prepare("SELECT timestamp FROM kb_newsletters WHERE id = ?")?
             .query_row(params![id.0], |row| row.get(0))?;
@@ -203,16 +205,21 @@ impl Provider for ArchiveProvider {
         uctx: ProviderUserContext,
         id: NoteId,
     ) -> Result<Note, ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
-        let (name, content): (String, String) = txn
-            .prepare("SELECT name, content FROM kb_newsletters WHERE id = ?")?
-            .query_row(params![id.0], |row| Ok((row.get(0)?, row.get(1)?)))?;
-        // TODO: entities.
+        let txn = NestedTransaction::deferred(&self.db)?;
+        let (name, content, entities, attachments): (String, String, Option<String>, _) = txn
+            .prepare(concat!(
+                "SELECT name, content, entities, attachments ",
+                "FROM kb_newsletters WHERE id = ?",
+            ))?
+            .query_row(params![id.0], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?;
         let note = Note {
             text: FormattedText {
                 raw_text: content,
-                entities: None,
+                entities: entities_from_json(entities)?,
             },
+            attachments: attachments_from_json(attachments)?,
         };
 
         if !ctx.newsletters[&name](&uctx.permissions) {
@@ -289,6 +296,50 @@ impl Provider for ArchiveProvider {
         Err(ProviderError::OperationNotSupported)
     }
 
+    fn search_notes<'c>(
+        &self,
+        ctx: ProviderContext<'c>,
+        uctx: ProviderUserContext,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit<'c>>, ProviderError> {
+        let fts_query = match fts_query(query) {
+            Some(x) => x,
+            None => return Ok(Vec::new()),
+        };
+        let allowed_names: Vec<_> = self
+            .ids_map
+            .values()
+            .filter(|&name| ctx.newsletters[name](&uctx.permissions))
+            .collect();
+        if allowed_names.is_empty() {
+            return Ok(Vec::new());
+        }
+        let allowed_names = serde_json::to_string(&allowed_names)?;
+
+        let txn = NestedTransaction::deferred(&self.db)?;
+        let hits = txn
+            .prepare(concat!(
+                "SELECT kb_newsletters.id, bm25(kb_newsletters_fts) AS score,\n",
+                "    snippet(kb_newsletters_fts, 0, '', '', '…', 16)\n",
+                "FROM kb_newsletters_fts\n",
+                "    JOIN kb_newsletters ON kb_newsletters.id = kb_newsletters_fts.rowid\n",
+                "WHERE kb_newsletters_fts MATCH ?\n",
+                "    AND kb_newsletters.name IN (SELECT value FROM json_each(?))\n",
+                "ORDER BY score LIMIT ?\n",
+            ))?
+            .query_map(params![fts_query, allowed_names, limit], |row| {
+                let id: NoteId = row.get::<_, u64>(0)?.into();
+                Ok(SearchHit {
+                    note: NoteRef::new(id, self.id(), ctx),
+                    rank: row.get(1)?,
+                    snippet: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(hits)
+    }
+
     fn add_mount_point(
         &mut self,
         _ctx: ProviderContext<'_>,
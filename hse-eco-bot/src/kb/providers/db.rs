@@ -1,9 +1,13 @@
+use super::fts_query;
 use crate::kb::{
-    Directory, DirectoryId, DirectoryRef, ItemRef, Note, NoteId, NoteRef, Provider,
-    ProviderContext, ProviderError, ProviderId, ProviderUserContext,
+    Directory, DirectoryId, DirectoryRef, ItemId, ItemRef, Note, NoteId, NoteRef, Provider,
+    ProviderContext, ProviderError, ProviderId, ProviderUserContext, Revision, RevisionAction,
+    RevisionId, SearchHit,
 };
-use crate::message::FormattedText;
-use crate::util::UnsafeRc;
+use crate::media::{attachments_from_json, attachments_to_json};
+use crate::message::{entities_from_json, entities_to_json, FormattedText};
+use crate::util::{NestedTransaction, UnsafeRc};
+use chrono::NaiveDateTime;
 use rusqlite::{params, Connection};
 use std::collections::HashMap;
 
@@ -47,7 +51,7 @@ where
         }
 
         if let Some(rusqlite::ErrorCode::ConstraintViolation) = e.sqlite_error_code() {
-            if let Some(ref err) = e.sqlite_error() {
+            if let Some(err) = e.sqlite_error() {
                 const FOREIGN_KEY_VIOLATION: i32 = 787;
                 const UNIQUE_VIOLATION: i32 = 2067;
                 match err.extended_code {
@@ -88,6 +92,8 @@ macro_rules! wrap {
     };
 }
 
+mod history;
+
 impl Provider for DbProvider {
     fn name(&self) -> String {
         String::from("db")
@@ -109,13 +115,11 @@ impl Provider for DbProvider {
         note: Note,
         name: &str,
     ) -> Result<NoteRef<'c>, ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
-        // TODO: entity serialization.
-        txn.prepare(concat!(
-            "INSERT INTO kb_notes(content) VALUES (?);\n",
-            "SELECT last_insert_rowid;\n"
-        ))?
-        .execute(params![&note.text.raw_text])?;
+        let entities = entities_to_json(&note.text.entities)?;
+        let attachments = attachments_to_json(&note.attachments)?;
+        let txn = NestedTransaction::deferred(&self.db)?;
+        txn.prepare("INSERT INTO kb_notes(content, entities, attachments) VALUES (?, ?, ?)")?
+            .execute(params![&note.text.raw_text, entities, attachments])?;
         let note_raw_id = txn.last_insert_rowid() as u64;
 
         txn.prepare(
@@ -139,8 +143,8 @@ impl Provider for DbProvider {
         target: DirectoryId,
         name: &str,
     ) -> Result<DirectoryRef<'c>, ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
-        txn.prepare(concat!("INSERT INTO kb_dirs VALUES (NULL)\n",))?
+        let txn = NestedTransaction::deferred(&self.db)?;
+        txn.prepare("INSERT INTO kb_dirs VALUES (NULL)\n")?
             .execute(params![])?;
         let dir_raw_id = txn.last_insert_rowid() as u64;
 
@@ -177,7 +181,7 @@ impl Provider for DbProvider {
             return provider.root_directory(ctx, uctx)?.read(uctx);
         }
 
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
         let mut result = Directory {
             children: Vec::new(),
         };
@@ -227,7 +231,7 @@ impl Provider for DbProvider {
             return Ok(None);
         }
 
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
         let parent: DirectoryId = txn
             .prepare("SELECT parent_id FROM kb_dir_children WHERE child_id = ?")?
             .query_row(params![u64::from(id)], |row| Ok(u64::into(row.get(0)?)))
@@ -249,7 +253,7 @@ impl Provider for DbProvider {
         _uctx: ProviderUserContext,
         id: NoteId,
     ) -> Result<DirectoryRef<'c>, ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
         let parent: DirectoryId = txn
             .prepare("SELECT parent_id FROM kb_note_children WHERE child_id = ?")?
             .query_row(params![u64::from(id)], |row| Ok(u64::into(row.get(0)?)))
@@ -275,7 +279,7 @@ impl Provider for DbProvider {
             return Ok(None);
         }
 
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
         let parent_name = txn
             .prepare("SELECT child_name FROM kb_dir_children WHERE child_id = ?")?
             .query_row(params![u64::from(id)], |row| row.get(0))
@@ -293,7 +297,7 @@ impl Provider for DbProvider {
         _uctx: ProviderUserContext,
         id: NoteId,
     ) -> Result<String, ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
         let parent_name = txn
             .prepare("SELECT child_name FROM kb_note_children WHERE child_id = ?")?
             .query_row(params![u64::from(id)], |row| row.get(0))
@@ -311,10 +315,12 @@ impl Provider for DbProvider {
         _uctx: ProviderUserContext,
         id: NoteId,
     ) -> Result<Note, ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
-        let note_text = txn
-            .prepare("SELECT content FROM kb_notes WHERE id = ?")?
-            .query_row(params![u64::from(id)], |row| row.get(0))
+        let txn = NestedTransaction::deferred(&self.db)?;
+        let (note_text, entities, attachments) = txn
+            .prepare("SELECT content, entities, attachments FROM kb_notes WHERE id = ?")?
+            .query_row(params![u64::from(id)], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
             .map_err(wrap![
                 fk => ?,
                 unique => ?,
@@ -323,22 +329,26 @@ impl Provider for DbProvider {
         Ok(Note {
             text: FormattedText {
                 raw_text: note_text,
-                entities: None,
+                entities: entities_from_json(entities)?,
             },
+            attachments: attachments_from_json(attachments)?,
         })
     }
 
     fn update_note(
         &mut self,
         _ctx: ProviderContext<'_>,
-        _uctx: ProviderUserContext,
+        uctx: ProviderUserContext,
         id: NoteId,
         note: Note,
     ) -> Result<(), ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
+        let entities = entities_to_json(&note.text.entities)?;
+        let attachments = attachments_to_json(&note.attachments)?;
+        let txn = NestedTransaction::deferred(&self.db)?;
+        history::record_note(&txn, uctx, RevisionAction::Update, id)?;
         let num_rows_affected = txn
-            .prepare("UPDATE kb_notes SET content = ? WHERE id = ?")?
-            .execute(params![note.text.raw_text, u64::from(id)])?;
+            .prepare("UPDATE kb_notes SET content = ?, entities = ?, attachments = ? WHERE id = ?")?
+            .execute(params![note.text.raw_text, entities, attachments, u64::from(id)])?;
         match num_rows_affected {
             0 => Err(ProviderError::NoSuchNote(id)),
             1 => {
@@ -352,10 +362,11 @@ impl Provider for DbProvider {
     fn delete_note(
         &mut self,
         _ctx: ProviderContext<'_>,
-        _uctx: ProviderUserContext,
+        uctx: ProviderUserContext,
         id: NoteId,
     ) -> Result<(), ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
+        history::record_note(&txn, uctx, RevisionAction::Delete, id)?;
         let num_rows_affected = txn
             .prepare("DELETE FROM kb_notes WHERE id = ?")?
             .execute(params![u64::from(id)])?;
@@ -372,7 +383,7 @@ impl Provider for DbProvider {
     fn delete_directory(
         &mut self,
         _ctx: ProviderContext<'_>,
-        _uctx: ProviderUserContext,
+        uctx: ProviderUserContext,
         id: DirectoryId,
     ) -> Result<(), ProviderError> {
         if id == DirectoryId(0) {
@@ -381,16 +392,18 @@ impl Provider for DbProvider {
         if self.mount_points.contains_key(&id) {
             return Err(ProviderError::OperationNotSupported);
         }
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
+        history::record_subtree_deletion(&txn, uctx, id)?;
+        // The notes are deleted first, because deleting the directories removes the links between
+        // them and the notes.
+        txn.prepare(include_str!("db/sql/delete_dir_2.sql"))?
+            .execute(params![u64::from(id)])?;
         let num_dirs_affected = txn
             .prepare(include_str!("db/sql/delete_dir_1.sql"))?
             .execute(params![u64::from(id)])?;
-        match num_dirs_affected {
-            0 => return Err(ProviderError::NoSuchDirectory(id)),
-            _ => (),
+        if num_dirs_affected == 0 {
+            return Err(ProviderError::NoSuchDirectory(id));
         }
-        txn.prepare(include_str!("db/sql/delete_dir_2.sql"))?
-            .execute(params![u64::from(id)])?;
         txn.commit()?;
         Ok(())
     }
@@ -398,7 +411,7 @@ impl Provider for DbProvider {
     fn rename_directory(
         &mut self,
         _ctx: ProviderContext<'_>,
-        _uctx: ProviderUserContext,
+        uctx: ProviderUserContext,
         id: DirectoryId,
         new_name: &str,
     ) -> Result<(), ProviderError> {
@@ -408,7 +421,8 @@ impl Provider for DbProvider {
         if self.mount_points.contains_key(&id) {
             return Err(ProviderError::OperationNotSupported);
         }
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
+        history::record_directory(&txn, uctx, RevisionAction::Rename, id)?;
         let num_rows_affected = txn
             .prepare("UPDATE kb_dir_children SET child_name = ?1 WHERE child_id = ?2")?
             .execute(params![new_name, u64::from(id)])
@@ -430,11 +444,12 @@ impl Provider for DbProvider {
     fn rename_note(
         &mut self,
         _ctx: ProviderContext<'_>,
-        _uctx: ProviderUserContext,
+        uctx: ProviderUserContext,
         id: NoteId,
         new_name: &str,
     ) -> Result<(), ProviderError> {
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
+        history::record_note(&txn, uctx, RevisionAction::Rename, id)?;
         let num_rows_affected = txn
             .prepare("UPDATE kb_note_children SET child_name = ?1 WHERE child_id = ?2")?
             .execute(params![new_name, u64::from(id)])
@@ -470,10 +485,7 @@ impl Provider for DbProvider {
 
         // Immediate transaction is needed because we need to ensure no writes
         // occur between the `check ancestors` read operation and `move directory` write operation.
-        let txn = rusqlite::Transaction::new_unchecked(
-            &self.db,
-            rusqlite::TransactionBehavior::Immediate,
-        )?;
+        let txn = NestedTransaction::immediate(&self.db)?;
         let would_create_loop = txn
             .prepare(include_str!("db/sql/check_ancestors.sql"))?
             .query_row(params![u64::from(destination), u64::from(id)], |row| {
@@ -487,6 +499,7 @@ impl Provider for DbProvider {
         if would_create_loop {
             return Err(ProviderError::WouldCreateLoop);
         }
+        history::record_directory(&txn, uctx, RevisionAction::Move, id)?;
         let num_rows_affected = txn
             .prepare("UPDATE kb_dir_children SET parent_id = ?1 WHERE child_id = ?2")?
             .execute(params![u64::from(destination), u64::from(id)])
@@ -513,7 +526,8 @@ impl Provider for DbProvider {
         destination: DirectoryId,
     ) -> Result<(), ProviderError> {
         let name = self.get_note_name(ctx, uctx, id)?;
-        let txn = self.db.unchecked_transaction()?;
+        let txn = NestedTransaction::deferred(&self.db)?;
+        history::record_note(&txn, uctx, RevisionAction::Move, id)?;
         let num_rows_affected = txn
             .prepare("UPDATE kb_note_children SET parent_id = ?1 WHERE child_id = ?2")?
             .execute(params![u64::from(destination), u64::from(id)])
@@ -532,6 +546,107 @@ impl Provider for DbProvider {
         }
     }
 
+    fn search_notes<'c>(
+        &self,
+        ctx: ProviderContext<'c>,
+        _uctx: ProviderUserContext,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit<'c>>, ProviderError> {
+        let fts_query = match fts_query(query) {
+            Some(x) => x,
+            None => return Ok(Vec::new()),
+        };
+        let txn = NestedTransaction::deferred(&self.db)?;
+        // The matches in the name of a note are much more relevant than those in its text.
+        let hits = txn
+            .prepare(concat!(
+                "SELECT rowid, bm25(kb_notes_fts, 10.0, 1.0) AS score,\n",
+                "    snippet(kb_notes_fts, 1, '', '', '…', 16)\n",
+                "FROM kb_notes_fts WHERE kb_notes_fts MATCH ?\n",
+                "ORDER BY score LIMIT ?\n",
+            ))?
+            .query_map(params![fts_query, limit], |row| {
+                let id: NoteId = row.get::<_, u64>(0)?.into();
+                Ok(SearchHit {
+                    note: NoteRef::new(id, self.id(), ctx),
+                    rank: row.get(1)?,
+                    snippet: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(hits)
+    }
+
+    fn note_history(
+        &self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        id: NoteId,
+        limit: usize,
+    ) -> Result<Vec<Revision>, ProviderError> {
+        let txn = NestedTransaction::deferred(&self.db)?;
+        history::note_history(&txn, id, limit)
+    }
+
+    fn directory_history(
+        &self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        id: DirectoryId,
+        limit: usize,
+    ) -> Result<Vec<Revision>, ProviderError> {
+        let txn = NestedTransaction::deferred(&self.db)?;
+        history::directory_history(&txn, id, limit)
+    }
+
+    fn restore_revision<'c>(
+        &mut self,
+        ctx: ProviderContext<'c>,
+        uctx: ProviderUserContext,
+        id: RevisionId,
+    ) -> Result<ItemRef<'c>, ProviderError> {
+        let txn = NestedTransaction::immediate(&self.db)?;
+        let revision = history::get(&txn, id)?.ok_or(ProviderError::NoSuchRevision(id))?;
+        let item = match revision.item {
+            ItemId::Note(note_id) => {
+                history::restore_note(&txn, uctx, &revision, note_id)?;
+                ItemRef::Note(NoteRef::new(note_id, self.id(), ctx))
+            }
+            ItemId::Directory(dir_id) => {
+                if self.mount_points.contains_key(&dir_id) {
+                    return Err(ProviderError::OperationNotSupported);
+                }
+                history::restore_directory(&txn, uctx, &revision, dir_id)?;
+                ItemRef::Directory(DirectoryRef::new(dir_id, self.id(), ctx))
+            }
+        };
+        txn.commit()?;
+        Ok(item)
+    }
+
+    fn deleted_items(
+        &self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        limit: usize,
+    ) -> Result<Vec<Revision>, ProviderError> {
+        let txn = NestedTransaction::deferred(&self.db)?;
+        history::deleted_items(&txn, limit)
+    }
+
+    fn purge_deleted_items(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        deleted_before: NaiveDateTime,
+    ) -> Result<(), ProviderError> {
+        let txn = NestedTransaction::deferred(&self.db)?;
+        history::purge_deleted_items(&txn, deleted_before)?;
+        txn.commit()?;
+        Ok(())
+    }
+
     fn add_mount_point(
         &mut self,
         _ctx: ProviderContext<'_>,
@@ -0,0 +1,353 @@
+//! The history of changes of the items stored by [`DbProvider`](super::DbProvider).
+//!
+//! The state of an item is recorded as a revision right before it is changed, in the same
+//! transaction as the change.
+
+use super::{wrap_sqlite_error, FailureMap};
+use crate::events::{time_from_sql, time_to_sql};
+use crate::kb::{
+    DirectoryId, ItemId, Note, NoteId, ProviderError, ProviderUserContext, Revision,
+    RevisionAction, RevisionId,
+};
+use crate::media::attachments_from_json;
+use crate::message::{entities_from_json, FormattedText};
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use teloxide::types::UserId;
+
+const NOTE: &str = "note";
+const DIRECTORY: &str = "directory";
+
+fn action_to_sql(action: RevisionAction) -> &'static str {
+    match action {
+        RevisionAction::Update => "update",
+        RevisionAction::Rename => "rename",
+        RevisionAction::Move => "move",
+        RevisionAction::Delete => "delete",
+        RevisionAction::Restore => "restore",
+    }
+}
+
+fn action_from_sql(action: &str) -> Result<RevisionAction, ProviderError> {
+    match action {
+        "update" => Ok(RevisionAction::Update),
+        "rename" => Ok(RevisionAction::Rename),
+        "move" => Ok(RevisionAction::Move),
+        "delete" => Ok(RevisionAction::Delete),
+        "restore" => Ok(RevisionAction::Restore),
+        _ => Err(ProviderError::Corrupt {
+            description: format!("unknown revision action: {}", action),
+        }),
+    }
+}
+
+/// Read a revision from a row with the columns `id, kind, item, action, user_id, time, parent_id,
+/// name, content, entities, attachments`.
+fn revision_from_row(row: &Row<'_>) -> Result<Revision, ProviderError> {
+    let kind: String = row.get(1)?;
+    let item: u64 = row.get(2)?;
+    let (item, note) = match kind.as_str() {
+        NOTE => {
+            let note = Note {
+                text: FormattedText {
+                    raw_text: row.get(8)?,
+                    entities: entities_from_json(row.get(9)?)?,
+                },
+                attachments: attachments_from_json(row.get(10)?)?,
+            };
+            (ItemId::Note(item.into()), Some(note))
+        }
+        DIRECTORY => (ItemId::Directory(item.into()), None),
+        _ => {
+            return Err(ProviderError::Corrupt {
+                description: format!("unknown kind of revision: {}", kind),
+            })
+        }
+    };
+    Ok(Revision {
+        id: row.get::<_, u64>(0)?.into(),
+        item,
+        action: action_from_sql(&row.get::<_, String>(3)?)?,
+        user_id: row.get::<_, Option<u64>>(4)?.map(UserId),
+        time: time_from_sql(row, 5)?,
+        parent: row.get::<_, u64>(6)?.into(),
+        name: row.get(7)?,
+        note,
+    })
+}
+
+fn now() -> String {
+    time_to_sql(chrono::Local::now().naive_local())
+}
+
+/// Record the state of a note before it is changed.
+///
+/// Returns the number of recorded revisions, i.e. 0 if there is no such note.
+pub fn record_note(
+    db: &Connection,
+    uctx: ProviderUserContext,
+    action: RevisionAction,
+    id: NoteId,
+) -> rusqlite::Result<usize> {
+    db.prepare(concat!(
+        "INSERT INTO kb_revisions(\n",
+        "    kind, item, action, user_id, time, parent_id, name, content, entities, attachments\n",
+        ")\n",
+        "SELECT 'note', id, ?2, ?3, ?4, parent_id, child_name, content, entities, attachments\n",
+        "    FROM kb_notes JOIN kb_note_children ON kb_note_children.child_id = kb_notes.id\n",
+        "    WHERE id = ?1\n",
+    ))?
+    .execute(params![
+        u64::from(id),
+        action_to_sql(action),
+        uctx.user_id.map(|user_id| user_id.0),
+        now(),
+    ])
+}
+
+/// Record the state of a directory before it is changed.
+///
+/// Returns the number of recorded revisions, i.e. 0 if there is no such directory.
+pub fn record_directory(
+    db: &Connection,
+    uctx: ProviderUserContext,
+    action: RevisionAction,
+    id: DirectoryId,
+) -> rusqlite::Result<usize> {
+    db.prepare(concat!(
+        "INSERT INTO kb_revisions(kind, item, action, user_id, time, parent_id, name)\n",
+        "SELECT 'directory', child_id, ?2, ?3, ?4, parent_id, child_name\n",
+        "    FROM kb_dir_children\n",
+        "    WHERE child_id = ?1\n",
+    ))?
+    .execute(params![
+        u64::from(id),
+        action_to_sql(action),
+        uctx.user_id.map(|user_id| user_id.0),
+        now(),
+    ])
+}
+
+/// Record the state of a directory and of everything in it before the directory is deleted.
+///
+/// The revisions of the items in the directory refer to the revision of the directory as their
+/// batch, so that they are restored along with it.
+pub fn record_subtree_deletion(
+    db: &Connection,
+    uctx: ProviderUserContext,
+    id: DirectoryId,
+) -> rusqlite::Result<()> {
+    if record_directory(db, uctx, RevisionAction::Delete, id)? == 0 {
+        return Ok(());
+    }
+    let batch = db.last_insert_rowid();
+    db.prepare(include_str!("sql/record_subtree_deletion.sql"))?
+        .execute(params![
+            u64::from(id),
+            batch,
+            uctx.user_id.map(|user_id| user_id.0),
+            now(),
+        ])?;
+    Ok(())
+}
+
+/// Get a revision by its ID.
+pub fn get(db: &Connection, id: RevisionId) -> Result<Option<Revision>, ProviderError> {
+    let mut stmt = db.prepare(concat!(
+        "SELECT id, kind, item, action, user_id, time, parent_id, name, content, entities,\n",
+        "    attachments\n",
+        "FROM kb_revisions WHERE id = ?\n",
+    ))?;
+    let mut rows = stmt.query(params![u64::from(id)])?;
+    rows.next()?.map(revision_from_row).transpose()
+}
+
+fn collect_revisions(mut rows: rusqlite::Rows<'_>) -> Result<Vec<Revision>, ProviderError> {
+    let mut revisions = Vec::new();
+    while let Some(row) = rows.next()? {
+        revisions.push(revision_from_row(row)?);
+    }
+    Ok(revisions)
+}
+
+/// Get the revisions of a note, the latest first.
+pub fn note_history(
+    db: &Connection,
+    id: NoteId,
+    limit: usize,
+) -> Result<Vec<Revision>, ProviderError> {
+    let mut stmt = db.prepare(concat!(
+        "SELECT id, kind, item, action, user_id, time, parent_id, name, content, entities,\n",
+        "    attachments\n",
+        "FROM kb_revisions WHERE kind = 'note' AND item = ?\n",
+        "ORDER BY id DESC LIMIT ?\n",
+    ))?;
+    let rows = stmt.query(params![u64::from(id), limit])?;
+    collect_revisions(rows)
+}
+
+/// Get the revisions of a directory and the deletions of the items in it which have not been
+/// restored since, the latest first.
+pub fn directory_history(
+    db: &Connection,
+    id: DirectoryId,
+    limit: usize,
+) -> Result<Vec<Revision>, ProviderError> {
+    let mut stmt = db.prepare(include_str!("sql/directory_history.sql"))?;
+    let rows = stmt.query(params![u64::from(id), limit])?;
+    collect_revisions(rows)
+}
+
+/// Get the deletions of the items which have not been restored since, the latest first.
+///
+/// The items deleted along with a directory are not listed separately.
+pub fn deleted_items(db: &Connection, limit: usize) -> Result<Vec<Revision>, ProviderError> {
+    let mut stmt = db.prepare(include_str!("sql/deleted_items.sql"))?;
+    let rows = stmt.query(params![limit])?;
+    collect_revisions(rows)
+}
+
+/// Forget the items deleted before the provided time along with their history, so that they
+/// cannot be restored anymore.
+pub fn purge_deleted_items(db: &Connection, deleted_before: NaiveDateTime) -> rusqlite::Result<()> {
+    db.prepare(include_str!("sql/purge_deleted_1.sql"))?
+        .execute(params![time_to_sql(deleted_before)])?;
+    // The earlier revisions of the purged items are only needed to restore them.
+    db.prepare(include_str!("sql/purge_deleted_2.sql"))?
+        .execute(params![])?;
+    Ok(())
+}
+
+/// Get the directory in which an item is restored: its former parent, or the root directory if
+/// the parent no longer exists.
+fn restore_target(db: &Connection, revision: &Revision) -> rusqlite::Result<DirectoryId> {
+    let parent_exists = db
+        .prepare("SELECT 1 FROM kb_dirs WHERE id = ?")?
+        .query_row(params![u64::from(revision.parent)], |_| Ok(()))
+        .optional()?
+        .is_some();
+    Ok(if parent_exists {
+        revision.parent
+    } else {
+        DirectoryId::from(0)
+    })
+}
+
+/// Return a note to its state in a revision, creating it again if it has been deleted.
+///
+/// If the former parent of the note no longer exists, the note is restored in the root
+/// directory.
+pub fn restore_note(
+    db: &Connection,
+    uctx: ProviderUserContext,
+    revision: &Revision,
+    id: NoteId,
+) -> Result<(), ProviderError> {
+    let target = restore_target(db, revision)?;
+    let (parent, child) = (u64::from(target), u64::from(id));
+    let map_err = wrap![
+        fk => (ProviderError::NoSuchDirectory(target)),
+        unique => (ProviderError::TargetNameAlreadyExists(revision.name.clone())),
+        empty => ?,
+    ];
+    if record_note(db, uctx, RevisionAction::Restore, id)? != 0 {
+        db.prepare(concat!(
+            "UPDATE kb_notes SET (content, entities, attachments) =\n",
+            "    (SELECT content, entities, attachments FROM kb_revisions WHERE id = ?1)\n",
+            "WHERE id = ?2\n",
+        ))?
+        .execute(params![u64::from(revision.id), child])?;
+        db.prepare(
+            "UPDATE kb_note_children SET parent_id = ?1, child_name = ?3 WHERE child_id = ?2",
+        )?
+        .execute(params![parent, child, &revision.name])
+        .map_err(map_err)?;
+    } else {
+        db.prepare(concat!(
+            "INSERT INTO kb_notes(id, content, entities, attachments)\n",
+            "SELECT item, content, entities, attachments FROM kb_revisions WHERE id = ?\n",
+        ))?
+        .execute(params![u64::from(revision.id)])?;
+        db.prepare(
+            "INSERT INTO kb_note_children(parent_id, child_id, child_name) VALUES (?, ?, ?)",
+        )?
+        .execute(params![parent, child, &revision.name])
+        .map_err(map_err)?;
+    }
+    Ok(())
+}
+
+/// Return a directory to its state in a revision, creating it again if it has been deleted.
+///
+/// If the revision is the deletion of the directory, everything deleted along with it is
+/// restored too, even if the directory itself has been restored from another revision since. If
+/// the former parent of the directory no longer exists, the directory is restored in the root
+/// directory.
+pub fn restore_directory(
+    db: &Connection,
+    uctx: ProviderUserContext,
+    revision: &Revision,
+    id: DirectoryId,
+) -> Result<(), ProviderError> {
+    let target = restore_target(db, revision)?;
+    let (parent, child) = (u64::from(target), u64::from(id));
+    let map_err = wrap![
+        fk => (ProviderError::NoSuchDirectory(target)),
+        unique => (ProviderError::TargetNameAlreadyExists(revision.name.clone())),
+        empty => ?,
+    ];
+    let exists = |sql: &str| -> rusqlite::Result<bool> {
+        Ok(db
+            .prepare(sql)?
+            .query_row(params![child], |_| Ok(()))
+            .optional()?
+            .is_some())
+    };
+    let is_stored = exists("SELECT 1 FROM kb_dirs WHERE id = ?")?;
+    let is_attached = exists("SELECT 1 FROM kb_dir_children WHERE child_id = ?")?;
+    if is_attached {
+        let would_create_loop = db
+            .prepare(include_str!("sql/check_ancestors.sql"))?
+            .query_row(params![parent, child], |row| Ok(row.get::<_, u64>(0)? != 0))?;
+        if would_create_loop {
+            return Err(ProviderError::WouldCreateLoop);
+        }
+        record_directory(db, uctx, RevisionAction::Restore, id)?;
+        db.prepare(
+            "UPDATE kb_dir_children SET parent_id = ?1, child_name = ?3 WHERE child_id = ?2",
+        )?
+        .execute(params![parent, child, &revision.name])
+        .map_err(map_err)?;
+    } else {
+        if !is_stored {
+            db.prepare("INSERT INTO kb_dirs(id) VALUES (?)")?
+                .execute(params![child])?;
+        }
+        db.prepare(
+            "INSERT INTO kb_dir_children(parent_id, child_id, child_name) VALUES (?, ?, ?)",
+        )?
+        .execute(params![parent, child, &revision.name])
+        .map_err(map_err)?;
+    }
+    if revision.action == RevisionAction::Delete {
+        // The directory may have been restored from another revision since its deletion, so the
+        // items deleted along with it are merged into what it has now. The items already present
+        // are skipped, and a name taken by a new item fails the whole restoration. The
+        // directories are created before they are filled, so that the foreign keys hold.
+        for sql in [
+            include_str!("sql/restore_dir_1.sql"),
+            include_str!("sql/restore_dir_2.sql"),
+            include_str!("sql/restore_dir_3.sql"),
+            include_str!("sql/restore_dir_4.sql"),
+        ] {
+            db.prepare(sql)?
+                .execute(params![u64::from(revision.id)])
+                .map_err(wrap![
+                    fk => ?,
+                    unique => (ProviderError::TargetNameAlreadyExists(revision.name.clone())),
+                    empty => ?,
+                ])?;
+        }
+    }
+    Ok(())
+}
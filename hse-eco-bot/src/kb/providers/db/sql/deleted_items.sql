@@ -0,0 +1,15 @@
+SELECT id, kind, item, action, user_id, time, parent_id, name, content, entities, attachments
+    FROM kb_revisions
+    WHERE id IN (
+        SELECT max(id)
+            FROM kb_revisions
+            WHERE action = 'delete'
+            GROUP BY kind, item
+    )
+        AND batch IS NULL
+        AND CASE kind
+            WHEN 'note' THEN item NOT IN (SELECT child_id FROM kb_note_children)
+            ELSE item NOT IN (SELECT child_id FROM kb_dir_children)
+        END
+    ORDER BY id DESC
+    LIMIT ?1
@@ -0,0 +1,12 @@
+SELECT id, kind, item, action, user_id, time, parent_id, name, content, entities, attachments
+    FROM kb_revisions
+    WHERE (kind = 'directory' AND item = ?1)
+        OR (
+            action = 'delete' AND batch IS NULL AND parent_id = ?1
+            AND CASE kind
+                WHEN 'note' THEN item NOT IN (SELECT child_id FROM kb_note_children)
+                ELSE item NOT IN (SELECT child_id FROM kb_dir_children)
+            END
+        )
+    ORDER BY id DESC
+    LIMIT ?2
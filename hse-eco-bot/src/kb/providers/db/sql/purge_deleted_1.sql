@@ -0,0 +1,12 @@
+WITH
+    purged(id) AS (
+        SELECT id
+            FROM kb_revisions
+            WHERE action = 'delete' AND batch IS NULL AND time < ?1
+                AND CASE kind
+                    WHEN 'note' THEN item NOT IN (SELECT child_id FROM kb_note_children)
+                    ELSE item NOT IN (SELECT child_id FROM kb_dir_children)
+                END
+    )
+DELETE FROM kb_revisions
+WHERE id IN purged OR batch IN purged
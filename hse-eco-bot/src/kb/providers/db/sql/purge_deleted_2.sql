@@ -0,0 +1,13 @@
+DELETE FROM kb_revisions
+WHERE
+    CASE kind
+        WHEN 'note' THEN item NOT IN (SELECT child_id FROM kb_note_children)
+        ELSE item NOT IN (SELECT child_id FROM kb_dir_children)
+    END
+    AND NOT EXISTS (
+        SELECT 1
+            FROM kb_revisions AS deletion
+            WHERE deletion.kind = kb_revisions.kind
+                AND deletion.item = kb_revisions.item
+                AND deletion.action = 'delete'
+    )
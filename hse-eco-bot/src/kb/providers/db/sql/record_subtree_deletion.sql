@@ -0,0 +1,18 @@
+WITH RECURSIVE
+    subdirs(id) AS (
+        VALUES(?1)
+        UNION ALL
+        SELECT child_id
+            FROM kb_dir_children, subdirs
+            WHERE kb_dir_children.parent_id = subdirs.id
+    )
+INSERT INTO kb_revisions(
+    kind, item, action, batch, user_id, time, parent_id, name, content, entities, attachments
+)
+SELECT 'directory', child_id, 'delete', ?2, ?3, ?4, parent_id, child_name, NULL, NULL, NULL
+    FROM kb_dir_children
+    WHERE child_id IN subdirs AND child_id != ?1
+UNION ALL
+SELECT 'note', child_id, 'delete', ?2, ?3, ?4, parent_id, child_name, content, entities, attachments
+    FROM kb_note_children JOIN kb_notes ON kb_notes.id = kb_note_children.child_id
+    WHERE parent_id IN subdirs
@@ -0,0 +1,4 @@
+INSERT INTO kb_dirs(id)
+SELECT item
+    FROM kb_revisions
+    WHERE batch = ?1 AND kind = 'directory' AND item NOT IN (SELECT id FROM kb_dirs)
@@ -0,0 +1,4 @@
+INSERT INTO kb_notes(id, content, entities, attachments)
+SELECT item, content, entities, attachments
+    FROM kb_revisions
+    WHERE batch = ?1 AND kind = 'note' AND item NOT IN (SELECT id FROM kb_notes)
@@ -0,0 +1,5 @@
+INSERT INTO kb_note_children(parent_id, child_id, child_name)
+SELECT parent_id, item, name
+    FROM kb_revisions
+    WHERE batch = ?1 AND kind = 'note'
+        AND item NOT IN (SELECT child_id FROM kb_note_children)
@@ -0,0 +1,800 @@
+//! The provider serving a directory tree on disk: directories become knowledge base directories
+//! and files become notes.
+//!
+//! The IDs of the items are kept in a manifest stored in the database, so that they stay the same
+//! between restarts of the bot. The provider is read-only unless requested otherwise.
+
+use crate::kb::{
+    Directory, DirectoryId, DirectoryRef, ItemId, ItemRef, Note, NoteId, NoteRef, Provider,
+    ProviderContext, ProviderError, ProviderId, ProviderUserContext, SearchHit, Tree,
+};
+use crate::message::FormattedText;
+use crate::util::UnsafeRc;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+const ROOT_DIR_ID: DirectoryId = DirectoryId(0);
+
+/// The number of characters of the beginning of a note shown as a search snippet.
+const SNIPPET_LENGTH: usize = 64;
+
+/// The number of bytes of the beginning of a file searched for the words of a query, so that large
+/// files do not slow the search down.
+const SEARCHED_TEXT_LENGTH: u64 = 64 * 1024;
+
+/// The name of the manifest file which used to be kept in the root of the tree. It is read if the
+/// database has no manifest for the tree yet. Like all the hidden files, it is not served as a
+/// note.
+pub const LEGACY_MANIFEST_FILE_NAME: &str = ".kb-manifest.json";
+
+/// The IDs assigned to the items, by their paths relative to the root of the tree.
+///
+/// The paths consist of the names of the items separated by `/`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+struct Manifest {
+    /// The ID assigned to the next new item. IDs are never reused.
+    next_id: u64,
+    directories: BTreeMap<String, u64>,
+    notes: BTreeMap<String, u64>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            // The ID 0 belongs to the root directory.
+            next_id: 1,
+            directories: BTreeMap::new(),
+            notes: BTreeMap::new(),
+        }
+    }
+}
+
+impl Manifest {
+    /// Get the ID of the item at the path, assigning a new one if the item is new or its stored ID
+    /// has already been taken by another item.
+    fn assign_id(
+        &mut self,
+        old: &BTreeMap<String, u64>,
+        used: &mut HashSet<u64>,
+        key: &str,
+    ) -> u64 {
+        let id = match old.get(key) {
+            Some(&id) if id != 0 && used.insert(id) => id,
+            _ => {
+                let id = self.next_id;
+                used.insert(id);
+                id
+            }
+        };
+        self.next_id = self.next_id.max(id + 1);
+        id
+    }
+}
+
+/// A directory or a file in the tree.
+#[derive(Debug, Clone)]
+struct Entry {
+    parent: DirectoryId,
+    name: String,
+}
+
+fn relative_key(parent_key: &str, name: &str) -> String {
+    if parent_key.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", parent_key, name)
+    }
+}
+
+/// Check that a name can be used as a file name and is not hidden.
+fn check_name(name: &str) -> Result<(), ProviderError> {
+    if name.is_empty() || name.starts_with('.') || name.contains(&['/', '\\', '\0'][..]) {
+        Err(ProviderError::InvalidName(name.to_owned()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Convert the result of a filesystem operation on an item which may have vanished from the disk.
+fn map_not_found<T>(
+    result: io::Result<T>,
+    not_found: impl FnOnce() -> ProviderError,
+) -> Result<T, ProviderError> {
+    result.map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => not_found(),
+        _ => e.into(),
+    })
+}
+
+/// Mount the directory tree at `root` on the directory named `name` in the root directory.
+///
+/// The ID of the provider and the manifest are registered under `fs:{name}`, so they do not depend
+/// on the other mounted directories. Directories with the same name cannot be mounted together.
+pub fn mount(
+    tree: &mut Tree,
+    name: &str,
+    root: PathBuf,
+    read_only: bool,
+) -> Result<ProviderId, ProviderError> {
+    let mount_point = tree.mount_point(name)?;
+    let (root_provider, _) = tree.root_provider();
+    let key = format!("fs:{}", name);
+    // SAFETY: the connection belongs to the tree, and so does the provider.
+    let provider = unsafe {
+        FsProvider::open(
+            tree.db(),
+            key.clone(),
+            root,
+            (root_provider, mount_point),
+            read_only,
+        )
+    }?;
+    tree.mount(mount_point, &key, provider)
+}
+
+pub struct FsProvider {
+    db: UnsafeRc<Connection>,
+    /// The key the provider is registered under, which is also its name.
+    key: String,
+    root: PathBuf,
+    id: Option<ProviderId>,
+    mounted_on: (ProviderId, DirectoryId),
+    read_only: bool,
+    manifest: Manifest,
+    directories: HashMap<DirectoryId, Entry>,
+    notes: HashMap<NoteId, Entry>,
+}
+
+impl FsProvider {
+    /// Scan the directory tree at `root` and update its manifest, which is stored under `key`.
+    ///
+    /// If the tree is not `read_only`, changes made in the bot are written to the disk. Only the
+    /// text of the notes is stored, without formatting, and notes with attachments cannot be
+    /// saved.
+    ///
+    /// SAFETY: the caller must uphold the invariants of [`UnsafeRc`].
+    pub unsafe fn open(
+        db: UnsafeRc<Connection>,
+        key: String,
+        root: PathBuf,
+        mounted_on: (ProviderId, DirectoryId),
+        read_only: bool,
+    ) -> Result<Self, ProviderError> {
+        let mut me = Self {
+            db,
+            key,
+            root,
+            id: None,
+            mounted_on,
+            read_only,
+            manifest: Manifest::default(),
+            directories: HashMap::new(),
+            notes: HashMap::new(),
+        };
+        me.scan()?;
+        Ok(me)
+    }
+
+    /// Read the stored manifest, or the legacy manifest file in the root of the tree if there is
+    /// none yet.
+    fn read_manifest(&self) -> Result<Manifest, ProviderError> {
+        let stored: Option<String> = self
+            .db
+            .prepare("SELECT manifest FROM kb_fs_manifests WHERE provider = ?")?
+            .query_row(params![self.key], |row| row.get(0))
+            .optional()?;
+        if let Some(contents) = stored {
+            return Ok(serde_json::from_str(&contents)?);
+        }
+        match fs::read_to_string(self.root.join(LEGACY_MANIFEST_FILE_NAME)) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Save the manifest. Failing to do so is not fatal: the IDs are kept for as long as the
+    /// provider lives.
+    fn save_manifest(&self) {
+        let result = serde_json::to_string(&self.manifest)
+            .map_err(ProviderError::from)
+            .and_then(|contents| {
+                self.db
+                    .prepare(concat!(
+                        "INSERT INTO kb_fs_manifests(provider, manifest) VALUES (?, ?) ",
+                        "ON CONFLICT(provider) DO UPDATE SET manifest = excluded.manifest",
+                    ))?
+                    .execute(params![self.key, contents])?;
+                Ok(())
+            });
+        if let Err(e) = result {
+            warn!("Cannot save the manifest of `{}`: {}", self.key, e);
+        }
+    }
+
+    /// Read the text of a note searched for the words of a query, which is only the beginning of
+    /// a large file.
+    fn read_searched_text(&self, id: NoteId) -> Result<String, ProviderError> {
+        let path = self.root.join(self.note_key(id)?);
+        let file = map_not_found(fs::File::open(path), || ProviderError::NoSuchNote(id))?;
+        let mut contents = Vec::new();
+        file.take(SEARCHED_TEXT_LENGTH).read_to_end(&mut contents)?;
+        Ok(String::from_utf8_lossy(&contents).trim().to_owned())
+    }
+
+    /// Read the whole tree from the disk, keeping the IDs of the items listed in the manifest.
+    ///
+    /// The items are replaced only once the whole tree has been read, so the provider keeps
+    /// serving the previous tree if reading fails.
+    fn scan(&mut self) -> Result<(), ProviderError> {
+        let old = self.read_manifest()?;
+        let mut manifest = Manifest {
+            next_id: old.next_id.max(1),
+            ..Manifest::default()
+        };
+        let mut directories = HashMap::new();
+        let mut notes = HashMap::new();
+        let mut used_directory_ids = HashSet::new();
+        let mut used_note_ids = HashSet::new();
+
+        let mut pending = vec![(ROOT_DIR_ID, self.root.clone(), String::new())];
+        while let Some((parent, path, parent_key)) = pending.pop() {
+            for dir_entry in fs::read_dir(&path)? {
+                let dir_entry = dir_entry?;
+                let name = match dir_entry.file_name().into_string() {
+                    Ok(name) => name,
+                    Err(name) => {
+                        warn!(
+                            "Skipping `{}`: the name is not valid UTF-8",
+                            name.to_string_lossy()
+                        );
+                        continue;
+                    }
+                };
+                if name.starts_with('.') {
+                    continue;
+                }
+                let key = relative_key(&parent_key, &name);
+                let file_type = dir_entry.file_type()?;
+                let entry = Entry { parent, name };
+                if file_type.is_dir() {
+                    let id = manifest.assign_id(&old.directories, &mut used_directory_ids, &key);
+                    manifest.directories.insert(key.clone(), id);
+                    directories.insert(DirectoryId(id), entry);
+                    pending.push((DirectoryId(id), dir_entry.path(), key));
+                } else if file_type.is_file() {
+                    let id = manifest.assign_id(&old.notes, &mut used_note_ids, &key);
+                    manifest.notes.insert(key, id);
+                    notes.insert(NoteId(id), entry);
+                }
+            }
+        }
+
+        self.directories = directories;
+        self.notes = notes;
+        let changed = manifest != old;
+        self.manifest = manifest;
+        if changed {
+            self.save_manifest();
+        }
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<(), ProviderError> {
+        if self.read_only {
+            Err(ProviderError::OperationNotSupported)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get the path of a directory relative to the root, as stored in the manifest.
+    fn directory_key(&self, id: DirectoryId) -> Result<String, ProviderError> {
+        let mut names = Vec::new();
+        let mut current = id;
+        while current != ROOT_DIR_ID {
+            let entry = self
+                .directories
+                .get(&current)
+                .ok_or(ProviderError::NoSuchDirectory(current))?;
+            names.push(entry.name.as_str());
+            current = entry.parent;
+        }
+        names.reverse();
+        Ok(names.join("/"))
+    }
+
+    fn note_key(&self, id: NoteId) -> Result<String, ProviderError> {
+        let entry = self.notes.get(&id).ok_or(ProviderError::NoSuchNote(id))?;
+        Ok(relative_key(
+            &self.directory_key(entry.parent)?,
+            &entry.name,
+        ))
+    }
+
+    fn check_name_free(&self, parent: DirectoryId, name: &str) -> Result<(), ProviderError> {
+        let taken = self
+            .directories
+            .values()
+            .chain(self.notes.values())
+            .any(|entry| entry.parent == parent && entry.name == name);
+        if taken {
+            Err(ProviderError::TargetNameAlreadyExists(name.to_owned()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check whether `id` is `ancestor` or one of its subdirectories.
+    fn is_within(&self, mut id: DirectoryId, ancestor: DirectoryId) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.directories.get(&id) {
+                Some(entry) => id = entry.parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Rename or move an item on the disk and update the manifest accordingly.
+    fn move_item(
+        &mut self,
+        old_key: String,
+        new_parent: DirectoryId,
+        new_name: &str,
+    ) -> Result<String, ProviderError> {
+        check_name(new_name)?;
+        let new_key = relative_key(&self.directory_key(new_parent)?, new_name);
+        if new_key == old_key {
+            return Ok(new_key);
+        }
+        self.check_name_free(new_parent, new_name)?;
+        fs::rename(self.root.join(&old_key), self.root.join(&new_key))?;
+
+        // The keys of everything inside a moved directory change as well.
+        let old_prefix = format!("{}/", old_key);
+        let rekey = |map: &mut BTreeMap<String, u64>| {
+            let moved: Vec<_> = map
+                .keys()
+                .filter(|key| **key == old_key || key.starts_with(&old_prefix))
+                .cloned()
+                .collect();
+            for key in moved {
+                let id = map.remove(&key).unwrap();
+                map.insert(format!("{}{}", new_key, &key[old_key.len()..]), id);
+            }
+        };
+        rekey(&mut self.manifest.directories);
+        rekey(&mut self.manifest.notes);
+        Ok(new_key)
+    }
+
+    fn forget_directory(&mut self, id: DirectoryId) {
+        let key = match self.directory_key(id) {
+            Ok(key) => key,
+            Err(_) => return,
+        };
+        let prefix = format!("{}/", key);
+        let inside = |k: &String| *k == key || k.starts_with(&prefix);
+        self.manifest.directories.retain(|k, _| !inside(k));
+        self.manifest.notes.retain(|k, _| !inside(k));
+        let removed: HashSet<_> = self
+            .directories
+            .keys()
+            .copied()
+            .filter(|&dir| self.is_within(dir, id))
+            .collect();
+        self.directories.retain(|dir, _| !removed.contains(dir));
+        self.notes
+            .retain(|_, entry| !removed.contains(&entry.parent));
+    }
+}
+
+impl Provider for FsProvider {
+    fn name(&self) -> String {
+        self.key.clone()
+    }
+
+    fn create_note<'c>(
+        &mut self,
+        ctx: ProviderContext<'c>,
+        _uctx: ProviderUserContext,
+        target: DirectoryId,
+        note: Note,
+        name: &str,
+    ) -> Result<NoteRef<'c>, ProviderError> {
+        self.check_writable()?;
+        if !note.attachments.is_empty() {
+            return Err(ProviderError::OperationNotSupported);
+        }
+        check_name(name)?;
+        let key = relative_key(&self.directory_key(target)?, name);
+        self.check_name_free(target, name)?;
+        fs::write(self.root.join(&key), &note.text.raw_text)?;
+
+        let id = self.manifest.next_id;
+        self.manifest.next_id += 1;
+        self.manifest.notes.insert(key, id);
+        self.notes.insert(
+            NoteId(id),
+            Entry {
+                parent: target,
+                name: name.to_owned(),
+            },
+        );
+        self.save_manifest();
+        Ok(NoteRef::new(NoteId(id), self.id(), ctx))
+    }
+
+    fn create_directory<'c>(
+        &mut self,
+        ctx: ProviderContext<'c>,
+        _uctx: ProviderUserContext,
+        target: DirectoryId,
+        name: &str,
+    ) -> Result<DirectoryRef<'c>, ProviderError> {
+        self.check_writable()?;
+        check_name(name)?;
+        let key = relative_key(&self.directory_key(target)?, name);
+        self.check_name_free(target, name)?;
+        fs::create_dir(self.root.join(&key))?;
+
+        let id = self.manifest.next_id;
+        self.manifest.next_id += 1;
+        self.manifest.directories.insert(key, id);
+        self.directories.insert(
+            DirectoryId(id),
+            Entry {
+                parent: target,
+                name: name.to_owned(),
+            },
+        );
+        self.save_manifest();
+        Ok(DirectoryRef::new(DirectoryId(id), self.id(), ctx))
+    }
+
+    fn root_directory<'c>(
+        &self,
+        ctx: ProviderContext<'c>,
+        _uctx: ProviderUserContext,
+    ) -> Result<DirectoryRef<'c>, ProviderError> {
+        Ok(DirectoryRef::new(ROOT_DIR_ID, self.id(), ctx))
+    }
+
+    fn read_directory<'c>(
+        &self,
+        ctx: ProviderContext<'c>,
+        _uctx: ProviderUserContext,
+        id: DirectoryId,
+    ) -> Result<Directory<'c>, ProviderError> {
+        if id != ROOT_DIR_ID && !self.directories.contains_key(&id) {
+            return Err(ProviderError::NoSuchDirectory(id));
+        }
+        let directories = self
+            .directories
+            .iter()
+            .filter(|(_, entry)| entry.parent == id)
+            .map(|(&child, entry)| {
+                let item = ItemRef::Directory(DirectoryRef::new(child, self.id(), ctx));
+                (entry.name.clone(), item)
+            });
+        let notes = self
+            .notes
+            .iter()
+            .filter(|(_, entry)| entry.parent == id)
+            .map(|(&child, entry)| {
+                let item = ItemRef::Note(NoteRef::new(child, self.id(), ctx));
+                (entry.name.clone(), item)
+            });
+        Ok(Directory {
+            children: directories.chain(notes).collect(),
+        })
+    }
+
+    fn get_directory_parent<'c>(
+        &self,
+        ctx: ProviderContext<'c>,
+        uctx: ProviderUserContext,
+        id: DirectoryId,
+    ) -> Result<Option<DirectoryRef<'c>>, ProviderError> {
+        if id == ROOT_DIR_ID {
+            let mount_dir = DirectoryRef::new(self.mounted_on.1, self.mounted_on.0, ctx);
+            return mount_dir.parent(uctx);
+        }
+        let entry = self
+            .directories
+            .get(&id)
+            .ok_or(ProviderError::NoSuchDirectory(id))?;
+        Ok(Some(DirectoryRef::new(entry.parent, self.id(), ctx)))
+    }
+
+    fn get_note_parent<'c>(
+        &self,
+        ctx: ProviderContext<'c>,
+        _uctx: ProviderUserContext,
+        id: NoteId,
+    ) -> Result<DirectoryRef<'c>, ProviderError> {
+        let entry = self.notes.get(&id).ok_or(ProviderError::NoSuchNote(id))?;
+        Ok(DirectoryRef::new(entry.parent, self.id(), ctx))
+    }
+
+    fn get_directory_name<'c>(
+        &self,
+        ctx: ProviderContext<'c>,
+        uctx: ProviderUserContext,
+        id: DirectoryId,
+    ) -> Result<Option<String>, ProviderError> {
+        if id == ROOT_DIR_ID {
+            let (provider_id, directory_id) = self.mounted_on;
+            return ctx.provider_map[&provider_id].borrow().get_directory_name(
+                ctx,
+                uctx,
+                directory_id,
+            );
+        }
+        let entry = self
+            .directories
+            .get(&id)
+            .ok_or(ProviderError::NoSuchDirectory(id))?;
+        Ok(Some(entry.name.clone()))
+    }
+
+    fn get_note_name<'c>(
+        &self,
+        _ctx: ProviderContext<'c>,
+        _uctx: ProviderUserContext,
+        id: NoteId,
+    ) -> Result<String, ProviderError> {
+        let entry = self.notes.get(&id).ok_or(ProviderError::NoSuchNote(id))?;
+        Ok(entry.name.clone())
+    }
+
+    fn read_note(
+        &self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        id: NoteId,
+    ) -> Result<Note, ProviderError> {
+        let path = self.root.join(self.note_key(id)?);
+        let text = map_not_found(fs::read_to_string(path), || ProviderError::NoSuchNote(id))?;
+        Ok(Note {
+            text: FormattedText {
+                raw_text: text.trim().to_owned(),
+                entities: None,
+            },
+            attachments: Vec::new(),
+        })
+    }
+
+    fn update_note(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        id: NoteId,
+        note: Note,
+    ) -> Result<(), ProviderError> {
+        self.check_writable()?;
+        if !note.attachments.is_empty() {
+            return Err(ProviderError::OperationNotSupported);
+        }
+        let path = self.root.join(self.note_key(id)?);
+        fs::write(path, &note.text.raw_text)?;
+        Ok(())
+    }
+
+    fn delete_note(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        id: NoteId,
+    ) -> Result<(), ProviderError> {
+        self.check_writable()?;
+        let key = self.note_key(id)?;
+        map_not_found(fs::remove_file(self.root.join(&key)), || {
+            ProviderError::NoSuchNote(id)
+        })?;
+        self.manifest.notes.remove(&key);
+        self.notes.remove(&id);
+        self.save_manifest();
+        Ok(())
+    }
+
+    fn delete_directory(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        id: DirectoryId,
+    ) -> Result<(), ProviderError> {
+        self.check_writable()?;
+        if id == ROOT_DIR_ID {
+            return Err(ProviderError::CannotDeleteRoot);
+        }
+        let key = self.directory_key(id)?;
+        map_not_found(fs::remove_dir_all(self.root.join(&key)), || {
+            ProviderError::NoSuchDirectory(id)
+        })?;
+        self.forget_directory(id);
+        self.save_manifest();
+        Ok(())
+    }
+
+    fn rename_directory(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        id: DirectoryId,
+        new_name: &str,
+    ) -> Result<(), ProviderError> {
+        self.check_writable()?;
+        if id == ROOT_DIR_ID {
+            return Err(ProviderError::CannotRenameRoot);
+        }
+        let old_key = self.directory_key(id)?;
+        let parent = self.directories[&id].parent;
+        self.move_item(old_key, parent, new_name)?;
+        self.directories.get_mut(&id).unwrap().name = new_name.to_owned();
+        self.save_manifest();
+        Ok(())
+    }
+
+    fn rename_note(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        id: NoteId,
+        new_name: &str,
+    ) -> Result<(), ProviderError> {
+        self.check_writable()?;
+        let old_key = self.note_key(id)?;
+        let parent = self.notes[&id].parent;
+        self.move_item(old_key, parent, new_name)?;
+        self.notes.get_mut(&id).unwrap().name = new_name.to_owned();
+        self.save_manifest();
+        Ok(())
+    }
+
+    fn move_directory(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        id: DirectoryId,
+        destination: DirectoryId,
+    ) -> Result<(), ProviderError> {
+        self.check_writable()?;
+        if id == ROOT_DIR_ID {
+            return Err(ProviderError::CannotMoveRoot);
+        }
+        let old_key = self.directory_key(id)?;
+        if destination != ROOT_DIR_ID && !self.directories.contains_key(&destination) {
+            return Err(ProviderError::NoSuchDirectory(destination));
+        }
+        if self.is_within(destination, id) {
+            return Err(ProviderError::WouldCreateLoop);
+        }
+        let name = self.directories[&id].name.clone();
+        self.move_item(old_key, destination, &name)?;
+        self.directories.get_mut(&id).unwrap().parent = destination;
+        self.save_manifest();
+        Ok(())
+    }
+
+    fn move_note(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        id: NoteId,
+        destination: DirectoryId,
+    ) -> Result<(), ProviderError> {
+        self.check_writable()?;
+        let old_key = self.note_key(id)?;
+        let name = self.notes[&id].name.clone();
+        self.move_item(old_key, destination, &name)?;
+        self.notes.get_mut(&id).unwrap().parent = destination;
+        self.save_manifest();
+        Ok(())
+    }
+
+    /// Find the notes whose names or texts contain all the words of the query.
+    ///
+    /// The notes are read from the disk on every search, up to [`SEARCHED_TEXT_LENGTH`] bytes
+    /// each, so this is only suitable for small trees. The matches in the names come first, then
+    /// the notes are ordered by their paths. They follow the ranked matches of other providers.
+    fn search_notes<'c>(
+        &self,
+        ctx: ProviderContext<'c>,
+        _uctx: ProviderUserContext,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit<'c>>, ProviderError> {
+        let words: Vec<_> = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut matches = Vec::new();
+        for (&id, entry) in &self.notes {
+            let text = match self.read_searched_text(id) {
+                Ok(text) => text,
+                // The file may have vanished since the tree was scanned.
+                Err(_) => continue,
+            };
+            let name = entry.name.to_lowercase();
+            let text_lowercase = text.to_lowercase();
+            let in_name = words.iter().all(|word| name.contains(word.as_str()));
+            let is_match = in_name
+                || words.iter().all(|word| {
+                    name.contains(word.as_str()) || text_lowercase.contains(word.as_str())
+                });
+            if is_match {
+                matches.push((!in_name, self.note_key(id)?, id, text));
+            }
+        }
+        matches.sort_unstable();
+        matches.truncate(limit);
+        let hits = matches
+            .into_iter()
+            .map(|(_, _, id, text)| {
+                let snippet = match text.char_indices().nth(SNIPPET_LENGTH) {
+                    Some((end, _)) => format!("{}…", &text[..end]),
+                    None => text,
+                };
+                SearchHit {
+                    note: NoteRef::new(id, self.id(), ctx),
+                    rank: 0.0,
+                    snippet,
+                }
+            })
+            .collect();
+        Ok(hits)
+    }
+
+    /// Read the tree from the disk again.
+    ///
+    /// The texts of the notes are always read from the disk, so this is only needed to pick up
+    /// the added, removed, renamed and moved files and directories.
+    fn reload(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+    ) -> Result<Vec<ItemId>, ProviderError> {
+        let old_directories: Vec<_> = self.directories.keys().copied().collect();
+        let old_notes: Vec<_> = self.notes.keys().copied().collect();
+        self.scan()?;
+        let vanished_directories = old_directories
+            .into_iter()
+            .filter(|id| !self.directories.contains_key(id))
+            .map(ItemId::Directory);
+        let vanished_notes = old_notes
+            .into_iter()
+            .filter(|id| !self.notes.contains_key(id))
+            .map(ItemId::Note);
+        Ok(vanished_directories.chain(vanished_notes).collect())
+    }
+
+    fn add_mount_point(
+        &mut self,
+        _ctx: ProviderContext<'_>,
+        _uctx: ProviderUserContext,
+        _mount_dir: DirectoryId,
+        _provider: ProviderId,
+    ) -> Result<(), ProviderError> {
+        Err(ProviderError::OperationNotSupported)
+    }
+
+    fn id(&self) -> ProviderId {
+        self.id.unwrap()
+    }
+
+    fn assign_id(&mut self, provider_id: ProviderId) {
+        self.id = Some(provider_id);
+    }
+}
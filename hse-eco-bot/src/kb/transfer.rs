@@ -0,0 +1,417 @@
+//! Importing directory trees on disk into the knowledge base and exporting the knowledge base to
+//! files.
+
+use super::{DirectoryRef, ItemRef, Note, ProviderError, ProviderUserContext, Tree};
+use crate::message::FormattedText;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use teloxide::types::{MessageEntity, MessageEntityKind};
+
+/// What to do with an imported item whose name is already taken in the target directory.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ConflictPolicy {
+    /// Fail with [`ProviderError::TargetNameAlreadyExists`] before importing anything.
+    Fail,
+    /// Import the contents of a directory into the existing directory, and replace the text of
+    /// an existing note, keeping its attachments.
+    Merge,
+}
+
+/// The format of the text of the exported notes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TextFormat {
+    Markdown,
+    /// The subset of HTML accepted by Telegram.
+    Html,
+}
+
+impl FromStr for TextFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The numbers of the imported or exported items.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct TransferStats {
+    /// The number of the created directories. The merged directories are not counted.
+    pub directories: usize,
+    pub notes: usize,
+    /// The number of the exported notes whose attachments have been left out.
+    pub notes_with_attachments: usize,
+}
+
+/// A file or a directory on disk to import, read before anything is imported.
+struct Entry {
+    name: String,
+    content: EntryContent,
+}
+
+enum EntryContent {
+    Directory(Vec<Entry>),
+    Note(Note),
+}
+
+/// Read the entries of a directory on disk along with everything in them, sorted by name. Hidden
+/// entries are skipped.
+fn read_entries(path: &Path) -> Result<Vec<Entry>, ProviderError> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| ProviderError::InvalidName(name.to_string_lossy().into_owned()))?;
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let content = if fs::metadata(&path)?.is_dir() {
+            EntryContent::Directory(read_entries(&path)?)
+        } else {
+            EntryContent::Note(read_note(&path)?)
+        };
+        entries.push(Entry { name, content });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+fn read_note(path: &Path) -> Result<Note, ProviderError> {
+    let text = fs::read_to_string(path)?;
+    Ok(Note {
+        text: FormattedText {
+            raw_text: text.trim().to_owned(),
+            entities: None,
+        },
+        attachments: Vec::new(),
+    })
+}
+
+/// Import a directory tree on disk into a knowledge base directory.
+///
+/// Each file becomes a note with the trimmed text of the file, and each subdirectory becomes a
+/// directory. Hidden files and directories are skipped. The whole tree is read before anything is
+/// imported, so nothing is imported if any file cannot be read. With [`ConflictPolicy::Fail`],
+/// nothing is imported if any name is already taken in the target directory.
+pub fn import_directory(
+    target: &DirectoryRef<'_>,
+    uctx: ProviderUserContext,
+    path: &Path,
+    policy: ConflictPolicy,
+) -> Result<TransferStats, ProviderError> {
+    let entries = read_entries(path)?;
+    if policy == ConflictPolicy::Fail {
+        let existing = target.read(uctx)?.children;
+        let taken = entries
+            .iter()
+            .find(|entry| existing.iter().any(|(name, _)| *name == entry.name));
+        if let Some(entry) = taken {
+            return Err(ProviderError::TargetNameAlreadyExists(entry.name.clone()));
+        }
+    }
+    let mut stats = TransferStats::default();
+    import_entries(target, uctx, entries, policy, &mut stats)?;
+    Ok(stats)
+}
+
+fn import_entries(
+    target: &DirectoryRef<'_>,
+    uctx: ProviderUserContext,
+    entries: Vec<Entry>,
+    policy: ConflictPolicy,
+    stats: &mut TransferStats,
+) -> Result<(), ProviderError> {
+    let existing = match policy {
+        ConflictPolicy::Fail => Vec::new(),
+        ConflictPolicy::Merge => target.read(uctx)?.children,
+    };
+    for entry in entries {
+        let existing_item = existing
+            .iter()
+            .find(|(name, _)| *name == entry.name)
+            .map(|(_, item)| item);
+        match (entry.content, existing_item) {
+            (EntryContent::Directory(entries), Some(ItemRef::Directory(directory))) => {
+                import_entries(directory, uctx, entries, policy, stats)?;
+            }
+            (EntryContent::Directory(entries), _) => {
+                let directory = target.create_directory(uctx, &entry.name)?;
+                stats.directories += 1;
+                import_entries(&directory, uctx, entries, policy, stats)?;
+            }
+            (EntryContent::Note(imported), Some(ItemRef::Note(note))) => {
+                // Only the text is stored in the file, so the attachments are kept.
+                let mut updated = note.read(uctx)?;
+                updated.text = imported.text;
+                note.write(uctx, updated)?;
+                stats.notes += 1;
+            }
+            (EntryContent::Note(imported), _) => {
+                target.create_note(uctx, imported, &entry.name)?;
+                stats.notes += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Make the name of the file or the directory an item is exported to. The characters which
+/// cannot be used in file names are replaced, and the names which would be hidden are prefixed.
+fn file_name(name: &str) -> String {
+    let name = name.replace(&['/', '\\', '\0'][..], "_");
+    if name.is_empty() || name.starts_with('.') {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+/// Make the name of the file or the directory an item is exported to, distinct from the names
+/// already used in the same directory: the names which are only distinct before the replacement
+/// of the characters, e.g. `a/b` and `a_b`, get a numbered suffix.
+fn unique_file_name(name: &str, used_names: &mut HashSet<String>) -> String {
+    let base = file_name(name);
+    let mut unique = base.clone();
+    let mut number = 1;
+    while !used_names.insert(unique.clone()) {
+        number += 1;
+        unique = format!("{} ({})", base, number);
+    }
+    unique
+}
+
+/// Export the items of the root provider of a tree to a directory on disk, which is created if
+/// needed and must be empty.
+///
+/// Each note is written to a file with its text in the provided format, and each directory to a
+/// subdirectory. The trees mounted from other providers are left out, and so are the attachments,
+/// whose files are stored by Telegram.
+pub fn export_tree(
+    tree: &Tree,
+    uctx: ProviderUserContext,
+    path: &Path,
+    format: TextFormat,
+) -> Result<TransferStats, ProviderError> {
+    fs::create_dir_all(path)?;
+    if fs::read_dir(path)?.next().is_some() {
+        return Err(ProviderError::IoError(format!(
+            "`{}` is not empty",
+            path.display()
+        )));
+    }
+    let mut stats = TransferStats::default();
+    export_directory(
+        tree,
+        &tree.root_directory_ref()?,
+        uctx,
+        path,
+        format,
+        &mut stats,
+    )?;
+    Ok(stats)
+}
+
+fn export_directory(
+    tree: &Tree,
+    directory: &DirectoryRef<'_>,
+    uctx: ProviderUserContext,
+    path: &Path,
+    format: TextFormat,
+    stats: &mut TransferStats,
+) -> Result<(), ProviderError> {
+    let (root_provider, _) = tree.root_provider();
+    let mut used_names = HashSet::new();
+    for (name, item) in directory.read(uctx)?.children {
+        match item {
+            ItemRef::Directory(child) => {
+                if child.provider_id() != root_provider
+                    || tree.mounted_provider(child.id()).is_some()
+                {
+                    continue;
+                }
+                let item_path = path.join(unique_file_name(&name, &mut used_names));
+                fs::create_dir(&item_path)?;
+                stats.directories += 1;
+                export_directory(tree, &child, uctx, &item_path, format, stats)?;
+            }
+            ItemRef::Note(note) => {
+                if note.provider_id() != root_provider {
+                    continue;
+                }
+                let item_path = path.join(unique_file_name(&name, &mut used_names));
+                let note = note.read(uctx)?;
+                if !note.attachments.is_empty() {
+                    stats.notes_with_attachments += 1;
+                }
+                let mut file = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&item_path)?;
+                writeln!(file, "{}", render(&note.text, format))?;
+                stats.notes += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The characters escaped in the Markdown text outside of code.
+const MARKDOWN_SPECIAL: &[char] = &['\\', '*', '_', '`', '[', ']', '~', '|', '<'];
+
+fn escape(text: &str, format: TextFormat, in_code: bool) -> String {
+    match format {
+        TextFormat::Markdown if in_code => text.to_owned(),
+        TextFormat::Markdown => {
+            let mut escaped = String::with_capacity(text.len());
+            for c in text.chars() {
+                if MARKDOWN_SPECIAL.contains(&c) {
+                    escaped.push('\\');
+                }
+                escaped.push(c);
+            }
+            escaped
+        }
+        TextFormat::Html => text
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;"),
+    }
+}
+
+/// Get the markup opening and closing an entity. The entities which are not marked up, e.g.
+/// mentions or hashtags, are recognized by Telegram in the text itself.
+fn markup(kind: &MessageEntityKind, format: TextFormat) -> (String, String) {
+    use MessageEntityKind::*;
+
+    let (open, close) = match (format, kind) {
+        (TextFormat::Markdown, Bold) => ("**", "**"),
+        (TextFormat::Markdown, Italic) => ("_", "_"),
+        (TextFormat::Markdown, Underline) => ("<u>", "</u>"),
+        (TextFormat::Markdown, Strikethrough) => ("~~", "~~"),
+        (TextFormat::Markdown, Spoiler) => ("||", "||"),
+        (TextFormat::Markdown, Code) => ("`", "`"),
+        (TextFormat::Markdown, Pre { language }) => {
+            let language = language.as_deref().unwrap_or_default();
+            return (format!("```{}\n", language), String::from("\n```"));
+        }
+        (TextFormat::Markdown, TextLink { url }) => {
+            return (String::from("["), format!("]({})", url));
+        }
+        (TextFormat::Markdown, TextMention { user }) => {
+            return (String::from("["), format!("](tg://user?id={})", user.id.0));
+        }
+        (TextFormat::Html, Bold) => ("<b>", "</b>"),
+        (TextFormat::Html, Italic) => ("<i>", "</i>"),
+        (TextFormat::Html, Underline) => ("<u>", "</u>"),
+        (TextFormat::Html, Strikethrough) => ("<s>", "</s>"),
+        (TextFormat::Html, Spoiler) => ("<tg-spoiler>", "</tg-spoiler>"),
+        (TextFormat::Html, Code) => ("<code>", "</code>"),
+        (TextFormat::Html, Pre { language }) => {
+            return match language {
+                Some(language) => (
+                    format!(
+                        "<pre><code class=\"language-{}\">",
+                        escape(language, format, false)
+                    ),
+                    String::from("</code></pre>"),
+                ),
+                None => (String::from("<pre>"), String::from("</pre>")),
+            };
+        }
+        (TextFormat::Html, TextLink { url }) => {
+            return (
+                format!("<a href=\"{}\">", escape(url.as_str(), format, false)),
+                String::from("</a>"),
+            );
+        }
+        (TextFormat::Html, TextMention { user }) => {
+            return (
+                format!("<a href=\"tg://user?id={}\">", user.id.0),
+                String::from("</a>"),
+            );
+        }
+        _ => ("", ""),
+    };
+    (open.to_owned(), close.to_owned())
+}
+
+fn is_code(entity: &MessageEntity) -> bool {
+    matches!(
+        entity.kind,
+        MessageEntityKind::Code | MessageEntityKind::Pre { .. }
+    )
+}
+
+/// Render a formatted text as Markdown or as HTML, marking up its entities.
+///
+/// The entities are expected to be nested properly, as Telegram sends them.
+pub fn render(text: &FormattedText, format: TextFormat) -> String {
+    // The offsets of the entities are measured in UTF-16 code units.
+    let units: Vec<u16> = text.raw_text.encode_utf16().collect();
+    let entities: Vec<(usize, usize, &MessageEntity)> = text
+        .entities
+        .iter()
+        .flatten()
+        .map(|entity| {
+            let start = entity.offset.min(units.len());
+            let end = (entity.offset + entity.length).min(units.len());
+            (start, end, entity)
+        })
+        .filter(|(start, end, _)| start < end)
+        .collect();
+
+    let mut bounds: Vec<usize> = entities
+        .iter()
+        .flat_map(|&(start, end, _)| [start, end])
+        .chain([0, units.len()])
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut rendered = String::new();
+    let mut code_depth = 0;
+    for (i, &bound) in bounds.iter().enumerate() {
+        // The entities opened later are closed first, and the longer ones are opened first.
+        let mut ending: Vec<_> = entities
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, end, _))| *end == bound)
+            .collect();
+        ending.sort_by_key(|&(index, &(start, _, _))| std::cmp::Reverse((start, index)));
+        for (_, (_, _, entity)) in ending {
+            rendered.push_str(&markup(&entity.kind, format).1);
+            if is_code(entity) {
+                code_depth -= 1;
+            }
+        }
+        let mut starting: Vec<_> = entities
+            .iter()
+            .enumerate()
+            .filter(|(_, (start, _, _))| *start == bound)
+            .collect();
+        starting.sort_by_key(|&(index, &(_, end, _))| (std::cmp::Reverse(end), index));
+        for (_, (_, _, entity)) in starting {
+            rendered.push_str(&markup(&entity.kind, format).0);
+            if is_code(entity) {
+                code_depth += 1;
+            }
+        }
+
+        if let Some(&next) = bounds.get(i + 1) {
+            let segment = String::from_utf16_lossy(&units[bound..next]);
+            rendered.push_str(&escape(&segment, format, code_depth > 0));
+        }
+    }
+    rendered
+}
@@ -0,0 +1,123 @@
+use crate::db::{FullDirectoryId, FullItemId, FullNoteId};
+use crate::pins::{item_columns, item_from_columns};
+use crate::util::UnsafeRc;
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// The prefix of the `/start` payloads referring to an item by its slug.
+const SLUG_PREFIX: &str = "kb_";
+
+/// The prefix of the `/start` payloads referring to a note by its ID, e.g. `note_0_42`.
+const NOTE_PREFIX: &str = "note_";
+
+/// The prefix of the `/start` payloads referring to a directory by its ID, e.g. `dir_0_7`.
+const DIRECTORY_PREFIX: &str = "dir_";
+
+/// The number of random bytes in a slug. A slug is twice as long in hexadecimal.
+const SLUG_BYTES: u32 = 5;
+
+/// The number of attempts to generate a slug that is not used yet.
+const MAX_SLUG_ATTEMPTS: usize = 8;
+
+/// A knowledge base item referred to by the payload of a `/start` deep link.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DeepLink {
+    /// The item with the given ID.
+    Item(FullItemId),
+    /// The item with the given slug in the [`LinkStorage`].
+    Slug(String),
+}
+
+impl DeepLink {
+    /// Parse the payload of a `/start` deep link.
+    pub fn parse(payload: &str) -> Option<Self> {
+        fn parse_ids(ids: &str) -> Option<(u64, u64)> {
+            let (provider, item) = ids.split_once('_')?;
+            Some((provider.parse().ok()?, item.parse().ok()?))
+        }
+
+        if let Some(slug) = payload.strip_prefix(SLUG_PREFIX) {
+            let is_valid = !slug.is_empty() && slug.bytes().all(|c| c.is_ascii_alphanumeric());
+            is_valid.then(|| Self::Slug(slug.to_owned()))
+        } else if let Some(ids) = payload.strip_prefix(NOTE_PREFIX) {
+            let (provider, note) = parse_ids(ids)?;
+            Some(Self::Item(FullItemId::Note(FullNoteId {
+                provider: provider.into(),
+                note: note.into(),
+            })))
+        } else if let Some(ids) = payload.strip_prefix(DIRECTORY_PREFIX) {
+            let (provider, directory) = parse_ids(ids)?;
+            Some(Self::Item(FullItemId::Directory(FullDirectoryId {
+                provider: provider.into(),
+                directory: directory.into(),
+            })))
+        } else {
+            None
+        }
+    }
+
+    /// Get the `/start` payload referring to an item by its slug.
+    pub fn slug_payload(slug: &str) -> String {
+        format!("{}{}", SLUG_PREFIX, slug)
+    }
+}
+
+/// Persistent storage of the slugs of the shareable links to the knowledge base items.
+///
+/// A slug is random rather than derived from the ID of the item, so that the links do not reveal
+/// the structure of the knowledge base. Each item gets at most one slug, which is kept forever, so
+/// the links remain valid across restarts. The provider of the item is stored by the ID registered
+/// for its key in the tree rather than by the order of mounting, see [`Tree::mount`].
+///
+/// [`Tree::mount`]: crate::kb::Tree::mount
+pub struct LinkStorage {
+    db: UnsafeRc<Connection>,
+}
+
+impl LinkStorage {
+    /// SAFETY: the caller must uphold the invariants of [`UnsafeRc`].
+    pub unsafe fn new(db: UnsafeRc<Connection>) -> Self {
+        Self { db }
+    }
+
+    /// Get the slug of an item, creating it if the item has none yet.
+    pub fn get_or_create(&self, item: FullItemId) -> rusqlite::Result<String> {
+        let (kind, provider, item) = item_columns(item);
+        let mut select = self
+            .db
+            .prepare("SELECT slug FROM kb_links WHERE kind = ? AND provider = ? AND item = ?")?;
+        let mut insert = self.db.prepare(concat!(
+            "INSERT OR IGNORE INTO kb_links(slug, kind, provider, item) ",
+            "VALUES (lower(hex(randomblob(?))), ?, ?, ?)",
+        ))?;
+        for _ in 0..MAX_SLUG_ATTEMPTS {
+            let slug = select
+                .query_row(params![kind, provider, item], |row| row.get(0))
+                .optional()?;
+            if let Some(slug) = slug {
+                return Ok(slug);
+            }
+            // Nothing is inserted if the generated slug is already used, so another one is tried.
+            insert.execute(params![SLUG_BYTES, kind, provider, item])?;
+        }
+        Err(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    /// Find the item with the given slug.
+    pub fn resolve(&self, slug: &str) -> rusqlite::Result<Option<FullItemId>> {
+        let row = self
+            .db
+            .prepare("SELECT kind, provider, item FROM kb_links WHERE slug = ?")?
+            .query_row(params![slug], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, u64>(2)?,
+                ))
+            })
+            .optional()?;
+        match row {
+            Some((kind, provider, item)) => Ok(Some(item_from_columns(&kind, provider, item)?)),
+            None => Ok(None),
+        }
+    }
+}
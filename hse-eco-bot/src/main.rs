@@ -5,30 +5,47 @@ extern crate log;
 mod tests;
 
 mod app;
+mod audit;
+mod audit_error;
 mod callback_query;
+mod cli;
+mod config;
 mod db;
 mod dispatch;
+mod event_error;
+mod events;
 mod feedback;
 mod global_state;
 mod invalid_action;
 mod kb;
+mod links;
 mod media;
 mod message;
 mod message_format_error;
 mod message_queue;
+mod migrations;
 mod newsletter;
+mod pins;
+mod schedule;
+mod schedule_error;
 mod state;
 mod strings;
+mod trash;
 mod types;
 mod ui;
 mod user;
 mod user_error;
 mod user_facing_error;
+mod user_lookup_error;
 mod util;
 
 use crate::app::App;
+use crate::cli::Command;
+use crate::config::{Config, TokenSource};
 use std::env;
 use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 /// A wrapper around [`std::env::VarError`] containing the variable name that has caused the error.
 #[derive(Debug, Clone)]
@@ -49,20 +66,85 @@ impl std::fmt::Display for EnvError {
 
 impl Error for EnvError {}
 
-/// Read the bot API token from an environment variable.
-fn read_api_token() -> Result<String, EnvError> {
-    const VAR_NAME: &'static str = "C";
-    // Wrap error to include the variable name.
-    env::var(VAR_NAME).map_err(|e| EnvError {
-        inner: e,
-        variable_name: String::from(VAR_NAME),
-    })
+/// A file that cannot be read or parsed, with the path to it.
+#[derive(Debug)]
+struct FileError {
+    inner: Box<dyn Error>,
+    path: PathBuf,
+}
+
+impl std::fmt::Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error in file `{}`: {}", self.path.display(), self.inner)
+    }
+}
+
+impl Error for FileError {}
+
+/// Read the configuration from the file, or use the default one if no file is given.
+fn read_config(path: Option<&Path>) -> Result<Config, FileError> {
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(Config::default()),
+    };
+    // Wrap error to include the path.
+    let wrap = |inner: Box<dyn Error>| FileError {
+        inner,
+        path: path.to_owned(),
+    };
+    let text = fs::read_to_string(path).map_err(|e| wrap(e.into()))?;
+    Config::parse(&text).map_err(|e| wrap(e.into()))
+}
+
+/// Read the bot API token from the source given in the configuration.
+fn read_api_token(source: &TokenSource) -> Result<String, Box<dyn Error>> {
+    match source {
+        // Wrap error to include the variable name.
+        TokenSource::Env(name) => env::var(name).map_err(|e| {
+            EnvError {
+                inner: e,
+                variable_name: name.clone(),
+            }
+            .into()
+        }),
+        TokenSource::File(path) => match fs::read_to_string(path) {
+            Ok(token) if !token.trim().is_empty() => Ok(String::from(token.trim())),
+            Ok(_) => Err(FileError {
+                inner: "the token is empty".into(),
+                path: path.clone(),
+            }
+            .into()),
+            Err(e) => Err(FileError {
+                inner: e.into(),
+                path: path.clone(),
+            }
+            .into()),
+        },
+        TokenSource::Value(token) => Ok(token.clone()),
+    }
 }
 
 async fn fallible_main() -> Result<(), Box<dyn Error>> {
     pretty_env_logger::init();
-    let app = App::new(read_api_token()?);
-    app.run().await
+    let invocation = cli::parse_invocation(env::args().skip(1))?;
+    let config = read_config(invocation.config.as_deref())?;
+    match invocation.command {
+        Command::Run => {
+            let app = App::new(read_api_token(&config.token)?, config);
+            app.run().await
+        }
+        Command::ExportAuditLog(filter) => cli::export_audit_log(&config, &filter),
+        Command::InitDb { admins } => cli::init_db(&config, &admins),
+        Command::Import {
+            source,
+            target,
+            policy,
+        } => cli::import(&config, &source, &target, policy),
+        Command::Export {
+            destination,
+            format,
+        } => cli::export(&config, &destination, format),
+    }
 }
 
 #[tokio::main]
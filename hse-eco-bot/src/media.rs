@@ -1,19 +1,24 @@
-#[derive(Debug, Clone, Eq, PartialEq)]
+use serde::{Deserialize, Serialize};
+use teloxide::types::{
+    InputFile, InputMedia, InputMediaDocument, InputMediaPhoto, InputMediaVideo,
+};
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct File {
     pub id: String,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Image {
     pub file: File,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Video {
     pub file: File,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Location {
     pub longitude: f64,
     pub latitude: f64,
@@ -26,12 +31,12 @@ impl std::fmt::Display for Location {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Document {
     pub file: File,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum LocationOrAddress {
     Location(Location),
     Address(String),
@@ -46,9 +51,49 @@ impl std::fmt::Display for LocationOrAddress {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Attachment {
     Image(Image),
     Video(Video),
     Document(Document),
 }
+
+impl Attachment {
+    /// Get the file of the attachment.
+    pub fn file(&self) -> &File {
+        match self {
+            Self::Image(image) => &image.file,
+            Self::Video(video) => &video.file,
+            Self::Document(document) => &document.file,
+        }
+    }
+
+    /// Make the media to be sent as a part of a media group.
+    pub fn input_media(&self) -> InputMedia {
+        let input_file = InputFile::file_id(self.file().id.clone());
+        match self {
+            Self::Image(_) => InputMedia::Photo(InputMediaPhoto::new(input_file)),
+            Self::Video(_) => InputMedia::Video(InputMediaVideo::new(input_file)),
+            Self::Document(_) => InputMedia::Document(InputMediaDocument::new(input_file)),
+        }
+    }
+}
+
+/// Serialize a list of attachments for storage. An empty list is represented as SQL `NULL`.
+pub fn attachments_to_json(
+    attachments: &[Attachment],
+) -> Result<Option<String>, serde_json::Error> {
+    if attachments.is_empty() {
+        Ok(None)
+    } else {
+        serde_json::to_string(attachments).map(Some)
+    }
+}
+
+/// Deserialize a list of attachments stored by [`attachments_to_json`].
+pub fn attachments_from_json(json: Option<String>) -> Result<Vec<Attachment>, serde_json::Error> {
+    match json {
+        Some(json) => serde_json::from_str(&json),
+        None => Ok(Vec::new()),
+    }
+}
@@ -1,6 +1,8 @@
+use crate::media::Attachment;
+use serde::{Deserialize, Serialize};
 use teloxide::types::{MessageEntity, ReplyMarkup};
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct FormattedText {
     pub raw_text: String,
     pub entities: Option<Vec<MessageEntity>>,
@@ -27,12 +29,61 @@ impl FormattedText {
         }
         self
     }
+
+    /// Shorten the text to at most `max_len` UTF-16 code units, ending it with an ellipsis.
+    ///
+    /// The entities past the end of the shortened text are dropped and the ones crossing it are
+    /// cut short.
+    pub fn truncate(mut self, max_len: usize) -> Self {
+        const ELLIPSIS: char = '…';
+        if self.raw_text.encode_utf16().count() <= max_len {
+            return self;
+        }
+        let limit = max_len.saturating_sub(ELLIPSIS.len_utf16());
+        let mut len = 0;
+        let mut end = 0;
+        for (index, c) in self.raw_text.char_indices() {
+            if len + c.len_utf16() > limit {
+                end = index;
+                break;
+            }
+            len += c.len_utf16();
+        }
+        self.raw_text.truncate(end);
+        self.raw_text.push(ELLIPSIS);
+        if let Some(entities) = &mut self.entities {
+            entities.retain(|ent| ent.offset < len);
+            for ent in entities.iter_mut() {
+                ent.length = ent.length.min(len - ent.offset);
+            }
+        }
+        self
+    }
+}
+
+/// Serialize message entities for storage.
+///
+/// The entities are stored in the JSON format of the Telegram Bot API, which does not depend on the
+/// internals of this bot. `None` is represented as SQL `NULL`.
+pub fn entities_to_json(
+    entities: &Option<Vec<MessageEntity>>,
+) -> Result<Option<String>, serde_json::Error> {
+    entities.as_ref().map(serde_json::to_string).transpose()
+}
+
+/// Deserialize message entities stored by [`entities_to_json`].
+pub fn entities_from_json(
+    json: Option<String>,
+) -> Result<Option<Vec<MessageEntity>>, serde_json::Error> {
+    json.as_deref().map(serde_json::from_str).transpose()
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct FormattedMessage {
     pub text: FormattedText,
     pub reply_markup: Option<ReplyMarkup>,
+    /// The media sent before the text.
+    pub attachments: Vec<Attachment>,
 }
 
 impl FormattedMessage {
@@ -40,6 +91,7 @@ impl FormattedMessage {
         Self {
             text,
             reply_markup: None,
+            attachments: Vec::new(),
         }
     }
 
@@ -47,6 +99,7 @@ impl FormattedMessage {
         Self {
             text,
             reply_markup: Some(reply_markup),
+            attachments: Vec::new(),
         }
     }
 }
@@ -0,0 +1,216 @@
+//! The schema of the database and its migrations.
+//!
+//! The version of the schema is stored in `PRAGMA user_version`: it is the number of the applied
+//! migrations. A new database is created with the latest schema by `bootstrap.sql`, and an
+//! existing one is brought to it by applying the missing migrations in order.
+
+use rusqlite::{params, Connection};
+use std::error::Error;
+use std::fmt::Display;
+
+/// The names and the scripts of the migrations, in the order they are applied. A migration is
+/// added to the end of the list along with the same change of `bootstrap.sql`.
+///
+/// The scripts are run in a transaction with the foreign key constraints disabled, so they must
+/// not contain `BEGIN` and `COMMIT` themselves.
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "0001_message_entities",
+        include_str!("migrations/0001_message_entities.sql"),
+    ),
+    ("0002_users", include_str!("migrations/0002_users.sql")),
+    (
+        "0003_dialog_states",
+        include_str!("migrations/0003_dialog_states.sql"),
+    ),
+    ("0004_roles", include_str!("migrations/0004_roles.sql")),
+    ("0005_kb_pins", include_str!("migrations/0005_kb_pins.sql")),
+    ("0006_events", include_str!("migrations/0006_events.sql")),
+    (
+        "0007_event_reminders",
+        include_str!("migrations/0007_event_reminders.sql"),
+    ),
+    (
+        "0008_scheduled_announcements",
+        include_str!("migrations/0008_scheduled_announcements.sql"),
+    ),
+    (
+        "0009_note_attachments",
+        include_str!("migrations/0009_note_attachments.sql"),
+    ),
+    (
+        "0010_newsletter_attachments",
+        include_str!("migrations/0010_newsletter_attachments.sql"),
+    ),
+    (
+        "0011_full_text_search",
+        include_str!("migrations/0011_full_text_search.sql"),
+    ),
+    (
+        "0012_kb_links",
+        include_str!("migrations/0012_kb_links.sql"),
+    ),
+    (
+        "0013_kb_revisions",
+        include_str!("migrations/0013_kb_revisions.sql"),
+    ),
+    (
+        "0014_audit_log",
+        include_str!("migrations/0014_audit_log.sql"),
+    ),
+    (
+        "0015_kb_providers",
+        include_str!("migrations/0015_kb_providers.sql"),
+    ),
+    (
+        "0016_kb_fs_manifests",
+        include_str!("migrations/0016_kb_fs_manifests.sql"),
+    ),
+];
+
+/// The version of the schema created by `bootstrap.sql`.
+pub const LATEST_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// The queries which succeed only after the corresponding migration, used to find the version of
+/// a database migrated by hand before the version has been stored in it.
+const LEGACY_MARKERS: [&str; 16] = [
+    "SELECT entities FROM kb_notes",
+    "SELECT chat_id FROM users",
+    "SELECT dialog_state FROM users",
+    "SELECT role FROM user_roles",
+    "SELECT id FROM kb_pins",
+    "SELECT id FROM events",
+    "SELECT event_id FROM event_reminders",
+    "SELECT id FROM scheduled_announcements",
+    "SELECT attachments FROM kb_notes",
+    "SELECT attachments FROM kb_newsletters",
+    "SELECT rowid FROM kb_notes_fts",
+    "SELECT slug FROM kb_links",
+    "SELECT id FROM kb_revisions",
+    "SELECT id FROM audit_log",
+    "SELECT key FROM kb_providers",
+    "SELECT manifest FROM kb_fs_manifests",
+];
+
+/// The error when the schema of the database cannot be brought to the latest version.
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database has been migrated by a newer version of the bot.
+    NewerSchema {
+        version: u32,
+    },
+    /// A migration has left rows violating the foreign key constraints.
+    ForeignKeyViolation {
+        migration: &'static str,
+    },
+    /// A migration has failed.
+    Failed {
+        migration: &'static str,
+        source: rusqlite::Error,
+    },
+    SqliteError(rusqlite::Error),
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NewerSchema { version } => write!(
+                f,
+                "the database schema version {} is newer than the latest known version {}",
+                version, LATEST_VERSION
+            ),
+            Self::ForeignKeyViolation { migration } => write!(
+                f,
+                "migration `{}` violates foreign key constraints",
+                migration
+            ),
+            Self::Failed { migration, .. } => write!(f, "migration `{}` has failed", migration),
+            Self::SqliteError(e) => write!(f, "SQLite error: {}", e),
+        }
+    }
+}
+
+impl Error for MigrationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Failed { source, .. } => Some(source),
+            Self::SqliteError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::SqliteError(e)
+    }
+}
+
+/// Get the version of the schema of a database.
+pub fn schema_version(db: &Connection) -> rusqlite::Result<u32> {
+    db.query_row("PRAGMA user_version", params![], |row| row.get(0))
+}
+
+/// Find the version of a database created before the version has been stored in it: the
+/// migrations are assumed to have been applied in order.
+fn legacy_version(db: &Connection) -> u32 {
+    LEGACY_MARKERS
+        .iter()
+        .take_while(|query| db.prepare(query).is_ok())
+        .count() as u32
+}
+
+/// Bring the schema of a database to the latest version. An empty database is created with the
+/// latest schema.
+///
+/// Each migration is applied in its own transaction, so a failed migration leaves the database
+/// at the previous version. A database with a newer schema than this version of the bot knows is
+/// left untouched.
+pub fn migrate(db: &Connection) -> Result<(), MigrationError> {
+    let mut version = schema_version(db)?;
+    if version > LATEST_VERSION {
+        return Err(MigrationError::NewerSchema { version });
+    }
+    if version == 0 {
+        let has_tables = db
+            .prepare("SELECT 1 FROM sqlite_master")?
+            .exists(params![])?;
+        if !has_tables {
+            let txn = db.unchecked_transaction()?;
+            txn.execute_batch(include_str!("bootstrap.sql"))?;
+            txn.pragma_update(None, "user_version", LATEST_VERSION)?;
+            txn.commit()?;
+            info!("Created the database schema version {}", LATEST_VERSION);
+            return Ok(());
+        }
+        version = legacy_version(db);
+        info!("Found the legacy database schema version {}", version);
+        db.pragma_update(None, "user_version", version)?;
+    }
+    if version == LATEST_VERSION {
+        return Ok(());
+    }
+
+    // The foreign key constraints cannot be disabled inside a transaction, so they are disabled
+    // for all the migrations and checked before committing each of them.
+    let foreign_keys: bool = db.query_row("PRAGMA foreign_keys", params![], |row| row.get(0))?;
+    db.pragma_update(None, "foreign_keys", false)?;
+    let result = apply_migrations(db, version);
+    db.pragma_update(None, "foreign_keys", foreign_keys)?;
+    result
+}
+
+fn apply_migrations(db: &Connection, version: u32) -> Result<(), MigrationError> {
+    for (index, &(migration, sql)) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!("Applying migration `{}`", migration);
+        let txn = db.unchecked_transaction()?;
+        txn.execute_batch(sql)
+            .map_err(|source| MigrationError::Failed { migration, source })?;
+        if txn.prepare("PRAGMA foreign_key_check")?.exists(params![])? {
+            return Err(MigrationError::ForeignKeyViolation { migration });
+        }
+        txn.pragma_update(None, "user_version", index as u32 + 1)?;
+        txn.commit()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,4 @@
+-- Store Telegram message entities (bold text, links, spoilers etc.) of notes
+-- and archived newsletters. Existing rows are left without entities.
+ALTER TABLE kb_notes ADD COLUMN entities TEXT;
+ALTER TABLE kb_newsletters ADD COLUMN entities TEXT;
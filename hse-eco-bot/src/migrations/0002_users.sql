@@ -0,0 +1,19 @@
+-- Store the users known to the bot, the IDs of the chats with them and their
+-- newsletter subscriptions.
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY,
+    chat_id INTEGER NOT NULL,
+    edit_kb BOOL NOT NULL,
+    receive_service_notifications BOOL NOT NULL,
+    receive_feedback BOOL NOT NULL,
+    admin BOOL NOT NULL,
+    manage_events BOOL NOT NULL,
+    send_global_notifications BOOL NOT NULL
+);
+
+CREATE TABLE user_subscriptions (
+    user_id INTEGER NOT NULL
+        REFERENCES users(id) ON DELETE CASCADE,
+    newsletter TEXT NOT NULL,
+    PRIMARY KEY (user_id, newsletter)
+);
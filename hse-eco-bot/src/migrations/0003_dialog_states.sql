@@ -0,0 +1,3 @@
+-- Store the states of the dialogs with users, so that they can be resumed
+-- after a restart.
+ALTER TABLE users ADD COLUMN dialog_state TEXT;
@@ -0,0 +1,74 @@
+-- Replace the `permissions` table keyed by usernames and the permission columns
+-- of `users` with named roles assigned to numeric user IDs.
+--
+-- The permissions of the users who have already started the bot are converted
+-- into the corresponding roles. The rows of the `permissions` table are keyed by
+-- usernames, whose IDs are unknown until the users start the bot, so they are
+-- converted into roles pending in `pending_roles`: the roles are granted when a
+-- user with the username is stored.
+CREATE TABLE roles (
+    name TEXT PRIMARY KEY
+);
+
+CREATE TABLE role_permissions (
+    role TEXT NOT NULL
+        REFERENCES roles(name) ON DELETE CASCADE ON UPDATE CASCADE,
+    permission TEXT NOT NULL,
+    PRIMARY KEY (role, permission)
+);
+
+CREATE TABLE user_roles (
+    user_id INTEGER NOT NULL,
+    role TEXT NOT NULL
+        REFERENCES roles(name) ON DELETE CASCADE ON UPDATE CASCADE,
+    PRIMARY KEY (user_id, role)
+);
+
+CREATE TABLE pending_roles (
+    username TEXT NOT NULL COLLATE NOCASE,
+    role TEXT NOT NULL
+        REFERENCES roles(name) ON DELETE CASCADE ON UPDATE CASCADE,
+    PRIMARY KEY (username, role)
+);
+
+INSERT INTO roles(name) VALUES
+    ('admin'), ('editor'), ('feedback'), ('service'), ('events'), ('announcer');
+INSERT INTO role_permissions(role, permission) VALUES
+    ('admin', 'edit_kb'),
+    ('admin', 'receive_service_notifications'),
+    ('admin', 'receive_feedback'),
+    ('admin', 'admin'),
+    ('admin', 'manage_events'),
+    ('admin', 'send_global_notifications'),
+    ('editor', 'edit_kb'),
+    ('feedback', 'receive_feedback'),
+    ('service', 'receive_service_notifications'),
+    ('events', 'manage_events'),
+    ('announcer', 'send_global_notifications');
+
+INSERT INTO user_roles(user_id, role) SELECT id, 'admin' FROM users WHERE admin;
+INSERT INTO user_roles(user_id, role) SELECT id, 'editor' FROM users WHERE edit_kb;
+INSERT INTO user_roles(user_id, role) SELECT id, 'feedback' FROM users WHERE receive_feedback;
+INSERT INTO user_roles(user_id, role)
+    SELECT id, 'service' FROM users WHERE receive_service_notifications;
+INSERT INTO user_roles(user_id, role) SELECT id, 'events' FROM users WHERE manage_events;
+INSERT INTO user_roles(user_id, role)
+    SELECT id, 'announcer' FROM users WHERE send_global_notifications;
+
+INSERT OR IGNORE INTO pending_roles(username, role)
+    SELECT ltrim(user, '@'), 'editor' FROM permissions WHERE edit_kb;
+INSERT OR IGNORE INTO pending_roles(username, role)
+    SELECT ltrim(user, '@'), 'feedback' FROM permissions WHERE receive_feedback;
+
+CREATE TABLE new_users (
+    id INTEGER PRIMARY KEY,
+    chat_id INTEGER NOT NULL,
+    username TEXT,
+    dialog_state TEXT
+);
+INSERT INTO new_users(id, chat_id, dialog_state) SELECT id, chat_id, dialog_state FROM users;
+DROP TABLE users;
+ALTER TABLE new_users RENAME TO users;
+CREATE INDEX users_by_username ON users(username COLLATE NOCASE);
+
+DROP TABLE permissions;
@@ -0,0 +1,10 @@
+-- Add the knowledge base items pinned to the main menu.
+CREATE TABLE kb_pins (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK (kind IN ('note', 'directory')),
+    provider INTEGER NOT NULL,
+    item INTEGER NOT NULL,
+    label TEXT,
+    position INTEGER NOT NULL,
+    UNIQUE (kind, provider, item)
+);
@@ -0,0 +1,14 @@
+-- Add the events calendar.
+-- Eco events. The times are local and formatted as `YYYY-MM-DD HH:MM:SS`, so
+-- that they are ordered lexicographically. `location` is JSON.
+CREATE TABLE events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    description_entities TEXT,
+    start_time TEXT NOT NULL,
+    end_time TEXT NOT NULL,
+    location TEXT NOT NULL
+);
+
+CREATE INDEX events_by_start_time ON events(start_time);
@@ -0,0 +1,11 @@
+-- Add the reminders about the events.
+ALTER TABLE events ADD COLUMN reminder_sent BOOL NOT NULL DEFAULT FALSE;
+
+-- Users who have asked to be reminded about an event.
+CREATE TABLE event_reminders (
+    event_id INTEGER NOT NULL
+        REFERENCES events(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL
+        REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (event_id, user_id)
+);
@@ -0,0 +1,13 @@
+-- Add the announcements scheduled to be sent later.
+-- Announcements scheduled to be sent later. `send_at` is a local time in the same
+-- format as the times of the events, `attachments` is JSON.
+CREATE TABLE scheduled_announcements (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    send_at TEXT NOT NULL,
+    text TEXT NOT NULL,
+    text_entities TEXT,
+    attachments TEXT NOT NULL,
+    audience TEXT NOT NULL
+);
+
+CREATE INDEX scheduled_announcements_by_send_at ON scheduled_announcements(send_at);
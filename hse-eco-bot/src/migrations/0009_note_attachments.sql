@@ -0,0 +1,3 @@
+-- Store the photos, videos and documents attached to notes as JSON.
+-- Existing notes are left without attachments.
+ALTER TABLE kb_notes ADD COLUMN attachments TEXT;
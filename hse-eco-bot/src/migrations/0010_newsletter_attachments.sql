@@ -0,0 +1,3 @@
+-- Store the photos, videos and documents of archived newsletters as JSON.
+-- Existing newsletters are left without attachments.
+ALTER TABLE kb_newsletters ADD COLUMN attachments TEXT;
@@ -0,0 +1,38 @@
+-- Index the names and the texts of the knowledge base notes and the texts of
+-- the archived newsletters for full-text search. The rowids of the indexes are
+-- the IDs of the notes and the newsletters, the indexes are kept up to date by
+-- triggers.
+CREATE VIRTUAL TABLE kb_notes_fts USING fts5(name, content);
+
+CREATE VIRTUAL TABLE kb_newsletters_fts USING fts5(content);
+
+CREATE TRIGGER kb_notes_fts_insert AFTER INSERT ON kb_note_children BEGIN
+    INSERT INTO kb_notes_fts(rowid, name, content)
+        SELECT new.child_id, new.child_name, content FROM kb_notes WHERE id = new.child_id;
+END;
+
+CREATE TRIGGER kb_notes_fts_rename AFTER UPDATE OF child_name ON kb_note_children BEGIN
+    UPDATE kb_notes_fts SET name = new.child_name WHERE rowid = new.child_id;
+END;
+
+CREATE TRIGGER kb_notes_fts_update AFTER UPDATE OF content ON kb_notes BEGIN
+    UPDATE kb_notes_fts SET content = new.content WHERE rowid = new.id;
+END;
+
+CREATE TRIGGER kb_notes_fts_delete AFTER DELETE ON kb_note_children BEGIN
+    DELETE FROM kb_notes_fts WHERE rowid = old.child_id;
+END;
+
+CREATE TRIGGER kb_newsletters_fts_insert AFTER INSERT ON kb_newsletters BEGIN
+    INSERT INTO kb_newsletters_fts(rowid, content) VALUES (new.id, new.content);
+END;
+
+CREATE TRIGGER kb_newsletters_fts_delete AFTER DELETE ON kb_newsletters BEGIN
+    DELETE FROM kb_newsletters_fts WHERE rowid = old.id;
+END;
+
+INSERT INTO kb_notes_fts(rowid, name, content)
+    SELECT child_id, child_name, content
+    FROM kb_note_children JOIN kb_notes ON kb_notes.id = kb_note_children.child_id;
+
+INSERT INTO kb_newsletters_fts(rowid, content) SELECT id, content FROM kb_newsletters;
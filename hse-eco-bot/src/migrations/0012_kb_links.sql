@@ -0,0 +1,8 @@
+-- Add the slugs of the shareable links to the knowledge base items.
+CREATE TABLE kb_links (
+    slug TEXT PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('note', 'directory')),
+    provider INTEGER NOT NULL,
+    item INTEGER NOT NULL,
+    UNIQUE (kind, provider, item)
+);
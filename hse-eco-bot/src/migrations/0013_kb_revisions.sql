@@ -0,0 +1,21 @@
+-- Add the history of changes of the knowledge base items.
+CREATE TABLE kb_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK (kind IN ('note', 'directory')),
+    item INTEGER NOT NULL,
+    action TEXT NOT NULL
+        CHECK (action IN ('update', 'rename', 'move', 'delete', 'restore')),
+    batch INTEGER
+        REFERENCES kb_revisions(id) ON DELETE CASCADE,
+    user_id INTEGER,
+    time TEXT NOT NULL,
+    parent_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    content TEXT,
+    entities TEXT,
+    attachments TEXT
+);
+
+CREATE INDEX kb_revisions_by_item ON kb_revisions(kind, item);
+CREATE INDEX kb_revisions_by_parent_id ON kb_revisions(parent_id);
+CREATE INDEX kb_revisions_by_batch ON kb_revisions(batch);
@@ -0,0 +1,20 @@
+-- Add the append-only log of the administrative and editorial actions.
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    time TEXT NOT NULL,
+    user_id INTEGER,
+    action TEXT NOT NULL,
+    targets TEXT NOT NULL
+);
+
+CREATE INDEX audit_log_by_user_id ON audit_log(user_id);
+CREATE INDEX audit_log_by_action ON audit_log(action);
+CREATE INDEX audit_log_by_time ON audit_log(time);
+
+CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log BEGIN
+    SELECT RAISE(ABORT, 'the audit log is append-only');
+END;
+
+CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log BEGIN
+    SELECT RAISE(ABORT, 'the audit log is append-only');
+END;
@@ -0,0 +1,11 @@
+-- Register the IDs of the knowledge base providers by the keys they are mounted
+-- under. The root provider and the newsletter archive keep their IDs. The
+-- directories on disk are registered when they are mounted next time, in the
+-- order they are listed, so they get the same IDs as before as long as the
+-- order has not changed.
+CREATE TABLE kb_providers (
+    id INTEGER PRIMARY KEY,
+    key TEXT NOT NULL UNIQUE
+);
+
+INSERT INTO kb_providers(id, key) VALUES (0, 'db'), (1, 'newsletter-archive');
@@ -0,0 +1,8 @@
+-- Keep the IDs assigned to the files and directories of the directories on
+-- disk in the database instead of a `.kb-manifest.json` file in each of them,
+-- so that read-only directories are not written to. The existing files are
+-- read when the directories are mounted next time.
+CREATE TABLE kb_fs_manifests (
+    provider TEXT PRIMARY KEY,
+    manifest TEXT NOT NULL
+);
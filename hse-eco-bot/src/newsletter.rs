@@ -1,24 +1,26 @@
+pub mod announcements;
 pub mod archive;
+pub mod event_reminders;
 pub mod feedback;
 
+use crate::config::{Config, NewsletterDefinition};
 use crate::dispatch::UserDialog;
 use crate::global_state::GlobalState;
 use crate::message_queue::MessageQueueSender;
 use crate::kb::command::Command;
-use crate::kb::{Note, ProviderId};
+use crate::kb::Note;
+use crate::media::Attachment;
 use crate::message::{FormattedMessage, FormattedText};
 use crate::state::DialogState;
 use crate::strings::STRINGS;
 use crate::types::{BotType, HandlerResult};
-use crate::ui::form::{Form, FormInput};
 use crate::user::Permissions;
-use std::any::Any;
 use std::future::Future;
+use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use teloxide::types::UserId;
-use tokio::sync::mpsc::Sender;
 
 pub trait UserFilter {
     fn should_skip_user(&self, user_id: UserId) -> bool;
@@ -34,16 +36,88 @@ impl UserFilter for NoFilter {
 
 pub struct NewsletterMessage {
     pub text: FormattedText,
+    /// The media sent before the text.
+    pub attachments: Vec<Attachment>,
     pub user_filter: Box<dyn UserFilter + Send>,
     pub tags: Option<String>,
+    /// Send the message to the users that are not subscribed to the newsletter as well.
+    pub ignore_subscriptions: bool,
 }
 
+/// Decides whether the users with the given permissions may subscribe to a newsletter.
+pub type AccessCheck = Box<dyn Fn(&Permissions) -> bool + Send + Sync>;
+
 pub trait Newsletter {
     fn name(&self) -> String;
     fn description(&self) -> String;
-    fn allowed(&self) -> Box<dyn Fn(&Permissions) -> bool + Send + Sync>;
+    fn allowed(&self) -> AccessCheck;
     fn tags(&self) -> String;
     fn wait_until_ready(&self) -> Pin<Box<dyn Future<Output = NewsletterMessage> + Send + '_>>;
+
+    /// Called after the message returned by [`Self::wait_until_ready`] has been archived and
+    /// handed out to the recipients.
+    fn message_sent(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(async {})
+    }
+}
+
+/// A newsletter whose description and tags are overridden by the configuration.
+pub struct ConfiguredNewsletter<N> {
+    inner: N,
+    definition: NewsletterDefinition,
+}
+
+impl<N: Newsletter> ConfiguredNewsletter<N> {
+    pub fn new(inner: N, config: &Config) -> Self {
+        let definition = config.newsletter(&inner.name());
+        Self { inner, definition }
+    }
+}
+
+impl<N> Deref for ConfiguredNewsletter<N> {
+    type Target = N;
+
+    fn deref(&self) -> &N {
+        &self.inner
+    }
+}
+
+impl<N> DerefMut for ConfiguredNewsletter<N> {
+    fn deref_mut(&mut self) -> &mut N {
+        &mut self.inner
+    }
+}
+
+impl<N: Newsletter> Newsletter for ConfiguredNewsletter<N> {
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    fn description(&self) -> String {
+        match &self.definition.description {
+            Some(description) => description.clone(),
+            None => self.inner.description(),
+        }
+    }
+
+    fn allowed(&self) -> AccessCheck {
+        self.inner.allowed()
+    }
+
+    fn tags(&self) -> String {
+        match &self.definition.tags {
+            Some(tags) => tags.clone(),
+            None => self.inner.tags(),
+        }
+    }
+
+    fn wait_until_ready(&self) -> Pin<Box<dyn Future<Output = NewsletterMessage> + Send + '_>> {
+        self.inner.wait_until_ready()
+    }
+
+    fn message_sent(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        self.inner.message_sent()
+    }
 }
 
 pub struct NewsletterWorker<N> {
@@ -76,30 +150,34 @@ where
                 None => self.newsletter.tags(),
             };
 
-            let message =
+            let mut message =
                 FormattedMessage::new(STRINGS.newsletter.header(&all_tags).concat(nl_message.text));
+            message.attachments = nl_message.attachments;
             let mut dialogs = Vec::new();
             self.global_state
                 .dialog_storage
                 .inspect_dialogs(&mut |_user_id, dialog| dialogs.push(Arc::clone(dialog)));
             debug!("Sending newsletter `{}`", &name);
-            let text = message.text.clone();
+            let note = Note {
+                text: message.text.clone(),
+                attachments: message.attachments.clone(),
+            };
             let name_clone = name.clone();
             self.global_state
                 .db
                 .send(Command::new(move |ctx| {
-                    // TODO: save media.
-                    ctx.newsletter_sink
-                        .store(&name_clone, Note { text }, chrono::Local::now())
+                    ctx.newsletter_sink.store(&name_clone, note, chrono::Local::now())
                 }))
                 .await?;
             for dialog in dialogs {
                 let (should_send, state) = {
                     let dialog_data = dialog.data().read().unwrap();
-                    let is_subscribed = dialog_data.user.subscriptions().contains(&name);
+                    let is_subscribed = nl_message.ignore_subscriptions
+                        || dialog_data.user.subscriptions().contains(&name);
                     let is_allowed = self.newsletter.allowed()(dialog_data.user.permissions());
+                    let is_skipped = nl_message.user_filter.should_skip_user(dialog.user_id());
                     let state = dialog_data.state.clone();
-                    (is_subscribed && is_allowed, state)
+                    (is_subscribed && is_allowed && !is_skipped, state)
                 };
                 if !should_send {
                     continue;
@@ -123,11 +201,12 @@ where
                     }
                 }
             }
+            self.newsletter.message_sent().await;
         }
     }
 }
 
-async fn worker_retry_loop(bot: BotType, message: FormattedMessage, dialog: Arc<UserDialog>, mut message_queue_tx: MessageQueueSender) {
+async fn worker_retry_loop(_bot: BotType, message: FormattedMessage, dialog: Arc<UserDialog>, mut message_queue_tx: MessageQueueSender) {
     let starting_time = Instant::now();
 
     loop {
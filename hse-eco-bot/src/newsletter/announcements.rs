@@ -0,0 +1,211 @@
+use super::{AccessCheck, Newsletter, NewsletterMessage, NoFilter, UserFilter};
+use crate::db::CommandSender;
+use crate::media::Attachment;
+use crate::message::FormattedText;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::time::Duration;
+use teloxide::types::UserId;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::Mutex;
+
+/// The name of the newsletter.
+pub const NAME: &str = "announcements";
+
+/// The tags of the newsletter.
+pub const TAGS: &str = "#объявление";
+
+/// How often the scheduled announcements are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The users an announcement is sent to.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Default)]
+pub enum Audience {
+    /// The users subscribed to the announcements.
+    #[default]
+    Subscribers,
+    /// All the users of the bot regardless of their subscriptions.
+    Everyone,
+    /// The users that have a role regardless of their subscriptions.
+    Role { role: String },
+}
+
+
+impl Display for Audience {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Subscribers => write!(f, "{}", strings::SUBSCRIBERS),
+            Self::Everyone => write!(f, "{}", strings::EVERYONE),
+            Self::Role { role } => write!(f, "{}{}", strings::ROLE_PREFIX, role),
+        }
+    }
+}
+
+impl FromStr for Audience {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            strings::SUBSCRIBERS => Ok(Self::Subscribers),
+            strings::EVERYONE => Ok(Self::Everyone),
+            _ => match s.strip_prefix(strings::ROLE_PREFIX) {
+                Some(role) if !role.is_empty() => Ok(Self::Role {
+                    role: role.to_owned(),
+                }),
+                _ => Err(()),
+            },
+        }
+    }
+}
+
+mod strings {
+    pub const SUBSCRIBERS: &str = "subscribers";
+    pub const EVERYONE: &str = "everyone";
+    pub const ROLE_PREFIX: &str = "role:";
+}
+
+/// An announcement ready to be sent.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Announcement {
+    pub text: FormattedText,
+    pub attachments: Vec<Attachment>,
+    pub audience: Audience,
+}
+
+/// Announcements composed by the users who may send global notifications.
+pub struct AnnouncementNewsletter {
+    announcement_rx: Mutex<Receiver<Announcement>>,
+    db: CommandSender,
+    /// The ID of the scheduled announcement being sent, removed once it has been sent.
+    sending_scheduled: Mutex<Option<u64>>,
+}
+
+impl AnnouncementNewsletter {
+    pub fn new(db: CommandSender) -> (Self, Sender<Announcement>) {
+        let (announcement_tx, announcement_rx) = mpsc::channel(16);
+        let newsletter = Self {
+            announcement_rx: Mutex::new(announcement_rx),
+            db,
+            sending_scheduled: Mutex::new(None),
+        };
+        (newsletter, announcement_tx)
+    }
+
+    /// Wait for an announcement to be sent immediately or for a scheduled announcement to become
+    /// due, whichever comes first. Scheduled announcements are returned with their IDs.
+    ///
+    /// Returns `None` if nothing is ready after the [`CHECK_INTERVAL`].
+    async fn next_announcement(&self, db: &CommandSender) -> Option<(Option<u64>, Announcement)> {
+        match db.next_due_announcement(Local::now().naive_local()).await {
+            Ok(Some((id, announcement))) => return Some((Some(id), announcement)),
+            Ok(None) => (),
+            Err(e) => warn!("Cannot check the scheduled announcements: {}", e),
+        }
+        let mut announcement_rx = self.announcement_rx.lock().await;
+        tokio::select! {
+            announcement = announcement_rx.recv() => match announcement {
+                Some(announcement) => Some((None, announcement)),
+                // Nothing can be sent immediately anymore, but the scheduled announcements are
+                // still checked.
+                None => {
+                    tokio::time::sleep(CHECK_INTERVAL).await;
+                    None
+                }
+            },
+            _ = tokio::time::sleep(CHECK_INTERVAL) => None,
+        }
+    }
+}
+
+/// Skips the users who do not have the role the announcement is addressed to.
+struct RoleFilter {
+    users: HashSet<UserId>,
+}
+
+impl UserFilter for RoleFilter {
+    fn should_skip_user(&self, user_id: UserId) -> bool {
+        !self.users.contains(&user_id)
+    }
+}
+
+impl Newsletter for AnnouncementNewsletter {
+    fn name(&self) -> String {
+        String::from(NAME)
+    }
+
+    fn description(&self) -> String {
+        String::from("Объявления")
+    }
+
+    fn allowed(&self) -> AccessCheck {
+        Box::new(|_| true)
+    }
+
+    fn tags(&self) -> String {
+        String::from(TAGS)
+    }
+
+    fn wait_until_ready(&self) -> Pin<Box<dyn Future<Output = NewsletterMessage> + Send + '_>> {
+        Box::pin(async {
+            let db = &self.db;
+            // An announcement to be sent immediately whose recipients could not be found.
+            let mut retried = None;
+            loop {
+                let (scheduled, announcement) = match retried.take() {
+                    Some(announcement) => (None, announcement),
+                    None => match self.next_announcement(db).await {
+                        Some(x) => x,
+                        None => continue,
+                    },
+                };
+                let (user_filter, ignore_subscriptions): (Box<dyn UserFilter + Send>, _) =
+                    match &announcement.audience {
+                        Audience::Subscribers => (Box::new(NoFilter), false),
+                        Audience::Everyone => (Box::new(NoFilter), true),
+                        Audience::Role { role } => match db.users_with_role(role.clone()).await {
+                            Ok(users) => (
+                                Box::new(RoleFilter {
+                                    users: users.into_iter().collect(),
+                                }),
+                                true,
+                            ),
+                            Err(e) => {
+                                warn!("Cannot find the recipients of an announcement: {}", e);
+                                // The announcement is retried later. A scheduled one is found
+                                // again in the database, the other one is kept until then.
+                                tokio::time::sleep(CHECK_INTERVAL).await;
+                                if scheduled.is_none() {
+                                    retried = Some(announcement);
+                                }
+                                continue;
+                            }
+                        },
+                    };
+                *self.sending_scheduled.lock().await = scheduled;
+                return NewsletterMessage {
+                    text: announcement.text,
+                    attachments: announcement.attachments,
+                    tags: None,
+                    user_filter,
+                    ignore_subscriptions,
+                };
+            }
+        })
+    }
+
+    fn message_sent(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(async {
+            if let Some(id) = self.sending_scheduled.lock().await.take() {
+                if let Err(e) = self.db.remove_sent_announcement(id).await {
+                    warn!("Cannot remove the sent scheduled announcement {}: {}", id, e);
+                }
+            }
+        })
+    }
+}
@@ -1,5 +1,7 @@
 use crate::kb::{Note, NoteId, ProviderError};
-use crate::util::UnsafeRc;
+use crate::media::attachments_to_json;
+use crate::message::entities_to_json;
+use crate::util::{NestedTransaction, UnsafeRc};
 use chrono::prelude::*;
 use rusqlite::{params, Connection};
 
@@ -9,7 +11,7 @@ pub struct Sink {
 
 impl Sink {
     /// SAFETY: the caller must uphold the invariants of [`UnsafeRc`].
-    pub unsafe fn new<'a>(db: UnsafeRc<Connection>) -> Self {
+    pub unsafe fn new(db: UnsafeRc<Connection>) -> Self {
         Self { db }
     }
 
@@ -23,13 +25,20 @@ impl Sink {
         Tz: TimeZone,
         <Tz as TimeZone>::Offset: std::fmt::Display,
     {
-        let txn = self.db.unchecked_transaction()?;
-        txn.prepare("INSERT INTO kb_newsletters(name, content, timestamp) VALUES (?, ?, ?)")?
-            .execute(params![
-                newsletter_name,
-                &note.text.raw_text,
-                timestamp.to_rfc3339()
-            ])?;
+        let entities = entities_to_json(&note.text.entities)?;
+        let attachments = attachments_to_json(&note.attachments)?;
+        let txn = NestedTransaction::deferred(&self.db)?;
+        txn.prepare(concat!(
+            "INSERT INTO kb_newsletters(name, content, entities, attachments, timestamp) ",
+            "VALUES (?, ?, ?, ?, ?)",
+        ))?
+        .execute(params![
+            newsletter_name,
+            &note.text.raw_text,
+            entities,
+            attachments,
+            timestamp.to_rfc3339()
+        ])?;
         let id = NoteId::from(txn.last_insert_rowid() as u64);
         txn.commit()?;
         trace!("Commit transaction");
@@ -0,0 +1,94 @@
+use super::{AccessCheck, Newsletter, NewsletterMessage, UserFilter};
+use crate::db::CommandSender;
+use crate::strings::STRINGS;
+use crate::ui::calendar::format_time_range;
+use chrono::Local;
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use teloxide::types::UserId;
+
+/// The name of the newsletter.
+pub const NAME: &str = "event-reminders";
+
+/// How often the upcoming events are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long before the start of an event its reminder is sent.
+fn reminder_advance() -> chrono::Duration {
+    chrono::Duration::days(1)
+}
+
+/// Reminders about the events sent to the users who have asked for them.
+pub struct EventReminderNewsletter {
+    db: CommandSender,
+}
+
+impl EventReminderNewsletter {
+    pub fn new(db: CommandSender) -> Self {
+        Self { db }
+    }
+}
+
+/// Skips the users who have not asked to be reminded about the event.
+struct ReminderFilter {
+    users: HashSet<UserId>,
+}
+
+impl UserFilter for ReminderFilter {
+    fn should_skip_user(&self, user_id: UserId) -> bool {
+        !self.users.contains(&user_id)
+    }
+}
+
+impl Newsletter for EventReminderNewsletter {
+    fn name(&self) -> String {
+        String::from(NAME)
+    }
+
+    fn description(&self) -> String {
+        String::from("Напоминания о мероприятиях")
+    }
+
+    fn allowed(&self) -> AccessCheck {
+        Box::new(|_| true)
+    }
+
+    fn tags(&self) -> String {
+        String::from("#напоминание")
+    }
+
+    fn wait_until_ready(&self) -> Pin<Box<dyn Future<Output = NewsletterMessage> + Send + '_>> {
+        Box::pin(async {
+            loop {
+                let now = Local::now().naive_local();
+                match self
+                    .db
+                    .take_due_event_reminder(now, now + reminder_advance())
+                    .await
+                {
+                    Ok(Some((_, event, users))) => {
+                        let text = STRINGS.calendar.reminder(
+                            &event.title,
+                            &format_time_range(event.start, event.end),
+                            &event.location,
+                        );
+                        return NewsletterMessage {
+                            text,
+                            attachments: Vec::new(),
+                            tags: None,
+                            user_filter: Box::new(ReminderFilter {
+                                users: users.into_iter().collect(),
+                            }),
+                            ignore_subscriptions: false,
+                        };
+                    }
+                    Ok(None) => (),
+                    Err(e) => warn!("Cannot check the event reminders: {}", e),
+                }
+                tokio::time::sleep(CHECK_INTERVAL).await;
+            }
+        })
+    }
+}
@@ -1,35 +1,57 @@
-use super::{Newsletter, NewsletterMessage, NoFilter};
+use super::{AccessCheck, Newsletter, NewsletterMessage, NoFilter};
+use crate::media::Attachment;
 use crate::message::FormattedText;
-use crate::ui::form::{Form, FormInput};
+use crate::ui::form::{CompletedForm, FormInput, FormInputType, DATE_TIME_FORMAT};
 use std::future::Future;
 use std::pin::Pin;
 use teloxide::types::MessageEntity;
 use tokio::sync::mpsc::{self, Receiver, Sender};
 use tokio::sync::Mutex;
-use crate::user::Permissions;
+
+/// The name of the newsletter.
+pub const NAME: &str = "feedback";
 
 pub struct FeedbackNewsletter {
-    form_response_rx: Mutex<Receiver<(Form, Vec<FormInput>)>>,
+    form_response_rx: Mutex<Receiver<CompletedForm>>,
 }
 
 impl FeedbackNewsletter {
-    pub fn new() -> (Self, Sender<(Form, Vec<FormInput>)>) {
+    pub fn new() -> (Self, Sender<CompletedForm>) {
         let (form_response_tx, form_response_rx) = mpsc::channel(16);
         let form_response_rx = Mutex::new(form_response_rx);
         (Self { form_response_rx }, form_response_tx)
     }
 }
 
+fn plain(raw_text: String) -> FormattedText {
+    FormattedText {
+        raw_text,
+        entities: None,
+    }
+}
+
+/// Attach the media to the message and refer to them by their numbers in the text.
+fn attach(attachments: &mut Vec<Attachment>, media: Vec<Attachment>) -> FormattedText {
+    let first = attachments.len() + 1;
+    let count = media.len();
+    attachments.extend(media);
+    plain(match count {
+        0 => String::from("Нет"),
+        1 => format!("📎 Вложение №{}", first),
+        _ => format!("📎 Вложения №{}–{}", first, first + count - 1),
+    })
+}
+
 impl Newsletter for FeedbackNewsletter {
     fn name(&self) -> String {
-        String::from("feedback")
+        String::from(NAME)
     }
 
     fn description(&self) -> String {
         String::from("Обратная связь")
     }
 
-    fn allowed(&self) -> Box<dyn Fn(&Permissions) -> bool + Send + Sync> {
+    fn allowed(&self) -> AccessCheck {
         Box::new(|p| p.receive_feedback)
     }
 
@@ -40,10 +62,11 @@ impl Newsletter for FeedbackNewsletter {
     fn wait_until_ready(&self) -> Pin<Box<dyn Future<Output = NewsletterMessage> + Send + '_>> {
         Box::pin(async {
             let (form, input) = self.form_response_rx.lock().await.recv().await.unwrap();
+            let mut attachments = Vec::new();
             let text = form
                 .elements
                 .into_iter()
-                .zip(input.into_iter())
+                .zip(input)
                 .map(|(elem, input)| {
                     let elem_entities =
                         vec![MessageEntity::bold(0, elem.text.encode_utf16().count())];
@@ -51,22 +74,38 @@ impl Newsletter for FeedbackNewsletter {
                         raw_text: format!("{}\n", elem.text),
                         entities: Some(elem_entities),
                     };
-                    // TODO: media.
                     let input_fmt = match input {
-                        FormInput::ShortText { text } => FormattedText {
-                            raw_text: text,
-                            entities: None,
+                        FormInput::Choice { index } => match elem.input_type {
+                            FormInputType::Choice { mut options } => {
+                                plain(options.swap_remove(index))
+                            }
+                            _ => plain(index.to_string()),
                         },
+                        FormInput::ShortText { text } => plain(text),
                         FormInput::Text { text } => text,
-                        FormInput::Number { number } => FormattedText {
-                            raw_text: number.to_string(),
-                            entities: None,
-                        },
-                        FormInput::Location { location } => FormattedText {
-                            raw_text: location.to_string(),
-                            entities: None,
-                        },
-                        _ => FormattedText { raw_text: String::from("<unimplemented>"), entities: None },
+                        FormInput::Message { text, attachments: media } if media.is_empty() => {
+                            text
+                        }
+                        FormInput::Message { text, attachments: media } => {
+                            let reference = attach(&mut attachments, media);
+                            if text.raw_text.is_empty() {
+                                reference
+                            } else {
+                                text.concat(plain(String::from("\n"))).concat(reference)
+                            }
+                        }
+                        FormInput::Number { number } => plain(number.to_string()),
+                        FormInput::Location { location } => plain(location.to_string()),
+                        FormInput::Image { image } => {
+                            attach(&mut attachments, vec![Attachment::Image(image)])
+                        }
+                        FormInput::ImageGallery { images } => attach(
+                            &mut attachments,
+                            images.into_iter().map(Attachment::Image).collect(),
+                        ),
+                        FormInput::DateTime { date_time } => {
+                            plain(date_time.format(DATE_TIME_FORMAT).to_string())
+                        }
                     };
                     elem_fmt.concat(input_fmt)
                 })
@@ -84,8 +123,12 @@ impl Newsletter for FeedbackNewsletter {
 
             NewsletterMessage {
                 text,
+                attachments,
                 tags: None,
                 user_filter: Box::new(NoFilter),
+                // Feedback reaches every user with a role that receives it, without an explicit
+                // subscription.
+                ignore_subscriptions: true,
             }
         })
     }
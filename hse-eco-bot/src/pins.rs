@@ -0,0 +1,189 @@
+use crate::db::{FullDirectoryId, FullItemId, FullNoteId};
+use crate::util::{NestedTransaction, UnsafeRc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// A knowledge base item pinned to the main menu.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Pin {
+    pub id: u64,
+    pub item: FullItemId,
+    /// The text of the main menu button. If it is not set, the name of the item is used.
+    pub label: Option<String>,
+}
+
+/// Persistent storage of the knowledge base items pinned to the main menu.
+///
+/// The pins are ordered by their positions. Items are referenced by the same IDs as in the dialog
+/// states, so the pins remain valid as long as the structure of the providers does not change.
+pub struct PinStorage {
+    db: UnsafeRc<Connection>,
+}
+
+const NOTE: &str = "note";
+const DIRECTORY: &str = "directory";
+
+/// Get the `kind`, `provider` and `item` columns identifying an item.
+pub fn item_columns(item: FullItemId) -> (&'static str, u64, u64) {
+    match item {
+        FullItemId::Note(note) => (NOTE, note.provider.into(), note.note.into()),
+        FullItemId::Directory(dir) => (DIRECTORY, dir.provider.into(), dir.directory.into()),
+    }
+}
+
+/// Get an item from the `kind`, `provider` and `item` columns identifying it.
+pub fn item_from_columns(kind: &str, provider: u64, item: u64) -> rusqlite::Result<FullItemId> {
+    match kind {
+        NOTE => Ok(FullItemId::Note(FullNoteId {
+            provider: provider.into(),
+            note: item.into(),
+        })),
+        DIRECTORY => Ok(FullItemId::Directory(FullDirectoryId {
+            provider: provider.into(),
+            directory: item.into(),
+        })),
+        _ => Err(rusqlite::Error::InvalidColumnType(
+            0,
+            String::from("kind"),
+            rusqlite::types::Type::Text,
+        )),
+    }
+}
+
+impl PinStorage {
+    /// SAFETY: the caller must uphold the invariants of [`UnsafeRc`].
+    pub unsafe fn new(db: UnsafeRc<Connection>) -> Self {
+        Self { db }
+    }
+
+    /// Get all the pins in the order of their positions.
+    pub fn list(&self) -> rusqlite::Result<Vec<Pin>> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT id, kind, provider, item, label FROM kb_pins ORDER BY position")?;
+        let rows = stmt.query_map(params![], |row| {
+            Ok((
+                row.get::<_, u64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u64>(2)?,
+                row.get::<_, u64>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+        let mut pins = Vec::new();
+        for row in rows {
+            let (id, kind, provider, item, label) = row?;
+            pins.push(Pin {
+                id,
+                item: item_from_columns(&kind, provider, item)?,
+                label,
+            });
+        }
+        Ok(pins)
+    }
+
+    /// Find the pin of an item.
+    pub fn find(&self, item: FullItemId) -> rusqlite::Result<Option<u64>> {
+        let (kind, provider, item) = item_columns(item);
+        self.db
+            .prepare("SELECT id FROM kb_pins WHERE kind = ? AND provider = ? AND item = ?")?
+            .query_row(params![kind, provider, item], |row| row.get(0))
+            .optional()
+    }
+
+    /// Pin an item after all the other pins.
+    ///
+    /// Returns `false` if the item is already pinned.
+    pub fn pin(&self, item: FullItemId) -> rusqlite::Result<bool> {
+        let (kind, provider, item) = item_columns(item);
+        let inserted = self
+            .db
+            .prepare(concat!(
+                "INSERT OR IGNORE INTO kb_pins(kind, provider, item, position) ",
+                "SELECT ?, ?, ?, COALESCE(MAX(position) + 1, 0) FROM kb_pins",
+            ))?
+            .execute(params![kind, provider, item])?;
+        Ok(inserted != 0)
+    }
+
+    /// Unpin an item.
+    ///
+    /// Returns `false` if the item is not pinned.
+    pub fn unpin(&self, item: FullItemId) -> rusqlite::Result<bool> {
+        let (kind, provider, item) = item_columns(item);
+        let deleted = self
+            .db
+            .prepare("DELETE FROM kb_pins WHERE kind = ? AND provider = ? AND item = ?")?
+            .execute(params![kind, provider, item])?;
+        Ok(deleted != 0)
+    }
+
+    /// Get a pin by its ID.
+    pub fn get(&self, pin: u64) -> rusqlite::Result<Option<Pin>> {
+        let row = self
+            .db
+            .prepare("SELECT kind, provider, item, label FROM kb_pins WHERE id = ?")?
+            .query_row(params![pin], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, u64>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })
+            .optional()?;
+        match row {
+            Some((kind, provider, item, label)) => Ok(Some(Pin {
+                id: pin,
+                item: item_from_columns(&kind, provider, item)?,
+                label,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    /// Set or reset the custom label of a pin.
+    ///
+    /// Returns `false` if there is no such pin.
+    pub fn set_label(&self, pin: u64, label: Option<&str>) -> rusqlite::Result<bool> {
+        let updated = self
+            .db
+            .prepare("UPDATE kb_pins SET label = ? WHERE id = ?")?
+            .execute(params![label, pin])?;
+        Ok(updated != 0)
+    }
+
+    /// Swap a pin with the previous one (if `up` is `true`) or with the next one.
+    ///
+    /// Returns `false` if there is no such pin or it cannot be moved further.
+    pub fn move_pin(&self, pin: u64, up: bool) -> rusqlite::Result<bool> {
+        let txn = NestedTransaction::deferred(&self.db)?;
+        let position: Option<i64> = txn
+            .prepare("SELECT position FROM kb_pins WHERE id = ?")?
+            .query_row(params![pin], |row| row.get(0))
+            .optional()?;
+        let position = match position {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        let neighbor_sql = if up {
+            "SELECT id, position FROM kb_pins WHERE position < ? ORDER BY position DESC LIMIT 1"
+        } else {
+            "SELECT id, position FROM kb_pins WHERE position > ? ORDER BY position ASC LIMIT 1"
+        };
+        let neighbor: Option<(u64, i64)> = txn
+            .prepare(neighbor_sql)?
+            .query_row(params![position], |row| Ok((row.get(0)?, row.get(1)?)))
+            .optional()?;
+        let (neighbor_id, neighbor_position) = match neighbor {
+            Some(x) => x,
+            None => return Ok(false),
+        };
+        {
+            let mut stmt = txn.prepare("UPDATE kb_pins SET position = ? WHERE id = ?")?;
+            stmt.execute(params![neighbor_position, pin])?;
+            stmt.execute(params![position, neighbor_id])?;
+        }
+        txn.commit()?;
+        Ok(true)
+    }
+}
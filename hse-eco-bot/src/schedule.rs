@@ -0,0 +1,162 @@
+use crate::events::{time_from_sql, time_to_sql};
+use crate::message::{entities_from_json, entities_to_json, FormattedText};
+use crate::newsletter::announcements::Announcement;
+use crate::util::UnsafeRc;
+use chrono::NaiveDateTime;
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+/// An announcement that is sent at a given time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledAnnouncement {
+    /// The local time when the announcement is sent.
+    pub send_at: NaiveDateTime,
+    pub announcement: Announcement,
+}
+
+const SCHEDULED_COLUMNS: &str = "id, send_at, text, text_entities, attachments, audience";
+
+fn conversion_error(
+    index: usize,
+    e: impl std::error::Error + Send + Sync + 'static,
+) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e))
+}
+
+fn scheduled_from_row(row: &Row<'_>) -> rusqlite::Result<(u64, ScheduledAnnouncement)> {
+    let entities_json: Option<String> = row.get(3)?;
+    let attachments_json: String = row.get(4)?;
+    let audience: String = row.get(5)?;
+    let announcement = Announcement {
+        text: FormattedText {
+            raw_text: row.get(2)?,
+            entities: entities_from_json(entities_json).map_err(|e| conversion_error(3, e))?,
+        },
+        attachments: serde_json::from_str(&attachments_json)
+            .map_err(|e| conversion_error(4, e))?,
+        audience: audience.parse().map_err(|()| {
+            rusqlite::Error::FromSqlConversionFailure(
+                5,
+                Type::Text,
+                format!("Invalid audience `{}`", audience).into(),
+            )
+        })?,
+    };
+    let scheduled = ScheduledAnnouncement {
+        send_at: time_from_sql(row, 1)?,
+        announcement,
+    };
+    Ok((row.get(0)?, scheduled))
+}
+
+fn to_sql_error(e: serde_json::Error) -> rusqlite::Error {
+    rusqlite::Error::ToSqlConversionFailure(Box::new(e))
+}
+
+/// Persistent storage of the announcements scheduled to be sent later.
+pub struct ScheduleStorage {
+    db: UnsafeRc<Connection>,
+}
+
+impl ScheduleStorage {
+    /// SAFETY: the caller must uphold the invariants of [`UnsafeRc`].
+    pub unsafe fn new(db: UnsafeRc<Connection>) -> Self {
+        Self { db }
+    }
+
+    /// Get all the scheduled announcements sorted by the time they are sent at.
+    pub fn list(&self) -> rusqlite::Result<Vec<(u64, ScheduledAnnouncement)>> {
+        let mut stmt = self.db.prepare(&format!(
+            "SELECT {} FROM scheduled_announcements ORDER BY send_at, id",
+            SCHEDULED_COLUMNS,
+        ))?;
+        let rows = stmt.query_map(params![], scheduled_from_row)?;
+        rows.collect()
+    }
+
+    pub fn get(&self, id: u64) -> rusqlite::Result<Option<ScheduledAnnouncement>> {
+        Ok(self
+            .db
+            .prepare(&format!(
+                "SELECT {} FROM scheduled_announcements WHERE id = ?",
+                SCHEDULED_COLUMNS,
+            ))?
+            .query_row(params![id], scheduled_from_row)
+            .optional()?
+            .map(|(_, scheduled)| scheduled))
+    }
+
+    /// Schedule an announcement and return the ID of the scheduled announcement.
+    pub fn create(&self, scheduled: &ScheduledAnnouncement) -> rusqlite::Result<u64> {
+        let announcement = &scheduled.announcement;
+        let entities = entities_to_json(&announcement.text.entities).map_err(to_sql_error)?;
+        let attachments = serde_json::to_string(&announcement.attachments).map_err(to_sql_error)?;
+        self.db
+            .prepare(concat!(
+                "INSERT INTO scheduled_announcements(send_at, text, text_entities, ",
+                "attachments, audience) VALUES (?, ?, ?, ?, ?)",
+            ))?
+            .execute(params![
+                time_to_sql(scheduled.send_at),
+                &announcement.text.raw_text,
+                entities,
+                attachments,
+                announcement.audience.to_string(),
+            ])?;
+        Ok(self.db.last_insert_rowid() as u64)
+    }
+
+    /// Replace a scheduled announcement.
+    ///
+    /// Returns `false` if there is no such announcement (e.g. it has already been sent).
+    pub fn update(&self, id: u64, scheduled: &ScheduledAnnouncement) -> rusqlite::Result<bool> {
+        let announcement = &scheduled.announcement;
+        let entities = entities_to_json(&announcement.text.entities).map_err(to_sql_error)?;
+        let attachments = serde_json::to_string(&announcement.attachments).map_err(to_sql_error)?;
+        let updated = self
+            .db
+            .prepare(concat!(
+                "UPDATE scheduled_announcements SET send_at = ?, text = ?, text_entities = ?, ",
+                "attachments = ?, audience = ? WHERE id = ?",
+            ))?
+            .execute(params![
+                time_to_sql(scheduled.send_at),
+                &announcement.text.raw_text,
+                entities,
+                attachments,
+                announcement.audience.to_string(),
+                id,
+            ])?;
+        Ok(updated != 0)
+    }
+
+    /// Cancel a scheduled announcement.
+    ///
+    /// Returns `false` if there is no such announcement (e.g. it has already been sent).
+    pub fn delete(&self, id: u64) -> rusqlite::Result<bool> {
+        let deleted = self
+            .db
+            .prepare("DELETE FROM scheduled_announcements WHERE id = ?")?
+            .execute(params![id])?;
+        Ok(deleted != 0)
+    }
+
+    /// Get the earliest announcement that is due to be sent no later than `now` with its ID.
+    ///
+    /// The announcement is kept until it is removed with [`Self::delete`] once it has been sent,
+    /// so that it is not lost if sending fails.
+    pub fn next_due(&self, now: NaiveDateTime) -> rusqlite::Result<Option<(u64, Announcement)>> {
+        Ok(self
+            .db
+            .prepare(&format!(
+                concat!(
+                    "SELECT {} FROM scheduled_announcements WHERE send_at <= ? ",
+                    "ORDER BY send_at, id LIMIT 1",
+                ),
+                SCHEDULED_COLUMNS,
+            ))?
+            .query_row(params![time_to_sql(now)], scheduled_from_row)
+            .optional()?
+            .map(|(id, scheduled)| (id, scheduled.announcement)))
+    }
+}
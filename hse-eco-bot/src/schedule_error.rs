@@ -0,0 +1,40 @@
+use crate::message::FormattedText;
+use crate::strings::STRINGS;
+use crate::user_facing_error::UserFacingError;
+use std::error::Error;
+use std::fmt::Display;
+
+/// The error when an announcement cannot be scheduled or a scheduled announcement cannot be
+/// changed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ScheduleError {
+    /// The scheduled announcement does not exist (e.g. it has already been sent or cancelled).
+    NoSuchAnnouncement,
+    /// The time is not in the expected format.
+    InvalidTime,
+    /// The time has already passed.
+    TimeInPast,
+}
+
+impl Display for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoSuchAnnouncement => write!(f, "No such scheduled announcement"),
+            Self::InvalidTime => write!(f, "Invalid time format"),
+            Self::TimeInPast => write!(f, "The time has already passed"),
+        }
+    }
+}
+
+impl Error for ScheduleError {}
+
+impl UserFacingError for ScheduleError {
+    fn user_message(&self) -> FormattedText {
+        let s = &STRINGS.errors.schedule;
+        match self {
+            Self::NoSuchAnnouncement => s.no_such_announcement(),
+            Self::InvalidTime => s.invalid_time(),
+            Self::TimeInPast => s.time_in_past(),
+        }
+    }
+}
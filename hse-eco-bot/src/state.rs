@@ -1,87 +1,201 @@
+use crate::db::FullItemId;
+use serde::{Deserialize, Serialize};
+
 pub mod states {
+    use crate::audit::AuditFilter;
     use crate::db::{FullDirectoryId, FullNoteId};
-    use crate::ui::form::{Form, FormInput, FormFillingState};
-    use tokio::sync::mpsc::Sender;
+    use crate::events::CalendarPeriod;
+    use crate::media::Attachment;
+    use crate::message::FormattedText;
+    use crate::newsletter::announcements::Audience;
+    use crate::ui::form::{FormCompletionTarget, FormFillingState};
+    use chrono::NaiveDate;
+    use serde::{Deserialize, Serialize};
+    use teloxide::types::UserId;
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbNavigation {
         pub id: FullDirectoryId,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbNoteViewing {
         pub id: FullNoteId,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbNoteDeletionConfirmation {
         pub id: FullNoteId,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbNoteRenaming {
         pub id: FullNoteId,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbNoteCreation {
         pub destination: FullDirectoryId,
     }
 
-    #[derive(Debug, Clone, Eq, PartialEq)]
+    /// The attachments of a note sent before its text.
+    #[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct NoteDraft {
+        /// The caption of the attachments, if any.
+        pub text: Option<FormattedText>,
+        pub attachments: Vec<Attachment>,
+        /// The media group of the last received attachment. The attachments of an album arrive as
+        /// separate messages, and the prompt is only repeated once per album.
+        pub last_media_group_id: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbNoteCreationNamed {
         pub destination: FullDirectoryId,
         pub name: String,
+        #[serde(default)]
+        pub draft: NoteDraft,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbDirectoryEditing {
         pub id: FullDirectoryId,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbNoteEditing {
         pub id: FullNoteId,
+        #[serde(default)]
+        pub draft: NoteDraft,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbNoteMovement {
         pub destination: FullDirectoryId,
         pub note: FullNoteId,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbDirectoryMovement {
         pub destination: FullDirectoryId,
         pub directory: FullDirectoryId,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbDirectoryCreation {
         pub destination: FullDirectoryId,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbDirectoryRenaming {
         pub id: FullDirectoryId,
     }
 
-    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
     pub struct KbDirectoryDeletion {
         pub id: FullDirectoryId,
     }
 
-    #[derive(Clone)]
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct KbSearch {
+        /// The directory the search has been started from.
+        pub from: FullDirectoryId,
+    }
+
+    /// A page of the search results. The query is stored here rather than in the callback
+    /// data, since the latter is limited to 64 bytes.
+    #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct KbSearchResults {
+        pub from: FullDirectoryId,
+        pub query: String,
+        pub page: usize,
+    }
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct KbNoteHistory {
+        pub id: FullNoteId,
+    }
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct KbDirectoryHistory {
+        pub id: FullDirectoryId,
+    }
+
+    /// A page of the deleted knowledge base items.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct Trash {
+        pub page: usize,
+    }
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct PinRenaming {
+        pub pin: u64,
+    }
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct AdminUserRoles {
+        pub user_id: UserId,
+    }
+
+    /// A page of the audit log.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct AuditLog {
+        pub filter: AuditFilter,
+        pub page: usize,
+    }
+
+    /// A page of the calendar.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct Calendar {
+        pub period: CalendarPeriod,
+        pub first_day: NaiveDate,
+    }
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct EventViewing {
+        pub id: u64,
+        /// The calendar page to return to.
+        pub calendar: Calendar,
+    }
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct EventDeletion {
+        pub id: u64,
+        pub calendar: Calendar,
+    }
+
+    /// An announcement being composed.
+    #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct AnnouncementDraft {
+        pub text: FormattedText,
+        pub attachments: Vec<Attachment>,
+        pub audience: Audience,
+        /// The media group of the last received attachment. The attachments of an album arrive as
+        /// separate messages, and the prompt is only repeated once per album.
+        pub last_media_group_id: Option<String>,
+        /// The scheduled announcement being edited.
+        #[serde(default)]
+        pub scheduled: Option<u64>,
+    }
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct ScheduledAnnouncementViewing {
+        pub id: u64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
     pub struct FormFilling {
         pub form_state: FormFillingState,
         pub return_state: Box<super::DialogState>,
         pub completion_state: Box<super::DialogState>,
-        pub on_completion: Sender<(Form, Vec<FormInput>)>,
+        pub on_completion: FormCompletionTarget,
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default)]
 pub enum DialogState {
+    #[default]
     Initial,
     MainMenu,
     KbNavigation(states::KbNavigation),
@@ -97,13 +211,82 @@ pub enum DialogState {
     KbDirectoryCreation(states::KbDirectoryCreation),
     KbDirectoryRenaming(states::KbDirectoryRenaming),
     KbDirectoryDeletion(states::KbDirectoryDeletion),
+    KbSearch(states::KbSearch),
+    KbSearchResults(states::KbSearchResults),
+    KbNoteHistory(states::KbNoteHistory),
+    KbDirectoryHistory(states::KbDirectoryHistory),
+    Trash(states::Trash),
     FormFilling(states::FormFilling),
     FeedbackTopicSelection,
     SubscriptionsMenu,
+    PinManagement,
+    PinRenaming(states::PinRenaming),
+    AdminPanel,
+    AdminUserLookup,
+    AdminUserRoles(states::AdminUserRoles),
+    AuditLog(states::AuditLog),
+    /// Choosing the action the audit log is filtered by.
+    AuditLogActionSelection(states::AuditLog),
+    Calendar(states::Calendar),
+    EventViewing(states::EventViewing),
+    EventDeletion(states::EventDeletion),
+    AnnouncementComposing(states::AnnouncementDraft),
+    AnnouncementPreview(states::AnnouncementDraft),
+    AnnouncementScheduling(states::AnnouncementDraft),
+    ScheduledAnnouncements,
+    ScheduledAnnouncementViewing(states::ScheduledAnnouncementViewing),
 }
 
-impl Default for DialogState {
-    fn default() -> Self {
-        Self::Initial
+
+impl DialogState {
+    /// Get the knowledge base items the state refers to, which it cannot be used without.
+    pub fn kb_items(&self) -> Vec<FullItemId> {
+        use FullItemId::{Directory, Note};
+        match self {
+            Self::KbNavigation(state) => vec![Directory(state.id)],
+            Self::KbNoteViewing(state) => vec![Note(state.id)],
+            Self::KbNoteDeletionConfirmation(state) => vec![Note(state.id)],
+            Self::KbNoteRenaming(state) => vec![Note(state.id)],
+            Self::KbNoteCreation(state) => vec![Directory(state.destination)],
+            Self::KbNoteCreationNamed(state) => vec![Directory(state.destination)],
+            Self::KbDirectoryEditing(state) => vec![Directory(state.id)],
+            Self::KbNoteEditing(state) => vec![Note(state.id)],
+            Self::KbNoteMovement(state) => vec![Directory(state.destination), Note(state.note)],
+            Self::KbDirectoryMovement(state) => {
+                vec![Directory(state.destination), Directory(state.directory)]
+            }
+            Self::KbDirectoryCreation(state) => vec![Directory(state.destination)],
+            Self::KbDirectoryRenaming(state) => vec![Directory(state.id)],
+            Self::KbDirectoryDeletion(state) => vec![Directory(state.id)],
+            Self::KbSearch(state) => vec![Directory(state.from)],
+            Self::KbSearchResults(state) => vec![Directory(state.from)],
+            Self::KbNoteHistory(state) => vec![Note(state.id)],
+            Self::KbDirectoryHistory(state) => vec![Directory(state.id)],
+            Self::FormFilling(state) => {
+                let mut items = state.return_state.kb_items();
+                items.extend(state.completion_state.kb_items());
+                items
+            }
+            Self::Initial
+            | Self::MainMenu
+            | Self::Trash(_)
+            | Self::FeedbackTopicSelection
+            | Self::SubscriptionsMenu
+            | Self::PinManagement
+            | Self::PinRenaming(_)
+            | Self::AdminPanel
+            | Self::AdminUserLookup
+            | Self::AdminUserRoles(_)
+            | Self::AuditLog(_)
+            | Self::AuditLogActionSelection(_)
+            | Self::Calendar(_)
+            | Self::EventViewing(_)
+            | Self::EventDeletion(_)
+            | Self::AnnouncementComposing(_)
+            | Self::AnnouncementPreview(_)
+            | Self::AnnouncementScheduling(_)
+            | Self::ScheduledAnnouncements
+            | Self::ScheduledAnnouncementViewing(_) => Vec::new(),
+        }
     }
 }
@@ -1 +1,18 @@
+mod audit;
+mod config;
 mod db_provider;
+mod events;
+mod fixtures;
+mod form;
+mod fs_provider;
+mod history;
+mod kb_transfer;
+mod links;
+mod message;
+mod migrations;
+mod pins;
+mod schedule;
+mod search;
+mod state;
+mod trash;
+mod user_storage;
@@ -0,0 +1,291 @@
+use crate::audit::{AuditAction, AuditFilter, AuditLog, AuditTarget};
+use crate::cli::{self, CliError, Command};
+use crate::config::Config;
+use crate::db::{FullItemId, FullNoteId, FullRevisionId};
+use crate::pins::PinStorage;
+use crate::tests::fixtures::TempDir;
+use crate::ui::audit::parse_period;
+use crate::util::UnsafeRc;
+use chrono::{Local, NaiveDate};
+use rusqlite::Connection;
+use teloxide::types::UserId;
+
+fn make_log() -> (AuditLog, UnsafeRc<Connection>) {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
+    let db = unsafe { UnsafeRc::new(db) };
+    (unsafe { AuditLog::new(UnsafeRc::clone(&db)) }, db)
+}
+
+fn note(provider: u64, note: u64) -> FullNoteId {
+    FullNoteId {
+        provider: provider.into(),
+        note: note.into(),
+    }
+}
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn entries_filtered() {
+    let (log, _) = make_log();
+    log.record(
+        Some(UserId(42)),
+        AuditAction::CreateNote,
+        &[AuditTarget::Note(note(0, 5))],
+    )
+    .unwrap();
+    log.record(
+        Some(UserId(43)),
+        AuditAction::GrantRole,
+        &[
+            AuditTarget::User(UserId(42)),
+            AuditTarget::Role(String::from("editor")),
+        ],
+    )
+    .unwrap();
+    log.record(
+        None,
+        AuditAction::UpdateNote,
+        &[AuditTarget::Note(note(0, 5))],
+    )
+    .unwrap();
+
+    let all = log.list(&AuditFilter::default(), 10, 0).unwrap();
+    let actions: Vec<_> = all.iter().map(|entry| entry.action).collect();
+    assert_eq!(
+        actions,
+        [
+            AuditAction::UpdateNote,
+            AuditAction::GrantRole,
+            AuditAction::CreateNote
+        ],
+    );
+    assert_eq!(all[0].user_id, None);
+    assert_eq!(
+        all[1].targets,
+        [
+            AuditTarget::User(UserId(42)),
+            AuditTarget::Role(String::from("editor"))
+        ],
+    );
+    assert_eq!(log.count(&AuditFilter::default()).unwrap(), 3);
+    assert_eq!(log.list(&AuditFilter::default(), 2, 2).unwrap(), &all[2..]);
+
+    let by_user = AuditFilter {
+        user_id: Some(UserId(42)),
+        ..AuditFilter::default()
+    };
+    assert_eq!(log.list(&by_user, 10, 0).unwrap(), &all[2..]);
+    let by_action = AuditFilter {
+        action: Some(AuditAction::GrantRole),
+        ..AuditFilter::default()
+    };
+    assert_eq!(log.list(&by_action, 10, 0).unwrap(), &all[1..2]);
+
+    let today = Local::now().naive_local().date();
+    let current = AuditFilter {
+        since: Some(today),
+        until: Some(today),
+        ..AuditFilter::default()
+    };
+    assert_eq!(log.count(&current).unwrap(), 3);
+    let past = AuditFilter {
+        until: Some(today.pred_opt().unwrap()),
+        ..AuditFilter::default()
+    };
+    assert_eq!(log.count(&past).unwrap(), 0);
+}
+
+#[test]
+fn entries_append_only() {
+    let (log, db) = make_log();
+    log.record(
+        Some(UserId(42)),
+        AuditAction::DeleteEvent,
+        &[AuditTarget::Event(1)],
+    )
+    .unwrap();
+    db.execute("UPDATE audit_log SET user_id = 43", [])
+        .unwrap_err();
+    db.execute("DELETE FROM audit_log", []).unwrap_err();
+    assert_eq!(log.count(&AuditFilter::default()).unwrap(), 1);
+}
+
+#[test]
+fn entries_exported() {
+    let (log, _) = make_log();
+    log.record(
+        Some(UserId(42)),
+        AuditAction::GrantRole,
+        &[
+            AuditTarget::User(UserId(41)),
+            AuditTarget::Role(String::from("editor")),
+        ],
+    )
+    .unwrap();
+    log.record(None, AuditAction::PinItem, &[AuditTarget::Pin(3)])
+        .unwrap();
+
+    let mut out = Vec::new();
+    log.export_csv(&AuditFilter::default(), &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "id,time,user_id,action,targets");
+    assert!(lines[1].starts_with("1,"));
+    assert!(lines[1].ends_with(",42,grant_role,user:41 role:editor"));
+    assert!(lines[2].ends_with(",,pin_item,pin:3"));
+}
+
+#[test]
+fn actions_and_targets_parsed() {
+    for action in AuditAction::ALL {
+        assert_eq!(action.name().parse(), Ok(action));
+    }
+    assert_eq!("no_such_action".parse::<AuditAction>(), Err(()));
+
+    let targets = [
+        AuditTarget::from(FullItemId::Note(note(1, 5))),
+        AuditTarget::Revision(FullRevisionId {
+            provider: 0.into(),
+            revision: 7.into(),
+        }),
+        AuditTarget::ScheduledAnnouncement(2),
+        AuditTarget::User(UserId(42)),
+        AuditTarget::Role(String::from("editor")),
+    ];
+    for target in targets {
+        assert_eq!(target.to_string().parse(), Ok(target));
+    }
+    assert_eq!(AuditTarget::Note(note(1, 5)).to_string(), "note:1:5");
+    assert_eq!("note:1".parse::<AuditTarget>(), Err(()));
+    assert_eq!("book:1".parse::<AuditTarget>(), Err(()));
+}
+
+#[test]
+fn periods_parsed() {
+    assert_eq!(
+        parse_period("01.09.2022"),
+        Some((date(2022, 9, 1), date(2022, 9, 1))),
+    );
+    assert_eq!(
+        parse_period("01.09.2022 - 30.09.2022"),
+        Some((date(2022, 9, 1), date(2022, 9, 30))),
+    );
+    assert_eq!(
+        parse_period("01.09.2022—30.09.2022"),
+        Some((date(2022, 9, 1), date(2022, 9, 30))),
+    );
+    assert_eq!(parse_period("30.09.2022 - 01.09.2022"), None);
+    assert_eq!(parse_period("сентябрь"), None);
+}
+
+#[test]
+fn export_arguments_parsed() {
+    assert_eq!(cli::parse_args(args(&[])), Ok(Command::Run));
+    assert_eq!(
+        cli::parse_args(args(&["export-audit-log"])),
+        Ok(Command::ExportAuditLog(AuditFilter::default())),
+    );
+    assert_eq!(
+        cli::parse_args(args(&[
+            "export-audit-log",
+            "--user",
+            "42",
+            "--action",
+            "grant_role",
+            "--since",
+            "2022-09-01",
+            "--until",
+            "2022-09-30",
+        ])),
+        Ok(Command::ExportAuditLog(AuditFilter {
+            user_id: Some(UserId(42)),
+            action: Some(AuditAction::GrantRole),
+            since: Some(date(2022, 9, 1)),
+            until: Some(date(2022, 9, 30)),
+        })),
+    );
+    assert_eq!(
+        cli::parse_args(args(&["export-audit-log", "--user"])),
+        Err(CliError::MissingValue(String::from("--user"))),
+    );
+    assert_eq!(
+        cli::parse_args(args(&["export-audit-log", "--action", "fly"])),
+        Err(CliError::InvalidValue {
+            option: String::from("--action"),
+            value: String::from("fly"),
+        }),
+    );
+    assert_eq!(
+        cli::parse_args(args(&["backup"])),
+        Err(CliError::UnknownCommand(String::from("backup"))),
+    );
+}
+
+#[test]
+fn entries_committed_with_actions() {
+    let (log, db) = make_log();
+    let pins = unsafe { PinStorage::new(db) };
+    let (first, second) = (FullItemId::Note(note(0, 1)), FullItemId::Note(note(0, 2)));
+    pins.pin(first).unwrap();
+    pins.pin(second).unwrap();
+    let pin = pins.find(first).unwrap().unwrap();
+    let first_pinned = || pins.list().unwrap()[0].item;
+
+    // The transaction of the storage is nested in the one of the log, so it is rolled back too.
+    {
+        let _txn = log.transaction().unwrap();
+        assert!(pins.move_pin(pin, false).unwrap());
+        log.record(Some(UserId(1)), AuditAction::MovePin, &[AuditTarget::Pin(pin)])
+            .unwrap();
+    }
+    assert_eq!(log.count(&AuditFilter::default()).unwrap(), 0);
+    assert_eq!(first_pinned(), first);
+
+    let txn = log.transaction().unwrap();
+    assert!(pins.move_pin(pin, false).unwrap());
+    log.record(Some(UserId(1)), AuditAction::MovePin, &[AuditTarget::Pin(pin)])
+        .unwrap();
+    txn.commit().unwrap();
+    assert_eq!(log.count(&AuditFilter::default()).unwrap(), 1);
+    assert_eq!(first_pinned(), second);
+}
+
+#[test]
+fn admins_granted_on_init() {
+    let temp = TempDir::new("init-db");
+    let config = Config::parse(&format!(
+        "admins = [43, 44]\n[database]\npath = {:?}",
+        temp.0.join("bot.sqlite"),
+    ))
+    .unwrap();
+    cli::init_db(&config, &[UserId(42), UserId(43)]).unwrap();
+
+    let db = unsafe { UnsafeRc::new(Connection::open(&config.database.path).unwrap()) };
+    let log = unsafe { AuditLog::new(db) };
+    let mut entries = log.list(&AuditFilter::default(), 10, 0).unwrap();
+    entries.reverse();
+    let grants: Vec<_> = entries
+        .into_iter()
+        .map(|entry| (entry.user_id, entry.action, entry.targets))
+        .collect();
+    let grant = |user_id| {
+        (
+            None,
+            AuditAction::GrantRole,
+            vec![
+                AuditTarget::User(UserId(user_id)),
+                AuditTarget::Role(String::from("admin")),
+            ],
+        )
+    };
+    assert_eq!(grants, [grant(42), grant(43), grant(44)]);
+}
@@ -0,0 +1,203 @@
+use crate::cli::{self, CliError, Command, Invocation};
+use crate::config::{
+    Config, ConfigError, NewsletterDefinition, TokenSource, DEFAULT_ARCHIVE_DIR,
+    DEFAULT_DATABASE_PATH, DEFAULT_TOKEN_VARIABLE,
+};
+use crate::kb::transfer::TextFormat;
+use crate::newsletter::feedback::FeedbackNewsletter;
+use crate::newsletter::{ConfiguredNewsletter, Newsletter};
+use crate::trash;
+use std::path::PathBuf;
+use teloxide::types::UserId;
+
+fn invalid(key: &str, reason: &'static str) -> Result<Config, ConfigError> {
+    Err(ConfigError::InvalidValue {
+        key: String::from(key),
+        reason,
+    })
+}
+
+#[test]
+fn defaults_used() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config, Config::default());
+    assert_eq!(
+        config.token,
+        TokenSource::Env(String::from(DEFAULT_TOKEN_VARIABLE))
+    );
+    assert_eq!(config.database.path, PathBuf::from(DEFAULT_DATABASE_PATH));
+    assert_eq!(config.kb.archive_dir, DEFAULT_ARCHIVE_DIR);
+    assert!(config.kb.directories.is_empty());
+    assert_eq!(config.trash.retention_days, trash::DEFAULT_RETENTION_DAYS);
+
+    // The example configuration lists the defaults.
+    assert_eq!(
+        Config::parse(include_str!("../../../config.example.toml")).unwrap(),
+        Config::default()
+    );
+}
+
+#[test]
+fn config_parsed() {
+    let config = Config::parse(
+        r##"
+        admins = [42, 43]
+
+        [token]
+        file = "/run/secrets/token"
+
+        [database]
+        path = "/var/lib/hse-eco-bot/bot.sqlite"
+
+        [kb]
+        archive_dir = "Рассылки"
+        directories = ["kb/Сортировка", "/srv/kb/Проекты"]
+
+        [trash]
+        retention_days = 7
+
+        [throttle]
+        messages_per_min_chat = 10
+
+        [newsletters.announcements]
+        tags = "#новости"
+        "##,
+    )
+    .unwrap();
+    assert_eq!(
+        config.token,
+        TokenSource::File(PathBuf::from("/run/secrets/token"))
+    );
+    assert_eq!(
+        config.database.path,
+        PathBuf::from("/var/lib/hse-eco-bot/bot.sqlite")
+    );
+    assert_eq!(config.kb.archive_dir, "Рассылки");
+    assert_eq!(
+        config.kb.directories,
+        [
+            PathBuf::from("kb/Сортировка"),
+            PathBuf::from("/srv/kb/Проекты")
+        ],
+    );
+    assert_eq!(config.trash.retention_days, 7);
+    assert_eq!(config.throttle.messages_per_min_chat, 10);
+    assert_eq!(config.throttle.messages_per_sec_chat, 1);
+    assert_eq!(
+        config.admin_users().collect::<Vec<_>>(),
+        [UserId(42), UserId(43)]
+    );
+    assert_eq!(
+        config.newsletter("announcements"),
+        NewsletterDefinition {
+            description: None,
+            tags: Some(String::from("#новости")),
+        },
+    );
+    assert_eq!(
+        config.newsletter("feedback"),
+        NewsletterDefinition::default()
+    );
+}
+
+#[test]
+fn invalid_config_refused() {
+    assert!(matches!(
+        Config::parse("[database]\nfile = \"bot.sqlite\""),
+        Err(ConfigError::Syntax(_)),
+    ));
+    assert!(matches!(
+        Config::parse("[token]\nenv = \"TOKEN\"\nvalue = \"123:abc\""),
+        Err(ConfigError::Syntax(_)),
+    ));
+    assert!(matches!(
+        Config::parse("admins = [-1]"),
+        Err(ConfigError::Syntax(_)),
+    ));
+    assert_eq!(
+        Config::parse("[token]\nvalue = \" \""),
+        invalid("token.value", "must not be empty"),
+    );
+    assert_eq!(
+        Config::parse("[kb]\narchive_dir = \"\""),
+        invalid("kb.archive_dir", "must not be empty"),
+    );
+    assert_eq!(
+        Config::parse("[kb]\ndirectories = [\"kb\", \"\"]"),
+        invalid("kb.directories", "paths must not be empty"),
+    );
+    assert_eq!(
+        Config::parse("[trash]\nretention_days = 0"),
+        invalid("trash.retention_days", "must be positive"),
+    );
+    assert_eq!(
+        Config::parse("[throttle]\nmessages_per_sec_overall = 0"),
+        invalid("throttle.messages_per_sec_overall", "must be positive"),
+    );
+    assert_eq!(
+        Config::parse("admins = [0]"),
+        invalid("admins", "user IDs must be positive"),
+    );
+    assert_eq!(
+        Config::parse("[newsletters.news]\ntags = \"#новости\""),
+        invalid("newsletters.news", "unknown newsletter"),
+    );
+    assert_eq!(
+        Config::parse("[newsletters.feedback]\ndescription = \"\""),
+        invalid("newsletters.feedback.description", "must not be empty"),
+    );
+}
+
+#[test]
+fn newsletter_overridden() {
+    let config = Config::parse(
+        r#"
+        [newsletters.feedback]
+        description = "Отзывы"
+        "#,
+    )
+    .unwrap();
+    let (feedback, _feedback_tx) = FeedbackNewsletter::new();
+    let feedback = ConfiguredNewsletter::new(feedback, &config);
+    assert_eq!(feedback.name(), "feedback");
+    assert_eq!(feedback.description(), "Отзывы");
+    assert_eq!(feedback.tags(), "#обратнаясвязь");
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn config_path_parsed() {
+    assert_eq!(
+        cli::parse_invocation(args(&[])),
+        Ok(Invocation {
+            config: None,
+            command: Command::Run,
+        }),
+    );
+    assert_eq!(
+        cli::parse_invocation(args(&["--config", "bot.toml", "export", "backup"])),
+        Ok(Invocation {
+            config: Some(PathBuf::from("bot.toml")),
+            command: Command::Export {
+                destination: PathBuf::from("backup"),
+                format: TextFormat::Markdown,
+            },
+        }),
+    );
+    assert_eq!(
+        cli::parse_invocation(args(&["--config"])),
+        Err(CliError::MissingValue(String::from("--config"))),
+    );
+    assert_eq!(
+        cli::parse_invocation(args(&["--verbose", "run"])),
+        Err(CliError::UnknownOption(String::from("--verbose"))),
+    );
+    // The options of the commands are not global.
+    assert_eq!(
+        cli::parse_invocation(args(&["run", "--config", "bot.toml"])),
+        Err(CliError::UnknownOption(String::from("--config"))),
+    );
+}
@@ -1,22 +1,30 @@
-use crate::kb::{ItemRef, Note, ProviderError, Tree};
+use crate::config::DEFAULT_ARCHIVE_DIR;
+use crate::kb::{DirectoryRef, ItemRef, Note, ProviderError, Tree};
+use crate::media::{Attachment, Document, File, Image};
 use crate::message::FormattedText;
-use crate::util::UnsafeRc;
-use rusqlite::Connection;
-
-fn make_tree() -> (Tree, UnsafeRc<Connection>) {
-    let db = Connection::open_in_memory().unwrap();
-    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
-    let rc = unsafe { UnsafeRc::new(db) };
-    let tree = unsafe { Tree::new(UnsafeRc::clone(&rc)) };
-    (tree, rc)
+use crate::newsletter::feedback::FeedbackNewsletter;
+use crate::newsletter::Newsletter;
+use crate::tests::fixtures::{make_db, make_tree, uctx};
+use teloxide::types::MessageEntity;
+
+/// List the children of a directory except for the newsletter archive mount point.
+fn read_without_archive<'c>(dir: &DirectoryRef<'c>) -> Vec<(String, ItemRef<'c>)> {
+    dir.read(uctx())
+        .unwrap()
+        .children
+        .into_iter()
+        .filter(|(name, _)| name != DEFAULT_ARCHIVE_DIR)
+        .collect()
 }
 
 #[test]
 fn kb_initially_empty() {
-    let tree = make_tree().0;
+    let tree = make_tree();
     let root = tree.root_directory_ref().unwrap();
-    let dir = root.read().unwrap();
-    assert!(dir.children.is_empty(), "KB not empty: {:?}", &dir.children);
+    let dir = root.read(uctx()).unwrap();
+    // Only the mount point of the newsletter archive is present.
+    assert_eq!(dir.children.len(), 1, "KB not empty: {:?}", &dir.children);
+    assert_eq!(dir.children[0].0, DEFAULT_ARCHIVE_DIR);
 }
 
 #[test]
@@ -26,102 +34,107 @@ fn note_create_read_delete_ok() {
             raw_text: String::from("This is a test note"),
             entities: None,
         },
+        attachments: Vec::new(),
     };
 
-    let tree = make_tree().0;
+    let tree = make_tree();
     let root = tree.root_directory_ref().unwrap();
-    root.create_note(my_note.clone(), "Test note").unwrap();
-    let dir = root.read().unwrap();
-    assert_eq!(dir.children.len(), 1);
-    assert_eq!(dir.children[0].0, "Test note");
-    let note_ref = match dir.children[0].1 {
+    root.create_note(uctx(), my_note.clone(), "Test note").unwrap();
+    let children = read_without_archive(&root);
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0].0, "Test note");
+    let note_ref = match children[0].1 {
         ItemRef::Directory(_) => panic!("Created note is a directory"),
         ItemRef::Note(note_ref) => note_ref,
     };
-    let note = note_ref.read().unwrap();
+    let note = note_ref.read(uctx()).unwrap();
     assert_eq!(note, my_note);
 
-    assert_eq!(note_ref.name().unwrap(), "Test note");
+    assert_eq!(note_ref.name(uctx()).unwrap(), "Test note");
 
-    note_ref.delete().unwrap();
+    note_ref.delete(uctx()).unwrap();
 
-    let dir = root.read().unwrap();
-    assert!(dir.children.is_empty(), "KB not empty: {:?}", &dir.children);
+    let children = read_without_archive(&root);
+    assert!(children.is_empty(), "KB not empty: {:?}", &children);
 }
 
 #[test]
 fn root_dir_ok() {
-    let tree = make_tree().0;
+    let tree = make_tree();
     let root = tree.root_directory_ref().unwrap();
-    assert_eq!(root.name().unwrap(), None);
-    assert_eq!(root.move_to(root.id()), Err(ProviderError::CannotMoveRoot));
-    assert_eq!(root.rename("New root"), Err(ProviderError::CannotRenameRoot));
-    assert_eq!(root.delete(), Err(ProviderError::CannotDeleteRoot));
+    assert_eq!(root.name(uctx()).unwrap(), None);
+    assert_eq!(root.move_to(uctx(), root.id()), Err(ProviderError::CannotMoveRoot));
+    assert_eq!(root.rename(uctx(), "New root"), Err(ProviderError::CannotRenameRoot));
+    assert_eq!(root.delete(uctx()), Err(ProviderError::CannotDeleteRoot));
 }
 
 #[test]
 fn dir_create_rename_delete_ok() {
-    let tree = make_tree().0;
+    let tree = make_tree();
     let root = tree.root_directory_ref().unwrap();
-    root.create_directory("foo").unwrap();
-    let dir = root.read().unwrap();
-    assert_eq!(dir.children.len(), 1);
-    assert_eq!(dir.children[0].0, "foo");
-    let dir_ref = match dir.children[0].1 {
+    root.create_directory(uctx(), "foo").unwrap();
+    let children = read_without_archive(&root);
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0].0, "foo");
+    let dir_ref = match children[0].1 {
         ItemRef::Directory(dir_ref) => dir_ref,
         ItemRef::Note(_) => panic!("Created directory is a note"),
     };
-    assert_eq!(dir_ref.name().unwrap().as_deref(), Some("foo"));
+    assert_eq!(dir_ref.name(uctx()).unwrap().as_deref(), Some("foo"));
 
-    dir_ref.rename("bar").unwrap();
-    assert_eq!(dir_ref.name().unwrap().as_deref(), Some("bar"));
-    let dir = root.read().unwrap();
-    assert_eq!(dir.children.len(), 1);
-    assert_eq!(dir.children[0].0, "bar");
+    dir_ref.rename(uctx(), "bar").unwrap();
+    assert_eq!(dir_ref.name(uctx()).unwrap().as_deref(), Some("bar"));
+    let children = read_without_archive(&root);
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0].0, "bar");
 
-    dir_ref.delete().unwrap();
-    let dir = root.read().unwrap();
-    assert!(dir.children.is_empty());
+    dir_ref.delete(uctx()).unwrap();
+    let children = read_without_archive(&root);
+    assert!(children.is_empty());
 }
 
 #[test]
 fn moves_renames_ok() {
-    let tree = make_tree().0;
+    let tree = make_tree();
     let root = tree.root_directory_ref().unwrap();
-    let foo = root.create_directory("foo").unwrap();
-    let bar = root.create_directory("bar").unwrap();
-    let baz = bar.create_directory("baz").unwrap();
+    let foo = root.create_directory(uctx(), "foo").unwrap();
+    let bar = root.create_directory(uctx(), "bar").unwrap();
+    let baz = bar.create_directory(uctx(), "baz").unwrap();
     let aaa = root
         .create_note(
+            uctx(),
             Note {
                 text: FormattedText {
                     raw_text: String::from("AAA"),
                     entities: None,
                 },
+                attachments: Vec::new(),
             },
             "aaa",
         )
         .unwrap();
     let bbb = foo
         .create_note(
+            uctx(),
             Note {
                 text: FormattedText {
                     raw_text: String::from("BBB"),
                     entities: None,
                 },
+                attachments: Vec::new(),
             },
             "bbb",
         )
         .unwrap();
 
-    aaa.rename("Aaa").unwrap();
-    baz.rename("quux").unwrap();
+    aaa.rename(uctx(), "Aaa").unwrap();
+    baz.rename(uctx(), "quux").unwrap();
 
-    assert_eq!(aaa.name().unwrap(), "Aaa");
-    assert_eq!(baz.name().unwrap().as_deref(), Some("quux"));
+    assert_eq!(aaa.name(uctx()).unwrap(), "Aaa");
+    assert_eq!(baz.name(uctx()).unwrap().as_deref(), Some("quux"));
 
     assert_eq!(
-        root.read()
+        root.read(uctx())
             .unwrap()
             .children
             .into_iter()
@@ -133,7 +146,7 @@ fn moves_renames_ok() {
     );
 
     assert_eq!(
-        bar.read()
+        bar.read(uctx())
             .unwrap()
             .children
             .into_iter()
@@ -146,9 +159,9 @@ fn moves_renames_ok() {
 
     let quux = baz;
 
-    quux.move_to(root.id()).unwrap();
+    quux.move_to(uctx(), root.id()).unwrap();
     assert_eq!(
-        root.read()
+        root.read(uctx())
             .unwrap()
             .children
             .into_iter()
@@ -159,7 +172,7 @@ fn moves_renames_ok() {
         "`quux` not in `root`",
     );
     assert_eq!(
-        bar.read()
+        bar.read(uctx())
             .unwrap()
             .children
             .into_iter()
@@ -170,9 +183,9 @@ fn moves_renames_ok() {
         "`quux` in `bar`",
     );
 
-    quux.move_to(bar.id()).unwrap();
+    quux.move_to(uctx(), bar.id()).unwrap();
     assert_eq!(
-        root.read()
+        root.read(uctx())
             .unwrap()
             .children
             .into_iter()
@@ -183,7 +196,7 @@ fn moves_renames_ok() {
         "`quux` in `root`",
     );
     assert_eq!(
-        bar.read()
+        bar.read(uctx())
             .unwrap()
             .children
             .into_iter()
@@ -194,9 +207,9 @@ fn moves_renames_ok() {
         "`quux` not in `bar`",
     );
 
-    bbb.move_to(root.id()).unwrap();
+    bbb.move_to(uctx(), root.id()).unwrap();
     assert_eq!(
-        root.read()
+        root.read(uctx())
             .unwrap()
             .children
             .into_iter()
@@ -207,7 +220,7 @@ fn moves_renames_ok() {
         "`bbb` not in root",
     );
     assert_eq!(
-        foo.read()
+        foo.read(uctx())
             .unwrap()
             .children
             .into_iter()
@@ -218,69 +231,223 @@ fn moves_renames_ok() {
         "`bbb` in `foo`",
     );
 
-    assert_eq!(bar.move_to(quux.id()), Err(ProviderError::WouldCreateLoop));
+    assert_eq!(bar.move_to(uctx(), quux.id()), Err(ProviderError::WouldCreateLoop));
 }
 
 #[test]
 fn name_conflicts_ok() {
-    let tree = make_tree().0;
+    let tree = make_tree();
     let root = tree.root_directory_ref().unwrap();
-    let foo = root.create_directory("foo").unwrap();
-    let bar = root.create_directory("bar").unwrap();
-    let baz = bar.create_directory("baz").unwrap();
+    let foo = root.create_directory(uctx(), "foo").unwrap();
+    let bar = root.create_directory(uctx(), "bar").unwrap();
+    let baz = bar.create_directory(uctx(), "baz").unwrap();
     let aaa = foo
         .create_note(
+            uctx(),
             Note {
                 text: FormattedText {
                     raw_text: String::from("AAA"),
                     entities: None,
                 },
+                attachments: Vec::new(),
             },
             "aaa",
         )
         .unwrap();
     let _bbb = foo
         .create_note(
+            uctx(),
             Note {
                 text: FormattedText {
                     raw_text: String::from("BBB"),
                     entities: None,
                 },
+                attachments: Vec::new(),
             },
             "bbb",
         )
         .unwrap();
     let ccc = bar
         .create_note(
+            uctx(),
             Note {
                 text: FormattedText {
                     raw_text: String::from("CCC"),
                     entities: None,
                 },
+                attachments: Vec::new(),
             },
             "ccc",
         )
         .unwrap();
 
     assert_eq!(
-        foo.rename("bar"),
+        foo.rename(uctx(), "bar"),
         Err(ProviderError::TargetNameAlreadyExists(String::from("bar"))),
     );
 
     assert_eq!(
-        aaa.rename("bbb"),
+        aaa.rename(uctx(), "bbb"),
         Err(ProviderError::TargetNameAlreadyExists(String::from("bbb"))),
     );
 
-    baz.rename("foo").unwrap();
+    baz.rename(uctx(), "foo").unwrap();
     assert_eq!(
-        baz.move_to(root.id()),
+        baz.move_to(uctx(), root.id()),
         Err(ProviderError::TargetNameAlreadyExists(String::from("foo"))),
     );
     
-    ccc.rename("bbb").unwrap();
+    ccc.rename(uctx(), "bbb").unwrap();
     assert_eq!(
-        ccc.move_to(foo.id()),
+        ccc.move_to(uctx(), foo.id()),
         Err(ProviderError::TargetNameAlreadyExists(String::from("bbb"))),
     );
 }
+
+fn formatted_note(raw_text: &str) -> Note {
+    Note {
+        text: FormattedText {
+            raw_text: String::from(raw_text),
+            entities: Some(vec![
+                MessageEntity::bold(0, 4),
+                MessageEntity::spoiler(5, 6),
+                MessageEntity::text_link("https://example.com".parse().unwrap(), 12, 4),
+            ]),
+        },
+        attachments: Vec::new(),
+    }
+}
+
+#[test]
+fn note_entities_round_trip_ok() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+
+    let note = formatted_note("Bold hidden link");
+    let note_ref = root.create_note(uctx(), note.clone(), "Formatted").unwrap();
+    assert_eq!(note_ref.read(uctx()).unwrap(), note);
+
+    let updated = Note {
+        text: FormattedText {
+            raw_text: String::from("Bold text"),
+            entities: Some(vec![MessageEntity::bold(0, 4)]),
+        },
+        attachments: Vec::new(),
+    };
+    note_ref.write(uctx(), updated.clone()).unwrap();
+    assert_eq!(note_ref.read(uctx()).unwrap(), updated);
+
+    // An empty list of entities is distinct from no entities at all.
+    let no_entities = Note {
+        text: FormattedText {
+            raw_text: String::from("Plain"),
+            entities: Some(Vec::new()),
+        },
+        attachments: Vec::new(),
+    };
+    note_ref.write(uctx(), no_entities.clone()).unwrap();
+    assert_eq!(note_ref.read(uctx()).unwrap(), no_entities);
+}
+
+#[test]
+fn note_attachments_round_trip_ok() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+
+    let mut note = formatted_note("Bold hidden link");
+    note.attachments = vec![
+        Attachment::Image(Image {
+            file: File {
+                id: String::from("photo"),
+            },
+        }),
+        Attachment::Document(Document {
+            file: File {
+                id: String::from("document"),
+            },
+        }),
+    ];
+    let note_ref = root.create_note(uctx(), note.clone(), "With media").unwrap();
+    assert_eq!(note_ref.read(uctx()).unwrap(), note);
+
+    note.attachments.truncate(1);
+    note_ref.write(uctx(), note.clone()).unwrap();
+    assert_eq!(note_ref.read(uctx()).unwrap(), note);
+
+    note.attachments.clear();
+    note_ref.write(uctx(), note.clone()).unwrap();
+    assert_eq!(note_ref.read(uctx()).unwrap(), note);
+}
+
+#[test]
+fn archived_note_entities_round_trip_ok() {
+    let (feedback, _feedback_tx) = FeedbackNewsletter::new();
+    let (tree, provider_registry, sink) =
+        unsafe { Tree::new(make_db(), &[&feedback], DEFAULT_ARCHIVE_DIR) }.unwrap();
+
+    let note = formatted_note("Bold hidden link");
+    let note_id = sink
+        .store(&feedback.name(), note.clone(), chrono::Local::now())
+        .unwrap();
+    let note_ref = tree
+        .make_note_ref(provider_registry["newsletter-archive"], note_id)
+        .unwrap();
+    assert_eq!(note_ref.read(uctx()).unwrap(), note);
+}
+
+#[test]
+fn archived_note_attachments_round_trip_ok() {
+    let (feedback, _feedback_tx) = FeedbackNewsletter::new();
+    let (tree, provider_registry, sink) =
+        unsafe { Tree::new(make_db(), &[&feedback], DEFAULT_ARCHIVE_DIR) }.unwrap();
+
+    let mut note = formatted_note("Bold hidden link");
+    note.attachments = vec![Attachment::Image(Image {
+        file: File {
+            id: String::from("photo"),
+        },
+    })];
+    let note_id = sink
+        .store(&feedback.name(), note.clone(), chrono::Local::now())
+        .unwrap();
+    let note_ref = tree
+        .make_note_ref(provider_registry["newsletter-archive"], note_id)
+        .unwrap();
+    assert_eq!(note_ref.read(uctx()).unwrap(), note);
+}
+
+#[test]
+fn item_paths_ok() {
+    let (feedback, _feedback_tx) = FeedbackNewsletter::new();
+    let (tree, provider_registry, sink) =
+        unsafe { Tree::new(make_db(), &[&feedback], DEFAULT_ARCHIVE_DIR) }.unwrap();
+
+    let root = tree.root_directory_ref().unwrap();
+    assert_eq!(root.path(uctx()).unwrap(), Vec::<String>::new());
+    let dir = root.create_directory(uctx(), "Отходы").unwrap();
+    let subdir = dir.create_directory(uctx(), "Опасные").unwrap();
+    let note_ref = subdir
+        .create_note(uctx(), formatted_note("Text"), "Батарейки")
+        .unwrap();
+    assert_eq!(subdir.path(uctx()).unwrap(), vec!["Отходы", "Опасные"]);
+    assert_eq!(
+        note_ref.path(uctx()).unwrap(),
+        vec!["Отходы", "Опасные", "Батарейки"]
+    );
+
+    // The path of an archived note continues through the mount point of the archive.
+    let note_id = sink
+        .store(
+            &feedback.name(),
+            formatted_note("Text"),
+            chrono::Local::now(),
+        )
+        .unwrap();
+    let archived_ref = tree
+        .make_note_ref(provider_registry["newsletter-archive"], note_id)
+        .unwrap();
+    let path = archived_ref.path(uctx()).unwrap();
+    assert_eq!(path.len(), 3, "Unexpected path: {:?}", &path);
+    assert_eq!(path[..2], [DEFAULT_ARCHIVE_DIR, "Обратная связь"]);
+    assert_eq!(path[2], archived_ref.name(uctx()).unwrap());
+}
@@ -0,0 +1,165 @@
+use crate::events::{CalendarPeriod, Event, EventStorage};
+use crate::media::LocationOrAddress;
+use crate::message::FormattedText;
+use crate::util::UnsafeRc;
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::Connection;
+use teloxide::types::UserId;
+
+fn make_storage() -> EventStorage {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
+    // The users who request reminders.
+    db.execute_batch("INSERT INTO users(id, chat_id) VALUES (1, 1), (2, 2)")
+        .unwrap();
+    unsafe { EventStorage::new(UnsafeRc::new(db)) }
+}
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn time(year: i32, month: u32, day: u32, hour: u32) -> NaiveDateTime {
+    date(year, month, day).and_hms_opt(hour, 0, 0).unwrap()
+}
+
+fn event(title: &str, start: NaiveDateTime, end: NaiveDateTime) -> Event {
+    Event {
+        title: String::from(title),
+        description: FormattedText {
+            raw_text: String::from("Description"),
+            entities: None,
+        },
+        start,
+        end,
+        location: LocationOrAddress::Address(String::from("Pokrovsky Blvd, 11")),
+    }
+}
+
+fn titles(events: Vec<(u64, Event)>) -> Vec<String> {
+    events.into_iter().map(|(_, event)| event.title).collect()
+}
+
+#[test]
+fn event_create_get_ok() {
+    let storage = make_storage();
+    let original = event("Cleanup", time(2023, 9, 1, 10), time(2023, 9, 1, 12));
+    let id = storage.create(&original).unwrap();
+    assert_eq!(storage.get(id).unwrap(), Some(original));
+    assert_eq!(storage.get(id + 1).unwrap(), None);
+}
+
+#[test]
+fn event_update_delete_ok() {
+    let storage = make_storage();
+    let id = storage
+        .create(&event("Cleanup", time(2023, 9, 1, 10), time(2023, 9, 1, 12)))
+        .unwrap();
+    let updated = event("Tree planting", time(2023, 9, 2, 10), time(2023, 9, 2, 12));
+    assert!(storage.update(id, &updated).unwrap());
+    assert_eq!(storage.get(id).unwrap(), Some(updated.clone()));
+    assert!(!storage.update(id + 1, &updated).unwrap());
+    assert!(storage.delete(id).unwrap());
+    assert!(!storage.delete(id).unwrap());
+    assert_eq!(storage.get(id).unwrap(), None);
+}
+
+#[test]
+fn events_between_ok() {
+    let storage = make_storage();
+    for e in [
+        event("Late", time(2023, 9, 7, 18), time(2023, 9, 8, 2)),
+        event("Before", time(2023, 9, 3, 10), time(2023, 9, 3, 12)),
+        event("Multi-day", time(2023, 8, 30, 10), time(2023, 9, 5, 12)),
+        event("Inside", time(2023, 9, 5, 10), time(2023, 9, 5, 12)),
+        event("After", time(2023, 9, 11, 0), time(2023, 9, 11, 1)),
+    ] {
+        storage.create(&e).unwrap();
+    }
+    assert_eq!(
+        titles(storage.between(date(2023, 9, 4), date(2023, 9, 10)).unwrap()),
+        vec!["Multi-day", "Inside", "Late"],
+    );
+}
+
+#[test]
+fn event_reminders_ok() {
+    let storage = make_storage();
+    let id = storage
+        .create(&event("Cleanup", time(2023, 9, 1, 10), time(2023, 9, 1, 12)))
+        .unwrap();
+    assert!(!storage.has_reminder(id, UserId(1)).unwrap());
+    assert!(storage.add_reminder(id, UserId(1)).unwrap());
+    assert!(!storage.add_reminder(id, UserId(1)).unwrap());
+    assert!(storage.add_reminder(id, UserId(2)).unwrap());
+    assert!(storage.has_reminder(id, UserId(1)).unwrap());
+    assert!(storage.remove_reminder(id, UserId(2)).unwrap());
+    assert!(!storage.remove_reminder(id, UserId(2)).unwrap());
+    assert!(!storage.has_reminder(id, UserId(2)).unwrap());
+}
+
+#[test]
+fn take_due_reminder_ok() {
+    let storage = make_storage();
+    let requested = event("Requested", time(2023, 9, 1, 10), time(2023, 9, 1, 12));
+    let requested_id = storage.create(&requested).unwrap();
+    let not_requested_id = storage
+        .create(&event("Not requested", time(2023, 9, 1, 9), time(2023, 9, 1, 12)))
+        .unwrap();
+    let later_id = storage
+        .create(&event("Later", time(2023, 9, 3, 10), time(2023, 9, 3, 12)))
+        .unwrap();
+    storage.add_reminder(requested_id, UserId(1)).unwrap();
+    storage.add_reminder(requested_id, UserId(2)).unwrap();
+    storage.add_reminder(later_id, UserId(1)).unwrap();
+
+    let (from, until) = (time(2023, 8, 31, 10), time(2023, 9, 1, 10));
+    let (id, taken, mut users) = storage.take_due_reminder(from, until).unwrap().unwrap();
+    users.sort_by_key(|user_id| user_id.0);
+    assert_eq!(id, requested_id);
+    assert_eq!(taken, requested);
+    assert_eq!(users, vec![UserId(1), UserId(2)]);
+    assert!(storage.is_reminder_sent(requested_id).unwrap());
+    assert!(!storage.is_reminder_sent(not_requested_id).unwrap());
+    assert_eq!(storage.take_due_reminder(from, until).unwrap(), None);
+}
+
+#[test]
+fn reminder_resent_after_rescheduling() {
+    let storage = make_storage();
+    let original = event("Cleanup", time(2023, 9, 1, 10), time(2023, 9, 1, 12));
+    let id = storage.create(&original).unwrap();
+    storage.add_reminder(id, UserId(1)).unwrap();
+    let (from, until) = (time(2023, 8, 31, 0), time(2023, 9, 2, 0));
+    assert!(storage.take_due_reminder(from, until).unwrap().is_some());
+
+    let renamed = Event {
+        title: String::from("Big cleanup"),
+        ..original.clone()
+    };
+    storage.update(id, &renamed).unwrap();
+    assert!(storage.is_reminder_sent(id).unwrap());
+
+    let rescheduled = Event {
+        start: time(2023, 9, 1, 11),
+        ..renamed
+    };
+    storage.update(id, &rescheduled).unwrap();
+    assert!(!storage.is_reminder_sent(id).unwrap());
+    assert!(storage.take_due_reminder(from, until).unwrap().is_some());
+}
+
+#[test]
+fn calendar_period_ok() {
+    let week = CalendarPeriod::Week;
+    assert_eq!(week.first_day(date(2023, 9, 6)), date(2023, 9, 4));
+    assert_eq!(week.first_day(date(2023, 9, 4)), date(2023, 9, 4));
+    assert_eq!(week.next(date(2023, 9, 4)), date(2023, 9, 11));
+    assert_eq!(week.previous(date(2023, 9, 4)), date(2023, 8, 28));
+
+    let month = CalendarPeriod::Month;
+    assert_eq!(month.first_day(date(2023, 12, 31)), date(2023, 12, 1));
+    assert_eq!(month.next(date(2023, 12, 1)), date(2024, 1, 1));
+    assert_eq!(month.previous(date(2024, 1, 1)), date(2023, 12, 1));
+    assert_eq!(month.previous(date(2024, 3, 1)), date(2024, 2, 1));
+}
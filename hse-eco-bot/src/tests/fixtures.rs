@@ -0,0 +1,110 @@
+//! Fixtures shared by the tests of the knowledge base.
+
+use crate::config::DEFAULT_ARCHIVE_DIR;
+use crate::kb::{DirectoryRef, ItemRef, Note, NoteRef, ProviderUserContext, Tree};
+use crate::message::FormattedText;
+use crate::user::Permissions;
+use crate::util::UnsafeRc;
+use rusqlite::Connection;
+use std::fs;
+use std::path::PathBuf;
+use teloxide::types::UserId;
+
+/// The user on behalf of whom the changes are made in [`uctx`].
+pub const USER: UserId = UserId(42);
+
+/// Create a new in-memory database with the latest schema.
+pub fn make_db() -> UnsafeRc<Connection> {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
+    unsafe { UnsafeRc::new(db) }
+}
+
+/// Create a tree stored in the database, like the bot does on startup.
+pub fn open_tree(db: &UnsafeRc<Connection>) -> Tree {
+    unsafe { Tree::new(UnsafeRc::clone(db), &[], DEFAULT_ARCHIVE_DIR) }
+        .unwrap()
+        .0
+}
+
+/// Create a tree stored in a new in-memory database.
+pub fn make_tree() -> Tree {
+    open_tree(&make_db())
+}
+
+/// The context of [`USER`] with all the permissions.
+pub fn uctx() -> ProviderUserContext {
+    ProviderUserContext {
+        permissions: Permissions::all(),
+        user_id: Some(USER),
+    }
+}
+
+/// A note with plain text and no attachments.
+pub fn note(raw_text: &str) -> Note {
+    Note {
+        text: FormattedText {
+            raw_text: String::from(raw_text),
+            entities: None,
+        },
+        attachments: Vec::new(),
+    }
+}
+
+/// Find an item in a directory by its name.
+pub fn child<'c>(dir: &DirectoryRef<'c>, name: &str) -> ItemRef<'c> {
+    dir.read(uctx())
+        .unwrap()
+        .children
+        .into_iter()
+        .find(|(child_name, _)| child_name == name)
+        .unwrap_or_else(|| panic!("`{}` not found", name))
+        .1
+}
+
+pub fn child_directory<'c>(dir: &DirectoryRef<'c>, name: &str) -> DirectoryRef<'c> {
+    match child(dir, name) {
+        ItemRef::Directory(dir) => dir,
+        ItemRef::Note(_) => panic!("`{}` is a note", name),
+    }
+}
+
+pub fn child_note<'c>(dir: &DirectoryRef<'c>, name: &str) -> NoteRef<'c> {
+    match child(dir, name) {
+        ItemRef::Note(note) => note,
+        ItemRef::Directory(_) => panic!("`{}` is a directory", name),
+    }
+}
+
+/// The names of the items in a directory, sorted.
+pub fn child_names(dir: &DirectoryRef<'_>) -> Vec<String> {
+    let mut names: Vec<_> = dir
+        .read(uctx())
+        .unwrap()
+        .children
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+    names
+}
+
+/// An empty directory removed at the end of a test.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    /// Create the directory. The name must be unique among the tests.
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("hse-eco-bot-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
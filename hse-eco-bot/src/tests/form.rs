@@ -0,0 +1,157 @@
+use crate::media::{Attachment, Document, File, Image};
+use crate::message::{FormattedMessage, FormattedText};
+use crate::ui::form::{
+    Form, FormElement, FormFillingState, FormInput, FormInputType, FormProgress, FormRawInput,
+};
+
+fn image(id: &str) -> Image {
+    Image {
+        file: File {
+            id: String::from(id),
+        },
+    }
+}
+
+fn photo(id: &str, media_group_id: Option<&str>) -> FormRawInput {
+    FormRawInput::Photo {
+        image: image(id),
+        caption: None,
+        media_group_id: media_group_id.map(str::to_owned),
+    }
+}
+
+fn form(input_types: Vec<FormInputType>) -> FormFillingState {
+    FormFillingState::new(Form {
+        elements: input_types
+            .into_iter()
+            .map(|input_type| FormElement {
+                text: String::from("Element"),
+                input_type,
+            })
+            .collect(),
+    })
+}
+
+#[test]
+fn image_gallery_ok() {
+    let mut state = form(vec![FormInputType::ImageGallery]);
+    assert_eq!(
+        state.next(photo("a", None)).unwrap(),
+        FormProgress::Pending {
+            is_same_album: false
+        },
+    );
+    assert_eq!(
+        state.next(photo("b", Some("album"))).unwrap(),
+        FormProgress::Pending {
+            is_same_album: false
+        },
+    );
+    assert_eq!(
+        state.next(photo("c", Some("album"))).unwrap(),
+        FormProgress::Pending {
+            is_same_album: true
+        },
+    );
+    assert_eq!(state.pending_attachments().len(), 3);
+    assert!(!state.is_done());
+
+    assert_eq!(state.next(FormRawInput::Done).unwrap(), FormProgress::Next);
+    assert!(state.is_done());
+    let (_, input) = state.into_parts();
+    assert_eq!(
+        input,
+        vec![FormInput::ImageGallery {
+            images: vec![image("a"), image("b"), image("c")],
+        }],
+    );
+}
+
+#[test]
+fn image_gallery_back_discards_images() {
+    let mut state = form(vec![FormInputType::ShortText, FormInputType::ImageGallery]);
+    state
+        .next(FormRawInput::Text {
+            text: String::from("Name"),
+        })
+        .unwrap();
+    state.next(photo("a", None)).unwrap();
+    state.back();
+    assert!(state.pending_attachments().is_empty());
+    assert!(state.can_go_back());
+    state.back();
+    assert!(!state.can_go_back());
+}
+
+#[test]
+fn image_ignores_rest_of_album() {
+    let mut state = form(vec![FormInputType::Image, FormInputType::ShortText]);
+    assert_eq!(state.next(photo("a", Some("album"))).unwrap(), FormProgress::Next);
+    assert_eq!(state.next(photo("b", Some("album"))).unwrap(), FormProgress::SameAlbum);
+    assert!(state.next(photo("c", None)).is_err());
+    state
+        .next(FormRawInput::Text {
+            text: String::from("Text"),
+        })
+        .unwrap();
+    let (_, input) = state.into_parts();
+    assert_eq!(input[0], FormInput::Image { image: image("a") });
+}
+
+#[test]
+fn message_album_ok() {
+    let mut state = form(vec![FormInputType::Message, FormInputType::Message]);
+    let document = Attachment::Document(Document {
+        file: File {
+            id: String::from("document"),
+        },
+    });
+    let mut message = FormattedMessage::new(FormattedText {
+        raw_text: String::from("Caption"),
+        entities: None,
+    });
+    message.attachments = vec![document.clone()];
+
+    state.next(photo("a", Some("album"))).unwrap();
+    assert_eq!(
+        state
+            .next(FormRawInput::Message {
+                message: Box::new(message),
+                media_group_id: Some(String::from("album")),
+            })
+            .unwrap(),
+        FormProgress::Pending {
+            is_same_album: true
+        },
+    );
+    assert_eq!(state.next(FormRawInput::Done).unwrap(), FormProgress::Next);
+
+    // The text sent after an album completes the message.
+    state.next(photo("b", Some("another"))).unwrap();
+    state
+        .next(FormRawInput::Text {
+            text: String::from("Text"),
+        })
+        .unwrap();
+
+    let (_, input) = state.into_parts();
+    assert_eq!(
+        input,
+        vec![
+            FormInput::Message {
+                text: FormattedText {
+                    raw_text: String::from("Caption"),
+                    entities: None,
+                },
+                attachments: vec![Attachment::Image(image("a")), document],
+            },
+            FormInput::Message {
+                text: FormattedText {
+                    raw_text: String::from("Text"),
+                    entities: None,
+                },
+                attachments: vec![Attachment::Image(image("b"))],
+            },
+        ],
+    );
+}
@@ -0,0 +1,378 @@
+use crate::config::DEFAULT_ARCHIVE_DIR;
+use crate::kb::providers::fs::{self as kb_fs, LEGACY_MANIFEST_FILE_NAME};
+use crate::kb::{DirectoryRef, ItemId, ProviderError, ProviderId, Tree};
+use crate::tests::fixtures::{
+    child_directory, child_names, child_note, make_db, make_tree, note, open_tree, uctx, TempDir,
+};
+use crate::util::UnsafeRc;
+use std::fs;
+use std::path::Path;
+
+const MOUNT_DIR: &str = "Материалы";
+
+/// Create a directory with a few notes, removed at the end of a test.
+fn populated_dir(name: &str) -> TempDir {
+    let temp = TempDir::new(name);
+    fs::create_dir_all(temp.0.join("Отходы")).unwrap();
+    fs::write(
+        temp.0.join("Отходы").join("Стекло"),
+        "Стекло сдаётся отдельно.\n",
+    )
+    .unwrap();
+    fs::write(temp.0.join("Отходы").join("Бумага"), "Бумагу нужно сложить.").unwrap();
+    fs::write(temp.0.join("О проекте"), "Зелёная Вышка").unwrap();
+    temp
+}
+
+fn mount(tree: &mut Tree, root: &Path, read_only: bool) -> ProviderId {
+    kb_fs::mount(tree, MOUNT_DIR, root.to_owned(), read_only).unwrap()
+}
+
+fn mounted_root(tree: &Tree) -> DirectoryRef<'_> {
+    child_directory(&tree.root_directory_ref().unwrap(), MOUNT_DIR)
+}
+
+#[test]
+fn directory_tree_served() {
+    let temp = populated_dir("served");
+    let mut tree = make_tree();
+    let provider_id = mount(&mut tree, &temp.0, true);
+
+    let root = mounted_root(&tree);
+    assert_eq!(child_names(&root), ["О проекте", "Отходы"]);
+    let waste = child_directory(&root, "Отходы");
+    assert_eq!(waste.provider_id(), provider_id);
+    assert_eq!(child_names(&waste), ["Бумага", "Стекло"]);
+
+    let glass = child_note(&waste, "Стекло");
+    assert_eq!(
+        glass.read(uctx()).unwrap(),
+        note("Стекло сдаётся отдельно.")
+    );
+    assert_eq!(glass.path(uctx()).unwrap(), [MOUNT_DIR, "Отходы", "Стекло"]);
+    let parent = waste.parent(uctx()).unwrap().unwrap();
+    assert_eq!(parent.name(uctx()).unwrap().as_deref(), Some(MOUNT_DIR));
+    assert_eq!(
+        parent
+            .parent(uctx())
+            .unwrap()
+            .unwrap()
+            .name(uctx())
+            .unwrap(),
+        None
+    );
+
+    // The manifest is kept in the database, so the directory is not written to.
+    assert!(!temp.0.join(LEGACY_MANIFEST_FILE_NAME).exists());
+}
+
+#[test]
+fn legacy_manifest_read() {
+    let temp = populated_dir("legacy-manifest");
+    fs::write(
+        temp.0.join(LEGACY_MANIFEST_FILE_NAME),
+        r#"{"next_id": 10, "directories": {"Отходы": 7}, "notes": {"Отходы/Стекло": 8}}"#,
+    )
+    .unwrap();
+    let db = make_db();
+    let mut tree = open_tree(&db);
+    mount(&mut tree, &temp.0, true);
+    let root = mounted_root(&tree);
+    // The manifest file is not served as a note.
+    assert_eq!(child_names(&root), ["О проекте", "Отходы"]);
+    let waste = child_directory(&root, "Отходы");
+    assert_eq!(u64::from(waste.id()), 7);
+    assert_eq!(u64::from(child_note(&waste, "Стекло").id()), 8);
+    assert!(u64::from(child_note(&waste, "Бумага").id()) >= 10);
+
+    // The IDs are read from the database from now on.
+    fs::remove_file(temp.0.join(LEGACY_MANIFEST_FILE_NAME)).unwrap();
+    let mut tree = open_tree(&db);
+    mount(&mut tree, &temp.0, true);
+    let waste = child_directory(&mounted_root(&tree), "Отходы");
+    assert_eq!(u64::from(waste.id()), 7);
+}
+
+#[test]
+fn ids_kept_between_scans() {
+    let temp = populated_dir("ids");
+    let db = make_db();
+    let ids = {
+        let mut tree = open_tree(&db);
+        mount(&mut tree, &temp.0, true);
+        let root = mounted_root(&tree);
+        let waste = child_directory(&root, "Отходы");
+        (
+            waste.id(),
+            child_note(&waste, "Стекло").id(),
+            child_note(&waste, "Бумага").id(),
+        )
+    };
+
+    fs::remove_file(temp.0.join("Отходы").join("Бумага")).unwrap();
+    fs::write(temp.0.join("Отходы").join("Пластик"), "Пластик").unwrap();
+    fs::write(temp.0.join("Отходы").join("Бумага"), "Снова бумага").unwrap();
+
+    let mut tree = open_tree(&db);
+    mount(&mut tree, &temp.0, true);
+    let root = mounted_root(&tree);
+    let waste = child_directory(&root, "Отходы");
+    assert_eq!(waste.id(), ids.0);
+    assert_eq!(child_note(&waste, "Стекло").id(), ids.1);
+    // A file replaced between the scans keeps its ID, and new files get new ones.
+    assert_eq!(child_note(&waste, "Бумага").id(), ids.2);
+    let plastic = child_note(&waste, "Пластик").id();
+    assert!(plastic != ids.1 && plastic != ids.2);
+}
+
+#[test]
+fn read_only_tree_not_changed() {
+    let temp = populated_dir("read-only");
+    let mut tree = make_tree();
+    mount(&mut tree, &temp.0, true);
+    let root = mounted_root(&tree);
+    let waste = child_directory(&root, "Отходы");
+
+    assert_eq!(
+        waste
+            .create_note(uctx(), note("Металл"), "Металл")
+            .unwrap_err(),
+        ProviderError::OperationNotSupported,
+    );
+    assert_eq!(
+        child_note(&waste, "Стекло").delete(uctx()).unwrap_err(),
+        ProviderError::OperationNotSupported,
+    );
+    assert_eq!(
+        waste.rename(uctx(), "Мусор").unwrap_err(),
+        ProviderError::OperationNotSupported,
+    );
+    assert!(temp.0.join("Отходы").join("Стекло").exists());
+}
+
+#[test]
+fn writable_tree_changed() {
+    let temp = populated_dir("writable");
+    let db = make_db();
+    let mut tree = open_tree(&db);
+    mount(&mut tree, &temp.0, false);
+    let root = mounted_root(&tree);
+    let waste = child_directory(&root, "Отходы");
+
+    let hazardous = waste.create_directory(uctx(), "Опасные").unwrap();
+    let batteries = hazardous
+        .create_note(uctx(), note("Батарейки"), "Батарейки")
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(temp.0.join("Отходы/Опасные/Батарейки")).unwrap(),
+        "Батарейки",
+    );
+    assert_eq!(
+        waste
+            .create_note(uctx(), note("Стекло"), "Стекло")
+            .unwrap_err(),
+        ProviderError::TargetNameAlreadyExists(String::from("Стекло")),
+    );
+    assert_eq!(
+        waste.create_note(uctx(), note(""), ".hidden").unwrap_err(),
+        ProviderError::InvalidName(String::from(".hidden")),
+    );
+
+    // The mount point belongs to the root provider, so the items are moved to the root directory
+    // of the mounted tree.
+    let mounted_root_id = waste.parent(uctx()).unwrap().unwrap().id();
+    hazardous.move_to(uctx(), mounted_root_id).unwrap();
+    hazardous.rename(uctx(), "Опасные отходы").unwrap();
+    batteries
+        .write(uctx(), note("Батарейки сдаются в ящик."))
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(temp.0.join("Опасные отходы/Батарейки")).unwrap(),
+        "Батарейки сдаются в ящик.",
+    );
+    assert_eq!(
+        waste.move_to(uctx(), waste.id()).unwrap_err(),
+        ProviderError::WouldCreateLoop,
+    );
+    child_note(&waste, "Бумага").delete(uctx()).unwrap();
+    assert!(!temp.0.join("Отходы/Бумага").exists());
+
+    // The moved items keep their IDs after a rescan.
+    let ids = (hazardous.id(), batteries.id());
+    let mut tree = open_tree(&db);
+    mount(&mut tree, &temp.0, true);
+    let root = mounted_root(&tree);
+    let hazardous = child_directory(&root, "Опасные отходы");
+    assert_eq!(hazardous.id(), ids.0);
+    assert_eq!(child_note(&hazardous, "Батарейки").id(), ids.1);
+    assert_eq!(child_names(&child_directory(&root, "Отходы")), ["Стекло"]);
+
+    let mut tree = open_tree(&db);
+    mount(&mut tree, &temp.0, false);
+    let root = mounted_root(&tree);
+    child_directory(&root, "Опасные отходы")
+        .delete(uctx())
+        .unwrap();
+    assert!(!temp.0.join("Опасные отходы").exists());
+    assert_eq!(child_names(&root), ["О проекте", "Отходы"]);
+}
+
+#[test]
+fn tree_reloaded() {
+    let temp = populated_dir("reload");
+    let mut tree = make_tree();
+    let provider_id = mount(&mut tree, &temp.0, true);
+    let root = mounted_root(&tree);
+    let waste = child_directory(&root, "Отходы");
+    let paper = child_note(&waste, "Бумага").id();
+    let glass = child_note(&waste, "Стекло").id();
+    let about = child_note(&root, "О проекте").id();
+    let reload = tree.reload(uctx());
+    assert!(reload.vanished.is_empty() && reload.failed.is_empty());
+
+    fs::remove_file(temp.0.join("Отходы/Бумага")).unwrap();
+    fs::rename(temp.0.join("Отходы"), temp.0.join("Мусор")).unwrap();
+    fs::write(temp.0.join("Новости"), "Новости").unwrap();
+    let mut vanished = tree.reload(uctx()).vanished;
+    vanished.sort_by_key(|(_, item)| format!("{:?}", item));
+    let mut expected = vec![
+        (provider_id, ItemId::Directory(waste.id())),
+        (provider_id, ItemId::Note(paper)),
+        (provider_id, ItemId::Note(glass)),
+    ];
+    expected.sort_by_key(|(_, item)| format!("{:?}", item));
+    assert_eq!(vanished, expected);
+    assert_eq!(child_names(&root), ["Мусор", "Новости", "О проекте"]);
+    assert_eq!(child_note(&root, "О проекте").id(), about);
+    assert!(matches!(
+        waste.read(uctx()),
+        Err(ProviderError::NoSuchDirectory(id)) if id == waste.id(),
+    ));
+
+    // The previous tree is served if the directory cannot be read, and the other providers are
+    // still reloaded.
+    let other = populated_dir("reload-other");
+    kb_fs::mount(&mut tree, "Другие материалы", other.0.clone(), true).unwrap();
+    fs::remove_dir_all(&temp.0).unwrap();
+    fs::remove_file(other.0.join("О проекте")).unwrap();
+    let reload = tree.reload(uctx());
+    assert_eq!(reload.failed.len(), 1);
+    // The provider is named after the key it is registered under.
+    assert_eq!(reload.failed[0].0, format!("fs:{}", MOUNT_DIR));
+    assert!(matches!(reload.failed[0].1, ProviderError::IoError(_)));
+    assert_eq!(reload.vanished.len(), 1);
+    let root = mounted_root(&tree);
+    assert_eq!(child_names(&root), ["Мусор", "Новости", "О проекте"]);
+}
+
+#[test]
+fn notes_found() {
+    let temp = populated_dir("search");
+    let mut tree = make_tree();
+    mount(&mut tree, &temp.0, true);
+
+    let hits = tree.search_notes(uctx(), "стекло ОТДЕЛЬНО", 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].note.name(uctx()).unwrap(), "Стекло");
+    assert_eq!(hits[0].snippet, "Стекло сдаётся отдельно.");
+    assert!(tree.search_notes(uctx(), "металл", 10).unwrap().is_empty());
+
+    // The matches in the names come first, then the notes are ordered by their paths, and only
+    // then the number of hits is limited.
+    fs::write(
+        temp.0.join("Отходы").join("Металл"),
+        "Не бумага и не стекло",
+    )
+    .unwrap();
+    fs::write(temp.0.join("Бумага"), "Макулатура").unwrap();
+    tree.reload(uctx());
+    let paths = |query, limit| {
+        let hits = tree.search_notes(uctx(), query, limit).unwrap();
+        hits.iter()
+            .map(|hit| hit.note.path(uctx()).unwrap().join("/"))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        paths("бумага", 10),
+        [
+            format!("{}/Бумага", MOUNT_DIR),
+            format!("{}/Отходы/Бумага", MOUNT_DIR),
+            format!("{}/Отходы/Металл", MOUNT_DIR),
+        ],
+    );
+    assert_eq!(paths("бумага", 1), [format!("{}/Бумага", MOUNT_DIR)]);
+}
+
+#[test]
+fn provider_ids_kept_by_name() {
+    let temp = populated_dir("provider-ids");
+    let db = make_db();
+
+    let mut tree = open_tree(&db);
+    let first = kb_fs::mount(&mut tree, "Первый", temp.0.clone(), true).unwrap();
+    let second = kb_fs::mount(&mut tree, "Второй", temp.0.clone(), true).unwrap();
+    assert_ne!(first, second);
+
+    // The directories are mounted in the other order after a restart.
+    let mut tree = open_tree(&db);
+    assert_eq!(
+        kb_fs::mount(&mut tree, "Второй", temp.0.clone(), true).unwrap(),
+        second,
+    );
+    assert_eq!(
+        kb_fs::mount(&mut tree, "Первый", temp.0.clone(), true).unwrap(),
+        first,
+    );
+}
+
+#[test]
+fn mount_point_checked() {
+    let temp = populated_dir("mount-point");
+    let mut tree = make_tree();
+    {
+        let root = tree.root_directory_ref().unwrap();
+        let dir = root.create_directory(uctx(), MOUNT_DIR).unwrap();
+        dir.create_note(uctx(), note("Старая заметка"), "Старая").unwrap();
+    }
+    assert_eq!(
+        kb_fs::mount(&mut tree, MOUNT_DIR, temp.0.clone(), true).unwrap_err(),
+        ProviderError::MountPointNotEmpty,
+    );
+
+    // Directories with the same name cannot be mounted together.
+    kb_fs::mount(&mut tree, "Новый", temp.0.clone(), true).unwrap();
+    assert_eq!(
+        kb_fs::mount(&mut tree, "Новый", temp.0.clone(), true).unwrap_err(),
+        ProviderError::MountPointTaken,
+    );
+    assert_eq!(
+        kb_fs::mount(&mut tree, DEFAULT_ARCHIVE_DIR, temp.0.clone(), true).unwrap_err(),
+        ProviderError::MountPointTaken,
+    );
+}
+
+#[test]
+fn archive_dir_checked() {
+    let db = make_db();
+    let make_tree = |archive_dir| unsafe { Tree::new(UnsafeRc::clone(&db), &[], archive_dir) };
+    {
+        let (tree, _, _) = make_tree(DEFAULT_ARCHIVE_DIR).unwrap();
+        let root = tree.root_directory_ref().unwrap();
+        let dir = root.create_directory(uctx(), MOUNT_DIR).unwrap();
+        dir.create_note(uctx(), note("Старая заметка"), "Старая")
+            .unwrap();
+    }
+    assert_eq!(
+        make_tree(MOUNT_DIR).err().unwrap(),
+        ProviderError::MountPointNotEmpty,
+    );
+
+    // The previous archive directory is left as an empty directory.
+    let (tree, _, _) = make_tree("Архив").unwrap();
+    let root = tree.root_directory_ref().unwrap();
+    assert!(child_directory(&root, DEFAULT_ARCHIVE_DIR)
+        .read(uctx())
+        .unwrap()
+        .children
+        .is_empty());
+}
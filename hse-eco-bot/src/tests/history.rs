@@ -0,0 +1,216 @@
+use crate::kb::{ItemId, ItemRef, ProviderError, RevisionAction, RevisionId};
+use crate::tests::fixtures::{make_tree, note, uctx, USER};
+use crate::ui::history::{diff_lines, DiffLine};
+
+#[test]
+fn diff_lines_ok() {
+    assert_eq!(diff_lines("a\nb\nc", "a\nb\nc"), vec![]);
+    assert_eq!(
+        diff_lines("a\nb\nc", "a\nc\nd"),
+        vec![DiffLine::Removed("b"), DiffLine::Added("d")],
+    );
+    assert_eq!(
+        diff_lines("", "a\nb"),
+        vec![DiffLine::Added("a"), DiffLine::Added("b")],
+    );
+    assert_eq!(
+        diff_lines("x\na\ny", "x\nb\ny"),
+        vec![DiffLine::Removed("a"), DiffLine::Added("b")],
+    );
+}
+
+#[test]
+fn note_changes_recorded_and_restored() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let dir = root.create_directory(uctx(), "Отходы").unwrap();
+    let note_ref = root
+        .create_note(uctx(), note("Батарейки сдают на первом этаже"), "Батарейки")
+        .unwrap();
+    assert_eq!(note_ref.history(uctx(), 10).unwrap(), vec![]);
+
+    note_ref
+        .write(uctx(), note("Батарейки сдают на втором этаже"))
+        .unwrap();
+    note_ref.rename(uctx(), "Аккумуляторы").unwrap();
+    note_ref.move_to(uctx(), dir.id()).unwrap();
+
+    let history = note_ref.history(uctx(), 10).unwrap();
+    let actions: Vec<_> = history.iter().map(|revision| revision.action).collect();
+    assert_eq!(
+        actions,
+        vec![
+            RevisionAction::Move,
+            RevisionAction::Rename,
+            RevisionAction::Update
+        ],
+    );
+    assert!(history
+        .iter()
+        .all(|revision| revision.item == ItemId::Note(note_ref.id())));
+    assert!(history
+        .iter()
+        .all(|revision| revision.user_id == Some(USER)));
+    assert_eq!(history[0].parent, root.id());
+    assert_eq!(history[0].name, "Аккумуляторы");
+    assert_eq!(history[1].name, "Батарейки");
+    assert_eq!(
+        history[1].note,
+        Some(note("Батарейки сдают на втором этаже"))
+    );
+    assert_eq!(
+        history[2].note,
+        Some(note("Батарейки сдают на первом этаже"))
+    );
+    assert_eq!(note_ref.history(uctx(), 1).unwrap().len(), 1);
+
+    let restored = tree
+        .restore_revision(uctx(), note_ref.provider_id(), history[2].id)
+        .unwrap();
+    match restored {
+        ItemRef::Note(restored) => assert_eq!(restored.id(), note_ref.id()),
+        ItemRef::Directory(_) => panic!("a note has been restored as a directory"),
+    }
+    assert_eq!(
+        note_ref.read(uctx()).unwrap(),
+        note("Батарейки сдают на первом этаже")
+    );
+    assert_eq!(note_ref.name(uctx()).unwrap(), "Батарейки");
+    assert_eq!(note_ref.parent(uctx()).unwrap().id(), root.id());
+
+    let history = note_ref.history(uctx(), 10).unwrap();
+    assert_eq!(history.len(), 4);
+    assert_eq!(history[0].action, RevisionAction::Restore);
+    assert_eq!(history[0].name, "Аккумуляторы");
+    assert_eq!(history[0].parent, dir.id());
+}
+
+#[test]
+fn deleted_note_restored() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let note_ref = root
+        .create_note(uctx(), note("Пластик сдают в жёлтые контейнеры"), "Пластик")
+        .unwrap();
+    note_ref.delete(uctx()).unwrap();
+    assert!(matches!(
+        note_ref.read(uctx()),
+        Err(ProviderError::NoSuchNote(_))
+    ));
+
+    let history = root.history(uctx(), 10).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].action, RevisionAction::Delete);
+    assert_eq!(history[0].item, ItemId::Note(note_ref.id()));
+
+    tree.restore_revision(uctx(), root.provider_id(), history[0].id)
+        .unwrap();
+    assert_eq!(
+        note_ref.read(uctx()).unwrap(),
+        note("Пластик сдают в жёлтые контейнеры")
+    );
+    assert_eq!(note_ref.name(uctx()).unwrap(), "Пластик");
+    // The restored note is searchable again.
+    assert_eq!(
+        tree.search_notes(uctx(), "контейнеры", 10).unwrap().len(),
+        1
+    );
+    // The deletion is no longer listed in the history of the directory.
+    assert_eq!(root.history(uctx(), 10).unwrap(), vec![]);
+}
+
+#[test]
+fn deleted_subtree_restored() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let dir = root.create_directory(uctx(), "Отходы").unwrap();
+    let subdir = dir.create_directory(uctx(), "Опасные").unwrap();
+    let glass = dir.create_note(uctx(), note("Стекло"), "Стекло").unwrap();
+    let batteries = subdir
+        .create_note(uctx(), note("Батарейки"), "Батарейки")
+        .unwrap();
+    dir.delete(uctx()).unwrap();
+    assert!(matches!(
+        batteries.read(uctx()),
+        Err(ProviderError::NoSuchNote(_))
+    ));
+
+    // Only the deleted directory itself is listed, not everything in it.
+    let history = root.history(uctx(), 10).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].item, ItemId::Directory(dir.id()));
+    assert_eq!(history[0].name, "Отходы");
+
+    tree.restore_revision(uctx(), root.provider_id(), history[0].id)
+        .unwrap();
+    assert_eq!(dir.name(uctx()).unwrap().unwrap(), "Отходы");
+    assert_eq!(subdir.parent(uctx()).unwrap().unwrap().id(), dir.id());
+    assert_eq!(glass.read(uctx()).unwrap(), note("Стекло"));
+    assert_eq!(glass.parent(uctx()).unwrap().id(), dir.id());
+    assert_eq!(batteries.read(uctx()).unwrap(), note("Батарейки"));
+    assert_eq!(
+        batteries.path(uctx()).unwrap(),
+        vec!["Отходы", "Опасные", "Батарейки"]
+    );
+    assert_eq!(root.history(uctx(), 10).unwrap(), vec![]);
+}
+
+#[test]
+fn deleted_subtree_merged_after_restore() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let provider = root.provider_id();
+    let dir = root.create_directory(uctx(), "Отходы").unwrap();
+    let glass = dir.create_note(uctx(), note("Стекло"), "Стекло").unwrap();
+    dir.rename(uctx(), "Вторсырьё").unwrap();
+    let rename = dir.history(uctx(), 10).unwrap()[0].id;
+    dir.delete(uctx()).unwrap();
+    let deletion = root.history(uctx(), 10).unwrap()[0].id;
+
+    // The directory restored from an earlier revision is empty.
+    tree.restore_revision(uctx(), provider, rename).unwrap();
+    assert_eq!(dir.name(uctx()).unwrap().unwrap(), "Отходы");
+    assert!(dir.read(uctx()).unwrap().children.is_empty());
+
+    // Its deletion still brings back everything deleted along with it.
+    tree.restore_revision(uctx(), provider, deletion).unwrap();
+    assert_eq!(dir.name(uctx()).unwrap().unwrap(), "Вторсырьё");
+    assert_eq!(glass.read(uctx()).unwrap(), note("Стекло"));
+    assert_eq!(glass.parent(uctx()).unwrap().id(), dir.id());
+}
+
+#[test]
+fn restore_conflicts() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let provider = root.provider_id();
+    let note_ref = root.create_note(uctx(), note("Старая"), "Заметка").unwrap();
+    note_ref.delete(uctx()).unwrap();
+    root.create_note(uctx(), note("Новая"), "Заметка").unwrap();
+
+    let revision = root.history(uctx(), 10).unwrap()[0].id;
+    assert!(matches!(
+        tree.restore_revision(uctx(), provider, revision),
+        Err(ProviderError::TargetNameAlreadyExists(name)) if name == "Заметка"
+    ));
+    assert!(matches!(
+        note_ref.read(uctx()),
+        Err(ProviderError::NoSuchNote(_))
+    ));
+
+    let missing = RevisionId::from(1000);
+    assert!(matches!(
+        tree.restore_revision(uctx(), provider, missing),
+        Err(ProviderError::NoSuchRevision(id)) if id == missing
+    ));
+
+    // A note whose directory has been deleted is restored in the root directory.
+    let dir = root.create_directory(uctx(), "Раздел").unwrap();
+    let inner = dir.create_note(uctx(), note("Текст"), "Внутри").unwrap();
+    inner.rename(uctx(), "Внутри раздела").unwrap();
+    let revision = inner.history(uctx(), 10).unwrap()[0].id;
+    dir.delete(uctx()).unwrap();
+    tree.restore_revision(uctx(), provider, revision).unwrap();
+    assert_eq!(inner.parent(uctx()).unwrap().id(), root.id());
+    assert_eq!(inner.name(uctx()).unwrap(), "Внутри");
+}
@@ -0,0 +1,326 @@
+use crate::cli::{self, CliError, Command};
+use crate::kb::transfer::{
+    export_tree, import_directory, render, ConflictPolicy, TextFormat, TransferStats,
+};
+use crate::kb::{Note, ProviderError};
+use crate::media::{Attachment, Document, File};
+use crate::message::FormattedText;
+use crate::tests::fixtures::{
+    child_directory, child_names, child_note, make_tree, note, uctx, TempDir,
+};
+use std::fs;
+use std::path::PathBuf;
+use teloxide::types::{MessageEntity, UserId};
+
+fn text(raw_text: &str, entities: Option<Vec<MessageEntity>>) -> FormattedText {
+    FormattedText {
+        raw_text: String::from(raw_text),
+        entities,
+    }
+}
+
+fn document(id: &str) -> Attachment {
+    Attachment::Document(Document {
+        file: File {
+            id: String::from(id),
+        },
+    })
+}
+
+#[test]
+fn tree_imported() {
+    let temp = TempDir::new("import");
+    fs::create_dir(temp.0.join("Отходы")).unwrap();
+    fs::write(temp.0.join("Отходы/Стекло"), "\nСтекло сдаётся отдельно.\n").unwrap();
+    fs::write(temp.0.join("Отходы/Бумага"), "Бумагу нужно сложить.").unwrap();
+    fs::write(temp.0.join("О проекте"), "Зелёная Вышка").unwrap();
+    fs::write(temp.0.join(".hidden"), "").unwrap();
+
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let stats = import_directory(&root, uctx(), &temp.0, ConflictPolicy::Fail).unwrap();
+    assert_eq!(
+        stats,
+        TransferStats {
+            directories: 1,
+            notes: 3,
+            notes_with_attachments: 0,
+        },
+    );
+    assert_eq!(
+        child_names(&root),
+        ["Архив рассылок", "О проекте", "Отходы"]
+    );
+    let waste = child_directory(&root, "Отходы");
+    assert_eq!(child_names(&waste), ["Бумага", "Стекло"]);
+    assert_eq!(
+        child_note(&waste, "Стекло").read(uctx()).unwrap(),
+        note("Стекло сдаётся отдельно.")
+    );
+
+    // Nothing is imported if a name is taken.
+    fs::write(temp.0.join("Отходы/Стекло"), "Стекло сдаётся в контейнер.").unwrap();
+    fs::write(temp.0.join("Отходы/Пластик"), "Пластик").unwrap();
+    assert_eq!(
+        import_directory(&root, uctx(), &temp.0, ConflictPolicy::Fail).unwrap_err(),
+        ProviderError::TargetNameAlreadyExists(String::from("О проекте")),
+    );
+    assert_eq!(child_names(&waste), ["Бумага", "Стекло"]);
+
+    let stats = import_directory(&root, uctx(), &temp.0, ConflictPolicy::Merge).unwrap();
+    assert_eq!(
+        stats,
+        TransferStats {
+            directories: 0,
+            notes: 4,
+            notes_with_attachments: 0,
+        },
+    );
+    assert_eq!(child_names(&waste), ["Бумага", "Пластик", "Стекло"]);
+    assert_eq!(
+        child_note(&waste, "Стекло").read(uctx()).unwrap(),
+        note("Стекло сдаётся в контейнер.")
+    );
+
+    // A directory is imported into a nested directory.
+    let hazardous = TempDir::new("import-nested");
+    fs::write(hazardous.0.join("Батарейки"), "Батарейки").unwrap();
+    import_directory(&waste, uctx(), &hazardous.0, ConflictPolicy::Fail).unwrap();
+    assert_eq!(
+        child_note(&waste, "Батарейки").path(uctx()).unwrap(),
+        ["Отходы", "Батарейки"]
+    );
+}
+
+#[test]
+fn tree_exported() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let waste = root.create_directory(uctx(), "Отходы").unwrap();
+    waste
+        .create_note(
+            uctx(),
+            Note {
+                text: text(
+                    "Стекло сдаётся отдельно.",
+                    Some(vec![MessageEntity::bold(0, 6)]),
+                ),
+                attachments: Vec::new(),
+            },
+            "Стекло",
+        )
+        .unwrap();
+    waste
+        .create_note(
+            uctx(),
+            Note {
+                text: text("Бумага", None),
+                attachments: vec![document("paper")],
+            },
+            "Бумага",
+        )
+        .unwrap();
+    root.create_note(uctx(), note("Вопросы и ответы"), "Вопросы/ответы")
+        .unwrap();
+
+    let temp = TempDir::new("export");
+    let destination = temp.0.join("kb");
+    let stats = export_tree(&tree, uctx(), &destination, TextFormat::Markdown).unwrap();
+    assert_eq!(
+        stats,
+        TransferStats {
+            directories: 1,
+            notes: 3,
+            notes_with_attachments: 1,
+        },
+    );
+    assert_eq!(
+        fs::read_to_string(destination.join("Отходы/Стекло")).unwrap(),
+        "**Стекло** сдаётся отдельно.\n",
+    );
+    assert_eq!(
+        fs::read_to_string(destination.join("Вопросы_ответы")).unwrap(),
+        "Вопросы и ответы\n",
+    );
+    // The archive is mounted from another provider.
+    assert!(!destination.join("Архив рассылок").exists());
+
+    assert!(matches!(
+        export_tree(&tree, uctx(), &destination, TextFormat::Html).unwrap_err(),
+        ProviderError::IoError(_),
+    ));
+
+    // The exported tree can be imported back.
+    let copy = make_tree();
+    let copy_root = copy.root_directory_ref().unwrap();
+    import_directory(&copy_root, uctx(), &destination, ConflictPolicy::Fail).unwrap();
+    assert_eq!(
+        child_names(&copy_root),
+        ["Архив рассылок", "Вопросы_ответы", "Отходы"]
+    );
+    assert_eq!(
+        child_names(&child_directory(&copy_root, "Отходы")),
+        ["Бумага", "Стекло"]
+    );
+}
+
+#[test]
+fn import_keeps_attachments() {
+    let temp = TempDir::new("import-attachments");
+    fs::write(temp.0.join("Бумага"), "Бумагу нужно сложить.").unwrap();
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let paper = Note {
+        text: text("Бумага", Some(vec![MessageEntity::bold(0, 6)])),
+        attachments: vec![document("paper")],
+    };
+    root.create_note(uctx(), paper, "Бумага").unwrap();
+
+    import_directory(&root, uctx(), &temp.0, ConflictPolicy::Merge).unwrap();
+    assert_eq!(
+        child_note(&root, "Бумага").read(uctx()).unwrap(),
+        Note {
+            text: text("Бумагу нужно сложить.", None),
+            attachments: vec![document("paper")],
+        },
+    );
+}
+
+#[test]
+fn import_reads_everything_first() {
+    let temp = TempDir::new("import-unreadable");
+    fs::create_dir(temp.0.join("Отходы")).unwrap();
+    fs::write(temp.0.join("Отходы/Бумага"), "Бумагу нужно сложить.").unwrap();
+    fs::write(temp.0.join("Отходы/Стекло"), b"\xff\xfe").unwrap();
+    fs::write(temp.0.join("О проекте"), "Зелёная Вышка").unwrap();
+
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    assert!(matches!(
+        import_directory(&root, uctx(), &temp.0, ConflictPolicy::Fail).unwrap_err(),
+        ProviderError::IoError(_),
+    ));
+    assert_eq!(child_names(&root), ["Архив рассылок"]);
+}
+
+#[test]
+fn exported_names_unique() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    root.create_note(uctx(), note("Первая"), "Вопросы/ответы")
+        .unwrap();
+    root.create_note(uctx(), note("Вторая"), "Вопросы_ответы")
+        .unwrap();
+    root.create_directory(uctx(), "Вопросы\\ответы").unwrap();
+
+    let temp = TempDir::new("export-names");
+    let destination = temp.0.join("kb");
+    let stats = export_tree(&tree, uctx(), &destination, TextFormat::Markdown).unwrap();
+    assert_eq!((stats.directories, stats.notes), (1, 2));
+    let mut names: Vec<_> = fs::read_dir(&destination)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        ["Вопросы_ответы", "Вопросы_ответы (2)", "Вопросы_ответы (3)"]
+    );
+}
+
+#[test]
+fn text_rendered() {
+    // The emoji takes two UTF-16 code units.
+    let formatted = text(
+        "🌱 Сортировка: см. сайт",
+        Some(vec![
+            MessageEntity::bold(3, 10),
+            MessageEntity::italic(3, 4),
+            MessageEntity::text_link("https://example.com".parse().unwrap(), 19, 4),
+        ]),
+    );
+    assert_eq!(
+        render(&formatted, TextFormat::Markdown),
+        "🌱 **_Сорт_ировка**: см. [сайт](https://example.com/)",
+    );
+    assert_eq!(
+        render(&formatted, TextFormat::Html),
+        "🌱 <b><i>Сорт</i>ировка</b>: см. <a href=\"https://example.com/\">сайт</a>",
+    );
+
+    let special = text("x_y <z> & code*", Some(vec![MessageEntity::code(10, 5)]));
+    assert_eq!(
+        render(&special, TextFormat::Markdown),
+        "x\\_y \\<z> & `code*`",
+    );
+    assert_eq!(
+        render(&special, TextFormat::Html),
+        "x_y &lt;z&gt; &amp; <code>code*</code>",
+    );
+    assert_eq!(render(&text("", None), TextFormat::Html), "");
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn transfer_arguments_parsed() {
+    assert_eq!(
+        cli::parse_args(args(&["init-db", "--admin", "1", "--admin", "2"])),
+        Ok(Command::InitDb {
+            admins: vec![UserId(1), UserId(2)],
+        }),
+    );
+    assert_eq!(
+        cli::parse_args(args(&["import", "kb"])),
+        Ok(Command::Import {
+            source: PathBuf::from("kb"),
+            target: Vec::new(),
+            policy: ConflictPolicy::Fail,
+        }),
+    );
+    assert_eq!(
+        cli::parse_args(args(&[
+            "import",
+            "--merge",
+            "kb",
+            "--target",
+            "/Отходы/Пластик/"
+        ])),
+        Ok(Command::Import {
+            source: PathBuf::from("kb"),
+            target: vec![String::from("Отходы"), String::from("Пластик")],
+            policy: ConflictPolicy::Merge,
+        }),
+    );
+    assert_eq!(
+        cli::parse_args(args(&["export", "backup", "--format", "html"])),
+        Ok(Command::Export {
+            destination: PathBuf::from("backup"),
+            format: TextFormat::Html,
+        }),
+    );
+    assert_eq!(
+        cli::parse_args(args(&["import"])),
+        Err(CliError::MissingArgument(String::from("<dir>"))),
+    );
+    assert_eq!(
+        cli::parse_args(args(&["import", "kb", "more"])),
+        Err(CliError::UnknownOption(String::from("more"))),
+    );
+    assert_eq!(
+        cli::parse_args(args(&["export", "backup", "--format", "pdf"])),
+        Err(CliError::InvalidValue {
+            option: String::from("--format"),
+            value: String::from("pdf"),
+        }),
+    );
+    assert_eq!(
+        cli::parse_args(args(&["init-db", "--admin", "me"])),
+        Err(CliError::InvalidValue {
+            option: String::from("--admin"),
+            value: String::from("me"),
+        }),
+    );
+}
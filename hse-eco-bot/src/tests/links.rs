@@ -0,0 +1,87 @@
+use crate::db::{FullDirectoryId, FullItemId, FullNoteId};
+use crate::kb::providers::fs as kb_fs;
+use crate::links::{DeepLink, LinkStorage};
+use crate::tests::fixtures::{make_db, open_tree, TempDir};
+use crate::util::UnsafeRc;
+use rusqlite::Connection;
+
+fn make_storage() -> LinkStorage {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
+    unsafe { LinkStorage::new(UnsafeRc::new(db)) }
+}
+
+fn note(provider: u64, note: u64) -> FullItemId {
+    FullItemId::Note(FullNoteId {
+        provider: provider.into(),
+        note: note.into(),
+    })
+}
+
+fn directory(provider: u64, directory: u64) -> FullItemId {
+    FullItemId::Directory(FullDirectoryId {
+        provider: provider.into(),
+        directory: directory.into(),
+    })
+}
+
+#[test]
+fn parse_deep_link_ok() {
+    assert_eq!(
+        DeepLink::parse("note_0_42"),
+        Some(DeepLink::Item(note(0, 42)))
+    );
+    assert_eq!(
+        DeepLink::parse("dir_1_7"),
+        Some(DeepLink::Item(directory(1, 7)))
+    );
+    assert_eq!(
+        DeepLink::parse("kb_0a1b2c3d4e"),
+        Some(DeepLink::Slug(String::from("0a1b2c3d4e")))
+    );
+    assert_eq!(DeepLink::slug_payload("0a1b2c3d4e"), "kb_0a1b2c3d4e");
+}
+
+#[test]
+fn parse_deep_link_invalid() {
+    assert_eq!(DeepLink::parse(""), None);
+    assert_eq!(DeepLink::parse("note_0"), None);
+    assert_eq!(DeepLink::parse("note_0_x"), None);
+    assert_eq!(DeepLink::parse("dir_0_1_2"), None);
+    assert_eq!(DeepLink::parse("kb_"), None);
+    assert_eq!(DeepLink::parse("kb_a-b"), None);
+    assert_eq!(DeepLink::parse("event_1"), None);
+}
+
+#[test]
+fn link_slugs_are_stable() {
+    let storage = make_storage();
+    let note_slug = storage.get_or_create(note(0, 1)).unwrap();
+    let dir_slug = storage.get_or_create(directory(0, 1)).unwrap();
+    assert_ne!(note_slug, dir_slug);
+    assert_eq!(note_slug.len(), 10);
+    assert!(note_slug.bytes().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(storage.get_or_create(note(0, 1)).unwrap(), note_slug);
+
+    assert_eq!(storage.resolve(&note_slug).unwrap(), Some(note(0, 1)));
+    assert_eq!(storage.resolve(&dir_slug).unwrap(), Some(directory(0, 1)));
+    assert_eq!(storage.resolve("0000000000").unwrap(), None);
+}
+
+#[test]
+fn link_slugs_survive_remounting() {
+    let temp = TempDir::new("link-slugs");
+    let db = make_db();
+    let storage = unsafe { LinkStorage::new(UnsafeRc::clone(&db)) };
+
+    let mut tree = open_tree(&db);
+    kb_fs::mount(&mut tree, "Первый", temp.0.clone(), true).unwrap();
+    let second = kb_fs::mount(&mut tree, "Второй", temp.0.clone(), true).unwrap();
+    let slug = storage.get_or_create(note(second.into(), 1)).unwrap();
+
+    // The directories are mounted in the other order after a restart.
+    let mut tree = open_tree(&db);
+    let second = kb_fs::mount(&mut tree, "Второй", temp.0.clone(), true).unwrap();
+    kb_fs::mount(&mut tree, "Первый", temp.0.clone(), true).unwrap();
+    assert_eq!(storage.resolve(&slug).unwrap(), Some(note(second.into(), 1)));
+}
@@ -0,0 +1,28 @@
+use crate::message::FormattedText;
+use teloxide::types::MessageEntity;
+
+#[test]
+fn truncate_ok() {
+    let text = FormattedText {
+        raw_text: String::from("Пластик 🌍 и стекло"),
+        entities: Some(vec![
+            MessageEntity::bold(0, 7),
+            MessageEntity::italic(8, 4),
+            MessageEntity::underline(13, 6),
+        ]),
+    };
+    assert_eq!(text.clone().truncate(19), text);
+
+    // The emoji takes two UTF-16 code units and is not split.
+    let truncated = text.truncate(10);
+    assert_eq!(truncated.raw_text, "Пластик …");
+    assert_eq!(truncated.entities, Some(vec![MessageEntity::bold(0, 7)]));
+
+    let text = FormattedText {
+        raw_text: String::from("abcdef"),
+        entities: Some(vec![MessageEntity::bold(2, 4)]),
+    };
+    let truncated = text.truncate(5);
+    assert_eq!(truncated.raw_text, "abcd…");
+    assert_eq!(truncated.entities, Some(vec![MessageEntity::bold(2, 2)]));
+}
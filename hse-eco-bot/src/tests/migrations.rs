@@ -0,0 +1,159 @@
+use crate::migrations::{migrate, schema_version, MigrationError, LATEST_VERSION};
+use rusqlite::{params, Connection};
+
+fn has_table(db: &Connection, name: &str) -> bool {
+    db.prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?")
+        .unwrap()
+        .exists(params![name])
+        .unwrap()
+}
+
+/// The version of the schema which has added the audit log.
+const AUDIT_LOG_VERSION: u32 = 14;
+
+/// Make a database with the schema before the audit log, stored with the provided version.
+fn make_db_without_audit_log(version: u32) -> Connection {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
+    db.execute_batch("DROP TABLE audit_log; DROP TABLE kb_providers; DROP TABLE kb_fs_manifests")
+        .unwrap();
+    db.pragma_update(None, "user_version", version).unwrap();
+    db
+}
+
+#[test]
+fn new_database_created() {
+    let db = Connection::open_in_memory().unwrap();
+    migrate(&db).unwrap();
+    assert_eq!(schema_version(&db).unwrap(), LATEST_VERSION);
+    assert!(has_table(&db, "kb_notes"));
+    assert!(has_table(&db, "audit_log"));
+
+    // Migrating an up-to-date database changes nothing.
+    migrate(&db).unwrap();
+    assert_eq!(schema_version(&db).unwrap(), LATEST_VERSION);
+}
+
+#[test]
+fn missing_migrations_applied() {
+    let db = make_db_without_audit_log(AUDIT_LOG_VERSION - 1);
+    migrate(&db).unwrap();
+    assert_eq!(schema_version(&db).unwrap(), LATEST_VERSION);
+    assert!(has_table(&db, "audit_log"));
+    // The providers mounted before the IDs have been registered keep their IDs.
+    let archive_id: u64 = db
+        .query_row(
+            "SELECT id FROM kb_providers WHERE key = 'newsletter-archive'",
+            params![],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(archive_id, 1);
+}
+
+#[test]
+fn legacy_version_found() {
+    // The database has been migrated by hand before the version has been stored in it.
+    let db = make_db_without_audit_log(0);
+    migrate(&db).unwrap();
+    assert_eq!(schema_version(&db).unwrap(), LATEST_VERSION);
+    assert!(has_table(&db, "audit_log"));
+
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
+    migrate(&db).unwrap();
+    assert_eq!(schema_version(&db).unwrap(), LATEST_VERSION);
+}
+
+/// The schema of the databases created before the migrations, with permissions granted by
+/// usernames.
+const BASELINE_SCHEMA: &str = "
+    CREATE TABLE kb_notes (id INTEGER PRIMARY KEY AUTOINCREMENT, content TEXT NOT NULL);
+    CREATE TABLE kb_dirs (id INTEGER PRIMARY KEY AUTOINCREMENT);
+    CREATE TABLE kb_note_children (
+        parent_id INTEGER NOT NULL REFERENCES kb_dirs(id) ON DELETE CASCADE,
+        child_id INTEGER PRIMARY KEY REFERENCES kb_notes(id) ON DELETE CASCADE,
+        child_name TEXT NOT NULL,
+        UNIQUE (parent_id, child_name)
+    );
+    CREATE TABLE kb_dir_children (
+        parent_id INTEGER NOT NULL REFERENCES kb_dirs(id) ON DELETE CASCADE,
+        child_id INTEGER PRIMARY KEY REFERENCES kb_dirs(id) ON DELETE CASCADE,
+        child_name TEXT NOT NULL,
+        UNIQUE (parent_id, child_name)
+    );
+    CREATE TABLE kb_newsletters (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        content TEXT NOT NULL,
+        timestamp TEXT NOT NULL
+    );
+    CREATE TABLE permissions (
+        user TEXT UNIQUE NOT NULL,
+        edit_kb BOOL NOT NULL,
+        receive_feedback BOOL NOT NULL
+    );
+    INSERT INTO kb_dirs(id) VALUES (0);
+";
+
+#[test]
+fn username_permissions_kept() {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(BASELINE_SCHEMA).unwrap();
+    db.execute_batch(concat!(
+        "INSERT INTO permissions VALUES ('editor', TRUE, FALSE);\n",
+        "INSERT INTO permissions VALUES ('curator', TRUE, TRUE);\n",
+    ))
+    .unwrap();
+    migrate(&db).unwrap();
+    assert_eq!(schema_version(&db).unwrap(), LATEST_VERSION);
+    assert!(!has_table(&db, "permissions"));
+    let pending: Vec<(String, String)> = db
+        .prepare("SELECT username, role FROM pending_roles ORDER BY username, role")
+        .unwrap()
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let pending: Vec<_> = pending
+        .iter()
+        .map(|(username, role)| (username.as_str(), role.as_str()))
+        .collect();
+    assert_eq!(
+        pending,
+        [
+            ("curator", "editor"),
+            ("curator", "feedback"),
+            ("editor", "editor")
+        ],
+    );
+}
+
+#[test]
+fn failed_migration_rolled_back() {
+    let db = make_db_without_audit_log(AUDIT_LOG_VERSION - 1);
+    // The name of an index created by the migration is taken.
+    db.execute_batch("CREATE TABLE audit_log_by_time (id INTEGER)")
+        .unwrap();
+    assert!(matches!(
+        migrate(&db).unwrap_err(),
+        MigrationError::Failed {
+            migration: "0014_audit_log",
+            ..
+        },
+    ));
+    assert_eq!(schema_version(&db).unwrap(), AUDIT_LOG_VERSION - 1);
+    assert!(!has_table(&db, "audit_log"));
+}
+
+#[test]
+fn newer_schema_refused() {
+    let db = Connection::open_in_memory().unwrap();
+    db.pragma_update(None, "user_version", LATEST_VERSION + 1)
+        .unwrap();
+    assert!(matches!(
+        migrate(&db).unwrap_err(),
+        MigrationError::NewerSchema { version } if version == LATEST_VERSION + 1,
+    ));
+    assert!(!has_table(&db, "kb_notes"));
+}
@@ -0,0 +1,101 @@
+use crate::db::{FullDirectoryId, FullItemId, FullNoteId};
+use crate::pins::{Pin, PinStorage};
+use crate::util::UnsafeRc;
+use rusqlite::Connection;
+
+fn make_storage() -> PinStorage {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
+    unsafe { PinStorage::new(UnsafeRc::new(db)) }
+}
+
+fn note(provider: u64, note: u64) -> FullItemId {
+    FullItemId::Note(FullNoteId {
+        provider: provider.into(),
+        note: note.into(),
+    })
+}
+
+fn directory(provider: u64, directory: u64) -> FullItemId {
+    FullItemId::Directory(FullDirectoryId {
+        provider: provider.into(),
+        directory: directory.into(),
+    })
+}
+
+fn items(storage: &PinStorage) -> Vec<FullItemId> {
+    storage.list().unwrap().into_iter().map(|pin| pin.item).collect()
+}
+
+#[test]
+fn pins_initially_empty() {
+    let storage = make_storage();
+    assert_eq!(storage.list().unwrap(), Vec::new());
+}
+
+#[test]
+fn pin_unpin_ok() {
+    let storage = make_storage();
+    assert!(storage.pin(note(0, 1)).unwrap());
+    assert!(storage.pin(directory(0, 1)).unwrap());
+    assert!(storage.pin(note(1, 1)).unwrap());
+    // Notes and directories with the same IDs are different items.
+    assert_eq!(items(&storage), vec![note(0, 1), directory(0, 1), note(1, 1)]);
+
+    assert!(!storage.pin(note(0, 1)).unwrap());
+    assert_eq!(items(&storage).len(), 3);
+
+    assert!(storage.find(directory(0, 1)).unwrap().is_some());
+    assert!(storage.unpin(directory(0, 1)).unwrap());
+    assert!(!storage.unpin(directory(0, 1)).unwrap());
+    assert_eq!(storage.find(directory(0, 1)).unwrap(), None);
+    assert_eq!(items(&storage), vec![note(0, 1), note(1, 1)]);
+
+    // Pinned again after unpinning, the item goes to the end.
+    assert!(storage.pin(directory(0, 1)).unwrap());
+    assert_eq!(items(&storage), vec![note(0, 1), note(1, 1), directory(0, 1)]);
+}
+
+#[test]
+fn move_pin_ok() {
+    let storage = make_storage();
+    for i in 1..=3 {
+        storage.pin(note(0, i)).unwrap();
+    }
+    let ids: Vec<u64> = storage.list().unwrap().into_iter().map(|pin| pin.id).collect();
+
+    assert!(storage.move_pin(ids[2], true).unwrap());
+    assert_eq!(items(&storage), vec![note(0, 1), note(0, 3), note(0, 2)]);
+    assert!(storage.move_pin(ids[2], true).unwrap());
+    assert_eq!(items(&storage), vec![note(0, 3), note(0, 1), note(0, 2)]);
+    assert!(!storage.move_pin(ids[2], true).unwrap());
+
+    assert!(storage.move_pin(ids[0], false).unwrap());
+    assert_eq!(items(&storage), vec![note(0, 3), note(0, 2), note(0, 1)]);
+    assert!(!storage.move_pin(ids[0], false).unwrap());
+
+    assert!(!storage.move_pin(1000, true).unwrap());
+}
+
+#[test]
+fn pin_label_ok() {
+    let storage = make_storage();
+    storage.pin(note(0, 1)).unwrap();
+    let id = storage.find(note(0, 1)).unwrap().unwrap();
+    assert_eq!(
+        storage.get(id).unwrap(),
+        Some(Pin {
+            id,
+            item: note(0, 1),
+            label: None,
+        })
+    );
+
+    assert!(storage.set_label(id, Some("Важное")).unwrap());
+    assert_eq!(storage.get(id).unwrap().unwrap().label.as_deref(), Some("Важное"));
+    assert!(storage.set_label(id, None).unwrap());
+    assert_eq!(storage.get(id).unwrap().unwrap().label, None);
+
+    assert!(!storage.set_label(1000, Some("Важное")).unwrap());
+    assert_eq!(storage.get(1000).unwrap(), None);
+}
@@ -0,0 +1,127 @@
+use crate::media::{Attachment, File, Image};
+use crate::message::FormattedText;
+use crate::newsletter::announcements::{Announcement, Audience};
+use crate::schedule::{ScheduleStorage, ScheduledAnnouncement};
+use crate::util::UnsafeRc;
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::Connection;
+use teloxide::types::MessageEntity;
+
+fn make_storage() -> ScheduleStorage {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
+    unsafe { ScheduleStorage::new(UnsafeRc::new(db)) }
+}
+
+fn time(day: u32, hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2023, 9, day)
+        .unwrap()
+        .and_hms_opt(hour, 0, 0)
+        .unwrap()
+}
+
+fn scheduled(text: &str, send_at: NaiveDateTime, audience: Audience) -> ScheduledAnnouncement {
+    ScheduledAnnouncement {
+        send_at,
+        announcement: Announcement {
+            text: FormattedText {
+                raw_text: String::from(text),
+                entities: None,
+            },
+            attachments: Vec::new(),
+            audience,
+        },
+    }
+}
+
+fn texts(list: Vec<(u64, ScheduledAnnouncement)>) -> Vec<String> {
+    list.into_iter()
+        .map(|(_, scheduled)| scheduled.announcement.text.raw_text)
+        .collect()
+}
+
+#[test]
+fn scheduled_create_get_ok() {
+    let storage = make_storage();
+    let mut original = scheduled(
+        "Cleanup",
+        time(1, 10),
+        Audience::Role {
+            role: String::from("events"),
+        },
+    );
+    original.announcement.text.entities = Some(vec![MessageEntity::bold(0, 7)]);
+    original.announcement.attachments = vec![Attachment::Image(Image {
+        file: File {
+            id: String::from("photo"),
+        },
+    })];
+    let id = storage.create(&original).unwrap();
+    assert_eq!(storage.get(id).unwrap(), Some(original));
+    assert_eq!(storage.get(id + 1).unwrap(), None);
+}
+
+#[test]
+fn scheduled_list_sorted_ok() {
+    let storage = make_storage();
+    storage
+        .create(&scheduled("Second", time(2, 10), Audience::Everyone))
+        .unwrap();
+    storage
+        .create(&scheduled("First", time(1, 10), Audience::Subscribers))
+        .unwrap();
+    assert_eq!(texts(storage.list().unwrap()), vec!["First", "Second"]);
+}
+
+#[test]
+fn scheduled_update_delete_ok() {
+    let storage = make_storage();
+    let id = storage
+        .create(&scheduled("Cleanup", time(1, 10), Audience::Subscribers))
+        .unwrap();
+    let updated = scheduled("Tree planting", time(2, 10), Audience::Everyone);
+    assert!(storage.update(id, &updated).unwrap());
+    assert_eq!(storage.get(id).unwrap(), Some(updated.clone()));
+    assert!(!storage.update(id + 1, &updated).unwrap());
+    assert!(storage.delete(id).unwrap());
+    assert!(!storage.delete(id).unwrap());
+    assert_eq!(storage.list().unwrap(), Vec::new());
+}
+
+#[test]
+fn next_due_ok() {
+    let storage = make_storage();
+    storage
+        .create(&scheduled("Later", time(1, 12), Audience::Subscribers))
+        .unwrap();
+    storage
+        .create(&scheduled("Earlier", time(1, 10), Audience::Subscribers))
+        .unwrap();
+
+    assert_eq!(storage.next_due(time(1, 9)).unwrap(), None);
+    let (id, due) = storage.next_due(time(1, 12)).unwrap().unwrap();
+    assert_eq!(due.text.raw_text, "Earlier");
+    // The announcement is kept until it is removed after sending.
+    assert_eq!(storage.next_due(time(1, 12)).unwrap(), Some((id, due)));
+    assert!(storage.delete(id).unwrap());
+    let (id, due) = storage.next_due(time(1, 12)).unwrap().unwrap();
+    assert_eq!(due.text.raw_text, "Later");
+    assert!(storage.delete(id).unwrap());
+    assert_eq!(storage.next_due(time(1, 12)).unwrap(), None);
+    assert_eq!(storage.list().unwrap(), Vec::new());
+}
+
+#[test]
+fn audience_parse_ok() {
+    for audience in [
+        Audience::Subscribers,
+        Audience::Everyone,
+        Audience::Role {
+            role: String::from("announcer"),
+        },
+    ] {
+        assert_eq!(audience.to_string().parse(), Ok(audience));
+    }
+    assert_eq!("role:".parse::<Audience>(), Err(()));
+    assert_eq!("nobody".parse::<Audience>(), Err(()));
+}
@@ -0,0 +1,113 @@
+use crate::config::DEFAULT_ARCHIVE_DIR;
+use crate::kb::providers::fts_query;
+use crate::kb::{ProviderUserContext, Tree};
+use crate::newsletter::feedback::FeedbackNewsletter;
+use crate::newsletter::Newsletter;
+use crate::tests::fixtures::{make_tree, note, uctx};
+use crate::util::UnsafeRc;
+use rusqlite::Connection;
+
+/// Search the tree and return the names of the found notes.
+fn search(tree: &Tree, uctx: ProviderUserContext, query: &str) -> Vec<String> {
+    tree.search_notes(uctx, query, 10)
+        .unwrap()
+        .into_iter()
+        .map(|hit| hit.note.name(uctx).unwrap())
+        .collect()
+}
+
+#[test]
+fn fts_query_ok() {
+    assert_eq!(fts_query("Батарейки"), Some(String::from("\"Батарейки\"*")));
+    assert_eq!(
+        fts_query("  куда \"сдать\" OR*  "),
+        Some(String::from("\"куда\"* \"сдать\"* \"OR\"*")),
+    );
+    assert_eq!(fts_query(" -* \" "), None);
+}
+
+#[test]
+fn search_ranks_names_first() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    root.create_note(uctx(), note("Пластик сдают в жёлтые контейнеры"), "Пластик")
+        .unwrap();
+    let dir = root.create_directory(uctx(), "Опасные отходы").unwrap();
+    dir.create_note(uctx(), note("Их принимают на первом этаже"), "Батарейки")
+        .unwrap();
+    root.create_note(
+        uctx(),
+        note("Батарейки и пластик сдают отдельно"),
+        "Сортировка",
+    )
+    .unwrap();
+
+    assert_eq!(
+        search(&tree, uctx(), "батар"),
+        vec!["Батарейки", "Сортировка"]
+    );
+    assert_eq!(
+        search(&tree, uctx(), "пластик сдают"),
+        vec!["Пластик", "Сортировка"]
+    );
+    assert_eq!(search(&tree, uctx(), "стекло"), Vec::<String>::new());
+    assert_eq!(search(&tree, uctx(), "  "), Vec::<String>::new());
+
+    let hit = tree.search_notes(uctx(), "первом", 10).unwrap().remove(0);
+    assert_eq!(hit.snippet, "Их принимают на первом этаже");
+    assert_eq!(
+        hit.note.parent(uctx()).unwrap().path(uctx()).unwrap(),
+        vec!["Опасные отходы"]
+    );
+}
+
+#[test]
+fn search_follows_changes() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let note_ref = root
+        .create_note(
+            uctx(),
+            note("Батарейки принимают на первом этаже"),
+            "Батарейки",
+        )
+        .unwrap();
+
+    note_ref.rename(uctx(), "Аккумуляторы").unwrap();
+    assert_eq!(search(&tree, uctx(), "аккумуляторы"), vec!["Аккумуляторы"]);
+    note_ref
+        .write(uctx(), note("Принимают на втором этаже"))
+        .unwrap();
+    assert_eq!(search(&tree, uctx(), "батарейки"), Vec::<String>::new());
+    assert_eq!(search(&tree, uctx(), "втором"), vec!["Аккумуляторы"]);
+    note_ref.delete(uctx()).unwrap();
+    assert_eq!(search(&tree, uctx(), "аккумуляторы"), Vec::<String>::new());
+
+    let dir = root.create_directory(uctx(), "Отходы").unwrap();
+    dir.create_note(uctx(), note("Стекло"), "Стекло").unwrap();
+    dir.delete(uctx()).unwrap();
+    assert_eq!(search(&tree, uctx(), "стекло"), Vec::<String>::new());
+}
+
+#[test]
+fn search_archive_respects_permissions() {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
+    let (feedback, _feedback_tx) = FeedbackNewsletter::new();
+    let (tree, _, sink) =
+        unsafe { Tree::new(UnsafeRc::new(db), &[&feedback], DEFAULT_ARCHIVE_DIR) }.unwrap();
+    sink.store(
+        &feedback.name(),
+        note("Поставьте урну для батареек"),
+        chrono::Local::now(),
+    )
+    .unwrap();
+
+    let hits = tree.search_notes(uctx(), "урну", 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    let path = hits[0].note.parent(uctx()).unwrap().path(uctx()).unwrap();
+    assert_eq!(path, vec!["Архив рассылок", "Обратная связь"]);
+
+    let anonymous = ProviderUserContext::anonymous();
+    assert_eq!(tree.search_notes(anonymous, "урну", 10).unwrap().len(), 0);
+}
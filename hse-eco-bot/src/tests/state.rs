@@ -0,0 +1,35 @@
+use crate::db::{FullDirectoryId, FullItemId, FullNoteId};
+use crate::state::{states, DialogState};
+
+fn directory(id: u64) -> FullDirectoryId {
+    FullDirectoryId {
+        provider: 1.into(),
+        directory: id.into(),
+    }
+}
+
+fn note(id: u64) -> FullNoteId {
+    FullNoteId {
+        provider: 1.into(),
+        note: id.into(),
+    }
+}
+
+#[test]
+fn kb_items_found() {
+    let movement = DialogState::KbNoteMovement(states::KbNoteMovement {
+        destination: directory(2),
+        note: note(3),
+    });
+    assert_eq!(
+        movement.kb_items(),
+        [FullItemId::Directory(directory(2)), FullItemId::Note(note(3))],
+    );
+    let search = DialogState::KbSearchResults(states::KbSearchResults {
+        from: directory(4),
+        query: String::from("стекло"),
+        page: 0,
+    });
+    assert_eq!(search.kb_items(), [FullItemId::Directory(directory(4))]);
+    assert!(DialogState::MainMenu.kb_items().is_empty());
+}
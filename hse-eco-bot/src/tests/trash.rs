@@ -0,0 +1,72 @@
+use crate::kb::{ItemId, ProviderError, ProviderUserContext};
+use crate::tests::fixtures::{make_tree, note, uctx, USER};
+use chrono::{Duration, Local};
+
+#[test]
+fn deleted_items_listed() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let dir = root.create_directory(uctx(), "Отходы").unwrap();
+    dir.create_note(uctx(), note("Стекло"), "Стекло").unwrap();
+    let paper = root.create_note(uctx(), note("Бумага"), "Бумага").unwrap();
+    let kept = root
+        .create_note(uctx(), note("Пластик"), "Пластик")
+        .unwrap();
+    assert!(tree.deleted_items(uctx(), 10).unwrap().is_empty());
+
+    paper.delete(uctx()).unwrap();
+    dir.delete(uctx()).unwrap();
+    // The notes deleted along with the directory are not listed separately.
+    let deleted = tree.deleted_items(uctx(), 10).unwrap();
+    let items: Vec<_> = deleted.iter().map(|(_, revision)| revision.item).collect();
+    assert_eq!(
+        items,
+        vec![ItemId::Directory(dir.id()), ItemId::Note(paper.id())]
+    );
+    assert_eq!(deleted[1].1.name, "Бумага");
+    assert_eq!(deleted[1].1.user_id, Some(USER));
+    assert_eq!(tree.deleted_items(uctx(), 1).unwrap().len(), 1);
+
+    // A restored item leaves the trash.
+    let (provider, revision) = &deleted[1];
+    tree.restore_revision(uctx(), *provider, revision.id)
+        .unwrap();
+    assert_eq!(paper.read(uctx()).unwrap(), note("Бумага"));
+    let deleted = tree.deleted_items(uctx(), 10).unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(deleted[0].1.item, ItemId::Directory(dir.id()));
+    assert_eq!(kept.read(uctx()).unwrap(), note("Пластик"));
+}
+
+#[test]
+fn deleted_items_purged() {
+    let tree = make_tree();
+    let root = tree.root_directory_ref().unwrap();
+    let dir = root.create_directory(uctx(), "Отходы").unwrap();
+    dir.create_note(uctx(), note("Стекло"), "Стекло").unwrap();
+    let paper = root.create_note(uctx(), note("Бумага"), "Бумага").unwrap();
+    paper.write(uctx(), note("Макулатура")).unwrap();
+    paper.delete(uctx()).unwrap();
+    dir.delete(uctx()).unwrap();
+    let deleted = tree.deleted_items(uctx(), 10).unwrap();
+    assert_eq!(deleted.len(), 2);
+
+    // The items deleted recently are kept.
+    let anonymous = ProviderUserContext::anonymous();
+    let yesterday = Local::now().naive_local() - Duration::days(1);
+    tree.purge_deleted_items(anonymous, yesterday).unwrap();
+    assert_eq!(tree.deleted_items(uctx(), 10).unwrap(), deleted);
+
+    let tomorrow = Local::now().naive_local() + Duration::days(1);
+    tree.purge_deleted_items(anonymous, tomorrow).unwrap();
+    assert!(tree.deleted_items(uctx(), 10).unwrap().is_empty());
+    for (provider, revision) in deleted {
+        assert!(matches!(
+            tree.restore_revision(uctx(), provider, revision.id),
+            Err(ProviderError::NoSuchRevision(id)) if id == revision.id
+        ));
+    }
+    // The history of the purged items is gone too.
+    assert_eq!(root.history(uctx(), 10).unwrap(), vec![]);
+    assert!(paper.history(uctx(), 10).unwrap().is_empty());
+}
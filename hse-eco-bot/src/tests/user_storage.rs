@@ -0,0 +1,278 @@
+use crate::db::{FullDirectoryId, FullNoteId};
+use crate::kb::{DirectoryId, NoteId, ProviderId};
+use crate::state::{states, DialogState};
+use crate::ui::form::{Form, FormCompletionTarget, FormElement, FormFillingState, FormInputType};
+use crate::user::storage::{load_users, PrivilegedUser, StoredUser, UserStorage};
+use crate::user::{Permission, Permissions, User};
+use crate::util::UnsafeRc;
+use rusqlite::Connection;
+use teloxide::types::{ChatId, UserId};
+
+fn make_storage() -> (UserStorage, UnsafeRc<Connection>) {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(include_str!("../bootstrap.sql")).unwrap();
+    let rc = unsafe { UnsafeRc::new(db) };
+    let storage = unsafe { UserStorage::new(UnsafeRc::clone(&rc)) };
+    (storage, rc)
+}
+
+fn stored(chat_id: i64, user: User, state: Option<DialogState>) -> StoredUser {
+    StoredUser {
+        chat_id: ChatId(chat_id),
+        user,
+        state,
+    }
+}
+
+#[test]
+fn users_initially_empty() {
+    let (_storage, db) = make_storage();
+    assert!(load_users(&db).unwrap().is_empty());
+}
+
+#[test]
+fn user_store_load_ok() {
+    let (storage, db) = make_storage();
+
+    let mut user = User::new();
+    user.set_username(Some(String::from("test_user")));
+    user.subscriptions_mut().insert(String::from("feedback"));
+    user.subscriptions_mut().insert(String::from("events"));
+    storage.store(UserId(42), ChatId(42), &user).unwrap();
+    storage.store(UserId(43), ChatId(43), &User::new()).unwrap();
+
+    let users = load_users(&db).unwrap();
+    assert_eq!(users.len(), 2);
+    assert_eq!(users[&UserId(42)], stored(42, user, None));
+    assert_eq!(users[&UserId(43)], stored(43, User::new(), None));
+}
+
+#[test]
+fn user_store_overwrites_ok() {
+    let (storage, db) = make_storage();
+
+    let mut user = User::new();
+    user.subscriptions_mut().insert(String::from("feedback"));
+    storage.store(UserId(42), ChatId(42), &user).unwrap();
+
+    user.subscriptions_mut().clear();
+    user.set_username(Some(String::from("test_user")));
+    storage.store(UserId(42), ChatId(42), &user).unwrap();
+
+    let users = load_users(&db).unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[&UserId(42)], stored(42, user, None));
+}
+
+#[test]
+fn pending_roles_granted() {
+    let (storage, db) = make_storage();
+    db.execute_batch(concat!(
+        "INSERT INTO pending_roles(username, role) ",
+        "VALUES ('Old_Editor', 'editor'), ('Old_Editor', 'feedback'), ('other', 'admin')",
+    ))
+    .unwrap();
+
+    let mut user = User::new();
+    assert_eq!(storage.store(UserId(42), ChatId(42), &user).unwrap(), None);
+    user.set_username(Some(String::from("old_editor")));
+    let permissions = storage.store(UserId(42), ChatId(42), &user).unwrap().unwrap();
+    assert!(permissions.edit_kb && permissions.receive_feedback && !permissions.admin);
+    assert_eq!(storage.permissions(UserId(42)).unwrap(), permissions);
+
+    // The pending roles are only granted once.
+    storage.revoke_role(UserId(42), "editor").unwrap();
+    assert_eq!(storage.store(UserId(42), ChatId(42), &user).unwrap(), None);
+    assert!(!storage.permissions(UserId(42)).unwrap().edit_kb);
+    let pending: u32 = db
+        .query_row("SELECT count(*) FROM pending_roles", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(pending, 1);
+}
+
+#[test]
+fn dialog_state_store_load_ok() {
+    let (storage, db) = make_storage();
+    storage.store(UserId(42), ChatId(42), &User::new()).unwrap();
+
+    let note = FullNoteId {
+        provider: ProviderId::from(0),
+        note: NoteId::from(7),
+    };
+    let destination = FullDirectoryId {
+        provider: ProviderId::from(0),
+        directory: DirectoryId::from(3),
+    };
+    let form = Form {
+        elements: vec![FormElement {
+            text: String::from("Test"),
+            input_type: FormInputType::Choice {
+                options: vec![String::from("A"), String::from("B")],
+            },
+        }],
+    };
+    let state = DialogState::FormFilling(states::FormFilling {
+        form_state: FormFillingState::new(form),
+        return_state: Box::new(DialogState::KbNoteMovement(states::KbNoteMovement {
+            destination,
+            note,
+        })),
+        completion_state: Box::new(DialogState::MainMenu),
+        on_completion: FormCompletionTarget::Feedback,
+    });
+    storage.store_state(UserId(42), &state).unwrap();
+
+    let users = load_users(&db).unwrap();
+    assert_eq!(users[&UserId(42)].state, Some(state));
+}
+
+#[test]
+fn dialog_state_of_unknown_user_ignored() {
+    let (storage, db) = make_storage();
+    storage
+        .store_state(UserId(42), &DialogState::SubscriptionsMenu)
+        .unwrap();
+    assert!(load_users(&db).unwrap().is_empty());
+}
+
+#[test]
+fn unreadable_dialog_state_skipped() {
+    let (storage, db) = make_storage();
+    storage.store(UserId(42), ChatId(42), &User::new()).unwrap();
+    db.execute(
+        "UPDATE users SET dialog_state = ? WHERE id = ?",
+        rusqlite::params!["{\"NoSuchState\":null}", 42],
+    )
+    .unwrap();
+
+    let users = load_users(&db).unwrap();
+    assert_eq!(users[&UserId(42)], stored(42, User::new(), None));
+}
+
+#[test]
+fn roles_grant_revoke_ok() {
+    let (storage, db) = make_storage();
+    storage.store(UserId(42), ChatId(42), &User::new()).unwrap();
+
+    assert_eq!(storage.permissions(UserId(42)).unwrap(), Permissions::default());
+    assert_eq!(storage.user_roles(UserId(42)).unwrap(), Vec::<String>::new());
+
+    let permissions = storage.grant_role(UserId(42), "editor").unwrap();
+    assert_eq!(permissions, [Permission::EditKb].into_iter().collect());
+    let permissions = storage.grant_role(UserId(42), "feedback").unwrap();
+    assert_eq!(
+        permissions,
+        [Permission::EditKb, Permission::ReceiveFeedback]
+            .into_iter()
+            .collect()
+    );
+    // Granting a role twice changes nothing.
+    storage.grant_role(UserId(42), "feedback").unwrap();
+    assert_eq!(
+        storage.user_roles(UserId(42)).unwrap(),
+        vec![String::from("editor"), String::from("feedback")]
+    );
+    assert_eq!(load_users(&db).unwrap()[&UserId(42)].user.permissions(), &permissions);
+
+    let permissions = storage.revoke_role(UserId(42), "editor").unwrap();
+    assert_eq!(permissions, [Permission::ReceiveFeedback].into_iter().collect());
+    let permissions = storage.revoke_role(UserId(42), "feedback").unwrap();
+    assert_eq!(permissions, Permissions::default());
+}
+
+#[test]
+fn users_with_role_ok() {
+    let (storage, _db) = make_storage();
+    assert_eq!(storage.users_with_role("announcer").unwrap(), Vec::new());
+
+    storage.grant_role(UserId(43), "announcer").unwrap();
+    storage.grant_role(UserId(42), "announcer").unwrap();
+    storage.grant_role(UserId(41), "editor").unwrap();
+    assert_eq!(
+        storage.users_with_role("announcer").unwrap(),
+        vec![UserId(42), UserId(43)]
+    );
+    assert_eq!(storage.users_with_role("editor").unwrap(), vec![UserId(41)]);
+    assert_eq!(storage.users_with_role("no_such_role").unwrap(), Vec::new());
+}
+
+#[test]
+fn role_of_unknown_user_ok() {
+    let (storage, db) = make_storage();
+    let permissions = storage.grant_role(UserId(42), "admin").unwrap();
+    assert_eq!(permissions, Permissions::all());
+    assert!(load_users(&db).unwrap().is_empty());
+    assert_eq!(storage.all_permissions().unwrap()[&UserId(42)], Permissions::all());
+}
+
+#[test]
+fn unknown_role_err() {
+    let (storage, _db) = make_storage();
+    storage.grant_role(UserId(42), "no_such_role").unwrap_err();
+}
+
+#[test]
+fn roles_cover_all_permissions() {
+    let (storage, _db) = make_storage();
+    let roles = storage.roles().unwrap();
+    let admin = roles.iter().find(|role| role.name == "admin").unwrap();
+    assert_eq!(admin.permissions, Permissions::all());
+    // The admin permission is only granted by the admin role.
+    for permission in Permission::ALL
+        .into_iter()
+        .filter(|&permission| permission != Permission::Admin)
+    {
+        assert!(
+            roles
+                .iter()
+                .any(|role| role.name != "admin" && role.permissions.contains(permission)),
+            "No dedicated role for {}",
+            permission
+        );
+    }
+}
+
+#[test]
+fn find_by_username_ok() {
+    let (storage, _db) = make_storage();
+    let mut user = User::new();
+    user.set_username(Some(String::from("Test_User")));
+    storage.store(UserId(42), ChatId(42), &user).unwrap();
+
+    assert_eq!(storage.find_by_username("test_user").unwrap(), Some(UserId(42)));
+    assert_eq!(storage.find_by_username("other_user").unwrap(), None);
+}
+
+#[test]
+fn privileged_users_ok() {
+    let (storage, _db) = make_storage();
+    assert_eq!(storage.privileged_users().unwrap(), Vec::new());
+
+    let mut user = User::new();
+    user.set_username(Some(String::from("test_user")));
+    storage.store(UserId(42), ChatId(42), &user).unwrap();
+    storage.store(UserId(43), ChatId(43), &User::new()).unwrap();
+    storage.grant_role(UserId(42), "feedback").unwrap();
+    storage.grant_role(UserId(42), "editor").unwrap();
+    // The user has never started the bot.
+    storage.grant_role(UserId(41), "admin").unwrap();
+
+    assert_eq!(
+        storage.privileged_users().unwrap(),
+        vec![
+            PrivilegedUser {
+                id: UserId(41),
+                username: None,
+                roles: vec![String::from("admin")],
+            },
+            PrivilegedUser {
+                id: UserId(42),
+                username: Some(String::from("test_user")),
+                roles: vec![String::from("editor"), String::from("feedback")],
+            },
+        ]
+    );
+    assert_eq!(storage.username(UserId(42)).unwrap().as_deref(), Some("test_user"));
+    assert_eq!(storage.username(UserId(43)).unwrap(), None);
+    assert_eq!(storage.username(UserId(41)).unwrap(), None);
+}
@@ -0,0 +1,24 @@
+//! Purging the knowledge base items which have been in the trash for too long.
+
+use crate::db::CommandSender;
+use crate::kb::ProviderUserContext;
+use std::time::Duration;
+
+/// The number of days the deleted items are kept by default.
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// Periodically forget the items deleted more than `retention_days` days ago.
+pub async fn purge_expired(db: CommandSender, retention_days: u32, period: Duration) {
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        let deleted_before =
+            chrono::Local::now().naive_local() - chrono::Duration::days(retention_days.into());
+        if let Err(e) = db
+            .purge_deleted_items(ProviderUserContext::anonymous(), deleted_before)
+            .await
+        {
+            warn!("Cannot purge the trash: {}", e);
+        }
+    }
+}
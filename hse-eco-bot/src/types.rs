@@ -1,27 +1,36 @@
+use crate::audit_error::AuditError;
 use crate::dispatch::InvalidChatError;
+use crate::event_error::EventError;
 use crate::invalid_action::InvalidAction;
 use crate::ui::form::FormInputError;
 use crate::kb::ProviderError;
 use crate::message_format_error::MessageFormatError;
+use crate::schedule_error::ScheduleError;
 use crate::user_error::UserError;
+use crate::user_lookup_error::UserLookupError;
 use std::error::Error;
 use std::fmt::Display;
 use teloxide::adaptors::{AutoSend, Throttle};
 use teloxide::{Bot, RequestError};
 
 /// Type alias for the actual bot type used.
-pub type BotType = AutoSend<Bot>;
+pub type BotType = AutoSend<Throttle<Bot>>;
 
 /// The non-user error type of a dialog state handler.
 #[derive(Debug)]
 pub enum InternalError {
     Teloxide(RequestError),
+    Database(rusqlite::Error),
+    /// The worker of the newsletter with the given name has stopped and accepts no more messages.
+    NewsletterStopped(&'static str),
 }
 
 impl Display for InternalError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Teloxide(e) => write!(f, "Telegram Bot API Error: {}", e),
+            Self::Database(e) => write!(f, "Database error: {}", e),
+            Self::NewsletterStopped(name) => write!(f, "The newsletter `{}` has stopped", name),
         }
     }
 }
@@ -34,6 +43,12 @@ impl From<RequestError> for InternalError {
     }
 }
 
+impl From<rusqlite::Error> for InternalError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Database(e)
+    }
+}
+
 /// The error type of a dialog state handler.
 #[derive(Debug)]
 pub enum HandlerError {
@@ -70,6 +85,12 @@ impl From<RequestError> for HandlerError {
     }
 }
 
+impl From<rusqlite::Error> for HandlerError {
+    fn from(e: rusqlite::Error) -> Self {
+        InternalError::from(e).into()
+    }
+}
+
 impl From<ProviderError> for HandlerError {
     fn from(e: ProviderError) -> Self {
         UserError::from(e).into()
@@ -100,5 +121,29 @@ impl From<FormInputError> for HandlerError {
     }
 }
 
+impl From<UserLookupError> for HandlerError {
+    fn from(e: UserLookupError) -> Self {
+        UserError::from(e).into()
+    }
+}
+
+impl From<EventError> for HandlerError {
+    fn from(e: EventError) -> Self {
+        UserError::from(e).into()
+    }
+}
+
+impl From<ScheduleError> for HandlerError {
+    fn from(e: ScheduleError) -> Self {
+        UserError::from(e).into()
+    }
+}
+
+impl From<AuditError> for HandlerError {
+    fn from(e: AuditError) -> Self {
+        UserError::from(e).into()
+    }
+}
+
 /// The result type of a dialog state handler.
 pub type HandlerResult<T> = Result<T, HandlerError>;
@@ -1,21 +1,38 @@
+pub mod admin;
+pub mod announcements;
+pub mod audit;
+pub mod calendar;
 pub mod form;
-
+pub mod history;
+pub mod inline;
+pub mod links;
+pub mod pins;
+pub mod search;
+pub mod trash;
+
+use crate::audit::AuditFilter;
 use crate::callback_query::{parse_callback_query, Query};
-use crate::db::{FullDirectoryId, FullItemId, FullNoteId};
+use crate::db::{FullDirectoryId, FullItemId, FullNoteId, KbReload};
 use crate::dispatch::UserDialog;
+use crate::events::CalendarPeriod;
 use crate::feedback::FeedbackTopic;
 use crate::global_state::GlobalState;
 use crate::invalid_action::InvalidAction;
 use crate::kb::{Note, ProviderError, ProviderUserContext};
-use crate::media::Location;
+use crate::media::{self, Attachment, Location};
 use crate::message::{FormattedMessage, FormattedText};
 use crate::message_format_error::MessageFormatError;
 use crate::message_queue::MessageQueueSender;
+use crate::newsletter::feedback;
 use crate::state::{states, DialogState};
 use crate::strings::STRINGS;
-use crate::types::{BotType, HandlerError, HandlerResult};
+use crate::types::{BotType, HandlerError, HandlerResult, InternalError};
 use crate::user_facing_error::UserFacingError;
-use form::{Form, FormElement, FormFillingState, FormInputType, FormRawInput};
+use form::{
+    Form, FormCompletionTarget, FormElement, FormFillingState, FormInputType, FormProgress,
+    FormRawInput,
+};
+use links::start_payload;
 use std::sync::Arc;
 use teloxide::prelude::*;
 use teloxide::types::{
@@ -23,29 +40,34 @@ use teloxide::types::{
     MediaKind, MessageKind,
 };
 
+/// Format the path to a knowledge base item, starting from the root of the knowledge base.
+fn breadcrumbs(path: &[String]) -> String {
+    std::iter::once("База знаний")
+        .chain(path.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" › ")
+}
+
 fn is_name_valid(name: &str) -> bool {
-    name.find(&['\0', '/', '\\']).is_none()
+    name.find(['\0', '/', '\\']).is_none()
 }
 
 fn extract_name(message: &Message) -> Result<&str, MessageFormatError> {
     let name = match message.text() {
         Some(text) => text,
-        None => return Err(MessageFormatError::NoText.into()),
+        None => return Err(MessageFormatError::NoText),
     };
 
     let has_attachments = match &message.kind {
-        MessageKind::Common(common) => match common.media_kind {
-            MediaKind::Text(_) => false,
-            _ => true,
-        },
+        MessageKind::Common(common) => !matches!(common.media_kind, MediaKind::Text(_)),
         _ => true,
     };
     if has_attachments {
-        return Err(MessageFormatError::HasAttachments.into());
+        return Err(MessageFormatError::HasAttachments);
     }
 
     if !is_name_valid(name) {
-        return Err(MessageFormatError::InvalidName.into());
+        return Err(MessageFormatError::InvalidName);
     }
 
     Ok(name)
@@ -54,7 +76,7 @@ fn extract_name(message: &Message) -> Result<&str, MessageFormatError> {
 fn extract_formatted_text(message: &Message) -> Result<FormattedText, MessageFormatError> {
     let raw_text = match message.text().or_else(|| message.caption()) {
         Some(text) => text.to_owned(),
-        None => return Err(MessageFormatError::NoText.into()),
+        None => return Err(MessageFormatError::NoText),
     };
     let entities = message
         .entities()
@@ -64,6 +86,82 @@ fn extract_formatted_text(message: &Message) -> Result<FormattedText, MessageFor
     Ok(text)
 }
 
+/// Get the photo, the video or the document attached to a message.
+///
+/// Only the largest size of a photo is kept.
+fn extract_attachment(message: &Message) -> Option<Attachment> {
+    let file = |id: &str| media::File { id: id.to_owned() };
+    if let Some(sizes) = message.photo() {
+        let largest = sizes.iter().max_by_key(|size| size.width * size.height)?;
+        Some(Attachment::Image(media::Image {
+            file: file(&largest.file_id),
+        }))
+    } else if let Some(video) = message.video() {
+        Some(Attachment::Video(media::Video {
+            file: file(&video.file_id),
+        }))
+    } else {
+        message.document().map(|document| {
+            Attachment::Document(media::Document {
+                file: file(&document.file_id),
+            })
+        })
+    }
+}
+
+/// The result of adding a message to a note draft.
+enum NoteDraftProgress {
+    /// The message completes the note.
+    Complete(Note),
+    /// The message adds an attachment, and more attachments may follow.
+    Pending {
+        /// Whether the attachment belongs to the same album as the previous one.
+        is_same_album: bool,
+    },
+}
+
+/// Add the text or the attachment of a message to a note draft.
+///
+/// A text message or a single attachment with a caption completes the note. Attachments without
+/// a caption and albums are collected until the text is sent or the note is saved explicitly.
+fn add_to_note_draft(
+    message: &Message,
+    draft: &mut states::NoteDraft,
+) -> Result<NoteDraftProgress, MessageFormatError> {
+    let text = extract_formatted_text(message).ok();
+    let attachment = match extract_attachment(message) {
+        Some(attachment) => attachment,
+        None => {
+            let text = text.ok_or(MessageFormatError::NoText)?;
+            return Ok(NoteDraftProgress::Complete(note_from_draft(draft, Some(text))));
+        }
+    };
+    draft.attachments.push(attachment);
+    let media_group_id = message.media_group_id().map(str::to_owned);
+    if media_group_id.is_none() && text.is_some() {
+        return Ok(NoteDraftProgress::Complete(note_from_draft(draft, text)));
+    }
+    if text.is_some() {
+        draft.text = text;
+    }
+    let is_same_album = media_group_id.is_some() && media_group_id == draft.last_media_group_id;
+    draft.last_media_group_id = media_group_id;
+    Ok(NoteDraftProgress::Pending { is_same_album })
+}
+
+/// Take the attachments collected in a draft and make a note of them. The note text defaults to
+/// the caption of the attachments, if any.
+fn note_from_draft(draft: &mut states::NoteDraft, text: Option<FormattedText>) -> Note {
+    let text = text.or_else(|| draft.text.take()).unwrap_or(FormattedText {
+        raw_text: String::new(),
+        entities: None,
+    });
+    Note {
+        text,
+        attachments: std::mem::take(&mut draft.attachments),
+    }
+}
+
 struct Context<'bot, 'dialog, 'gs, 'mq> {
     pub bot: &'bot BotType,
     pub dialog: &'dialog UserDialog,
@@ -79,17 +177,16 @@ pub async fn handle_message(
     mut message_queue_tx: MessageQueueSender,
 ) -> HandlerResult<()> {
     let (user_id, maybe_username) = match message.from() {
-        Some(ref user) => (user.id, user.username.as_deref()),
+        Some(user) => (user.id, user.username.clone()),
         None => {
             // Ignore messages from an unknown sender or without a sender.
             return Ok(());
         }
     };
 
-    let dialog =
-        global_state
-            .dialog_storage
-            .get_dialog(message.chat.id, user_id, maybe_username)?;
+    let dialog = global_state
+        .dialog_storage
+        .get_dialog(message.chat.id, user_id)?;
     let state = dialog.data().read().unwrap().state.clone();
 
     let mut context = Context {
@@ -98,68 +195,118 @@ pub async fn handle_message(
         global_state: &global_state,
         message_queue_tx: &mut message_queue_tx,
     };
+    context.update_username(maybe_username).await?;
 
-    let result = match state {
-        DialogState::Initial => context.handle_initial_message(message).await,
-        DialogState::MainMenu => context.handle_main_menu_message(message).await,
-        DialogState::KbNavigation(_) => Err(InvalidAction::UnexpectedMessage.into()),
-        DialogState::KbNoteViewing(_) => Err(InvalidAction::UnexpectedMessage.into()),
-        DialogState::KbNoteDeletionConfirmation(_) => Err(InvalidAction::UnexpectedMessage.into()),
-        DialogState::KbNoteRenaming(state_struct) => {
-            context
-                .handle_note_renaming_message(message, state_struct)
-                .await
-        }
-        DialogState::KbNoteCreation(state_struct) => {
-            context
-                .handle_note_creation_message(message, state_struct)
-                .await
-        }
-        DialogState::KbNoteCreationNamed(state_struct) => {
-            context
-                .handle_note_creation_named_message(message, state_struct)
-                .await
-        }
-        DialogState::KbDirectoryEditing(_) => Err(InvalidAction::UnexpectedMessage.into()),
-        DialogState::KbNoteEditing(state_struct) => {
-            context
-                .handle_note_editing_message(message, state_struct)
-                .await
-        }
-        DialogState::KbNoteMovement(_) => Err(InvalidAction::UnexpectedMessage.into()),
-        DialogState::KbDirectoryMovement(_) => Err(InvalidAction::UnexpectedMessage.into()),
-        DialogState::KbDirectoryCreation(state_struct) => {
-            context
-                .handle_directory_creation_message(message, state_struct)
-                .await
-        }
-        DialogState::KbDirectoryRenaming(state_struct) => {
-            context
-                .handle_directory_renaming_message(message, state_struct)
-                .await
-        }
-        DialogState::KbDirectoryDeletion(_) => Err(InvalidAction::UnexpectedMessage.into()),
-        DialogState::FeedbackTopicSelection => Err(InvalidAction::UnexpectedMessage.into()),
-        DialogState::FormFilling(state_struct) => {
-            context
-                .handle_form_filling_message(message, state_struct)
-                .await
+    // Following a deep link leaves whatever the user has been doing. In the initial state, the link
+    // is opened after the greeting.
+    let deep_link = match state {
+        DialogState::Initial => None,
+        _ => start_payload(&message).map(str::to_owned),
+    };
+    let result = if let Some(payload) = deep_link {
+        context.open_deep_link(&payload).await
+    } else {
+        match state {
+            DialogState::Initial => context.handle_initial_message(message).await,
+            DialogState::MainMenu => context.handle_main_menu_message(message).await,
+            DialogState::KbNavigation(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::KbNoteViewing(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::KbNoteDeletionConfirmation(_) => {
+                Err(InvalidAction::UnexpectedMessage.into())
+            }
+            DialogState::KbNoteRenaming(state_struct) => {
+                context
+                    .handle_note_renaming_message(message, state_struct)
+                    .await
+            }
+            DialogState::KbNoteCreation(state_struct) => {
+                context
+                    .handle_note_creation_message(message, state_struct)
+                    .await
+            }
+            DialogState::KbNoteCreationNamed(state_struct) => {
+                context
+                    .handle_note_creation_named_message(message, state_struct)
+                    .await
+            }
+            DialogState::KbDirectoryEditing(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::KbNoteEditing(state_struct) => {
+                context
+                    .handle_note_editing_message(message, state_struct)
+                    .await
+            }
+            DialogState::KbNoteMovement(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::KbDirectoryMovement(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::KbDirectoryCreation(state_struct) => {
+                context
+                    .handle_directory_creation_message(message, state_struct)
+                    .await
+            }
+            DialogState::KbDirectoryRenaming(state_struct) => {
+                context
+                    .handle_directory_renaming_message(message, state_struct)
+                    .await
+            }
+            DialogState::KbDirectoryDeletion(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::KbSearch(search) => {
+                context.handle_search_message(message, search.from).await
+            }
+            DialogState::KbSearchResults(results) => {
+                context.handle_search_message(message, results.from).await
+            }
+            DialogState::KbNoteHistory(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::KbDirectoryHistory(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::Trash(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::FeedbackTopicSelection => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::FormFilling(state_struct) => {
+                context
+                    .handle_form_filling_message(message, state_struct)
+                    .await
+            }
+            DialogState::SubscriptionsMenu => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::PinManagement => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::PinRenaming(state_struct) => {
+                context
+                    .handle_pin_renaming_message(message, state_struct)
+                    .await
+            }
+            DialogState::AdminPanel => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::AdminUserLookup => context.handle_admin_user_lookup_message(message).await,
+            DialogState::AdminUserRoles(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::AuditLog(log) => context.handle_audit_log_message(message, log).await,
+            DialogState::AuditLogActionSelection(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::Calendar(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::EventViewing(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::EventDeletion(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::AnnouncementComposing(draft) => {
+                context.handle_announcement_message(message, draft).await
+            }
+            DialogState::AnnouncementPreview(_) => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::AnnouncementScheduling(draft) => {
+                context
+                    .handle_announcement_scheduling_message(message, draft)
+                    .await
+            }
+            DialogState::ScheduledAnnouncements => Err(InvalidAction::UnexpectedMessage.into()),
+            DialogState::ScheduledAnnouncementViewing(_) => {
+                Err(InvalidAction::UnexpectedMessage.into())
+            }
         }
-        DialogState::SubscriptionsMenu => Err(InvalidAction::UnexpectedMessage.into()),
     };
 
     match result {
-        Ok(()) => Ok(()),
-        Err(HandlerError::Internal(e)) => Err(e.into()),
+        Ok(()) => (),
+        Err(HandlerError::Internal(e)) => return Err(e.into()),
         Err(HandlerError::User(e)) => {
             debug!("User error: {:?}", &e);
             context
                 .send_message(FormattedMessage::new(e.user_message()))
                 .await?;
             context.send_state_prompt().await?;
-            Ok(())
         }
     }
+
+    context.store_state().await
 }
 
 /// Handle an incoming callback query.
@@ -178,11 +325,9 @@ pub async fn handle_callback_query(
         None => return Ok(()),
     };
     let user_id = query.from.id;
-    let maybe_username = query.from.username.as_deref();
+    let maybe_username = query.from.username.clone();
 
-    let dialog = global_state
-        .dialog_storage
-        .get_dialog(chat_id, user_id, maybe_username)?;
+    let dialog = global_state.dialog_storage.get_dialog(chat_id, user_id)?;
 
     let mut context = Context {
         bot: &bot,
@@ -190,15 +335,12 @@ pub async fn handle_callback_query(
         global_state: &global_state,
         message_queue_tx: &mut message_queue_tx,
     };
+    context.update_username(maybe_username).await?;
 
     bot.answer_callback_query(query.id).await?;
 
     // Save match result into a temporary variable to drop the lock before the next `await`.
-    let is_initial = if let DialogState::Initial = dialog.data().read().unwrap().state {
-        true
-    } else {
-        false
-    };
+    let is_initial = matches!(dialog.data().read().unwrap().state, DialogState::Initial);
     if is_initial {
         context
             .send_message(FormattedMessage::new(STRINGS.initial.invalid_action()))
@@ -222,15 +364,131 @@ pub async fn handle_callback_query(
     let result = context.handle_callback_query(&parsed_query).await;
 
     match result {
-        Ok(()) => Ok(()),
-        Err(HandlerError::Internal(e)) => Err(e.into()),
+        Ok(()) => (),
+        Err(HandlerError::Internal(e)) => return Err(e.into()),
         Err(HandlerError::User(e)) => {
             debug!("User error: {:?}", &e);
             context
                 .send_message(FormattedMessage::new(e.user_message()))
                 .await?;
             context.send_state_prompt().await?;
-            Ok(())
+        }
+    }
+
+    context.store_state().await
+}
+
+/// Re-prompt the users whose dialogs have been restored in the middle of some action.
+///
+/// This is meant to be called once after the bot has been restarted.
+pub async fn resume_dialogs(
+    bot: BotType,
+    global_state: Arc<GlobalState>,
+    mut message_queue_tx: MessageQueueSender,
+) -> HandlerResult<()> {
+    let mut dialogs = Vec::new();
+    global_state
+        .dialog_storage
+        .inspect_dialogs(&mut |_user_id, dialog| {
+            let is_idle = matches!(
+                dialog.data().read().unwrap().state,
+                DialogState::Initial | DialogState::MainMenu
+            );
+            if !is_idle {
+                dialogs.push(Arc::clone(dialog));
+            }
+        });
+    debug!("Resuming {} dialogs", dialogs.len());
+
+    for dialog in dialogs {
+        let mut context = Context {
+            bot: &bot,
+            dialog: &dialog,
+            global_state: &global_state,
+            message_queue_tx: &mut message_queue_tx,
+        };
+        if let Err(e) = context.resume().await {
+            warn!("Cannot resume the dialog with user {}: {}", dialog.user_id().0, e);
+        }
+    }
+    Ok(())
+}
+
+/// Read the knowledge base items served from external sources again.
+///
+/// The users whose dialogs refer to the items which have disappeared, e.g. to view, edit or move
+/// them, are returned to the main menu. The sources which cannot be read are logged.
+///
+/// Returns the number of the disappeared items and the number of the sources which could not be
+/// read.
+pub async fn reload_kb(
+    bot: &BotType,
+    global_state: &Arc<GlobalState>,
+    message_queue_tx: &mut MessageQueueSender,
+    uctx: ProviderUserContext,
+) -> HandlerResult<(usize, usize)> {
+    let KbReload { vanished, failed } = global_state.db.reload_kb(uctx).await?;
+    for (provider, e) in &failed {
+        warn!("Cannot reload the knowledge base provider `{}`: {}", provider, e);
+    }
+    if vanished.is_empty() {
+        return Ok((0, failed.len()));
+    }
+
+    let mut dialogs = Vec::new();
+    global_state
+        .dialog_storage
+        .inspect_dialogs(&mut |_user_id, dialog| {
+            let items = dialog.data().read().unwrap().state.kb_items();
+            if items.iter().any(|item| vanished.contains(item)) {
+                dialogs.push(Arc::clone(dialog));
+            }
+        });
+    debug!("Returning {} dialogs to the main menu", dialogs.len());
+
+    for dialog in dialogs {
+        let mut context = Context {
+            bot,
+            dialog: &dialog,
+            global_state,
+            message_queue_tx: &mut *message_queue_tx,
+        };
+        context.set_state(DialogState::MainMenu);
+        let result = async {
+            context.store_state().await?;
+            context
+                .send_message(STRINGS.kb.item_vanished().into())
+                .await?;
+            context.send_main_menu().await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!(
+                "Cannot return user {} to the main menu: {}",
+                dialog.user_id().0,
+                e
+            );
+        }
+    }
+    Ok((vanished.len(), failed.len()))
+}
+
+/// Periodically read the knowledge base items served from external sources again, so that the
+/// changes made to them show up without a restart.
+pub async fn reload_kb_periodically(
+    bot: BotType,
+    global_state: Arc<GlobalState>,
+    mut message_queue_tx: MessageQueueSender,
+    period: std::time::Duration,
+) {
+    let mut interval = tokio::time::interval(period);
+    // The items have just been read on startup.
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let uctx = ProviderUserContext::anonymous();
+        if let Err(e) = reload_kb(&bot, &global_state, &mut message_queue_tx, uctx).await {
+            warn!("Cannot reload the knowledge base: {}", e);
         }
     }
 }
@@ -250,16 +508,74 @@ impl Context<'_, '_, '_, '_> {
         self.dialog.data().read().unwrap().state.clone()
     }
 
-    async fn send_todo(&mut self, text: &str) -> HandlerResult<()> {
-        self.send_message(FormattedMessage::new(STRINGS.technical.todo(text)))
+    fn uctx(&self) -> ProviderUserContext {
+        ProviderUserContext {
+            permissions: *self.dialog.data().read().unwrap().user.permissions(),
+            user_id: Some(self.dialog.user_id()),
+        }
+    }
+
+    /// Continue the restored dialog by repeating the prompt of its state.
+    ///
+    /// If the state cannot be resumed (e.g. it refers to a note that has been deleted since), the
+    /// user is returned to the main menu.
+    async fn resume(&mut self) -> HandlerResult<()> {
+        self.send_message(STRINGS.technical.resumed().into())
+            .await?;
+        match self.send_state_prompt().await {
+            Ok(()) => Ok(()),
+            Err(HandlerError::User(e)) => {
+                debug!("Cannot resume the dialog state: {:?}", &e);
+                self.set_state(DialogState::MainMenu);
+                self.store_state().await?;
+                self.send_main_menu().await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write the current dialog state through to the database.
+    async fn store_state(&self) -> HandlerResult<()> {
+        let state = self.state();
+        self.global_state
+            .db
+            .store_dialog_state(self.dialog.user_id(), state)
             .await?;
         Ok(())
     }
 
-    fn uctx(&self) -> ProviderUserContext {
-        ProviderUserContext {
-            permissions: *self.dialog.data().read().unwrap().user.permissions(),
+    /// Remember the current username of the user, so that they can be looked up by it.
+    async fn update_username(&self, username: Option<String>) -> HandlerResult<()> {
+        let is_stored = {
+            let mut dialog_data = self.dialog.data().write().unwrap();
+            if dialog_data.user.username() == username.as_deref() {
+                return Ok(());
+            }
+            dialog_data.user.set_username(username);
+            // Users in the initial state are stored once they leave it.
+            !matches!(dialog_data.state, DialogState::Initial)
+        };
+        if is_stored {
+            self.store_user().await?;
+        }
+        Ok(())
+    }
+
+    /// Write the data of the current user through to the database. The roles pending for the
+    /// username of the user take effect immediately.
+    async fn store_user(&self) -> HandlerResult<()> {
+        let user = self.dialog.data().read().unwrap().user.clone();
+        let granted = self
+            .global_state
+            .db
+            .store_user(self.dialog.user_id(), self.dialog.chat_id(), user)
+            .await?;
+        if let Some(permissions) = granted {
+            self.global_state
+                .dialog_storage
+                .set_permissions(self.dialog.user_id(), permissions);
         }
+        Ok(())
     }
 
     async fn handle_callback_query(&mut self, query: &Query) -> HandlerResult<()> {
@@ -279,16 +595,14 @@ impl Context<'_, '_, '_, '_> {
                     .await?
                     .directories
                     .into_iter()
-                    .find(|&(ref name, _)| name == "Архив рассылок")
+                    .find(|(name, _)| name == "Архив рассылок")
                     .unwrap()
                     .1;
                 self.set_state(DialogState::KbNavigation(states::KbNavigation {
                     id: archive_id,
                 }));
             }
-            Query::OpenCalendar => {
-                self.send_todo("Open calendar").await?;
-            }
+            Query::OpenCalendar => self.open_calendar(),
             Query::OpenFeedback => {
                 self.set_state(DialogState::FeedbackTopicSelection);
             }
@@ -368,6 +682,15 @@ impl Context<'_, '_, '_, '_> {
                     id: *id,
                 }));
             }
+            Query::KbSearch { from } => {
+                self.set_state(DialogState::KbSearch(states::KbSearch { from: *from }));
+            }
+            Query::KbSearchPage { page } => self.open_search_page(*page)?,
+            Query::KbNoteHistory { id } => self.open_note_history(*id)?,
+            Query::KbDirectoryHistory { id } => self.open_directory_history(*id)?,
+            Query::KbRestoreRevision { id } => self.restore_revision(*id).await?,
+            Query::OpenTrash => self.open_trash(0)?,
+            Query::TrashPage { page } => self.open_trash_page(*page)?,
             Query::OpenNlSettings => self.set_state(DialogState::SubscriptionsMenu),
             Query::GoBack => {
                 let db = &self.global_state.db;
@@ -424,6 +747,22 @@ impl Context<'_, '_, '_, '_> {
                             id: ren.id,
                         }));
                     }
+                    DialogState::KbSearch(states::KbSearch { from })
+                    | DialogState::KbSearchResults(states::KbSearchResults { from, .. }) => {
+                        self.set_state(DialogState::KbNavigation(states::KbNavigation {
+                            id: from,
+                        }));
+                    }
+                    DialogState::KbNoteHistory(history) => {
+                        self.set_state(DialogState::KbNoteViewing(states::KbNoteViewing {
+                            id: history.id,
+                        }));
+                    }
+                    DialogState::KbDirectoryHistory(history) => {
+                        self.set_state(DialogState::KbDirectoryEditing(
+                            states::KbDirectoryEditing { id: history.id },
+                        ));
+                    }
                     DialogState::FormFilling(mut fill) => {
                         if !fill.form_state.can_go_back() {
                             return Err(InvalidAction::InvalidState.into());
@@ -431,15 +770,56 @@ impl Context<'_, '_, '_, '_> {
                         fill.form_state.back();
                         self.set_state(DialogState::FormFilling(fill));
                     }
-                    DialogState::SubscriptionsMenu => {
+                    DialogState::SubscriptionsMenu
+                    | DialogState::PinManagement
+                    | DialogState::Trash(_)
+                    | DialogState::AdminPanel => {
                         self.set_state(DialogState::MainMenu);
                     }
+                    DialogState::PinRenaming(_) => {
+                        self.set_state(DialogState::PinManagement);
+                    }
+                    DialogState::AdminUserLookup
+                    | DialogState::AdminUserRoles(_)
+                    | DialogState::AuditLog(_) => {
+                        self.set_state(DialogState::AdminPanel);
+                    }
+                    DialogState::AuditLogActionSelection(log) => {
+                        self.set_state(DialogState::AuditLog(log));
+                    }
+                    DialogState::Calendar(_) => self.set_state(DialogState::MainMenu),
+                    DialogState::EventViewing(view) => {
+                        self.set_state(DialogState::Calendar(view.calendar));
+                    }
+                    DialogState::EventDeletion(del) => {
+                        self.set_state(DialogState::EventViewing(states::EventViewing {
+                            id: del.id,
+                            calendar: del.calendar,
+                        }));
+                    }
+                    DialogState::AnnouncementComposing(draft) => match draft.scheduled {
+                        Some(id) => self.set_state(DialogState::ScheduledAnnouncementViewing(
+                            states::ScheduledAnnouncementViewing { id },
+                        )),
+                        None => self.set_state(DialogState::MainMenu),
+                    },
+                    DialogState::AnnouncementPreview(draft) => {
+                        self.set_state(DialogState::AnnouncementComposing(draft));
+                    }
+                    DialogState::AnnouncementScheduling(draft) => {
+                        self.set_state(DialogState::AnnouncementPreview(draft));
+                    }
+                    DialogState::ScheduledAnnouncements => self.set_state(DialogState::MainMenu),
+                    DialogState::ScheduledAnnouncementViewing(_) => {
+                        self.set_state(DialogState::ScheduledAnnouncements);
+                    }
                     _ => return Err(InvalidAction::InvalidState.into()),
                 }
             }
             Query::KbEditNote { id } => {
                 self.set_state(DialogState::KbNoteEditing(states::KbNoteEditing {
                     id: *id,
+                    draft: states::NoteDraft::default(),
                 }))
             }
             Query::KbRenameNote { id } => {
@@ -462,8 +842,10 @@ impl Context<'_, '_, '_, '_> {
                 }
                 _ => return Err(InvalidAction::InvalidState.into()),
             },
-            Query::KbPinNote { id } => self.send_todo(&format!("Pin note {}", id)).await?,
-            Query::KbUnpinNote { id } => self.send_todo(&format!("Unpin note {}", id)).await?,
+            Query::KbPinNote { id } => self.pin_item(FullItemId::Note(*id)).await?,
+            Query::KbUnpinNote { id } => self.unpin_item(FullItemId::Note(*id)).await?,
+            // The link is sent below the note, so there is no need to send the note again.
+            Query::KbShareNote { id } => return self.share_note(*id).await,
             Query::KbConfirmNoteDeletion { id } => {
                 let db = &self.global_state.db;
                 match self.state() {
@@ -488,6 +870,8 @@ impl Context<'_, '_, '_, '_> {
                 }
                 _ => return Err(InvalidAction::InvalidState.into()),
             },
+            Query::KbSaveNote => self.save_note_draft().await?,
+            Query::KbClearNoteAttachments { id } => self.clear_note_attachments(*id).await?,
             Query::KbEditDir { id } => match self.state() {
                 DialogState::KbNavigation(nav) if nav.id == *id => self.set_state(
                     DialogState::KbDirectoryEditing(states::KbDirectoryEditing { id: *id }),
@@ -586,12 +970,8 @@ impl Context<'_, '_, '_, '_> {
                     states::KbDirectoryDeletion { id: *id },
                 ));
             }
-            Query::KbPinDirectory { id } => {
-                self.send_todo(&format!("pin directory {}", id)).await?;
-            }
-            Query::KbUnpinDirectory { id } => {
-                self.send_todo(&format!("unpin directory {}", id)).await?;
-            }
+            Query::KbPinDirectory { id } => self.pin_item(FullItemId::Directory(*id)).await?,
+            Query::KbUnpinDirectory { id } => self.unpin_item(FullItemId::Directory(*id)).await?,
             Query::KbConfirmDirectoryDeletion { id } => match self.state() {
                 DialogState::KbDirectoryDeletion(del) if del.id == *id => {
                     self.require_kb_edit_permission()?;
@@ -619,7 +999,18 @@ impl Context<'_, '_, '_, '_> {
                     fill.form_state
                         .next(FormRawInput::Choice { index: *index })?;
                     if fill.form_state.is_done() {
-                        self.set_state(*fill.completion_state);
+                        self.complete_form(fill).await?;
+                    } else {
+                        self.set_state(DialogState::FormFilling(fill));
+                    }
+                }
+                _ => return Err(InvalidAction::InvalidState.into()),
+            },
+            Query::FormDone => match self.state() {
+                DialogState::FormFilling(mut fill) => {
+                    fill.form_state.next(FormRawInput::Done)?;
+                    if fill.form_state.is_done() {
+                        self.complete_form(fill).await?;
                     } else {
                         self.set_state(DialogState::FormFilling(fill));
                     }
@@ -640,6 +1031,7 @@ impl Context<'_, '_, '_, '_> {
                 };
 
                 if ok {
+                    self.store_user().await?;
                     self.send_message(STRINGS.newsletter.subscribed().into())
                         .await?;
                 } else {
@@ -661,6 +1053,7 @@ impl Context<'_, '_, '_, '_> {
                 };
 
                 if ok {
+                    self.store_user().await?;
                     self.send_message(STRINGS.newsletter.unsubscribed().into())
                         .await?;
                 } else {
@@ -671,6 +1064,100 @@ impl Context<'_, '_, '_, '_> {
             Query::ManageSubscriptions => {
                 self.set_state(DialogState::SubscriptionsMenu);
             }
+            Query::OpenPinManagement => {
+                self.require_kb_edit_permission()?;
+                self.set_state(DialogState::PinManagement);
+            }
+            Query::KbMovePinUp { pin } => self.move_pin(*pin, true).await?,
+            Query::KbMovePinDown { pin } => self.move_pin(*pin, false).await?,
+            Query::KbRenamePin { pin } => {
+                self.require_kb_edit_permission()?;
+                self.set_state(DialogState::PinRenaming(states::PinRenaming { pin: *pin }));
+            }
+            Query::KbResetPinLabel { pin } => self.reset_pin_label(*pin).await?,
+            Query::OpenAdminPanel => {
+                self.require_admin_permission()?;
+                self.set_state(DialogState::AdminPanel);
+            }
+            Query::AdminFindUser => {
+                self.require_admin_permission()?;
+                self.set_state(DialogState::AdminUserLookup);
+            }
+            Query::AdminOpenUser { user_id } => {
+                self.require_admin_permission()?;
+                self.set_state(DialogState::AdminUserRoles(states::AdminUserRoles {
+                    user_id: *user_id,
+                }));
+            }
+            Query::AdminGrantRole { user_id, role } => self.grant_role(*user_id, role).await?,
+            Query::AdminRevokeRole { user_id, role } => self.revoke_role(*user_id, role).await?,
+            Query::AdminReloadKb => self.reload_kb().await?,
+            Query::OpenAuditLog => self.open_audit_log(AuditFilter::default())?,
+            Query::AuditLogUser { user_id } => self.open_audit_log(AuditFilter {
+                user_id: Some(*user_id),
+                ..Default::default()
+            })?,
+            Query::AuditLogPage { page } => self.open_audit_log_page(*page)?,
+            Query::AuditLogActions => self.open_audit_log_actions()?,
+            Query::AuditLogFilterAction { action } => self.filter_audit_log_action(*action)?,
+            Query::AuditLogResetFilter => self.open_audit_log(AuditFilter::default())?,
+            Query::CalendarWeek { first_day } => {
+                self.open_calendar_page(CalendarPeriod::Week, *first_day);
+            }
+            Query::CalendarMonth { first_day } => {
+                self.open_calendar_page(CalendarPeriod::Month, *first_day);
+            }
+            Query::OpenEvent { id } => self.open_event(*id).await?,
+            Query::CreateEvent => {
+                self.start_event_form_filling(FormCompletionTarget::CreateEvent)
+                    .await?;
+            }
+            Query::EditEvent { id } => {
+                self.start_event_form_filling(FormCompletionTarget::EditEvent { id: *id })
+                    .await?;
+            }
+            Query::DeleteEvent { id } => match self.state() {
+                DialogState::EventViewing(view) if view.id == *id => {
+                    self.require_event_management_permission()?;
+                    self.set_state(DialogState::EventDeletion(states::EventDeletion {
+                        id: *id,
+                        calendar: view.calendar,
+                    }));
+                }
+                _ => return Err(InvalidAction::InvalidState.into()),
+            },
+            Query::ConfirmEventDeletion { id } => self.delete_event(*id).await?,
+            Query::RemindAboutEvent { id } => self.set_event_reminder(*id, true).await?,
+            Query::CancelEventReminder { id } => self.set_event_reminder(*id, false).await?,
+            Query::OpenAnnouncement => self.open_announcement()?,
+            Query::PreviewAnnouncement => self.preview_announcement()?,
+            Query::AnnouncementAudience { audience } => {
+                self.set_announcement_audience(audience.clone()).await?;
+            }
+            Query::SendAnnouncement => self.send_announcement().await?,
+            Query::ScheduleAnnouncement => match self.state() {
+                DialogState::AnnouncementPreview(draft) => {
+                    self.require_announcement_permission()?;
+                    self.set_state(DialogState::AnnouncementScheduling(draft));
+                }
+                _ => return Err(InvalidAction::InvalidState.into()),
+            },
+            Query::OpenScheduledAnnouncements => {
+                self.require_announcement_permission()?;
+                self.set_state(DialogState::ScheduledAnnouncements);
+            }
+            Query::OpenScheduledAnnouncement { id } => {
+                self.require_announcement_permission()?;
+                self.set_state(DialogState::ScheduledAnnouncementViewing(
+                    states::ScheduledAnnouncementViewing { id: *id },
+                ));
+            }
+            Query::EditScheduledAnnouncement { id } => {
+                self.edit_scheduled_announcement(*id).await?;
+            }
+            Query::CancelScheduledAnnouncement { id } => {
+                self.cancel_scheduled_announcement(*id).await?;
+            }
         };
         self.send_state_prompt().await?;
 
@@ -689,11 +1176,15 @@ impl Context<'_, '_, '_, '_> {
             }
             DialogState::KbNoteRenaming(ren) => self.send_note_renaming_prompt(ren.id).await,
             DialogState::KbNoteCreation(_) => self.send_note_creation_prompt().await,
-            DialogState::KbNoteCreationNamed(_) => self.send_note_creation_named_prompt().await,
+            DialogState::KbNoteCreationNamed(cre) => {
+                self.send_note_creation_named_prompt(&cre.draft).await
+            }
             DialogState::KbDirectoryEditing(edit) => {
                 self.send_directory_editing_prompt(edit.id).await
             }
-            DialogState::KbNoteEditing(edit) => self.send_note_editing_prompt(edit.id).await,
+            DialogState::KbNoteEditing(edit) => {
+                self.send_note_editing_prompt(edit.id, &edit.draft).await
+            }
             DialogState::KbNoteMovement(mv) => {
                 self.send_kb_directory(mv.destination, Some(FullItemId::Note(mv.note)))
                     .await
@@ -709,18 +1200,46 @@ impl Context<'_, '_, '_, '_> {
             DialogState::KbDirectoryDeletion(del) => {
                 self.send_directory_deletion_confirmation(del.id).await
             }
+            DialogState::KbSearch(_) => self.send_search_prompt().await,
+            DialogState::KbSearchResults(results) => self.send_search_results(results).await,
+            DialogState::KbNoteHistory(history) => self.send_note_history(history.id).await,
+            DialogState::KbDirectoryHistory(history) => {
+                self.send_directory_history(history.id).await
+            }
+            DialogState::Trash(trash) => self.send_trash(trash).await,
             DialogState::FeedbackTopicSelection => self.send_feedback_prompt().await,
             DialogState::FormFilling(fill) => self.send_form_filling_prompt(fill).await,
             DialogState::SubscriptionsMenu => self.send_subscriptions_menu().await,
+            DialogState::PinManagement => self.send_pin_management().await,
+            DialogState::PinRenaming(ren) => self.send_pin_renaming_prompt(ren.pin).await,
+            DialogState::AdminPanel => self.send_admin_panel().await,
+            DialogState::AdminUserLookup => self.send_admin_user_lookup_prompt().await,
+            DialogState::AdminUserRoles(roles) => self.send_admin_user_roles(roles.user_id).await,
+            DialogState::AuditLog(log) => self.send_audit_log(log).await,
+            DialogState::AuditLogActionSelection(_) => self.send_audit_log_actions().await,
+            DialogState::Calendar(cal) => self.send_calendar(cal).await,
+            DialogState::EventViewing(view) => self.send_event(view).await,
+            DialogState::EventDeletion(del) => self.send_event_deletion_prompt(del).await,
+            DialogState::AnnouncementComposing(draft) => {
+                self.send_announcement_composing_prompt(draft).await
+            }
+            DialogState::AnnouncementPreview(draft) => {
+                self.send_announcement_preview(draft).await
+            }
+            DialogState::AnnouncementScheduling(_) => {
+                self.send_announcement_scheduling_prompt().await
+            }
+            DialogState::ScheduledAnnouncements => self.send_scheduled_announcements().await,
+            DialogState::ScheduledAnnouncementViewing(view) => {
+                self.send_scheduled_announcement(view.id).await
+            }
         }
     }
 
     async fn handle_initial_message(&mut self, message: Message) -> HandlerResult<()> {
+        let deep_link = start_payload(&message).map(str::to_owned);
         let has_attachments = match message.kind {
-            MessageKind::Common(common) => match common.media_kind {
-                MediaKind::Text(_) => false,
-                _ => true,
-            },
+            MessageKind::Common(common) => !matches!(common.media_kind, MediaKind::Text(_)),
             _ => true,
         };
         if has_attachments {
@@ -732,7 +1251,11 @@ impl Context<'_, '_, '_, '_> {
         trace!("Sending welcome message");
         self.send_message(STRINGS.initial.welcome().into()).await?;
         self.set_state(DialogState::MainMenu);
-        self.send_main_menu().await?;
+        self.store_user().await?;
+        match deep_link {
+            Some(payload) => self.open_deep_link(&payload).await?,
+            None => self.send_main_menu().await?,
+        }
 
         Ok(())
     }
@@ -772,12 +1295,14 @@ impl Context<'_, '_, '_, '_> {
         state: states::KbNoteCreation,
     ) -> HandlerResult<()> {
         let name = extract_name(&message)?;
-        self.send_note_creation_named_prompt().await?;
+        self.send_note_creation_named_prompt(&states::NoteDraft::default())
+            .await?;
 
         self.set_state(DialogState::KbNoteCreationNamed(
             states::KbNoteCreationNamed {
                 destination: state.destination,
                 name: name.to_owned(),
+                draft: states::NoteDraft::default(),
             },
         ));
         Ok(())
@@ -786,14 +1311,67 @@ impl Context<'_, '_, '_, '_> {
     async fn handle_note_creation_named_message(
         &mut self,
         message: Message,
-        state: states::KbNoteCreationNamed,
+        mut state: states::KbNoteCreationNamed,
     ) -> HandlerResult<()> {
-        // TODO: save attachments.
-        let note = Note {
-            text: extract_formatted_text(&message)?,
-        };
         self.require_kb_edit_permission()?;
+        match add_to_note_draft(&message, &mut state.draft)? {
+            NoteDraftProgress::Complete(note) => {
+                self.finish_note_creation(state, note).await?;
+                self.send_state_prompt().await?;
+            }
+            NoteDraftProgress::Pending { is_same_album } => {
+                self.set_state(DialogState::KbNoteCreationNamed(state));
+                if !is_same_album {
+                    self.send_state_prompt().await?;
+                }
+            }
+        }
+        Ok(())
+    }
 
+    async fn handle_note_editing_message(
+        &mut self,
+        message: Message,
+        mut state: states::KbNoteEditing,
+    ) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        match add_to_note_draft(&message, &mut state.draft)? {
+            NoteDraftProgress::Complete(note) => {
+                self.finish_note_editing(state.id, note).await?;
+                self.send_state_prompt().await?;
+            }
+            NoteDraftProgress::Pending { is_same_album } => {
+                self.set_state(DialogState::KbNoteEditing(state));
+                if !is_same_album {
+                    self.send_state_prompt().await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Save the note being created or edited with the attachments sent so far and without any
+    /// further text.
+    async fn save_note_draft(&mut self) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        match self.state() {
+            DialogState::KbNoteCreationNamed(mut state) if !state.draft.attachments.is_empty() => {
+                let note = note_from_draft(&mut state.draft, None);
+                self.finish_note_creation(state, note).await
+            }
+            DialogState::KbNoteEditing(mut state) if !state.draft.attachments.is_empty() => {
+                let note = note_from_draft(&mut state.draft, None);
+                self.finish_note_editing(state.id, note).await
+            }
+            _ => Err(InvalidAction::InvalidState.into()),
+        }
+    }
+
+    async fn finish_note_creation(
+        &mut self,
+        state: states::KbNoteCreationNamed,
+        note: Note,
+    ) -> HandlerResult<()> {
         self.global_state
             .db
             .create_note(self.uctx(), state.destination, state.name.clone(), note)
@@ -805,28 +1383,20 @@ impl Context<'_, '_, '_, '_> {
 
         self.send_message(STRINGS.kb.note_creation_ok(&state.name).into())
             .await?;
-        self.send_state_prompt().await?;
         Ok(())
     }
 
-    async fn handle_note_editing_message(
-        &mut self,
-        message: Message,
-        state: states::KbNoteEditing,
-    ) -> HandlerResult<()> {
-        // TODO: save attachments.
-        let note = Note {
-            text: extract_formatted_text(&message)?,
-        };
-        self.require_kb_edit_permission()?;
-
+    /// Replace the note with the edited one. If no attachments were sent while editing, the
+    /// existing attachments of the note are kept.
+    async fn finish_note_editing(&mut self, id: FullNoteId, mut note: Note) -> HandlerResult<()> {
         let uctx = self.uctx();
-        self.global_state
-            .db
-            .update_note(uctx, state.id, note)
-            .await?;
-        let parent = self.global_state.db.note_parent(uctx, state.id).await?;
-        let note_name = self.global_state.db.note_name(uctx, state.id).await?;
+        let db = &self.global_state.db;
+        if note.attachments.is_empty() {
+            note.attachments = db.read_note(uctx, id).await?.attachments;
+        }
+        db.update_note(uctx, id, note).await?;
+        let parent = db.note_parent(uctx, id).await?;
+        let note_name = db.note_name(uctx, id).await?;
 
         self.set_state(DialogState::KbNavigation(states::KbNavigation {
             id: parent,
@@ -834,7 +1404,24 @@ impl Context<'_, '_, '_, '_> {
 
         self.send_message(STRINGS.kb.note_editing_ok(&note_name).into())
             .await?;
-        self.send_state_prompt().await?;
+        Ok(())
+    }
+
+    async fn clear_note_attachments(&mut self, id: FullNoteId) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        let mut state = match self.state() {
+            DialogState::KbNoteEditing(state) if state.id == id => state,
+            _ => return Err(InvalidAction::InvalidState.into()),
+        };
+        let uctx = self.uctx();
+        let db = &self.global_state.db;
+        let mut note = db.read_note(uctx, id).await?;
+        note.attachments.clear();
+        db.update_note(uctx, id, note).await?;
+        state.draft = states::NoteDraft::default();
+        self.set_state(DialogState::KbNoteEditing(state));
+        self.send_message(STRINGS.kb.note_attachments_cleared().into())
+            .await?;
         Ok(())
     }
 
@@ -913,43 +1500,87 @@ impl Context<'_, '_, '_, '_> {
                     accuracy: loc.location.horizontal_accuracy,
                 },
             },
-            MediaKind::Photo(photo) => FormRawInput::Message {
-                // TODO: attachments.
-                message: FormattedMessage::new(FormattedText {
-                    raw_text: photo.caption.unwrap_or_default(),
-                    entities: Some(photo.caption_entities),
-                }),
-            },
-            MediaKind::Document(doc) => FormRawInput::Message {
-                // TODO: attachments.
-                message: FormattedMessage::new(FormattedText {
+            MediaKind::Photo(photo) => {
+                let largest = photo
+                    .photo
+                    .iter()
+                    .max_by_key(|size| size.width * size.height)
+                    .ok_or(InvalidAction::UnexpectedMessageKind)?;
+                let image = media::Image {
+                    file: media::File {
+                        id: largest.file_id.clone(),
+                    },
+                };
+                let caption_entities = photo.caption_entities;
+                FormRawInput::Photo {
+                    image,
+                    caption: photo.caption.map(|raw_text| FormattedText {
+                        raw_text,
+                        entities: Some(caption_entities),
+                    }),
+                    media_group_id: photo.media_group_id,
+                }
+            }
+            MediaKind::Document(doc) => {
+                let mut message = FormattedMessage::new(FormattedText {
                     raw_text: doc.caption.unwrap_or_default(),
                     entities: Some(doc.caption_entities),
-                }),
-            },
-            MediaKind::Video(video) => FormRawInput::Message {
-                // TODO: attachments.
-                message: FormattedMessage::new(FormattedText {
+                });
+                message.attachments = vec![Attachment::Document(media::Document {
+                    file: media::File {
+                        id: doc.document.file_id,
+                    },
+                })];
+                FormRawInput::Message {
+                    message: Box::new(message),
+                    media_group_id: doc.media_group_id,
+                }
+            }
+            MediaKind::Video(video) => {
+                let mut message = FormattedMessage::new(FormattedText {
                     raw_text: video.caption.unwrap_or_default(),
                     entities: Some(video.caption_entities),
-                }),
-            },
+                });
+                message.attachments = vec![Attachment::Video(media::Video {
+                    file: media::File {
+                        id: video.video.file_id,
+                    },
+                })];
+                FormRawInput::Message {
+                    message: Box::new(message),
+                    media_group_id: video.media_group_id,
+                }
+            }
             _ => return Err(InvalidAction::UnexpectedMessageKind.into()),
         };
 
-        state.form_state.next(raw_input)?;
+        let progress = state.form_state.next(raw_input)?;
         if state.form_state.is_done() {
-            state
-                .on_completion
-                .send(state.form_state.into_parts())
-                .await
-                .unwrap();
-            self.set_state(*state.completion_state);
-            self.send_message(STRINGS.form.complete().into()).await?;
+            self.complete_form(state).await?;
         } else {
             self.set_state(DialogState::FormFilling(state));
         }
-        self.send_state_prompt().await?;
+        match progress {
+            FormProgress::Next | FormProgress::Pending { is_same_album: false } => {
+                self.send_state_prompt().await?;
+            }
+            FormProgress::Pending { is_same_album: true } | FormProgress::SameAlbum => (),
+        }
+        Ok(())
+    }
+
+    /// Hand the input of a completed form over to its target and leave the form.
+    async fn complete_form(&mut self, state: states::FormFilling) -> HandlerResult<()> {
+        let sender = match self.global_state.form_completion_sender(state.on_completion)? {
+            Some(sender) => sender,
+            None => return self.complete_event_form(state).await,
+        };
+        sender
+            .send(state.form_state.into_parts())
+            .await
+            .map_err(|_| InternalError::NewsletterStopped(feedback::NAME))?;
+        self.set_state(*state.completion_state);
+        self.send_message(STRINGS.form.complete().into()).await?;
         Ok(())
     }
 
@@ -972,52 +1603,81 @@ impl Context<'_, '_, '_, '_> {
     /// Send the main menu to the user.
     async fn send_main_menu(&mut self) -> HandlerResult<()> {
         trace!("Sending main menu");
+        let mut kb_buttons = vec![
+            vec![InlineKeyboardButton::callback(
+                "📂 Все заметки",
+                Query::OpenKb,
+            )],
+            vec![InlineKeyboardButton::callback(
+                "🗂 Архив рассылок",
+                Query::OpenNewsletterArchive,
+            )],
+        ];
+        kb_buttons.extend(self.pinned_item_buttons().await?);
+        if self.dialog.data().read().unwrap().user.permissions().edit_kb {
+            kb_buttons.push(vec![InlineKeyboardButton::callback(
+                "📌 Закреплённые материалы",
+                Query::OpenPinManagement,
+            )]);
+            kb_buttons.push(vec![InlineKeyboardButton::callback(
+                "🗑 Корзина",
+                Query::OpenTrash,
+            )]);
+        }
+        let mut actions = vec![
+            vec![InlineKeyboardButton::callback(
+                "🗞 Управление рассылками",
+                Query::OpenNlSettings,
+            )],
+            vec![InlineKeyboardButton::callback(
+                "💡 Обратная связь",
+                Query::OpenFeedback,
+            )],
+            vec![InlineKeyboardButton::callback(
+                "♻️ Предложить экологическую инициативу",
+                Query::OpenFeedbackTopic {
+                    topic: FeedbackTopic::SuggestEcoInitiative,
+                },
+            )],
+        ];
+        if self.can_send_announcements() {
+            actions.push(vec![InlineKeyboardButton::callback(
+                "📢 Отправить объявление",
+                Query::OpenAnnouncement,
+            )]);
+            actions.push(vec![InlineKeyboardButton::callback(
+                "🕒 Запланированные объявления",
+                Query::OpenScheduledAnnouncements,
+            )]);
+        }
+        if self.dialog.data().read().unwrap().user.permissions().admin {
+            actions.push(vec![InlineKeyboardButton::callback(
+                "🛡 Администрирование",
+                Query::OpenAdminPanel,
+            )]);
+        }
         let messages = [
             FormattedMessage::with_markup(
                 STRINGS.main_menu.header1(),
                 InlineKeyboardMarkup {
-                    inline_keyboard: vec![
-                        vec![InlineKeyboardButton::callback(
-                            "📂 Все заметки",
-                            Query::OpenKb,
-                        )],
-                        vec![InlineKeyboardButton::callback(
-                            "🗂 Архив рассылок",
-                            Query::OpenNewsletterArchive,
-                        )],
-                    ],
+                    inline_keyboard: kb_buttons,
+                }
+                .into(),
+            ),
+            FormattedMessage::with_markup(
+                STRINGS.main_menu.header2(),
+                InlineKeyboardMarkup {
+                    inline_keyboard: vec![vec![InlineKeyboardButton::callback(
+                        "📅 Календарь событий",
+                        Query::OpenCalendar,
+                    )]],
                 }
                 .into(),
             ),
-            //FormattedMessage::with_markup(
-            //    STRINGS.main_menu.header2(),
-            //    InlineKeyboardMarkup {
-            //        inline_keyboard: vec![vec![InlineKeyboardButton::callback(
-            //            "📅 Календарь событий",
-            //            Query::OpenCalendar,
-            //        )]],
-            //    }
-            //    .into(),
-            //),
             FormattedMessage::with_markup(
                 STRINGS.main_menu.header3(),
                 InlineKeyboardMarkup {
-                    inline_keyboard: vec![
-                        vec![InlineKeyboardButton::callback(
-                            "🗞 Управление рассылками",
-                            Query::OpenNlSettings,
-                        )],
-                        vec![InlineKeyboardButton::callback(
-                            "💡 Обратная связь",
-                            Query::OpenFeedback,
-                        )],
-                        vec![InlineKeyboardButton::callback(
-                            "♻️ Предложить экологическую инициативу",
-                            Query::OpenFeedbackTopic {
-                                topic: FeedbackTopic::SuggestEcoInitiative,
-                            },
-                        )],
-                    ],
+                    inline_keyboard: actions,
                 }
                 .into(),
             ),
@@ -1042,7 +1702,7 @@ impl Context<'_, '_, '_, '_> {
             fn cmp<T: Ord, U>(a: &(T, U), b: &(T, U)) -> std::cmp::Ordering {
                 let a_key = &a.0;
                 let b_key = &b.0;
-                a_key.cmp(&b_key)
+                a_key.cmp(b_key)
             }
 
             directory.notes.sort_unstable_by(cmp);
@@ -1074,7 +1734,7 @@ impl Context<'_, '_, '_, '_> {
                 } else {
                     0
                 };
-            let mut inline_keyboard = Vec::with_capacity(2 + num_children);
+            let mut inline_keyboard = Vec::with_capacity(3 + num_children);
             inline_keyboard.push(first_row);
 
             if let Some(item) = item_for_move {
@@ -1092,6 +1752,10 @@ impl Context<'_, '_, '_, '_> {
                     },
                 )])
             } else {
+                inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                    "🔍 Поиск",
+                    Query::KbSearch { from: id },
+                )]);
                 let is_editor = self
                     .dialog
                     .data()
@@ -1121,9 +1785,11 @@ impl Context<'_, '_, '_, '_> {
                 }
             }
 
-            let dir_description = match db.directory_name(uctx, id).await? {
-                Some(name) => format!("разделе «{}»", name),
-                None => String::from("корневом разделе"),
+            let path = db.directory_path(uctx, id).await?;
+            let dir_description = if path.is_empty() {
+                String::from("корневом разделе")
+            } else {
+                format!("разделе «{}»", breadcrumbs(&path))
             };
             let text = match item_for_move {
                 Some(FullItemId::Note(note)) => {
@@ -1161,7 +1827,11 @@ impl Context<'_, '_, '_, '_> {
             };
 
             let reply_markup = Some(InlineKeyboardMarkup { inline_keyboard }.into());
-            FormattedMessage { text, reply_markup }
+            FormattedMessage {
+                text,
+                reply_markup,
+                attachments: Vec::new(),
+            }
         };
         self.send_message(message).await?;
         Ok(())
@@ -1174,10 +1844,15 @@ impl Context<'_, '_, '_, '_> {
         let uctx = self.uctx();
         let db = &self.global_state.db;
         let note = db.read_note(uctx, id).await?;
-        let note_name = db.note_name(uctx, id).await?;
+        let mut path = db.note_path(uctx, id).await?;
+        let note_name = path.pop().unwrap();
 
-        let mut inline_keyboard = Vec::with_capacity(if is_editor { 6 } else { 1 });
+        let mut inline_keyboard = Vec::with_capacity(if is_editor { 8 } else { 2 });
 
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "🔗 Поделиться",
+            Query::KbShareNote { id },
+        )]);
         if is_editor {
             inline_keyboard.push(vec![InlineKeyboardButton::callback(
                 "📝 Редактировать",
@@ -1196,9 +1871,12 @@ impl Context<'_, '_, '_, '_> {
                 Query::KbDeleteNote { id },
             )]);
             inline_keyboard.push(vec![InlineKeyboardButton::callback(
-                "📌 Закрепить в главном меню",
-                Query::KbPinNote { id },
+                "🕓 История изменений",
+                Query::KbNoteHistory { id },
             )]);
+            inline_keyboard.push(vec![
+                self.pin_toggle_button(FullItemId::Note(id)).await?
+            ]);
         }
         inline_keyboard.push(vec![
             InlineKeyboardButton::callback("⬅️ Назад", Query::GoBack),
@@ -1206,15 +1884,20 @@ impl Context<'_, '_, '_, '_> {
         ]);
 
         let reply_markup = InlineKeyboardMarkup { inline_keyboard };
-        let text = STRINGS.kb.note_template(&note_name).concat(note.text);
-        self.send_message(FormattedMessage::with_markup(text, reply_markup.into()))
-            .await?;
+        let text = STRINGS
+            .kb
+            .note_location(&breadcrumbs(&path))
+            .concat(STRINGS.kb.note_template(&note_name))
+            .concat(note.text);
+        let mut message = FormattedMessage::with_markup(text, reply_markup.into());
+        message.attachments = note.attachments;
+        self.send_message(message).await?;
         Ok(())
     }
 
     async fn send_note_deletion_confirmation(&mut self, id: FullNoteId) -> HandlerResult<()> {
         let db = &self.global_state.db;
-        let note_name = db.note_name(self.uctx(), id).await?;
+        let path = db.note_path(self.uctx(), id).await?;
         let reply_markup = InlineKeyboardMarkup {
             inline_keyboard: vec![vec![
                 InlineKeyboardButton::callback("Да, удалить", Query::KbConfirmNoteDeletion { id }),
@@ -1224,9 +1907,8 @@ impl Context<'_, '_, '_, '_> {
                 ),
             ]],
         };
-        // TODO: print full path.
         self.send_message(FormattedMessage::with_markup(
-            STRINGS.kb.note_deletion_confirmation(&note_name),
+            STRINGS.kb.note_deletion_confirmation(&breadcrumbs(&path)),
             reply_markup.into(),
         ))
         .await?;
@@ -1265,18 +1947,26 @@ impl Context<'_, '_, '_, '_> {
         Ok(())
     }
 
-    async fn send_note_creation_named_prompt(&mut self) -> HandlerResult<()> {
-        let reply_markup = InlineKeyboardMarkup {
-            inline_keyboard: vec![vec![InlineKeyboardButton::callback(
-                "⬅️ Назад",
-                Query::GoBack,
-            )]],
-        };
-        self.send_message(FormattedMessage::with_markup(
-            STRINGS.kb.note_creation_named_prompt(),
-            reply_markup.into(),
-        ))
-        .await?;
+    async fn send_note_creation_named_prompt(
+        &mut self,
+        draft: &states::NoteDraft,
+    ) -> HandlerResult<()> {
+        let mut inline_keyboard = Vec::with_capacity(2);
+        let mut text = STRINGS.kb.note_creation_named_prompt();
+        if !draft.attachments.is_empty() {
+            text = text.concat(STRINGS.kb.note_draft_status(&draft.attachments.len()));
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                "💾 Сохранить",
+                Query::KbSaveNote,
+            )]);
+        }
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "⬅️ Назад",
+            Query::GoBack,
+        )]);
+        let reply_markup = InlineKeyboardMarkup { inline_keyboard };
+        self.send_message(FormattedMessage::with_markup(text, reply_markup.into()))
+            .await?;
         Ok(())
     }
 
@@ -1284,6 +1974,9 @@ impl Context<'_, '_, '_, '_> {
         &mut self,
         destination: FullDirectoryId,
     ) -> HandlerResult<()> {
+        let pin_button = self
+            .pin_toggle_button(FullItemId::Directory(destination))
+            .await?;
         let reply_markup = InlineKeyboardMarkup {
             inline_keyboard: vec![
                 vec![InlineKeyboardButton::callback(
@@ -1306,7 +1999,11 @@ impl Context<'_, '_, '_, '_> {
                     "🗑 Удалить",
                     Query::KbDeleteDirectory { id: destination },
                 )],
-                // TODO: pinning.
+                vec![InlineKeyboardButton::callback(
+                    "🕓 История изменений",
+                    Query::KbDirectoryHistory { id: destination },
+                )],
+                vec![pin_button],
                 vec![InlineKeyboardButton::callback("⬅️ Назад", Query::GoBack)],
             ],
         };
@@ -1326,19 +2023,37 @@ impl Context<'_, '_, '_, '_> {
         Ok(())
     }
 
-    async fn send_note_editing_prompt(&mut self, note: FullNoteId) -> HandlerResult<()> {
-        let reply_markup = InlineKeyboardMarkup {
-            inline_keyboard: vec![vec![InlineKeyboardButton::callback(
-                "⬅️ Назад",
-                Query::GoBack,
-            )]],
-        };
-        let name = self.global_state.db.note_name(self.uctx(), note).await?;
-        self.send_message(FormattedMessage::with_markup(
-            STRINGS.kb.note_editing_prompt(&name),
-            reply_markup.into(),
-        ))
-        .await?;
+    async fn send_note_editing_prompt(
+        &mut self,
+        note: FullNoteId,
+        draft: &states::NoteDraft,
+    ) -> HandlerResult<()> {
+        let uctx = self.uctx();
+        let db = &self.global_state.db;
+        let name = db.note_name(uctx, note).await?;
+        let has_attachments = !db.read_note(uctx, note).await?.attachments.is_empty();
+
+        let mut inline_keyboard = Vec::with_capacity(3);
+        let mut text = STRINGS.kb.note_editing_prompt(&name);
+        if !draft.attachments.is_empty() {
+            text = text.concat(STRINGS.kb.note_draft_status(&draft.attachments.len()));
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                "💾 Сохранить",
+                Query::KbSaveNote,
+            )]);
+        } else if has_attachments {
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                "🗑 Убрать вложения",
+                Query::KbClearNoteAttachments { id: note },
+            )]);
+        }
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "⬅️ Назад",
+            Query::GoBack,
+        )]);
+        let reply_markup = InlineKeyboardMarkup { inline_keyboard };
+        self.send_message(FormattedMessage::with_markup(text, reply_markup.into()))
+            .await?;
         Ok(())
     }
 
@@ -1383,10 +2098,10 @@ impl Context<'_, '_, '_, '_> {
         id: FullDirectoryId,
     ) -> HandlerResult<()> {
         let db = &self.global_state.db;
-        let directory_name = db
-            .directory_name(self.uctx(), id)
-            .await?
-            .ok_or(ProviderError::CannotDeleteRoot)?;
+        let path = db.directory_path(self.uctx(), id).await?;
+        if path.is_empty() {
+            return Err(ProviderError::CannotDeleteRoot.into());
+        }
         let reply_markup = InlineKeyboardMarkup {
             inline_keyboard: vec![vec![
                 InlineKeyboardButton::callback(
@@ -1399,9 +2114,10 @@ impl Context<'_, '_, '_, '_> {
                 ),
             ]],
         };
-        // TODO: print full path.
         self.send_message(FormattedMessage::with_markup(
-            STRINGS.kb.directory_deletion_confirmation(&directory_name),
+            STRINGS
+                .kb
+                .directory_deletion_confirmation(&breadcrumbs(&path)),
             reply_markup.into(),
         ))
         .await?;
@@ -1457,8 +2173,31 @@ impl Context<'_, '_, '_, '_> {
 
     async fn send_form_filling_prompt(&mut self, fil: states::FormFilling) -> HandlerResult<()> {
         let elem = fil.form_state.current_element();
-        let text = &elem.text;
+        let mut text = FormattedText {
+            raw_text: elem.text.clone(),
+            entities: None,
+        };
+        let num_pending = fil.form_state.pending_attachments().len();
+        if num_pending != 0 {
+            text = text.concat(STRINGS.form.pending_status(&num_pending));
+        }
+        // A gallery may be finished at any time, and an album sent as a message once it has been
+        // received.
+        let can_finish = match elem.input_type {
+            FormInputType::ImageGallery => true,
+            FormInputType::Message => num_pending != 0,
+            _ => false,
+        };
         let markup = match &elem.input_type {
+            _ if can_finish => Some(
+                InlineKeyboardMarkup {
+                    inline_keyboard: vec![vec![InlineKeyboardButton::callback(
+                        "✅ Готово",
+                        Query::FormDone,
+                    )]],
+                }
+                .into(),
+            ),
             FormInputType::Choice { options } => {
                 let inline_keyboard = options
                     .iter()
@@ -1488,11 +2227,9 @@ impl Context<'_, '_, '_, '_> {
             _ => None,
         };
         let message = FormattedMessage {
-            text: FormattedText {
-                raw_text: text.clone(),
-                entities: None,
-            },
+            text,
             reply_markup: markup,
+            attachments: Vec::new(),
         };
         self.send_message(message).await?;
         Ok(())
@@ -1519,6 +2256,13 @@ impl Context<'_, '_, '_, '_> {
                         text: String::from("Контактный email при наличии:"),
                         input_type: FormInputType::ShortText,
                     },
+                    FormElement {
+                        text: String::from(concat!(
+                            "Отправьте фотографии свалки (по одной или альбомом) ",
+                            "и нажмите «Готово». Если фотографий нет, просто нажмите «Готово».",
+                        )),
+                        input_type: FormInputType::ImageGallery,
+                    },
                     FormElement {
                         text: String::from("Опишите подробности, которые могут быть важными:"),
                         input_type: FormInputType::Message,
@@ -1542,14 +2286,7 @@ impl Context<'_, '_, '_, '_> {
             form_state,
             completion_state: Box::new(DialogState::MainMenu),
             return_state: Box::new(current_state),
-            on_completion: self
-                .global_state
-                .feedback_tx
-                .lock()
-                .unwrap()
-                .as_ref()
-                .unwrap()
-                .clone(),
+            on_completion: FormCompletionTarget::Feedback,
         };
         self.set_state(DialogState::FormFilling(state));
     }
@@ -1566,7 +2303,7 @@ impl Context<'_, '_, '_, '_> {
             .clone();
 
         let mut text = nl.menu_head();
-        for &(ref name, ref description, ref is_allowed) in self.global_state.newsletters.iter() {
+        for (name, description, is_allowed) in self.global_state.newsletters.iter() {
             if !is_allowed(self.dialog.data().read().unwrap().user.permissions()) {
                 continue;
             }
@@ -1580,7 +2317,7 @@ impl Context<'_, '_, '_, '_> {
         }
 
         let newsletter_buttons_iter = self.global_state.newsletters.iter().flat_map(
-            |&(ref name, ref desc, ref is_allowed)| {
+            |(name, desc, is_allowed)| {
                 if !is_allowed(self.dialog.data().read().unwrap().user.permissions()) {
                     return None;
                 }
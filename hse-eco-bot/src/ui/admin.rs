@@ -0,0 +1,267 @@
+//! The admin panel: viewing the privileged users and managing their roles.
+
+use super::Context;
+use crate::callback_query::Query;
+use crate::invalid_action::InvalidAction;
+use crate::message::FormattedMessage;
+use crate::state::{states, DialogState};
+use crate::strings::STRINGS;
+use crate::types::HandlerResult;
+use crate::user::Permissions;
+use crate::user_lookup_error::UserLookupError;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, UserId};
+
+/// Format a user for display, e.g. `@username (123456789)`.
+pub(super) fn user_label(user_id: UserId, username: Option<&str>) -> String {
+    match username {
+        Some(username) => format!("@{} ({})", username, user_id.0),
+        None => user_id.0.to_string(),
+    }
+}
+
+impl Context<'_, '_, '_, '_> {
+    pub(super) fn require_admin_permission(&self) -> Result<(), InvalidAction> {
+        if self.dialog.data().read().unwrap().user.permissions().admin {
+            Ok(())
+        } else {
+            Err(InvalidAction::PermissionDenied)
+        }
+    }
+
+    /// Determine the user from a forwarded message, a username or a numeric user ID.
+    pub(super) async fn handle_admin_user_lookup_message(
+        &mut self,
+        message: Message,
+    ) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        let user_id = if let Some(user) = message.forward_from_user() {
+            user.id
+        } else if message.forward_from_sender_name().is_some() {
+            return Err(UserLookupError::HiddenForward.into());
+        } else {
+            let text = message.text().ok_or(UserLookupError::InvalidInput)?.trim();
+            if let Some(username) = text.strip_prefix('@') {
+                self.global_state
+                    .db
+                    .find_user_by_username(username.to_owned())
+                    .await?
+                    .ok_or_else(|| UserLookupError::UnknownUsername(username.to_owned()))?
+            } else if let Ok(raw_id) = text.parse() {
+                UserId(raw_id)
+            } else {
+                return Err(UserLookupError::InvalidInput.into());
+            }
+        };
+        self.set_state(DialogState::AdminUserRoles(states::AdminUserRoles {
+            user_id,
+        }));
+        self.send_state_prompt().await
+    }
+
+    /// Grant a role to a user and apply the new permissions to their dialog immediately.
+    pub(super) async fn grant_role(&mut self, user_id: UserId, role: &str) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        self.require_role_exists(role).await?;
+        let permissions = self
+            .global_state
+            .db
+            .grant_role(self.uctx(), user_id, role.to_owned())
+            .await?;
+        self.apply_permissions(user_id, permissions);
+        let username = self.global_state.db.username(user_id).await?;
+        self.send_message(
+            STRINGS
+                .admin
+                .role_granted(&role, &user_label(user_id, username.as_deref()))
+                .into(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Revoke a role from a user and apply the new permissions to their dialog immediately.
+    pub(super) async fn revoke_role(&mut self, user_id: UserId, role: &str) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        self.require_role_exists(role).await?;
+        let permissions = self
+            .global_state
+            .db
+            .revoke_role(self.uctx(), user_id, role.to_owned())
+            .await?;
+        self.apply_permissions(user_id, permissions);
+        let username = self.global_state.db.username(user_id).await?;
+        self.send_message(
+            STRINGS
+                .admin
+                .role_revoked(&role, &user_label(user_id, username.as_deref()))
+                .into(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub(super) async fn require_role_exists(&self, role: &str) -> HandlerResult<()> {
+        let roles = self.global_state.db.roles().await?;
+        if roles.iter().any(|r| r.name == role) {
+            Ok(())
+        } else {
+            Err(InvalidAction::InvalidState.into())
+        }
+    }
+
+    fn apply_permissions(&self, user_id: UserId, permissions: Permissions) {
+        self.global_state
+            .dialog_storage
+            .set_permissions(user_id, permissions);
+        if user_id == self.dialog.user_id() {
+            self.set_state(if permissions.admin {
+                DialogState::AdminUserRoles(states::AdminUserRoles { user_id })
+            } else {
+                // The admin has revoked their own access to the panel.
+                DialogState::MainMenu
+            });
+        } else {
+            self.set_state(DialogState::AdminUserRoles(states::AdminUserRoles {
+                user_id,
+            }));
+        }
+    }
+
+    /// Read the knowledge base sections served from files again.
+    pub(super) async fn reload_kb(&mut self) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        let uctx = self.uctx();
+        let (vanished, failed) =
+            super::reload_kb(self.bot, self.global_state, self.message_queue_tx, uctx).await?;
+        let mut text = STRINGS.admin.kb_reloaded(&vanished);
+        if failed != 0 {
+            text = text.concat(STRINGS.admin.kb_reload_failed(&failed));
+        }
+        self.send_message(text.into()).await?;
+        Ok(())
+    }
+
+    pub(super) async fn send_admin_panel(&mut self) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        let users = self.global_state.db.privileged_users().await?;
+        let mut text = if users.is_empty() {
+            STRINGS.admin.panel_empty()
+        } else {
+            STRINGS.admin.panel()
+        };
+        let mut inline_keyboard = vec![
+            vec![InlineKeyboardButton::callback(
+                "➕ Выдать роль",
+                Query::AdminFindUser,
+            )],
+            vec![InlineKeyboardButton::callback(
+                "📜 Журнал действий",
+                Query::OpenAuditLog,
+            )],
+            vec![InlineKeyboardButton::callback(
+                "🔄 Перечитать разделы из файлов",
+                Query::AdminReloadKb,
+            )],
+        ];
+        for user in users {
+            let label = user_label(user.id, user.username.as_deref());
+            text = text.concat(STRINGS.admin.panel_item(&label, &user.roles.join(", ")));
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                format!("👤 {}", label),
+                Query::AdminOpenUser { user_id: user.id },
+            )]);
+        }
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "🏠 В главное меню",
+            Query::OpenMainMenu,
+        )]);
+
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub(super) async fn send_admin_user_lookup_prompt(&mut self) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        self.send_message(FormattedMessage::with_markup(
+            STRINGS.admin.user_lookup_prompt(),
+            InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
+                "⬅️ Назад",
+                Query::GoBack,
+            )]])
+            .into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Show the roles of a user, their effective permissions and the buttons to change the roles.
+    pub(super) async fn send_admin_user_roles(&mut self, user_id: UserId) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        let db = &self.global_state.db;
+        let roles = db.roles().await?;
+        let user_roles = db.user_roles(user_id).await?;
+        let username = db.username(user_id).await?;
+        let label = user_label(user_id, username.as_deref());
+
+        let role_list = if user_roles.is_empty() {
+            STRINGS.admin.no_roles().raw_text
+        } else {
+            user_roles.join(", ")
+        };
+        let mut text = STRINGS.admin.user_roles(&label, &role_list);
+        let permissions = roles
+            .iter()
+            .filter(|role| user_roles.contains(&role.name))
+            .flat_map(|role| role.permissions.iter())
+            .collect::<Permissions>();
+        if permissions == Permissions::default() {
+            text = text.concat(STRINGS.admin.no_permissions());
+        }
+        for permission in permissions.iter() {
+            text = text.concat(STRINGS.admin.permission_item(&permission.description()));
+        }
+
+        let mut inline_keyboard: Vec<_> = roles
+            .into_iter()
+            .map(|role| {
+                if user_roles.contains(&role.name) {
+                    vec![InlineKeyboardButton::callback(
+                        format!("✅ {} — Отозвать", &role.name),
+                        Query::AdminRevokeRole {
+                            user_id,
+                            role: role.name,
+                        },
+                    )]
+                } else {
+                    vec![InlineKeyboardButton::callback(
+                        format!("➕ {} — Выдать", &role.name),
+                        Query::AdminGrantRole {
+                            user_id,
+                            role: role.name,
+                        },
+                    )]
+                }
+            })
+            .collect();
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "📜 Журнал действий пользователя",
+            Query::AuditLogUser { user_id },
+        )]);
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "⬅️ Назад",
+            Query::GoBack,
+        )]);
+
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+}
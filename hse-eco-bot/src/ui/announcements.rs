@@ -0,0 +1,443 @@
+//! Composing and sending announcements to the users of the bot.
+
+use super::form::DATE_TIME_FORMAT;
+use super::{extract_attachment, extract_formatted_text, Context};
+use crate::callback_query::Query;
+use crate::invalid_action::InvalidAction;
+use crate::message::{FormattedMessage, FormattedText};
+use crate::newsletter::announcements::{self, Announcement, Audience};
+use crate::schedule::ScheduledAnnouncement;
+use crate::schedule_error::ScheduleError;
+use crate::state::{states, DialogState};
+use crate::strings::STRINGS;
+use crate::types::{HandlerResult, InternalError};
+use chrono::{Local, NaiveDateTime};
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+fn is_draft_empty(draft: &states::AnnouncementDraft) -> bool {
+    draft.text.raw_text.is_empty() && draft.attachments.is_empty()
+}
+
+/// Describe the recipients of an announcement.
+fn audience_label(audience: &Audience) -> String {
+    match audience {
+        Audience::Subscribers => String::from("подписчики рассылки «Объявления»"),
+        Audience::Everyone => String::from("все пользователи"),
+        Audience::Role { role } => format!("пользователи с ролью «{}»", role),
+    }
+}
+
+/// Get the beginning of the first line of a text to tell the announcements apart.
+fn text_summary(text: &str) -> String {
+    const MAX_CHARS: usize = 32;
+    let line = text.lines().next().unwrap_or_default();
+    if line.chars().count() > MAX_CHARS {
+        format!("{}…", line.chars().take(MAX_CHARS).collect::<String>())
+    } else {
+        line.to_owned()
+    }
+}
+
+fn audience_button(label: String, audience: Audience, current: &Audience) -> InlineKeyboardButton {
+    let label = if &audience == current {
+        format!("✅ {}", label)
+    } else {
+        label
+    };
+    InlineKeyboardButton::callback(label, Query::AnnouncementAudience { audience })
+}
+
+impl Context<'_, '_, '_, '_> {
+    pub(super) fn can_send_announcements(&self) -> bool {
+        self.dialog
+            .data()
+            .read()
+            .unwrap()
+            .user
+            .permissions()
+            .send_global_notifications
+    }
+
+    pub(super) fn require_announcement_permission(&self) -> Result<(), InvalidAction> {
+        if self.can_send_announcements() {
+            Ok(())
+        } else {
+            Err(InvalidAction::PermissionDenied)
+        }
+    }
+
+    pub(super) fn open_announcement(&mut self) -> Result<(), InvalidAction> {
+        self.require_announcement_permission()?;
+        self.set_state(DialogState::AnnouncementComposing(
+            states::AnnouncementDraft {
+                text: FormattedText {
+                    raw_text: String::new(),
+                    entities: None,
+                },
+                attachments: Vec::new(),
+                audience: Audience::default(),
+                last_media_group_id: None,
+                scheduled: None,
+            },
+        ));
+        Ok(())
+    }
+
+    /// Add the text or the attachment of a message to the announcement being composed.
+    ///
+    /// A new text replaces the previous one, and attachments are appended.
+    pub(super) async fn handle_announcement_message(
+        &mut self,
+        message: Message,
+        mut draft: states::AnnouncementDraft,
+    ) -> HandlerResult<()> {
+        self.require_announcement_permission()?;
+        let attachment = extract_attachment(&message);
+        let text = extract_formatted_text(&message).ok();
+        if attachment.is_none() && text.is_none() {
+            return Err(InvalidAction::UnexpectedMessageKind.into());
+        }
+
+        if let Some(text) = text.filter(|text| !text.raw_text.is_empty()) {
+            draft.text = text;
+        }
+        draft.attachments.extend(attachment);
+        let media_group_id = message.media_group_id().map(str::to_owned);
+        let is_same_album = media_group_id.is_some() && media_group_id == draft.last_media_group_id;
+        draft.last_media_group_id = media_group_id;
+
+        self.set_state(DialogState::AnnouncementComposing(draft));
+        if !is_same_album {
+            self.send_state_prompt().await?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn preview_announcement(&mut self) -> Result<(), InvalidAction> {
+        self.require_announcement_permission()?;
+        match self.state() {
+            DialogState::AnnouncementComposing(draft) if !is_draft_empty(&draft) => {
+                self.set_state(DialogState::AnnouncementPreview(draft));
+                Ok(())
+            }
+            _ => Err(InvalidAction::InvalidState),
+        }
+    }
+
+    pub(super) async fn set_announcement_audience(
+        &mut self,
+        audience: Audience,
+    ) -> HandlerResult<()> {
+        self.require_announcement_permission()?;
+        let mut draft = match self.state() {
+            DialogState::AnnouncementPreview(draft) => draft,
+            _ => return Err(InvalidAction::InvalidState.into()),
+        };
+        if let Audience::Role { ref role } = audience {
+            self.require_role_exists(role).await?;
+        }
+        draft.audience = audience;
+        self.set_state(DialogState::AnnouncementPreview(draft));
+        Ok(())
+    }
+
+    /// Hand the previewed announcement over to the newsletter and return to the main menu.
+    pub(super) async fn send_announcement(&mut self) -> HandlerResult<()> {
+        self.require_announcement_permission()?;
+        let draft = match self.state() {
+            DialogState::AnnouncementPreview(draft) => draft,
+            _ => return Err(InvalidAction::InvalidState.into()),
+        };
+        let uctx = self.uctx();
+        let announcement_tx = self.global_state.announcement_sender()?;
+        // Sending a scheduled announcement early cancels its scheduled sending. It is removed
+        // along with recording the sending, so that it is kept if the newsletter has stopped.
+        if let Some(id) = draft.scheduled {
+            let db = &self.global_state.db;
+            if db.scheduled_announcement(id).await?.is_none() {
+                return Err(ScheduleError::NoSuchAnnouncement.into());
+            }
+        }
+        let announcement = Announcement {
+            text: draft.text,
+            attachments: draft.attachments,
+            audience: draft.audience,
+        };
+        if announcement_tx.send(announcement).await.is_err() {
+            return Err(InternalError::NewsletterStopped(announcements::NAME).into());
+        }
+        self.global_state
+            .db
+            .record_sent_announcement(uctx, draft.scheduled)
+            .await?;
+        self.set_state(DialogState::MainMenu);
+        self.send_message(STRINGS.announcements.sent().into())
+            .await?;
+        Ok(())
+    }
+
+    pub(super) async fn send_announcement_composing_prompt(
+        &mut self,
+        draft: states::AnnouncementDraft,
+    ) -> HandlerResult<()> {
+        let has_text = if draft.text.raw_text.is_empty() {
+            STRINGS.announcements.no_text()
+        } else {
+            STRINGS.announcements.has_text()
+        };
+        let text = STRINGS.announcements.composing().concat(
+            STRINGS
+                .announcements
+                .draft_status(&has_text.raw_text, &draft.attachments.len()),
+        );
+
+        let mut inline_keyboard = Vec::with_capacity(2);
+        if !is_draft_empty(&draft) {
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                "👁 Предпросмотр",
+                Query::PreviewAnnouncement,
+            )]);
+        }
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "❌ Отменить",
+            Query::GoBack,
+        )]);
+
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Show the announcement the way the recipients will see it, followed by the choice of the
+    /// recipients.
+    pub(super) async fn send_announcement_preview(
+        &mut self,
+        draft: states::AnnouncementDraft,
+    ) -> HandlerResult<()> {
+        let mut preview = FormattedMessage::new(
+            STRINGS
+                .newsletter
+                .header(&self.global_state.announcement_tags)
+                .concat(draft.text),
+        );
+        preview.attachments = draft.attachments;
+        self.send_message(preview).await?;
+
+        let current = &draft.audience;
+        let mut inline_keyboard = vec![
+            vec![audience_button(
+                String::from("📣 Подписчикам рассылки"),
+                Audience::Subscribers,
+                current,
+            )],
+            vec![audience_button(
+                String::from("👥 Всем пользователям"),
+                Audience::Everyone,
+                current,
+            )],
+        ];
+        for role in self.global_state.db.roles().await? {
+            inline_keyboard.push(vec![audience_button(
+                format!("🎭 Роль «{}»", &role.name),
+                Audience::Role { role: role.name },
+                current,
+            )]);
+        }
+        inline_keyboard.push(vec![
+            InlineKeyboardButton::callback("📨 Отправить сейчас", Query::SendAnnouncement),
+            InlineKeyboardButton::callback("🕒 Запланировать", Query::ScheduleAnnouncement),
+        ]);
+        inline_keyboard.push(vec![
+            InlineKeyboardButton::callback("✏️ Изменить", Query::GoBack),
+            InlineKeyboardButton::callback("🏠 В главное меню", Query::OpenMainMenu),
+        ]);
+
+        self.send_message(FormattedMessage::with_markup(
+            STRINGS.announcements.preview(),
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Schedule the announcement being composed at the time entered by the user.
+    pub(super) async fn handle_announcement_scheduling_message(
+        &mut self,
+        message: Message,
+        draft: states::AnnouncementDraft,
+    ) -> HandlerResult<()> {
+        self.require_announcement_permission()?;
+        let text = message.text().ok_or(ScheduleError::InvalidTime)?;
+        let send_at = NaiveDateTime::parse_from_str(text.trim(), DATE_TIME_FORMAT)
+            .map_err(|_| ScheduleError::InvalidTime)?;
+        if send_at <= Local::now().naive_local() {
+            return Err(ScheduleError::TimeInPast.into());
+        }
+
+        let scheduled = ScheduledAnnouncement {
+            send_at,
+            announcement: Announcement {
+                text: draft.text,
+                attachments: draft.attachments,
+                audience: draft.audience,
+            },
+        };
+        let db = &self.global_state.db;
+        let uctx = self.uctx();
+        match draft.scheduled {
+            Some(id) => {
+                if !db
+                    .update_scheduled_announcement(uctx, id, scheduled)
+                    .await?
+                {
+                    return Err(ScheduleError::NoSuchAnnouncement.into());
+                }
+            }
+            None => {
+                db.schedule_announcement(uctx, scheduled).await?;
+            }
+        }
+
+        self.set_state(DialogState::ScheduledAnnouncements);
+        let send_at = send_at.format(DATE_TIME_FORMAT);
+        self.send_message(STRINGS.announcements.scheduled(&send_at).into())
+            .await?;
+        self.send_state_prompt().await
+    }
+
+    /// Open a scheduled announcement for editing as a draft.
+    pub(super) async fn edit_scheduled_announcement(&mut self, id: u64) -> HandlerResult<()> {
+        self.require_announcement_permission()?;
+        let scheduled = self
+            .global_state
+            .db
+            .scheduled_announcement(id)
+            .await?
+            .ok_or(ScheduleError::NoSuchAnnouncement)?;
+        let announcement = scheduled.announcement;
+        self.set_state(DialogState::AnnouncementComposing(
+            states::AnnouncementDraft {
+                text: announcement.text,
+                attachments: announcement.attachments,
+                audience: announcement.audience,
+                last_media_group_id: None,
+                scheduled: Some(id),
+            },
+        ));
+        Ok(())
+    }
+
+    pub(super) async fn cancel_scheduled_announcement(&mut self, id: u64) -> HandlerResult<()> {
+        self.require_announcement_permission()?;
+        let uctx = self.uctx();
+        if !self
+            .global_state
+            .db
+            .cancel_scheduled_announcement(uctx, id)
+            .await?
+        {
+            return Err(ScheduleError::NoSuchAnnouncement.into());
+        }
+        self.set_state(DialogState::ScheduledAnnouncements);
+        self.send_message(STRINGS.announcements.schedule_cancelled().into())
+            .await?;
+        Ok(())
+    }
+
+    pub(super) async fn send_announcement_scheduling_prompt(&mut self) -> HandlerResult<()> {
+        self.send_message(FormattedMessage::with_markup(
+            STRINGS.announcements.scheduling_prompt(),
+            InlineKeyboardMarkup {
+                inline_keyboard: vec![vec![InlineKeyboardButton::callback(
+                    "⬅️ Назад",
+                    Query::GoBack,
+                )]],
+            }
+            .into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub(super) async fn send_scheduled_announcements(&mut self) -> HandlerResult<()> {
+        let list = self.global_state.db.scheduled_announcements().await?;
+        let text = if list.is_empty() {
+            STRINGS.announcements.scheduled_list_empty()
+        } else {
+            STRINGS.announcements.scheduled_list()
+        };
+
+        let mut inline_keyboard = Vec::with_capacity(list.len() + 1);
+        for (id, scheduled) in list {
+            let label = format!(
+                "{} — {}",
+                scheduled.send_at.format(DATE_TIME_FORMAT),
+                text_summary(&scheduled.announcement.text.raw_text),
+            );
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                label,
+                Query::OpenScheduledAnnouncement { id },
+            )]);
+        }
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "🏠 В главное меню",
+            Query::OpenMainMenu,
+        )]);
+
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Show a scheduled announcement the way the recipients will see it, followed by the time it
+    /// is sent at and its recipients.
+    pub(super) async fn send_scheduled_announcement(&mut self, id: u64) -> HandlerResult<()> {
+        let scheduled = self
+            .global_state
+            .db
+            .scheduled_announcement(id)
+            .await?
+            .ok_or(ScheduleError::NoSuchAnnouncement)?;
+        let announcement = scheduled.announcement;
+
+        let mut preview = FormattedMessage::new(
+            STRINGS
+                .newsletter
+                .header(&self.global_state.announcement_tags)
+                .concat(announcement.text),
+        );
+        preview.attachments = announcement.attachments;
+        self.send_message(preview).await?;
+
+        let inline_keyboard = vec![
+            vec![InlineKeyboardButton::callback(
+                "✏️ Изменить",
+                Query::EditScheduledAnnouncement { id },
+            )],
+            vec![InlineKeyboardButton::callback(
+                "🗑 Отменить отправку",
+                Query::CancelScheduledAnnouncement { id },
+            )],
+            vec![
+                InlineKeyboardButton::callback("⬅️ Назад", Query::GoBack),
+                InlineKeyboardButton::callback("🏠 В главное меню", Query::OpenMainMenu),
+            ],
+        ];
+        self.send_message(FormattedMessage::with_markup(
+            STRINGS.announcements.scheduled_info(
+                &scheduled.send_at.format(DATE_TIME_FORMAT),
+                &audience_label(&announcement.audience),
+            ),
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+}
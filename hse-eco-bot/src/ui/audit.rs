@@ -0,0 +1,226 @@
+//! The audit log of the administrative and editorial actions, viewed by the admins.
+
+use super::admin::user_label;
+use super::form::DATE_TIME_FORMAT;
+use super::Context;
+use crate::audit::{AuditAction, AuditFilter};
+use crate::audit_error::AuditError;
+use crate::callback_query::Query;
+use crate::invalid_action::InvalidAction;
+use crate::message::FormattedMessage;
+use crate::message_format_error::MessageFormatError;
+use crate::state::{states, DialogState};
+use crate::strings::STRINGS;
+use crate::types::HandlerResult;
+use chrono::NaiveDate;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, UserId};
+
+/// The number of the audit log entries shown on a page.
+const PAGE_SIZE: usize = 10;
+
+/// The format of the dates of the audit log period.
+const DATE_FORMAT: &str = "%d.%m.%Y";
+
+/// Parse a period of the audit log: a single day, e.g. `01.09.2022`, or the first and the last
+/// days separated by a dash, e.g. `01.09.2022 - 30.09.2022`.
+pub fn parse_period(text: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let parse = |s: &str| NaiveDate::parse_from_str(s.trim(), DATE_FORMAT).ok();
+    let (since, until) = match text.split_once(&['-', '–', '—'][..]) {
+        Some((since, until)) => (parse(since)?, parse(until)?),
+        None => {
+            let day = parse(text)?;
+            (day, day)
+        }
+    };
+    (since <= until).then_some((since, until))
+}
+
+impl Context<'_, '_, '_, '_> {
+    pub(super) fn open_audit_log(&mut self, filter: AuditFilter) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        self.set_state(DialogState::AuditLog(states::AuditLog { filter, page: 0 }));
+        Ok(())
+    }
+
+    pub(super) fn open_audit_log_page(&mut self, page: usize) -> HandlerResult<()> {
+        match self.state() {
+            DialogState::AuditLog(log) => {
+                self.set_state(DialogState::AuditLog(states::AuditLog { page, ..log }));
+                Ok(())
+            }
+            _ => Err(InvalidAction::InvalidState.into()),
+        }
+    }
+
+    pub(super) fn open_audit_log_actions(&mut self) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        match self.state() {
+            DialogState::AuditLog(log) => {
+                self.set_state(DialogState::AuditLogActionSelection(log));
+                Ok(())
+            }
+            _ => Err(InvalidAction::InvalidState.into()),
+        }
+    }
+
+    pub(super) fn filter_audit_log_action(
+        &mut self,
+        action: Option<AuditAction>,
+    ) -> HandlerResult<()> {
+        match self.state() {
+            DialogState::AuditLogActionSelection(log) => self.open_audit_log(AuditFilter {
+                action,
+                ..log.filter
+            }),
+            _ => Err(InvalidAction::InvalidState.into()),
+        }
+    }
+
+    /// Filter the audit log by the period sent by the user.
+    pub(super) async fn handle_audit_log_message(
+        &mut self,
+        message: Message,
+        log: states::AuditLog,
+    ) -> HandlerResult<()> {
+        let text = message.text().ok_or(MessageFormatError::NoText)?;
+        let (since, until) = parse_period(text).ok_or(AuditError::InvalidPeriod)?;
+        self.open_audit_log(AuditFilter {
+            since: Some(since),
+            until: Some(until),
+            ..log.filter
+        })?;
+        self.send_state_prompt().await
+    }
+
+    async fn describe_user(&self, user_id: UserId) -> HandlerResult<String> {
+        let username = self.global_state.db.username(user_id).await?;
+        Ok(user_label(user_id, username.as_deref()))
+    }
+
+    /// Send a page of the audit log entries matching the filter, the latest first.
+    pub(super) async fn send_audit_log(&mut self, log: states::AuditLog) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        let db = &self.global_state.db;
+        let filter = log.filter;
+
+        let mut text = STRINGS.audit.header();
+        if let Some(user_id) = filter.user_id {
+            text = text.concat(
+                STRINGS
+                    .audit
+                    .filter_user(&self.describe_user(user_id).await?),
+            );
+        }
+        if let Some(action) = filter.action {
+            text = text.concat(STRINGS.audit.filter_action(&action.description()));
+        }
+        let format_day = |day: NaiveDate| day.format(DATE_FORMAT).to_string();
+        let period = match (filter.since, filter.until) {
+            (Some(since), Some(until)) => Some(
+                STRINGS
+                    .audit
+                    .filter_period(&format_day(since), &format_day(until)),
+            ),
+            (Some(since), None) => Some(STRINGS.audit.filter_since(&format_day(since))),
+            (None, Some(until)) => Some(STRINGS.audit.filter_until(&format_day(until))),
+            (None, None) => None,
+        };
+        if let Some(period) = period {
+            text = text.concat(period);
+        }
+
+        let count = db.count_audit_log(filter).await?;
+        let mut inline_keyboard = Vec::new();
+        if count == 0 {
+            text = text.concat(STRINGS.audit.empty());
+        } else {
+            let num_pages = count.div_ceil(PAGE_SIZE);
+            let page = log.page.min(num_pages - 1);
+            text = text.concat(STRINGS.audit.page(&(page + 1), &num_pages));
+            let entries = db.audit_log(filter, PAGE_SIZE, page * PAGE_SIZE).await?;
+            for entry in entries {
+                let time = entry.time.format(DATE_TIME_FORMAT).to_string();
+                let description = entry.action.description();
+                text = text.concat(match entry.user_id {
+                    Some(user_id) => STRINGS.audit.entry(
+                        &time,
+                        &self.describe_user(user_id).await?,
+                        &description,
+                    ),
+                    None => STRINGS.audit.anonymous_entry(&time, &description),
+                });
+                for target in &entry.targets {
+                    text = text.concat(STRINGS.audit.target(target));
+                }
+            }
+
+            let mut page_row = Vec::with_capacity(2);
+            if page > 0 {
+                page_row.push(InlineKeyboardButton::callback(
+                    "◀️",
+                    Query::AuditLogPage { page: page - 1 },
+                ));
+            }
+            if page + 1 < num_pages {
+                page_row.push(InlineKeyboardButton::callback(
+                    "▶️",
+                    Query::AuditLogPage { page: page + 1 },
+                ));
+            }
+            if !page_row.is_empty() {
+                inline_keyboard.push(page_row);
+            }
+        }
+        text = text.concat(STRINGS.audit.period_hint());
+
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "🔎 Выбрать действие",
+            Query::AuditLogActions,
+        )]);
+        if filter != AuditFilter::default() {
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                "✖️ Сбросить фильтры",
+                Query::AuditLogResetFilter,
+            )]);
+        }
+        inline_keyboard.push(vec![
+            InlineKeyboardButton::callback("⬅️ Назад", Query::GoBack),
+            InlineKeyboardButton::callback("🏠 В главное меню", Query::OpenMainMenu),
+        ]);
+
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub(super) async fn send_audit_log_actions(&mut self) -> HandlerResult<()> {
+        self.require_admin_permission()?;
+        let mut inline_keyboard = vec![vec![InlineKeyboardButton::callback(
+            "Все действия",
+            Query::AuditLogFilterAction { action: None },
+        )]];
+        inline_keyboard.extend(AuditAction::ALL.into_iter().map(|action| {
+            vec![InlineKeyboardButton::callback(
+                action.description(),
+                Query::AuditLogFilterAction {
+                    action: Some(action),
+                },
+            )]
+        }));
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "⬅️ Назад",
+            Query::GoBack,
+        )]);
+
+        self.send_message(FormattedMessage::with_markup(
+            STRINGS.audit.action_prompt(),
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,421 @@
+//! The calendar of eco events.
+
+use super::form::{
+    Form, FormCompletionTarget, FormElement, FormFillingState, FormInput, FormInputType,
+    DATE_TIME_FORMAT,
+};
+use super::Context;
+use crate::callback_query::Query;
+use crate::event_error::EventError;
+use crate::events::{CalendarPeriod, Event};
+use crate::invalid_action::InvalidAction;
+use crate::message::FormattedMessage;
+use crate::newsletter::event_reminders;
+use crate::state::{states, DialogState};
+use crate::strings::STRINGS;
+use crate::types::HandlerResult;
+use chrono::{Duration, Local, NaiveDate, NaiveDateTime};
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+const DATE_FORMAT: &str = "%d.%m.%Y";
+
+/// Format the time of an event, omitting the end date if it is the same as the start date.
+pub fn format_time_range(start: NaiveDateTime, end: NaiveDateTime) -> String {
+    let end_format = if start.date() == end.date() {
+        "%H:%M"
+    } else {
+        DATE_TIME_FORMAT
+    };
+    format!(
+        "{} — {}",
+        start.format(DATE_TIME_FORMAT),
+        end.format(end_format),
+    )
+}
+
+/// Get the calendar page of the current week.
+fn this_week() -> states::Calendar {
+    let today = Local::now().naive_local().date();
+    states::Calendar {
+        period: CalendarPeriod::Week,
+        first_day: CalendarPeriod::Week.first_day(today),
+    }
+}
+
+fn calendar_page_query(period: CalendarPeriod, first_day: NaiveDate) -> Query {
+    match period {
+        CalendarPeriod::Week => Query::CalendarWeek { first_day },
+        CalendarPeriod::Month => Query::CalendarMonth { first_day },
+    }
+}
+
+fn event_form() -> Form {
+    let date_time_hint = "в формате ДД.ММ.ГГГГ ЧЧ:ММ, например, 01.09.2023 18:30";
+    Form {
+        elements: vec![
+            FormElement {
+                text: String::from("Название мероприятия:"),
+                input_type: FormInputType::ShortText,
+            },
+            FormElement {
+                text: String::from("Описание мероприятия:"),
+                input_type: FormInputType::Text,
+            },
+            FormElement {
+                text: format!("Время начала {}:", date_time_hint),
+                input_type: FormInputType::DateTime,
+            },
+            FormElement {
+                text: format!("Время окончания {}:", date_time_hint),
+                input_type: FormInputType::DateTime,
+            },
+            FormElement {
+                text: String::from("Место проведения:"),
+                input_type: FormInputType::Location,
+            },
+        ],
+    }
+}
+
+fn event_from_form_input(input: Vec<FormInput>) -> Option<Event> {
+    match <[FormInput; 5]>::try_from(input).ok()? {
+        [
+            FormInput::Text { text: title },
+            FormInput::Text { text: description },
+            FormInput::DateTime { date_time: start },
+            FormInput::DateTime { date_time: end },
+            FormInput::Location { location },
+        ] => Some(Event {
+            title: title.raw_text,
+            description,
+            start,
+            end,
+            location,
+        }),
+        _ => None,
+    }
+}
+
+impl Context<'_, '_, '_, '_> {
+    fn can_manage_events(&self) -> bool {
+        self.dialog
+            .data()
+            .read()
+            .unwrap()
+            .user
+            .permissions()
+            .manage_events
+    }
+
+    pub(super) fn require_event_management_permission(&self) -> Result<(), InvalidAction> {
+        if self.can_manage_events() {
+            Ok(())
+        } else {
+            Err(InvalidAction::PermissionDenied)
+        }
+    }
+
+    /// Get the calendar page that the user has come from.
+    fn current_calendar(&self) -> states::Calendar {
+        match self.state() {
+            DialogState::Calendar(cal) => cal,
+            DialogState::EventViewing(view) => view.calendar,
+            DialogState::EventDeletion(del) => del.calendar,
+            _ => this_week(),
+        }
+    }
+
+    pub(super) fn open_calendar(&mut self) {
+        self.set_state(DialogState::Calendar(this_week()));
+    }
+
+    /// Open the calendar page of the given period containing `day`.
+    pub(super) fn open_calendar_page(&mut self, period: CalendarPeriod, day: NaiveDate) {
+        self.set_state(DialogState::Calendar(states::Calendar {
+            period,
+            first_day: period.first_day(day),
+        }));
+    }
+
+    pub(super) async fn open_event(&mut self, id: u64) -> HandlerResult<()> {
+        self.global_state
+            .db
+            .event(id)
+            .await?
+            .ok_or(EventError::NoSuchEvent)?;
+        let calendar = self.current_calendar();
+        self.set_state(DialogState::EventViewing(states::EventViewing {
+            id,
+            calendar,
+        }));
+        Ok(())
+    }
+
+    /// Start filling the form of a new event or of a replacement of an existing one.
+    pub(super) async fn start_event_form_filling(
+        &mut self,
+        target: FormCompletionTarget,
+    ) -> HandlerResult<()> {
+        self.require_event_management_permission()?;
+        let calendar = self.current_calendar();
+        let completion_state = match target {
+            FormCompletionTarget::EditEvent { id } => {
+                self.global_state
+                    .db
+                    .event(id)
+                    .await?
+                    .ok_or(EventError::NoSuchEvent)?;
+                DialogState::EventViewing(states::EventViewing { id, calendar })
+            }
+            _ => DialogState::Calendar(calendar),
+        };
+        let state = states::FormFilling {
+            form_state: FormFillingState::new(event_form()),
+            completion_state: Box::new(completion_state),
+            return_state: Box::new(self.state()),
+            on_completion: target,
+        };
+        self.set_state(DialogState::FormFilling(state));
+        Ok(())
+    }
+
+    /// Save the event from a completed form.
+    pub(super) async fn complete_event_form(
+        &mut self,
+        mut state: states::FormFilling,
+    ) -> HandlerResult<()> {
+        if let Err(e) = self.require_event_management_permission() {
+            self.set_state(*state.return_state);
+            return Err(e.into());
+        }
+        let (_, input) = state.form_state.clone().into_parts();
+        let event = event_from_form_input(input).ok_or(InvalidAction::InvalidState)?;
+        if event.end < event.start {
+            // Ask for the end time again.
+            state.form_state.back();
+            self.set_state(DialogState::FormFilling(state));
+            return Err(EventError::EndBeforeStart.into());
+        }
+
+        let db = &self.global_state.db;
+        let uctx = self.uctx();
+        let text = match state.on_completion {
+            FormCompletionTarget::EditEvent { id } => {
+                if !db.update_event(uctx, id, event).await? {
+                    self.set_state(DialogState::Calendar(this_week()));
+                    return Err(EventError::NoSuchEvent.into());
+                }
+                STRINGS.calendar.updated()
+            }
+            _ => {
+                db.create_event(uctx, event).await?;
+                STRINGS.calendar.created()
+            }
+        };
+        self.set_state(*state.completion_state);
+        self.send_message(text.into()).await?;
+        Ok(())
+    }
+
+    pub(super) async fn delete_event(&mut self, id: u64) -> HandlerResult<()> {
+        self.require_event_management_permission()?;
+        let calendar = match self.state() {
+            DialogState::EventDeletion(del) if del.id == id => del.calendar,
+            _ => return Err(InvalidAction::InvalidState.into()),
+        };
+        let uctx = self.uctx();
+        if !self.global_state.db.delete_event(uctx, id).await? {
+            return Err(EventError::NoSuchEvent.into());
+        }
+        self.set_state(DialogState::Calendar(calendar));
+        self.send_message(STRINGS.calendar.deleted().into()).await?;
+        Ok(())
+    }
+
+    /// Ask to remind the user about an event (if `enabled` is `true`) or cancel the reminder.
+    pub(super) async fn set_event_reminder(
+        &mut self,
+        id: u64,
+        enabled: bool,
+    ) -> HandlerResult<()> {
+        let user_id = self.dialog.user_id();
+        let db = &self.global_state.db;
+        let text = if enabled {
+            db.event(id).await?.ok_or(EventError::NoSuchEvent)?;
+            db.add_event_reminder(id, user_id).await?;
+            // The reminders are only delivered to the subscribers of the newsletter.
+            let newly_subscribed = {
+                let mut dialog_data = self.dialog.data().write().unwrap();
+                dialog_data
+                    .user
+                    .subscriptions_mut()
+                    .insert(String::from(event_reminders::NAME))
+            };
+            if newly_subscribed {
+                self.store_user().await?;
+            }
+            STRINGS.calendar.reminder_added()
+        } else {
+            db.remove_event_reminder(id, user_id).await?;
+            STRINGS.calendar.reminder_removed()
+        };
+        self.send_message(text.into()).await?;
+        Ok(())
+    }
+
+    /// Send a page of the calendar with the events of the period.
+    pub(super) async fn send_calendar(&mut self, cal: states::Calendar) -> HandlerResult<()> {
+        let next_first_day = cal.period.next(cal.first_day);
+        let last_day = next_first_day - Duration::days(1);
+        let events = self
+            .global_state
+            .db
+            .events_between(cal.first_day, last_day)
+            .await?;
+
+        let first_day_text = cal.first_day.format(DATE_FORMAT).to_string();
+        let last_day_text = last_day.format(DATE_FORMAT).to_string();
+        let mut text = match cal.period {
+            CalendarPeriod::Week => STRINGS.calendar.week(&first_day_text, &last_day_text),
+            CalendarPeriod::Month => STRINGS.calendar.month(&first_day_text, &last_day_text),
+        };
+        if events.is_empty() {
+            text = text.concat(STRINGS.calendar.empty());
+        }
+
+        let mut inline_keyboard = Vec::with_capacity(events.len() + 3);
+        for (i, (id, event)) in events.into_iter().enumerate() {
+            let number = i + 1;
+            text = text.concat(STRINGS.calendar.item(
+                &number,
+                &event.title,
+                &format_time_range(event.start, event.end),
+                &event.location,
+            ));
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                format!("{}. {}", number, event.title),
+                Query::OpenEvent { id },
+            )]);
+        }
+
+        let toggle_button = match cal.period {
+            CalendarPeriod::Week => InlineKeyboardButton::callback(
+                "🗓 Месяц",
+                calendar_page_query(
+                    CalendarPeriod::Month,
+                    CalendarPeriod::Month.first_day(cal.first_day),
+                ),
+            ),
+            CalendarPeriod::Month => InlineKeyboardButton::callback(
+                "📆 Неделя",
+                calendar_page_query(
+                    CalendarPeriod::Week,
+                    CalendarPeriod::Week.first_day(cal.first_day),
+                ),
+            ),
+        };
+        inline_keyboard.push(vec![
+            InlineKeyboardButton::callback(
+                "◀️",
+                calendar_page_query(cal.period, cal.period.previous(cal.first_day)),
+            ),
+            toggle_button,
+            InlineKeyboardButton::callback("▶️", calendar_page_query(cal.period, next_first_day)),
+        ]);
+        if self.can_manage_events() {
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                "➕ Добавить мероприятие",
+                Query::CreateEvent,
+            )]);
+        }
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "🏠 В главное меню",
+            Query::OpenMainMenu,
+        )]);
+
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub(super) async fn send_event(&mut self, view: states::EventViewing) -> HandlerResult<()> {
+        let id = view.id;
+        let event = self
+            .global_state
+            .db
+            .event(id)
+            .await?
+            .ok_or(EventError::NoSuchEvent)?;
+
+        let mut inline_keyboard = Vec::with_capacity(4);
+        let db = &self.global_state.db;
+        let is_upcoming = event.start > Local::now().naive_local();
+        if is_upcoming && !db.is_event_reminder_sent(id).await? {
+            let (text, query) = if db.has_event_reminder(id, self.dialog.user_id()).await? {
+                ("🔕 Не напоминать", Query::CancelEventReminder { id })
+            } else {
+                ("🔔 Напомнить", Query::RemindAboutEvent { id })
+            };
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(text, query)]);
+        }
+        if self.can_manage_events() {
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                "📝 Редактировать",
+                Query::EditEvent { id },
+            )]);
+            inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                "🗑 Удалить",
+                Query::DeleteEvent { id },
+            )]);
+        }
+        inline_keyboard.push(vec![
+            InlineKeyboardButton::callback("⬅️ Назад", Query::GoBack),
+            InlineKeyboardButton::callback("🏠 В главное меню", Query::OpenMainMenu),
+        ]);
+
+        let text = STRINGS
+            .calendar
+            .event(
+                &event.title,
+                &format_time_range(event.start, event.end),
+                &event.location,
+            )
+            .concat(event.description);
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub(super) async fn send_event_deletion_prompt(
+        &mut self,
+        del: states::EventDeletion,
+    ) -> HandlerResult<()> {
+        let event = self
+            .global_state
+            .db
+            .event(del.id)
+            .await?
+            .ok_or(EventError::NoSuchEvent)?;
+        let reply_markup = InlineKeyboardMarkup {
+            inline_keyboard: vec![vec![
+                InlineKeyboardButton::callback(
+                    "Да, удалить",
+                    Query::ConfirmEventDeletion { id: del.id },
+                ),
+                InlineKeyboardButton::callback("Нет, не удалять", Query::GoBack),
+            ]],
+        };
+        self.send_message(FormattedMessage::with_markup(
+            STRINGS.calendar.deletion_prompt(&event.title),
+            reply_markup.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+}
@@ -1,16 +1,18 @@
-use crate::media::{Image, Location, LocationOrAddress};
+use crate::media::{Attachment, Image, Location, LocationOrAddress};
 use crate::message::{FormattedMessage, FormattedText};
 use crate::strings::STRINGS;
 use crate::user_facing_error::UserFacingError;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Form {
     pub elements: Vec<FormElement>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct FormElement {
     pub text: String,
     pub input_type: FormInputType,
@@ -22,11 +24,11 @@ impl FormElement {
             .parse_input(input)
             .map_err(|input| FormInputError {
                 element: self.clone(),
-                input,
+                input: Box::new(input),
             })
     }
 }
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum FormInputType {
     Choice { options: Vec<String> },
     Number,
@@ -36,18 +38,27 @@ pub enum FormInputType {
     Image,
     ImageGallery,
     Location,
+    /// A local date and time in the [`DATE_TIME_FORMAT`].
+    DateTime,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// The format of the date and time entered by the users.
+pub const DATE_TIME_FORMAT: &str = "%d.%m.%Y %H:%M";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FormInput {
     Choice { index: usize },
     Number { number: u64 },
     ShortText { text: String },
     Text { text: FormattedText },
-    //Message { TODO },
+    Message {
+        text: FormattedText,
+        attachments: Vec<Attachment>,
+    },
     Image { image: Image },
     ImageGallery { images: Vec<Image> },
     Location { location: LocationOrAddress },
+    DateTime { date_time: NaiveDateTime },
 }
 
 impl FormInputType {
@@ -95,27 +106,41 @@ impl FormInputType {
             },
             Self::Message => match input {
                 FormRawInput::Text { text } if text.encode_utf16().count() <= 3500 => {
-                    Ok(FormInput::Text {
+                    Ok(FormInput::Message {
                         text: FormattedText {
                             raw_text: text,
                             entities: None,
                         },
+                        attachments: Vec::new(),
                     })
                 }
                 FormRawInput::FormattedText { text }
                     if text.raw_text.encode_utf16().count() <= 3500 =>
                 {
-                    Ok(FormInput::Text { text })
-                }
-                FormRawInput::Message { message } => {
-                    // TODO: attachments.
-                    Ok(FormInput::Text { text: message.text })
+                    Ok(FormInput::Message {
+                        text,
+                        attachments: Vec::new(),
+                    })
                 }
+                FormRawInput::Message { message, .. } => Ok(FormInput::Message {
+                    text: message.text,
+                    attachments: message.attachments,
+                }),
+                FormRawInput::Photo { image, caption, .. } => Ok(FormInput::Message {
+                    text: caption.unwrap_or(FormattedText {
+                        raw_text: String::new(),
+                        entities: None,
+                    }),
+                    attachments: vec![Attachment::Image(image)],
+                }),
+                _ => Err(input),
+            },
+            Self::Image => match input {
+                FormRawInput::Photo { image, .. } => Ok(FormInput::Image { image }),
                 _ => Err(input),
             },
-            // TODO.
-            Self::Image => Err(input),
-            // TODO.
+            // The images of a gallery arrive one by one and are collected by
+            // [`FormFillingState::next`].
             Self::ImageGallery => Err(input),
             Self::Location => match input {
                 FormRawInput::Text { text } => Ok(FormInput::Location {
@@ -126,6 +151,15 @@ impl FormInputType {
                 }),
                 _ => Err(input),
             },
+            Self::DateTime => match input {
+                FormRawInput::Text { ref text } => {
+                    match NaiveDateTime::parse_from_str(text.trim(), DATE_TIME_FORMAT) {
+                        Ok(date_time) => Ok(FormInput::DateTime { date_time }),
+                        Err(_) => Err(input),
+                    }
+                }
+                _ => Err(input),
+            },
         }
     }
 }
@@ -136,13 +170,42 @@ pub enum FormRawInput {
     Text { text: String },
     FormattedText { text: FormattedText },
     Location { location: Location },
-    Message { message: FormattedMessage },
+    /// A message with a video or a document. The items of an album share the same
+    /// `media_group_id`.
+    Message {
+        message: Box<FormattedMessage>,
+        media_group_id: Option<String>,
+    },
+    /// A photo. The photos of an album share the same `media_group_id`.
+    Photo {
+        image: Image,
+        caption: Option<FormattedText>,
+        media_group_id: Option<String>,
+    },
+    /// The user has finished sending the images of a gallery.
+    Done,
+}
+
+/// What has been done with a piece of input given to [`FormFillingState::next`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FormProgress {
+    /// The input has been accepted for the current element.
+    Next,
+    /// An attachment has been added to those collected for the current element: a gallery or
+    /// an album sent as a message.
+    Pending {
+        /// Whether the attachment belongs to the same album as the previous one.
+        is_same_album: bool,
+    },
+    /// The input is an item of an album whose first item has already been accepted as a single
+    /// image, so it has been ignored.
+    SameAlbum,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct FormInputError {
     pub element: FormElement,
-    pub input: FormRawInput,
+    pub input: Box<FormRawInput>,
 }
 
 impl Display for FormInputError {
@@ -163,10 +226,20 @@ impl UserFacingError for FormInputError {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FormFillingState {
     form: Form,
     input: Vec<FormInput>,
+    /// The attachments collected for the current element: the images of a gallery or the items
+    /// of an album sent as a message.
+    #[serde(default)]
+    pending: Vec<Attachment>,
+    /// The caption of the album being collected.
+    #[serde(default)]
+    pending_text: Option<FormattedText>,
+    /// The media group of the last received photo, video or document.
+    #[serde(default)]
+    last_media_group_id: Option<String>,
 }
 
 impl FormFillingState {
@@ -175,11 +248,20 @@ impl FormFillingState {
         Self {
             form,
             input: Vec::with_capacity(num_elements),
+            pending: Vec::new(),
+            pending_text: None,
+            last_media_group_id: None,
         }
     }
 
+    /// Go back to the previous element. If some attachments have been collected for the current
+    /// element, they are discarded instead.
     pub fn back(&mut self) {
-        if self.can_go_back() {
+        self.last_media_group_id = None;
+        if !self.pending.is_empty() {
+            self.pending.clear();
+            self.pending_text = None;
+        } else if self.can_go_back() {
             self.input.pop().unwrap();
         } else {
             panic!(concat!(
@@ -190,18 +272,93 @@ impl FormFillingState {
     }
 
     pub fn can_go_back(&self) -> bool {
-        !self.input.is_empty()
+        !self.input.is_empty() || !self.pending.is_empty()
     }
 
-    pub fn next(&mut self, input: FormRawInput) -> Result<(), FormInputError> {
+    pub fn next(&mut self, input: FormRawInput) -> Result<FormProgress, FormInputError> {
         if self.is_done() {
             panic!("Cannot proceed with the form filling process, since it has alredy finished");
         }
 
-        let index = self.input.len();
-        let element = &self.form.elements[index];
-        self.input.push(element.parse_input(input)?);
-        Ok(())
+        let media_group_id = match &input {
+            FormRawInput::Photo { media_group_id, .. }
+            | FormRawInput::Message { media_group_id, .. } => media_group_id.clone(),
+            _ => None,
+        };
+        let is_album_item = media_group_id.is_some();
+        let is_same_album = is_album_item && media_group_id == self.last_media_group_id;
+        self.last_media_group_id = media_group_id;
+
+        let element = &self.form.elements[self.input.len()];
+        match element.input_type {
+            FormInputType::ImageGallery => match input {
+                FormRawInput::Photo { image, .. } => {
+                    self.pending.push(Attachment::Image(image));
+                    Ok(FormProgress::Pending { is_same_album })
+                }
+                FormRawInput::Done => {
+                    let images = std::mem::take(&mut self.pending)
+                        .into_iter()
+                        .filter_map(|attachment| match attachment {
+                            Attachment::Image(image) => Some(image),
+                            _ => None,
+                        })
+                        .collect();
+                    self.input.push(FormInput::ImageGallery { images });
+                    Ok(FormProgress::Next)
+                }
+                _ => Err(FormInputError {
+                    element: element.clone(),
+                    input: Box::new(input),
+                }),
+            },
+            // An album arrives as separate messages, so its items are collected until the text
+            // of the message is sent or the user is done.
+            FormInputType::Message if is_album_item || !self.pending.is_empty() => match input {
+                FormRawInput::Done => {
+                    let text = self.pending_text.take().unwrap_or(FormattedText {
+                        raw_text: String::new(),
+                        entities: None,
+                    });
+                    let attachments = std::mem::take(&mut self.pending);
+                    self.input.push(FormInput::Message { text, attachments });
+                    Ok(FormProgress::Next)
+                }
+                input @ (FormRawInput::Photo { .. } | FormRawInput::Message { .. })
+                    if is_album_item =>
+                {
+                    let (caption, attachments) = split_media(input);
+                    if let Some(caption) = caption.filter(|caption| !caption.raw_text.is_empty()) {
+                        self.pending_text = Some(caption);
+                    }
+                    self.pending.extend(attachments);
+                    Ok(FormProgress::Pending { is_same_album })
+                }
+                input => {
+                    let mut parsed = element.parse_input(input)?;
+                    if let FormInput::Message {
+                        ref mut attachments,
+                        ..
+                    } = parsed
+                    {
+                        attachments.splice(0..0, std::mem::take(&mut self.pending));
+                    }
+                    self.pending_text = None;
+                    self.input.push(parsed);
+                    Ok(FormProgress::Next)
+                }
+            },
+            _ if is_same_album => Ok(FormProgress::SameAlbum),
+            _ => {
+                self.input.push(element.parse_input(input)?);
+                Ok(FormProgress::Next)
+            }
+        }
+    }
+
+    /// The attachments collected for the current element so far.
+    pub fn pending_attachments(&self) -> &[Attachment] {
+        &self.pending
     }
 
     pub fn is_done(&self) -> bool {
@@ -216,7 +373,30 @@ impl FormFillingState {
         &self.form.elements[self.input.len()]
     }
 
-    pub fn into_parts(self) -> (Form, Vec<FormInput>) {
+    pub fn into_parts(self) -> CompletedForm {
         (self.form, self.input)
     }
 }
+
+/// Split a photo or a message with an attachment into its caption and its attachments.
+fn split_media(input: FormRawInput) -> (Option<FormattedText>, Vec<Attachment>) {
+    match input {
+        FormRawInput::Photo { image, caption, .. } => (caption, vec![Attachment::Image(image)]),
+        FormRawInput::Message { message, .. } => (Some(message.text), message.attachments),
+        _ => (None, Vec::new()),
+    }
+}
+
+/// A completed form along with the input of its elements.
+pub type CompletedForm = (Form, Vec<FormInput>);
+
+/// The receiver of the input of a completed form.
+///
+/// Unlike a channel, the target can be stored along with the dialog state, so that a form can
+/// still be completed after the bot has been restarted.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum FormCompletionTarget {
+    Feedback,
+    CreateEvent,
+    EditEvent { id: u64 },
+}
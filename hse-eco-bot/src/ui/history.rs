@@ -0,0 +1,282 @@
+//! The history of changes of the knowledge base items and restoring their previous versions.
+
+use super::admin::user_label;
+use super::form::DATE_TIME_FORMAT;
+use super::{breadcrumbs, Context};
+use crate::callback_query::Query;
+use crate::db::{FullDirectoryId, FullItemId, FullNoteId, FullRevisionId};
+use crate::invalid_action::InvalidAction;
+use crate::kb::{ItemId, ProviderId, Revision, RevisionAction};
+use crate::message::{FormattedMessage, FormattedText};
+use crate::state::{states, DialogState};
+use crate::strings::STRINGS;
+use crate::types::HandlerResult;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// The number of the latest revisions shown to the user.
+const HISTORY_LENGTH: usize = 8;
+
+/// The number of the restore buttons in a row.
+const BUTTONS_PER_ROW: usize = 4;
+
+/// The maximum number of the changed lines shown for a revision of a note.
+const MAX_DIFF_LINES: usize = 4;
+
+/// The maximum number of characters shown of a changed line.
+const MAX_LINE_LENGTH: usize = 60;
+
+/// The maximum size of the table computed by [`diff_lines`]. Larger texts are shown as entirely
+/// replaced.
+const MAX_DIFF_CELLS: usize = 250_000;
+
+/// A line changed between two versions of a text.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DiffLine<'a> {
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Find the lines removed from and added to a text, in the order of their appearance.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    if old.len() * new.len() > MAX_DIFF_CELLS {
+        return old
+            .iter()
+            .copied()
+            .map(DiffLine::Removed)
+            .chain(new.iter().copied().map(DiffLine::Added))
+            .collect();
+    }
+
+    // `lengths[i][j]` is the length of the longest common subsequence of `old[i..]` and
+    // `new[j..]`.
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            diff.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+fn shorten(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_LENGTH) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_owned(),
+    }
+}
+
+fn format_diff(old: &str, new: &str) -> FormattedText {
+    let diff = diff_lines(old, new);
+    if diff.is_empty() {
+        return STRINGS.history.attachments_changed();
+    }
+    let mut text = STRINGS.history.text_changed();
+    for line in diff.iter().take(MAX_DIFF_LINES) {
+        text = text.concat(match line {
+            DiffLine::Removed(line) => STRINGS.history.line_removed(&shorten(line)),
+            DiffLine::Added(line) => STRINGS.history.line_added(&shorten(line)),
+        });
+    }
+    if diff.len() > MAX_DIFF_LINES {
+        text = text.concat(STRINGS.history.more_lines(&(diff.len() - MAX_DIFF_LINES)));
+    }
+    text
+}
+
+impl Context<'_, '_, '_, '_> {
+    pub(super) fn open_note_history(&mut self, id: FullNoteId) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        self.set_state(DialogState::KbNoteHistory(states::KbNoteHistory { id }));
+        Ok(())
+    }
+
+    pub(super) fn open_directory_history(&mut self, id: FullDirectoryId) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        self.set_state(DialogState::KbDirectoryHistory(
+            states::KbDirectoryHistory { id },
+        ));
+        Ok(())
+    }
+
+    /// Return an item to its state in a revision and open the restored item.
+    pub(super) async fn restore_revision(&mut self, id: FullRevisionId) -> HandlerResult<()> {
+        let provider = match self.state() {
+            DialogState::KbNoteHistory(history) => Some(history.id.provider),
+            DialogState::KbDirectoryHistory(history) => Some(history.id.provider),
+            // The trash lists the deleted items of all providers.
+            DialogState::Trash(_) => None,
+            _ => return Err(InvalidAction::InvalidState.into()),
+        };
+        if provider.is_some_and(|provider| provider != id.provider) {
+            return Err(InvalidAction::InvalidState.into());
+        }
+        self.require_kb_edit_permission()?;
+        let item = self
+            .global_state
+            .db
+            .restore_revision(self.uctx(), id)
+            .await?;
+        self.set_state(match item {
+            FullItemId::Note(id) => DialogState::KbNoteViewing(states::KbNoteViewing { id }),
+            FullItemId::Directory(id) => DialogState::KbNavigation(states::KbNavigation { id }),
+        });
+        self.send_message(STRINGS.history.restored().into()).await?;
+        Ok(())
+    }
+
+    /// Describe a revision: when the change has been made, by whom, and what has been changed.
+    ///
+    /// `next_text` is the text of the note right after the change.
+    async fn describe_revision(
+        &self,
+        number: usize,
+        revision: &Revision,
+        provider: ProviderId,
+        next_text: Option<&str>,
+    ) -> HandlerResult<FormattedText> {
+        let db = &self.global_state.db;
+        let time = revision.time.format(DATE_TIME_FORMAT).to_string();
+        let header = match revision.user_id {
+            Some(user_id) => {
+                let author = user_label(user_id, db.username(user_id).await?.as_deref());
+                STRINGS.history.revision(&number, &time, &author)
+            }
+            None => STRINGS.history.anonymous_revision(&number, &time),
+        };
+        let description = match (revision.action, revision.item) {
+            (RevisionAction::Update, _) => {
+                let old = revision
+                    .note
+                    .as_ref()
+                    .map(|note| note.text.raw_text.as_str());
+                format_diff(old.unwrap_or_default(), next_text.unwrap_or_default())
+            }
+            (RevisionAction::Rename, _) => STRINGS.history.renamed(&revision.name),
+            (RevisionAction::Move, _) => {
+                let parent = FullDirectoryId {
+                    provider,
+                    directory: revision.parent,
+                };
+                match db.directory_path(self.uctx(), parent).await {
+                    Ok(path) => STRINGS.history.moved(&breadcrumbs(&path)),
+                    Err(_) => STRINGS.history.moved_from_deleted(),
+                }
+            }
+            (RevisionAction::Delete, ItemId::Note(_)) => {
+                STRINGS.history.note_deleted(&revision.name)
+            }
+            (RevisionAction::Delete, ItemId::Directory(_)) => {
+                STRINGS.history.directory_deleted(&revision.name)
+            }
+            (RevisionAction::Restore, _) => STRINGS.history.restored_earlier(),
+        };
+        Ok(header.concat(description))
+    }
+
+    /// Send the list of revisions with the buttons restoring them.
+    async fn send_revisions(
+        &mut self,
+        title: FormattedText,
+        revisions: Vec<Revision>,
+        provider: ProviderId,
+        current_text: Option<String>,
+    ) -> HandlerResult<()> {
+        let mut text = title;
+        if revisions.is_empty() {
+            text = text.concat(STRINGS.history.empty());
+        }
+        let mut buttons = Vec::with_capacity(revisions.len());
+        // The revisions are ordered from the latest, so the text right after a change is the
+        // current text or the text recorded in the previous revision.
+        let mut next_text = current_text;
+        for (i, revision) in revisions.iter().enumerate() {
+            let number = i + 1;
+            let description = self
+                .describe_revision(number, revision, provider, next_text.as_deref())
+                .await?;
+            text = text.concat(description);
+            if let Some(note) = &revision.note {
+                next_text = Some(note.text.raw_text.clone());
+            }
+            buttons.push(InlineKeyboardButton::callback(
+                format!("↩️ {}", number),
+                Query::KbRestoreRevision {
+                    id: FullRevisionId {
+                        provider,
+                        revision: revision.id,
+                    },
+                },
+            ));
+        }
+
+        let mut inline_keyboard: Vec<_> = buttons
+            .chunks(BUTTONS_PER_ROW)
+            .map(|row| row.to_vec())
+            .collect();
+        inline_keyboard.push(vec![
+            InlineKeyboardButton::callback("⬅️ Назад", Query::GoBack),
+            InlineKeyboardButton::callback("🏠 В главное меню", Query::OpenMainMenu),
+        ]);
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub(super) async fn send_note_history(&mut self, id: FullNoteId) -> HandlerResult<()> {
+        let uctx = self.uctx();
+        let db = &self.global_state.db;
+        let path = db.note_path(uctx, id).await?;
+        let current = db.read_note(uctx, id).await?;
+        let revisions = db.note_history(uctx, id, HISTORY_LENGTH).await?;
+        let title = STRINGS.history.note_title(&breadcrumbs(&path));
+        self.send_revisions(title, revisions, id.provider, Some(current.text.raw_text))
+            .await
+    }
+
+    pub(super) async fn send_directory_history(
+        &mut self,
+        id: FullDirectoryId,
+    ) -> HandlerResult<()> {
+        let uctx = self.uctx();
+        let db = &self.global_state.db;
+        let path = db.directory_path(uctx, id).await?;
+        let revisions = db.directory_history(uctx, id, HISTORY_LENGTH).await?;
+        let title = STRINGS.history.directory_title(&breadcrumbs(&path));
+        self.send_revisions(title, revisions, id.provider, None)
+            .await
+    }
+}
@@ -0,0 +1,67 @@
+//! The inline mode: sharing the knowledge base notes in any chat.
+
+use super::breadcrumbs;
+use crate::global_state::GlobalState;
+use crate::kb::ProviderUserContext;
+use crate::strings::STRINGS;
+use crate::types::{BotType, HandlerResult};
+use std::sync::Arc;
+use teloxide::prelude::*;
+use teloxide::types::{
+    InlineQuery, InlineQueryResult, InlineQueryResultArticle, InputMessageContent,
+    InputMessageContentText,
+};
+
+/// The maximum number of notes offered for an inline query.
+const MAX_RESULTS: usize = 20;
+
+/// The time in seconds for which Telegram may cache the answer to an inline query.
+const CACHE_TIME: u32 = 300;
+
+/// The maximum length of the text of a message allowed by Telegram.
+const MAX_TEXT_LENGTH: usize = 4096;
+
+/// Answer an inline query with the knowledge base notes matching it.
+///
+/// The chosen note is sent to a chat with people who may not be allowed to read everything the
+/// sender can, so only the notes readable by an anonymous user are offered.
+pub async fn handle_inline_query(
+    bot: BotType,
+    query: InlineQuery,
+    global_state: Arc<GlobalState>,
+) -> HandlerResult<()> {
+    let uctx = ProviderUserContext::anonymous();
+    let db = &global_state.db;
+    let found = db.search_notes(uctx, query.query, MAX_RESULTS).await?;
+
+    let mut results = Vec::with_capacity(found.len());
+    for result in found {
+        let note = db.read_note(uctx, result.id).await?;
+        let mut text = STRINGS.kb.note_template(&result.name).concat(note.text);
+        if !note.attachments.is_empty() {
+            text = text.concat(STRINGS.inline.attachments(&note.attachments.len()));
+        }
+        // A single note that is too long would make Telegram reject the whole answer.
+        let text = text.truncate(MAX_TEXT_LENGTH);
+        let mut content = InputMessageContentText::new(text.raw_text);
+        content.entities = text.entities;
+
+        let description = format!(
+            "{}\n{}",
+            breadcrumbs(&result.path),
+            result.snippet.replace('\n', " "),
+        );
+        let article = InlineQueryResultArticle::new(
+            result.id.to_string(),
+            result.name,
+            InputMessageContent::Text(content),
+        )
+        .description(description);
+        results.push(InlineQueryResult::Article(article));
+    }
+
+    bot.answer_inline_query(query.id, results)
+        .cache_time(CACHE_TIME)
+        .await?;
+    Ok(())
+}
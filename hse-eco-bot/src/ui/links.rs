@@ -0,0 +1,65 @@
+//! Deep links to the knowledge base items.
+
+use super::Context;
+use crate::db::{FullItemId, FullNoteId};
+use crate::links::DeepLink;
+use crate::state::{states, DialogState};
+use crate::strings::STRINGS;
+use crate::types::{HandlerError, HandlerResult};
+use teloxide::prelude::*;
+
+/// Get the payload of a `/start` command, which is sent when the user follows a deep link.
+pub(super) fn start_payload(message: &Message) -> Option<&str> {
+    let payload = message.text()?.strip_prefix("/start ")?.trim();
+    (!payload.is_empty()).then_some(payload)
+}
+
+impl Context<'_, '_, '_, '_> {
+    /// Open the knowledge base item referred to by the payload of a deep link.
+    ///
+    /// If the item does not exist or cannot be read by the user, they are sent to the main menu.
+    pub(super) async fn open_deep_link(&mut self, payload: &str) -> HandlerResult<()> {
+        let item = match DeepLink::parse(payload) {
+            Some(DeepLink::Item(item)) => Some(item),
+            Some(DeepLink::Slug(slug)) => self.global_state.db.resolve_link(slug).await?,
+            None => None,
+        };
+        let state = match item {
+            Some(FullItemId::Note(id)) => DialogState::KbNoteViewing(states::KbNoteViewing { id }),
+            Some(FullItemId::Directory(id)) => {
+                DialogState::KbNavigation(states::KbNavigation { id })
+            }
+            None => return self.reject_deep_link().await,
+        };
+        self.set_state(state);
+        match self.send_state_prompt().await {
+            Ok(()) => Ok(()),
+            Err(HandlerError::User(e)) => {
+                debug!("Cannot open the deep link {:?}: {:?}", payload, &e);
+                self.reject_deep_link().await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn reject_deep_link(&mut self) -> HandlerResult<()> {
+        self.send_message(STRINGS.links.invalid().into()).await?;
+        self.set_state(DialogState::MainMenu);
+        self.send_main_menu().await
+    }
+
+    /// Send the user a link that opens a note in the bot.
+    pub(super) async fn share_note(&mut self, id: FullNoteId) -> HandlerResult<()> {
+        let db = &self.global_state.db;
+        let note_name = db.note_name(self.uctx(), id).await?;
+        let slug = db.link_slug(FullItemId::Note(id)).await?;
+        let me = self.bot.get_me().await?;
+        let link = format!(
+            "https://t.me/{}?start={}",
+            me.username(),
+            DeepLink::slug_payload(&slug),
+        );
+        self.send_message(STRINGS.links.note(&note_name, &link).into())
+            .await
+    }
+}
@@ -0,0 +1,223 @@
+//! The knowledge base items pinned to the main menu.
+
+use super::{extract_name, Context};
+use crate::callback_query::Query;
+use crate::db::FullItemId;
+use crate::invalid_action::InvalidAction;
+use crate::kb::ProviderError;
+use crate::message::FormattedMessage;
+use crate::pins::Pin;
+use crate::state::{states, DialogState};
+use crate::strings::STRINGS;
+use crate::types::HandlerResult;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+impl Context<'_, '_, '_, '_> {
+    /// Get the name of a pinned item as seen by the current user.
+    async fn pinned_item_name(&self, item: FullItemId) -> Result<String, ProviderError> {
+        let uctx = self.uctx();
+        let db = &self.global_state.db;
+        match item {
+            FullItemId::Note(note) => Ok(format!("🗒 {}", db.note_name(uctx, note).await?)),
+            FullItemId::Directory(dir) => Ok(match db.directory_name(uctx, dir).await? {
+                Some(name) => format!("📂 {}", name),
+                None => String::from("📂 База знаний"),
+            }),
+        }
+    }
+
+    /// Get the main menu buttons of the pinned items.
+    ///
+    /// The items that cannot be read by the current user (e.g. deleted ones) are skipped.
+    pub(super) async fn pinned_item_buttons(
+        &self,
+    ) -> HandlerResult<Vec<Vec<InlineKeyboardButton>>> {
+        let pins = self.global_state.db.pins().await?;
+        let mut buttons = Vec::with_capacity(pins.len());
+        for pin in pins {
+            let name = match self.pinned_item_name(pin.item).await {
+                Ok(name) => name,
+                Err(e) => {
+                    debug!("Pinned item {:?} is skipped: {}", pin.item, e);
+                    continue;
+                }
+            };
+            let query = match pin.item {
+                FullItemId::Note(id) => Query::KbNavToNote { id },
+                FullItemId::Directory(id) => Query::KbNavToDir { id },
+            };
+            buttons.push(vec![InlineKeyboardButton::callback(
+                pin.label.unwrap_or(name),
+                query,
+            )]);
+        }
+        Ok(buttons)
+    }
+
+    /// Get the button to pin or unpin an item.
+    pub(super) async fn pin_toggle_button(
+        &self,
+        item: FullItemId,
+    ) -> HandlerResult<InlineKeyboardButton> {
+        let is_pinned = self.global_state.db.find_pin(item).await?.is_some();
+        let query = match (item, is_pinned) {
+            (FullItemId::Note(id), false) => Query::KbPinNote { id },
+            (FullItemId::Note(id), true) => Query::KbUnpinNote { id },
+            (FullItemId::Directory(id), false) => Query::KbPinDirectory { id },
+            (FullItemId::Directory(id), true) => Query::KbUnpinDirectory { id },
+        };
+        let text = if is_pinned {
+            "📍 Открепить от главного меню"
+        } else {
+            "📌 Закрепить в главном меню"
+        };
+        Ok(InlineKeyboardButton::callback(text, query))
+    }
+
+    pub(super) async fn pin_item(&mut self, item: FullItemId) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        // Make sure that the item exists.
+        self.pinned_item_name(item).await?;
+        let uctx = self.uctx();
+        let text = if self.global_state.db.pin_item(uctx, item).await? {
+            STRINGS.pins.pinned()
+        } else {
+            STRINGS.pins.already_pinned()
+        };
+        self.send_message(text.into()).await?;
+        Ok(())
+    }
+
+    pub(super) async fn unpin_item(&mut self, item: FullItemId) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        let uctx = self.uctx();
+        let text = if self.global_state.db.unpin_item(uctx, item).await? {
+            STRINGS.pins.unpinned()
+        } else {
+            STRINGS.pins.not_pinned()
+        };
+        self.send_message(text.into()).await?;
+        Ok(())
+    }
+
+    pub(super) async fn move_pin(&mut self, pin: u64, up: bool) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        let uctx = self.uctx();
+        if !self.global_state.db.move_pin(uctx, pin, up).await? {
+            return Err(InvalidAction::InvalidState.into());
+        }
+        Ok(())
+    }
+
+    pub(super) async fn reset_pin_label(&mut self, pin: u64) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        let uctx = self.uctx();
+        if !self.global_state.db.set_pin_label(uctx, pin, None).await? {
+            return Err(InvalidAction::InvalidState.into());
+        }
+        self.send_message(STRINGS.pins.label_reset().into()).await?;
+        self.set_state(DialogState::PinManagement);
+        Ok(())
+    }
+
+    pub(super) async fn handle_pin_renaming_message(
+        &mut self,
+        message: Message,
+        state: states::PinRenaming,
+    ) -> HandlerResult<()> {
+        let label = extract_name(&message)?;
+        self.require_kb_edit_permission()?;
+        if !self
+            .global_state
+            .db
+            .set_pin_label(self.uctx(), state.pin, Some(label.to_owned()))
+            .await?
+        {
+            return Err(InvalidAction::InvalidState.into());
+        }
+        self.send_message(STRINGS.pins.label_set(label).into())
+            .await?;
+        self.set_state(DialogState::PinManagement);
+        self.send_state_prompt().await
+    }
+
+    /// Send the list of the pinned items with the controls to reorder, rename and unpin them.
+    pub(super) async fn send_pin_management(&mut self) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        let pins = self.global_state.db.pins().await?;
+        let mut text = if pins.is_empty() {
+            STRINGS.pins.management_empty()
+        } else {
+            STRINGS.pins.management()
+        };
+        let mut inline_keyboard = Vec::with_capacity(pins.len() + 1);
+        for (i, Pin { id, item, label }) in pins.into_iter().enumerate() {
+            let number = i + 1;
+            let name = match self.pinned_item_name(item).await {
+                Ok(name) => name,
+                Err(_) => STRINGS.pins.unavailable().raw_text,
+            };
+            let item_text = match label {
+                Some(label) => STRINGS.pins.management_item_labeled(&number, &label, &name),
+                None => STRINGS.pins.management_item(&number, &name),
+            };
+            text = text.concat(item_text);
+            let unpin_query = match item {
+                FullItemId::Note(id) => Query::KbUnpinNote { id },
+                FullItemId::Directory(id) => Query::KbUnpinDirectory { id },
+            };
+            inline_keyboard.push(vec![
+                InlineKeyboardButton::callback(
+                    format!("⬆️ {}", number),
+                    Query::KbMovePinUp { pin: id },
+                ),
+                InlineKeyboardButton::callback(
+                    format!("⬇️ {}", number),
+                    Query::KbMovePinDown { pin: id },
+                ),
+                InlineKeyboardButton::callback(
+                    format!("🔤 {}", number),
+                    Query::KbRenamePin { pin: id },
+                ),
+                InlineKeyboardButton::callback(format!("📍 {}", number), unpin_query),
+            ]);
+        }
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "🏠 В главное меню",
+            Query::OpenMainMenu,
+        )]);
+
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    pub(super) async fn send_pin_renaming_prompt(&mut self, pin: u64) -> HandlerResult<()> {
+        let pin = self
+            .global_state
+            .db
+            .pin(pin)
+            .await?
+            .ok_or(InvalidAction::InvalidState)?;
+        let name = self.pinned_item_name(pin.item).await?;
+        let reply_markup = InlineKeyboardMarkup {
+            inline_keyboard: vec![
+                vec![InlineKeyboardButton::callback(
+                    "↩️ Использовать название",
+                    Query::KbResetPinLabel { pin: pin.id },
+                )],
+                vec![InlineKeyboardButton::callback("⬅️ Назад", Query::GoBack)],
+            ],
+        };
+        self.send_message(FormattedMessage::with_markup(
+            STRINGS.pins.renaming_prompt(&name),
+            reply_markup.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+}
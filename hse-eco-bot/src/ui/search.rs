@@ -0,0 +1,135 @@
+//! Full-text search over the knowledge base.
+
+use super::{breadcrumbs, Context};
+use crate::callback_query::Query;
+use crate::db::FullDirectoryId;
+use crate::invalid_action::InvalidAction;
+use crate::message::FormattedMessage;
+use crate::message_format_error::MessageFormatError;
+use crate::state::{states, DialogState};
+use crate::strings::STRINGS;
+use crate::types::HandlerResult;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// The number of search results shown on a page.
+const PAGE_SIZE: usize = 5;
+
+/// The maximum number of search results shown to the user.
+const MAX_RESULTS: usize = 50;
+
+impl Context<'_, '_, '_, '_> {
+    pub(super) async fn handle_search_message(
+        &mut self,
+        message: Message,
+        from: FullDirectoryId,
+    ) -> HandlerResult<()> {
+        let query = message.text().ok_or(MessageFormatError::NoText)?.trim();
+        self.set_state(DialogState::KbSearchResults(states::KbSearchResults {
+            from,
+            query: query.to_owned(),
+            page: 0,
+        }));
+        self.send_state_prompt().await
+    }
+
+    pub(super) fn open_search_page(&mut self, page: usize) -> Result<(), InvalidAction> {
+        match self.state() {
+            DialogState::KbSearchResults(results) => {
+                self.set_state(DialogState::KbSearchResults(states::KbSearchResults {
+                    page,
+                    ..results
+                }));
+                Ok(())
+            }
+            _ => Err(InvalidAction::InvalidState),
+        }
+    }
+
+    pub(super) async fn send_search_prompt(&mut self) -> HandlerResult<()> {
+        let reply_markup = InlineKeyboardMarkup {
+            inline_keyboard: vec![vec![InlineKeyboardButton::callback(
+                "⬅️ Назад",
+                Query::GoBack,
+            )]],
+        };
+        self.send_message(FormattedMessage::with_markup(
+            STRINGS.search.prompt(),
+            reply_markup.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// Send a page of the search results with the buttons to open the found notes.
+    pub(super) async fn send_search_results(
+        &mut self,
+        results: states::KbSearchResults,
+    ) -> HandlerResult<()> {
+        let found = self
+            .global_state
+            .db
+            .search_notes(self.uctx(), results.query.clone(), MAX_RESULTS)
+            .await?;
+
+        let mut inline_keyboard = Vec::with_capacity(PAGE_SIZE + 2);
+        let text = if found.is_empty() {
+            STRINGS.search.no_results(&results.query)
+        } else {
+            let num_pages = found.len().div_ceil(PAGE_SIZE);
+            // The number of the results may have changed since the page was opened.
+            let page = results.page.min(num_pages - 1);
+            let mut text = STRINGS
+                .search
+                .results(&results.query, &(page + 1), &num_pages);
+            for (i, result) in found
+                .into_iter()
+                .enumerate()
+                .skip(page * PAGE_SIZE)
+                .take(PAGE_SIZE)
+            {
+                let number = i + 1;
+                let snippet = result.snippet.replace('\n', " ");
+                text = text.concat(STRINGS.search.result(
+                    &number,
+                    &result.name,
+                    &breadcrumbs(&result.path),
+                    &snippet,
+                ));
+                inline_keyboard.push(vec![InlineKeyboardButton::callback(
+                    format!("{}. {}", number, result.name),
+                    Query::KbNavToNote { id: result.id },
+                )]);
+            }
+
+            let mut page_row = Vec::with_capacity(2);
+            if page > 0 {
+                page_row.push(InlineKeyboardButton::callback(
+                    "◀️",
+                    Query::KbSearchPage { page: page - 1 },
+                ));
+            }
+            if page + 1 < num_pages {
+                page_row.push(InlineKeyboardButton::callback(
+                    "▶️",
+                    Query::KbSearchPage { page: page + 1 },
+                ));
+            }
+            if !page_row.is_empty() {
+                inline_keyboard.push(page_row);
+            }
+            text
+        };
+        inline_keyboard.push(vec![
+            InlineKeyboardButton::callback("⬅️ Назад", Query::GoBack),
+            InlineKeyboardButton::callback("🏠 В главное меню", Query::OpenMainMenu),
+        ]);
+
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! The trash: the deleted knowledge base items which can still be restored.
+
+use super::admin::user_label;
+use super::form::DATE_TIME_FORMAT;
+use super::{breadcrumbs, Context};
+use crate::callback_query::Query;
+use crate::invalid_action::InvalidAction;
+use crate::message::FormattedMessage;
+use crate::state::{states, DialogState};
+use crate::strings::STRINGS;
+use crate::types::HandlerResult;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// The number of the deleted items shown on a page.
+const PAGE_SIZE: usize = 8;
+
+/// The maximum number of the deleted items shown to the user.
+const MAX_ITEMS: usize = 200;
+
+/// The number of the restore buttons in a row.
+const BUTTONS_PER_ROW: usize = 4;
+
+impl Context<'_, '_, '_, '_> {
+    pub(super) fn open_trash(&mut self, page: usize) -> HandlerResult<()> {
+        self.require_kb_edit_permission()?;
+        self.set_state(DialogState::Trash(states::Trash { page }));
+        Ok(())
+    }
+
+    pub(super) fn open_trash_page(&mut self, page: usize) -> HandlerResult<()> {
+        match self.state() {
+            DialogState::Trash(_) => self.open_trash(page),
+            _ => Err(InvalidAction::InvalidState.into()),
+        }
+    }
+
+    /// Send a page of the deleted items with the buttons restoring them.
+    pub(super) async fn send_trash(&mut self, trash: states::Trash) -> HandlerResult<()> {
+        let db = &self.global_state.db;
+        let deleted = db.deleted_items(self.uctx(), MAX_ITEMS).await?;
+
+        let mut text = STRINGS
+            .trash
+            .header(&self.global_state.trash_retention_days);
+        let mut inline_keyboard = Vec::new();
+        if deleted.is_empty() {
+            text = text.concat(STRINGS.trash.empty());
+        } else {
+            let num_pages = deleted.len().div_ceil(PAGE_SIZE);
+            // The number of the deleted items may have changed since the page was opened.
+            let page = trash.page.min(num_pages - 1);
+            text = text.concat(STRINGS.trash.page(&(page + 1), &num_pages));
+
+            let mut buttons = Vec::with_capacity(PAGE_SIZE);
+            for (i, item) in deleted
+                .into_iter()
+                .enumerate()
+                .skip(page * PAGE_SIZE)
+                .take(PAGE_SIZE)
+            {
+                let number = i + 1;
+                text = text.concat(if item.is_directory {
+                    STRINGS.trash.directory(&number, &item.name)
+                } else {
+                    STRINGS.trash.note(&number, &item.name)
+                });
+                text = text.concat(match &item.location {
+                    Some(path) => STRINGS.trash.location(&breadcrumbs(path)),
+                    None => STRINGS.trash.location_deleted(),
+                });
+                let time = item.time.format(DATE_TIME_FORMAT).to_string();
+                text = text.concat(match item.user_id {
+                    Some(user_id) => {
+                        let username = db.username(user_id).await?;
+                        STRINGS
+                            .trash
+                            .deleted_by(&time, &user_label(user_id, username.as_deref()))
+                    }
+                    None => STRINGS.trash.deleted_at(&time),
+                });
+                buttons.push(InlineKeyboardButton::callback(
+                    format!("↩️ {}", number),
+                    Query::KbRestoreRevision { id: item.revision },
+                ));
+            }
+            inline_keyboard.extend(buttons.chunks(BUTTONS_PER_ROW).map(|row| row.to_vec()));
+
+            let mut page_row = Vec::with_capacity(2);
+            if page > 0 {
+                page_row.push(InlineKeyboardButton::callback(
+                    "◀️",
+                    Query::TrashPage { page: page - 1 },
+                ));
+            }
+            if page + 1 < num_pages {
+                page_row.push(InlineKeyboardButton::callback(
+                    "▶️",
+                    Query::TrashPage { page: page + 1 },
+                ));
+            }
+            if !page_row.is_empty() {
+                inline_keyboard.push(page_row);
+            }
+        }
+        inline_keyboard.push(vec![InlineKeyboardButton::callback(
+            "🏠 В главное меню",
+            Query::OpenMainMenu,
+        )]);
+
+        self.send_message(FormattedMessage::with_markup(
+            text,
+            InlineKeyboardMarkup { inline_keyboard }.into(),
+        ))
+        .await?;
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
+pub mod storage;
+
 use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct User {
+    username: Option<String>,
     permissions: Permissions,
     subscriptions: HashSet<String>,
 }
@@ -9,11 +14,25 @@ pub struct User {
 impl User {
     pub fn new() -> Self {
         Self {
+            username: None,
             permissions: Permissions::default(),
             subscriptions: HashSet::new(),
         }
     }
 
+    /// Get the last known Telegram username of the user (without the leading `@`).
+    ///
+    /// The username is only a lookup aid: users are identified by their numeric IDs.
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    pub fn set_username(&mut self, username: Option<String>) {
+        self.username = username;
+    }
+
+    /// Get the effective permissions of the user, i.e. the union of the permissions of all their
+    /// roles.
     pub fn permissions(&self) -> &Permissions {
         &self.permissions
     }
@@ -52,4 +71,130 @@ impl Permissions {
             send_global_notifications: true,
         }
     }
+
+    fn flag(&self, permission: Permission) -> &bool {
+        match permission {
+            Permission::EditKb => &self.edit_kb,
+            Permission::ReceiveServiceNotifications => &self.receive_service_notifications,
+            Permission::ReceiveFeedback => &self.receive_feedback,
+            Permission::Admin => &self.admin,
+            Permission::ManageEvents => &self.manage_events,
+            Permission::SendGlobalNotifications => &self.send_global_notifications,
+        }
+    }
+
+    fn flag_mut(&mut self, permission: Permission) -> &mut bool {
+        match permission {
+            Permission::EditKb => &mut self.edit_kb,
+            Permission::ReceiveServiceNotifications => &mut self.receive_service_notifications,
+            Permission::ReceiveFeedback => &mut self.receive_feedback,
+            Permission::Admin => &mut self.admin,
+            Permission::ManageEvents => &mut self.manage_events,
+            Permission::SendGlobalNotifications => &mut self.send_global_notifications,
+        }
+    }
+
+    pub fn contains(&self, permission: Permission) -> bool {
+        *self.flag(permission)
+    }
+
+    pub fn insert(&mut self, permission: Permission) {
+        *self.flag_mut(permission) = true;
+    }
+
+    /// Iterate over the granted permissions.
+    pub fn iter(&self) -> impl Iterator<Item = Permission> + '_ {
+        Permission::ALL
+            .into_iter()
+            .filter(|&permission| self.contains(permission))
+    }
+}
+
+impl FromIterator<Permission> for Permissions {
+    fn from_iter<I: IntoIterator<Item = Permission>>(iter: I) -> Self {
+        let mut permissions = Self::default();
+        for permission in iter {
+            permissions.insert(permission);
+        }
+        permissions
+    }
+}
+
+/// A single field of [`Permissions`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Permission {
+    EditKb,
+    ReceiveServiceNotifications,
+    ReceiveFeedback,
+    Admin,
+    ManageEvents,
+    SendGlobalNotifications,
+}
+
+impl Permission {
+    pub const ALL: [Self; 6] = [
+        Self::EditKb,
+        Self::ReceiveServiceNotifications,
+        Self::ReceiveFeedback,
+        Self::Admin,
+        Self::ManageEvents,
+        Self::SendGlobalNotifications,
+    ];
+
+    /// Get the name of the permission, as stored in the database.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::EditKb => strings::EDIT_KB,
+            Self::ReceiveServiceNotifications => strings::RECEIVE_SERVICE_NOTIFICATIONS,
+            Self::ReceiveFeedback => strings::RECEIVE_FEEDBACK,
+            Self::Admin => strings::ADMIN,
+            Self::ManageEvents => strings::MANAGE_EVENTS,
+            Self::SendGlobalNotifications => strings::SEND_GLOBAL_NOTIFICATIONS,
+        }
+    }
+
+    /// Get the human-readable description of the permission.
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::EditKb => "Редактирование базы знаний",
+            Self::ReceiveServiceNotifications => "Получение служебных уведомлений",
+            Self::ReceiveFeedback => "Получение обратной связи",
+            Self::Admin => "Администрирование бота",
+            Self::ManageEvents => "Управление мероприятиями",
+            Self::SendGlobalNotifications => "Отправка объявлений всем пользователям",
+        }
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Permission {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|permission| permission.name() == s)
+            .ok_or(())
+    }
+}
+
+/// A named set of permissions that can be assigned to users.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Role {
+    pub name: String,
+    pub permissions: Permissions,
+}
+
+mod strings {
+    pub const EDIT_KB: &str = "edit_kb";
+    pub const RECEIVE_SERVICE_NOTIFICATIONS: &str = "receive_service_notifications";
+    pub const RECEIVE_FEEDBACK: &str = "receive_feedback";
+    pub const ADMIN: &str = "admin";
+    pub const MANAGE_EVENTS: &str = "manage_events";
+    pub const SEND_GLOBAL_NOTIFICATIONS: &str = "send_global_notifications";
 }
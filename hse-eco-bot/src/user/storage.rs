@@ -0,0 +1,323 @@
+use super::{Permission, Permissions, Role, User};
+use crate::state::DialogState;
+use crate::util::{NestedTransaction, UnsafeRc};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use teloxide::types::{ChatId, UserId};
+
+/// A user loaded from the [`UserStorage`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredUser {
+    pub chat_id: ChatId,
+    pub user: User,
+    /// The state of the dialog with the user, if it has been stored and can still be read.
+    pub state: Option<DialogState>,
+}
+
+/// A user with at least one role.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PrivilegedUser {
+    pub id: UserId,
+    /// The last known username, if the user has ever started the bot.
+    pub username: Option<String>,
+    /// The names of the roles sorted by name.
+    pub roles: Vec<String>,
+}
+
+/// Persistent storage of the users known to the bot and their roles.
+///
+/// Every user is stored together with the ID of the private chat with them, so that newsletters
+/// can reach them even after the bot has been restarted.
+///
+/// Roles are assigned by numeric user IDs, so a role can be granted to a user before they start a
+/// dialog with the bot.
+pub struct UserStorage {
+    db: UnsafeRc<Connection>,
+}
+
+impl UserStorage {
+    /// SAFETY: the caller must uphold the invariants of [`UnsafeRc`].
+    pub unsafe fn new(db: UnsafeRc<Connection>) -> Self {
+        Self { db }
+    }
+
+    /// Insert or replace the stored data of a user.
+    ///
+    /// The permissions of the user are not stored, since they are determined by the roles. The
+    /// roles pending for the username of the user are granted to the user, and then the new
+    /// effective permissions of the user are returned.
+    pub fn store(
+        &self,
+        user_id: UserId,
+        chat_id: ChatId,
+        user: &User,
+    ) -> rusqlite::Result<Option<Permissions>> {
+        let txn = NestedTransaction::deferred(&self.db)?;
+        txn.prepare(concat!(
+            "INSERT INTO users(id, chat_id, username) VALUES (?, ?, ?) ",
+            "ON CONFLICT(id) DO UPDATE SET chat_id = excluded.chat_id, ",
+            "username = excluded.username",
+        ))?
+        .execute(params![user_id.0, chat_id.0, user.username()])?;
+        let mut granted = 0;
+        if let Some(username) = user.username() {
+            granted = txn
+                .prepare(concat!(
+                    "INSERT OR IGNORE INTO user_roles(user_id, role) ",
+                    "SELECT ?, role FROM pending_roles WHERE username = ?",
+                ))?
+                .execute(params![user_id.0, username])?;
+            txn.prepare("DELETE FROM pending_roles WHERE username = ?")?
+                .execute(params![username])?;
+        }
+        txn.prepare("DELETE FROM user_subscriptions WHERE user_id = ?")?
+            .execute(params![user_id.0])?;
+        {
+            let mut stmt =
+                txn.prepare("INSERT INTO user_subscriptions(user_id, newsletter) VALUES (?, ?)")?;
+            for newsletter in user.subscriptions() {
+                stmt.execute(params![user_id.0, newsletter])?;
+            }
+        }
+        txn.commit()?;
+        if granted == 0 {
+            return Ok(None);
+        }
+        self.permissions(user_id).map(Some)
+    }
+
+    /// Store the state of the dialog with a user.
+    ///
+    /// Nothing is stored if the user is not known yet.
+    pub fn store_state(&self, user_id: UserId, state: &DialogState) -> rusqlite::Result<()> {
+        let json = serde_json::to_string(state)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        self.db
+            .prepare("UPDATE users SET dialog_state = ? WHERE id = ?")?
+            .execute(params![json, user_id.0])?;
+        Ok(())
+    }
+
+    /// Find a known user by their last known username (without the leading `@`).
+    pub fn find_by_username(&self, username: &str) -> rusqlite::Result<Option<UserId>> {
+        self.db
+            .prepare("SELECT id FROM users WHERE username = ? COLLATE NOCASE")?
+            .query_row(params![username], |row| Ok(UserId(row.get(0)?)))
+            .optional()
+    }
+
+    /// Get the last known username of a user.
+    pub fn username(&self, user_id: UserId) -> rusqlite::Result<Option<String>> {
+        Ok(self
+            .db
+            .prepare("SELECT username FROM users WHERE id = ?")?
+            .query_row(params![user_id.0], |row| row.get(0))
+            .optional()?
+            .flatten())
+    }
+
+    /// Get all the users with at least one role sorted by ID.
+    pub fn privileged_users(&self) -> rusqlite::Result<Vec<PrivilegedUser>> {
+        let mut stmt = self.db.prepare(concat!(
+            "SELECT user_roles.user_id, users.username, user_roles.role FROM user_roles ",
+            "LEFT JOIN users ON users.id = user_roles.user_id ",
+            "ORDER BY user_roles.user_id, user_roles.role",
+        ))?;
+        let rows = stmt.query_map(params![], |row| {
+            Ok((
+                UserId(row.get(0)?),
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        let mut users: Vec<PrivilegedUser> = Vec::new();
+        for row in rows {
+            let (id, username, role) = row?;
+            match users.last_mut() {
+                Some(user) if user.id == id => user.roles.push(role),
+                _ => users.push(PrivilegedUser {
+                    id,
+                    username,
+                    roles: vec![role],
+                }),
+            }
+        }
+        Ok(users)
+    }
+
+    /// Get all the roles sorted by name.
+    pub fn roles(&self) -> rusqlite::Result<Vec<Role>> {
+        let mut roles: Vec<Role> = self
+            .db
+            .prepare("SELECT name FROM roles ORDER BY name")?
+            .query_map(params![], |row| {
+                Ok(Role {
+                    name: row.get(0)?,
+                    permissions: Permissions::default(),
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        let mut stmt = self
+            .db
+            .prepare("SELECT role, permission FROM role_permissions")?;
+        let rows = stmt.query_map(params![], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (role_name, permission_name) = row?;
+            if let (Some(role), Some(permission)) = (
+                roles.iter_mut().find(|role| role.name == role_name),
+                parse_permission(&permission_name),
+            ) {
+                role.permissions.insert(permission);
+            }
+        }
+        Ok(roles)
+    }
+
+    /// Get the names of the roles of a user sorted by name.
+    pub fn user_roles(&self, user_id: UserId) -> rusqlite::Result<Vec<String>> {
+        self.db
+            .prepare("SELECT role FROM user_roles WHERE user_id = ? ORDER BY role")?
+            .query_map(params![user_id.0], |row| row.get(0))?
+            .collect()
+    }
+
+    /// Get the users that have a role.
+    pub fn users_with_role(&self, role: &str) -> rusqlite::Result<Vec<UserId>> {
+        self.db
+            .prepare("SELECT user_id FROM user_roles WHERE role = ? ORDER BY user_id")?
+            .query_map(params![role], |row| Ok(UserId(row.get(0)?)))?
+            .collect()
+    }
+
+    /// Grant a role to a user and return the new effective permissions of the user.
+    ///
+    /// Granting a role that the user already has is not an error.
+    pub fn grant_role(&self, user_id: UserId, role: &str) -> rusqlite::Result<Permissions> {
+        self.db
+            .prepare("INSERT OR IGNORE INTO user_roles(user_id, role) VALUES (?, ?)")?
+            .execute(params![user_id.0, role])?;
+        self.permissions(user_id)
+    }
+
+    /// Revoke a role from a user and return the new effective permissions of the user.
+    ///
+    /// Revoking a role that the user does not have is not an error.
+    pub fn revoke_role(&self, user_id: UserId, role: &str) -> rusqlite::Result<Permissions> {
+        self.db
+            .prepare("DELETE FROM user_roles WHERE user_id = ? AND role = ?")?
+            .execute(params![user_id.0, role])?;
+        self.permissions(user_id)
+    }
+
+    /// Get the effective permissions of a user.
+    pub fn permissions(&self, user_id: UserId) -> rusqlite::Result<Permissions> {
+        let mut stmt = self.db.prepare(concat!(
+            "SELECT role_permissions.permission FROM user_roles ",
+            "JOIN role_permissions ON role_permissions.role = user_roles.role ",
+            "WHERE user_roles.user_id = ?",
+        ))?;
+        let names = stmt.query_map(params![user_id.0], |row| row.get::<_, String>(0))?;
+        let mut permissions = Permissions::default();
+        for name in names {
+            if let Some(permission) = parse_permission(&name?) {
+                permissions.insert(permission);
+            }
+        }
+        Ok(permissions)
+    }
+
+    /// Get the effective permissions of all the users that have at least one role.
+    pub fn all_permissions(&self) -> rusqlite::Result<HashMap<UserId, Permissions>> {
+        load_permissions(&self.db)
+    }
+}
+
+fn parse_permission(name: &str) -> Option<Permission> {
+    match name.parse() {
+        Ok(permission) => Some(permission),
+        Err(()) => {
+            warn!("Unknown permission `{}` is ignored", name);
+            None
+        }
+    }
+}
+
+/// Load the effective permissions of all the users that have at least one role.
+pub fn load_permissions(db: &Connection) -> rusqlite::Result<HashMap<UserId, Permissions>> {
+    let mut stmt = db.prepare(concat!(
+        "SELECT user_roles.user_id, role_permissions.permission FROM user_roles ",
+        "JOIN role_permissions ON role_permissions.role = user_roles.role",
+    ))?;
+    let rows = stmt.query_map(params![], |row| {
+        Ok((UserId(row.get(0)?), row.get::<_, String>(1)?))
+    })?;
+    let mut permissions: HashMap<UserId, Permissions> = HashMap::new();
+    for row in rows {
+        let (user_id, name) = row?;
+        let user_permissions = permissions.entry(user_id).or_default();
+        if let Some(permission) = parse_permission(&name) {
+            user_permissions.insert(permission);
+        }
+    }
+    Ok(permissions)
+}
+
+/// Load all the stored users.
+///
+/// This function is meant to be called once at startup, before the connection is handed over to
+/// the database access task.
+///
+/// Dialog states that cannot be deserialized (e.g. the ones stored by an incompatible version of
+/// the bot) are skipped with a warning.
+pub fn load_users(db: &Connection) -> rusqlite::Result<HashMap<UserId, StoredUser>> {
+    let txn = NestedTransaction::deferred(db)?;
+    let permissions = load_permissions(&txn)?;
+    let mut users = HashMap::new();
+    {
+        let mut stmt = txn.prepare("SELECT id, chat_id, username, dialog_state FROM users")?;
+        let rows = stmt.query_map(params![], |row| {
+            let user_id = UserId(row.get(0)?);
+            let chat_id = ChatId(row.get(1)?);
+            let username: Option<String> = row.get(2)?;
+            let state: Option<String> = row.get(3)?;
+            Ok((user_id, chat_id, username, state))
+        })?;
+        for row in rows {
+            let (user_id, chat_id, username, state_json) = row?;
+            let mut user = User::new();
+            user.set_username(username);
+            *user.permissions_mut() = permissions.get(&user_id).copied().unwrap_or_default();
+            let state = state_json.and_then(|json| match serde_json::from_str(&json) {
+                Ok(state) => Some(state),
+                Err(e) => {
+                    warn!("Cannot read the stored dialog state of user {}: {}", user_id.0, e);
+                    None
+                }
+            });
+            users.insert(
+                user_id,
+                StoredUser {
+                    chat_id,
+                    user,
+                    state,
+                },
+            );
+        }
+    }
+    {
+        let mut stmt = txn.prepare("SELECT user_id, newsletter FROM user_subscriptions")?;
+        let rows = stmt.query_map(params![], |row| {
+            Ok((UserId(row.get(0)?), row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (user_id, newsletter) = row?;
+            if let Some(stored) = users.get_mut(&user_id) {
+                stored.user.subscriptions_mut().insert(newsletter);
+            }
+        }
+    }
+    txn.commit()?;
+    Ok(users)
+}
@@ -1,9 +1,13 @@
+use crate::audit_error::AuditError;
 use crate::dispatch::InvalidChatError;
+use crate::event_error::EventError;
 use crate::invalid_action::InvalidAction;
 use crate::kb::ProviderError;
 use crate::message::FormattedText;
 use crate::message_format_error::MessageFormatError;
+use crate::schedule_error::ScheduleError;
 use crate::user_facing_error::UserFacingError;
+use crate::user_lookup_error::UserLookupError;
 use std::error::Error;
 use crate::ui::form::FormInputError;
 use std::fmt::Display;
@@ -15,6 +19,10 @@ pub enum UserError {
     InvalidAction(InvalidAction),
     MessageFormat(MessageFormatError),
     FormInput(FormInputError),
+    UserLookup(UserLookupError),
+    Event(EventError),
+    Schedule(ScheduleError),
+    Audit(AuditError),
 }
 
 impl Display for UserError {
@@ -25,6 +33,10 @@ impl Display for UserError {
             Self::InvalidAction(e) => Display::fmt(&e, f),
             Self::MessageFormat(e) => Display::fmt(&e, f),
             Self::FormInput(e) => Display::fmt(&e, f),
+            Self::UserLookup(e) => Display::fmt(&e, f),
+            Self::Event(e) => Display::fmt(&e, f),
+            Self::Schedule(e) => Display::fmt(&e, f),
+            Self::Audit(e) => Display::fmt(&e, f),
         }
     }
 }
@@ -61,6 +73,30 @@ impl From<FormInputError> for UserError {
     }
 }
 
+impl From<UserLookupError> for UserError {
+    fn from(e: UserLookupError) -> Self {
+        Self::UserLookup(e)
+    }
+}
+
+impl From<EventError> for UserError {
+    fn from(e: EventError) -> Self {
+        Self::Event(e)
+    }
+}
+
+impl From<ScheduleError> for UserError {
+    fn from(e: ScheduleError) -> Self {
+        Self::Schedule(e)
+    }
+}
+
+impl From<AuditError> for UserError {
+    fn from(e: AuditError) -> Self {
+        Self::Audit(e)
+    }
+}
+
 impl UserFacingError for UserError {
     fn user_message(&self) -> FormattedText {
         match self {
@@ -69,6 +105,10 @@ impl UserFacingError for UserError {
             Self::InvalidAction(e) => e.user_message(),
             Self::MessageFormat(e) => e.user_message(),
             Self::FormInput(e) => e.user_message(),
+            Self::UserLookup(e) => e.user_message(),
+            Self::Event(e) => e.user_message(),
+            Self::Schedule(e) => e.user_message(),
+            Self::Audit(e) => e.user_message(),
         }
     }
 }
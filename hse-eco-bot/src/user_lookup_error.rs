@@ -0,0 +1,39 @@
+use crate::message::FormattedText;
+use crate::strings::STRINGS;
+use crate::user_facing_error::UserFacingError;
+use std::error::Error;
+use std::fmt::Display;
+
+/// The error when a user cannot be determined from a message.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum UserLookupError {
+    /// No user with the given username (without the leading `@`) has ever started the bot.
+    UnknownUsername(String),
+    /// The message is forwarded from a user who hides their account in forwarded messages.
+    HiddenForward,
+    /// The message is neither a forwarded message nor a username nor a user ID.
+    InvalidInput,
+}
+
+impl Display for UserLookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownUsername(username) => write!(f, "Unknown username `{}`", username),
+            Self::HiddenForward => write!(f, "The forwarded message hides its sender"),
+            Self::InvalidInput => write!(f, "The message does not identify a user"),
+        }
+    }
+}
+
+impl Error for UserLookupError {}
+
+impl UserFacingError for UserLookupError {
+    fn user_message(&self) -> FormattedText {
+        let s = &STRINGS.errors.user_lookup;
+        match self {
+            Self::UnknownUsername(username) => s.unknown_username(username),
+            Self::HiddenForward => s.hidden_forward(),
+            Self::InvalidInput => s.invalid_input(),
+        }
+    }
+}
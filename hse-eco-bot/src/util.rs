@@ -1,3 +1,4 @@
+use rusqlite::Connection;
 use std::ops::Deref;
 use std::rc::Rc;
 
@@ -30,3 +31,74 @@ impl<T> Clone for UnsafeRc<T> {
 }
 
 unsafe impl<T> Send for UnsafeRc<T> {}
+
+/// A transaction which may be nested in another one.
+///
+/// The storages share one connection, and a command of the database access task may change
+/// several of them along with the audit log, all of which must be committed together. A
+/// transaction started while another one is open is therefore a savepoint in it, and its changes
+/// are only committed along with the outer transaction.
+///
+/// Like [`rusqlite::Transaction`], it is rolled back if it is dropped without committing.
+pub struct NestedTransaction<'c> {
+    db: &'c Connection,
+    is_nested: bool,
+    is_finished: bool,
+}
+
+impl<'c> NestedTransaction<'c> {
+    /// Begin a deferred transaction, or a savepoint if a transaction is already open.
+    pub fn deferred(db: &'c Connection) -> rusqlite::Result<Self> {
+        Self::begin(db, "BEGIN DEFERRED")
+    }
+
+    /// Begin an immediate transaction, or a savepoint if a transaction is already open, in which
+    /// case the outer transaction decides when the database is locked.
+    pub fn immediate(db: &'c Connection) -> rusqlite::Result<Self> {
+        Self::begin(db, "BEGIN IMMEDIATE")
+    }
+
+    fn begin(db: &'c Connection, begin_sql: &str) -> rusqlite::Result<Self> {
+        let is_nested = !db.is_autocommit();
+        db.execute_batch(if is_nested {
+            "SAVEPOINT nested"
+        } else {
+            begin_sql
+        })?;
+        Ok(Self {
+            db,
+            is_nested,
+            is_finished: false,
+        })
+    }
+
+    /// Commit the transaction, or release the savepoint into the outer transaction.
+    pub fn commit(mut self) -> rusqlite::Result<()> {
+        self.is_finished = true;
+        self.db.execute_batch(if self.is_nested {
+            "RELEASE nested"
+        } else {
+            "COMMIT"
+        })
+    }
+}
+
+impl Deref for NestedTransaction<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Self::Target {
+        self.db
+    }
+}
+
+impl Drop for NestedTransaction<'_> {
+    fn drop(&mut self) {
+        if !self.is_finished {
+            let _ = self.db.execute_batch(if self.is_nested {
+                "ROLLBACK TO nested; RELEASE nested"
+            } else {
+                "ROLLBACK"
+            });
+        }
+    }
+}